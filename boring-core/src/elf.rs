@@ -0,0 +1,483 @@
+//! Defines the parts of the ELF format shared by the kernel and the loader.
+
+/// The index of the class in the identification bytes.
+pub const EI_CLASS: usize = 4;
+/// The index of the data encoding in the identification bytes.
+pub const EI_DATA: usize = 5;
+/// The index of the version in the identification bytes.
+pub const EI_VERSION: usize = 6;
+/// The index of the ABI in the identification bytes.
+pub const EI_OSABI: usize = 7;
+/// The index of the ABI version in the identification bytes.
+pub const EI_ABIVERSION: usize = 8;
+
+/// The magic number at the beginning of every ELF file.
+pub const ELF_MAGIC: [u8; 4] = [0x7f, 'E' as u8, 'L' as u8, 'F' as u8];
+/// A 64 bit file.
+pub const ELFCLASS64: u8 = 2;
+/// A file with the least significant byte first.
+pub const ELFDATA2LSB: u8 = 1;
+/// The current ELF version.
+pub const EV_CURRENT: u8 = 1;
+
+/// A file that may be executed.
+pub const ET_EXEC: u16 = 2;
+/// A shared object, which can be loaded at any address.
+pub const ET_DYN: u16 = 3;
+
+/// The x86_64 instruction set.
+pub const EM_X86_64: u16 = 0x3e;
+
+
+/// A loadable segment.
+pub const PT_LOAD: u32 = 1;
+/// The segment holding the dynamic section.
+pub const PT_DYNAMIC: u32 = 2;
+/// The segment holding the path to the program interpreter.
+pub const PT_INTERP: u32 = 3;
+
+/// The segment may be executed.
+pub const PF_X: u32 = 0x1;
+/// The segment may be written to.
+pub const PF_W: u32 = 0x2;
+/// The segment may be read.
+pub const PF_R: u32 = 0x4;
+
+/// Marks the end of the dynamic section.
+pub const DT_NULL: i64 = 0;
+/// The name of a needed shared object.
+pub const DT_NEEDED: i64 = 1;
+/// The total size of the PLT relocation table.
+pub const DT_PLTRELSZ: i64 = 2;
+/// The address of the global offset table.
+pub const DT_PLTGOT: i64 = 3;
+/// The address of the symbol hash table.
+pub const DT_HASH: i64 = 4;
+/// The address of the string table.
+pub const DT_STRTAB: i64 = 5;
+/// The address of the symbol table.
+pub const DT_SYMTAB: i64 = 6;
+/// The address of the relocation table.
+pub const DT_RELA: i64 = 7;
+/// The total size of the relocation table.
+pub const DT_RELASZ: i64 = 8;
+/// The size of a single relocation.
+pub const DT_RELAENT: i64 = 9;
+/// The type of the relocations in the PLT relocation table.
+pub const DT_PLTREL: i64 = 20;
+/// The object needs to modify non-writable segments.
+pub const DT_TEXTREL: i64 = 22;
+/// The address of the PLT relocation table.
+pub const DT_JMPREL: i64 = 23;
+/// All relocations should be processed before the program runs.
+pub const DT_BIND_NOW: i64 = 24;
+/// Flags for the object.
+pub const DT_FLAGS: i64 = 30;
+/// More flags for the object.
+pub const DT_FLAGS_1: i64 = 0x6ffffffb;
+
+/// The object needs to modify non-writable segments.
+pub const DF_TEXTREL: u64 = 0x4;
+/// All relocations should be processed before the program runs.
+pub const DF_BIND_NOW: u64 = 0x8;
+/// All relocations should be processed before the program runs.
+pub const DF_1_NOW: u64 = 0x1;
+
+/// A relocation that does nothing.
+pub const R_X86_64_NONE: u32 = 0;
+/// The address of the symbol plus the addend.
+pub const R_X86_64_64: u32 = 1;
+/// Copies the data of the symbol from the object that defines it.
+pub const R_X86_64_COPY: u32 = 5;
+/// The address of the symbol in the global offset table.
+pub const R_X86_64_GLOB_DAT: u32 = 6;
+/// The address of a function in the global offset table.
+pub const R_X86_64_JUMP_SLOT: u32 = 7;
+/// The load base plus the addend.
+pub const R_X86_64_RELATIVE: u32 = 8;
+
+/// The symbol is only visible within its object.
+pub const STB_LOCAL: u8 = 0;
+/// The symbol is visible to all objects.
+pub const STB_GLOBAL: u8 = 1;
+/// The symbol may be undefined.
+pub const STB_WEAK: u8 = 2;
+/// The symbol is undefined.
+pub const SHN_UNDEF: u16 = 0;
+
+/// Represents the header at the beginning of an ELF file.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Header {
+    /// The magic number, class, endianness and ABI of the file.
+    pub identification: [u8; 16],
+    /// The type of ELF file.
+    pub elf_type: u16,
+    /// The instruction set used by the ELF file.
+    pub instruction_set: u16,
+    /// The version of the ELF file.
+    pub version: u32,
+    /// The entry address for the program.
+    pub program_entry: usize,
+    /// The offset from file start to the program header.
+    pub program_header_offset: usize,
+    /// The offset from file start to the section header.
+    pub section_header_offset: usize,
+    /// Architecture specific flags.
+    pub flags: u32,
+    /// The size of the ELF header.
+    pub header_size: u16,
+    /// The size of a program header entry.
+    pub program_header_entry_size: u16,
+    /// The amount of program header entries.
+    pub program_header_entry_num: u16,
+    /// The size of a section header entry.
+    pub section_header_entry_size: u16,
+    /// The amount of section header entries.
+    pub section_header_entry_num: u16,
+    /// The index of the section header to the name strings.
+    pub name_string_table_index: u16,
+}
+
+impl Header {
+    /// Returns true if the file starts with the ELF magic number.
+    pub fn is_valid(&self) -> bool {
+        self.identification[..4] == ELF_MAGIC
+    }
+
+    /// Returns true if the file is a 64 bit little endian x86_64 file.
+    pub fn is_native(&self) -> bool {
+        self.is_valid() && self.identification[EI_CLASS] == ELFCLASS64
+            && self.identification[EI_DATA] == ELFDATA2LSB
+            && self.instruction_set == EM_X86_64
+    }
+
+    /// Returns true if the file is a 64 bit little endian x86_64 shared object.
+    pub fn is_shared_object(&self) -> bool {
+        self.is_native() && self.elf_type == ET_DYN
+    }
+}
+
+/// Represents a program header of an ELF file.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ProgramHeader {
+    /// The type of the segment.
+    pub segment_type: u32,
+    /// The flags of the segment.
+    pub flags: u32,
+    /// The offset from the beginning of the file.
+    pub offset: usize,
+    /// The virtual address at which the segment should be mapped.
+    pub virtual_address: usize,
+    /// The physical address at which the segment should be mapped.
+    pub physical_address: usize,
+    /// The size of the segment within the file.
+    pub size_in_file: usize,
+    /// The size of the segment within memory.
+    pub size_in_memory: usize,
+    /// The alignment of the segment.
+    pub align: usize,
+}
+
+/// Represents an entry in the dynamic section.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicEntry {
+    /// Specifies how the value is interpreted.
+    pub tag: i64,
+    /// The value or address of the entry.
+    pub value: u64,
+}
+
+/// Represents an entry in the symbol table.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Symbol {
+    /// The offset of the name in the string table.
+    pub name: u32,
+    /// The binding and the type of the symbol.
+    pub info: u8,
+    /// The visibility of the symbol.
+    pub other: u8,
+    /// The section the symbol is defined in.
+    pub section_index: u16,
+    /// The address of the symbol, relative to the load base.
+    pub value: usize,
+    /// The size of the symbol.
+    pub size: usize,
+}
+
+impl Symbol {
+    /// Returns the binding of the symbol.
+    pub fn binding(&self) -> u8 {
+        self.info >> 4
+    }
+
+    /// Returns true if the symbol is defined and visible to other objects.
+    pub fn is_exported(&self) -> bool {
+        self.section_index != SHN_UNDEF && self.binding() != STB_LOCAL
+    }
+}
+
+/// Represents a relocation with an explicit addend.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Relocation {
+    /// The address of the location to relocate, relative to the load base.
+    pub offset: usize,
+    /// The symbol index and the type of the relocation.
+    pub info: u64,
+    /// The constant addend used to compute the value.
+    pub addend: i64,
+}
+
+impl Relocation {
+    /// Returns the type of the relocation.
+    pub fn relocation_type(&self) -> u32 {
+        self.info as u32
+    }
+
+    /// Returns the index of the symbol the relocation refers to.
+    pub fn symbol_index(&self) -> usize {
+        (self.info >> 32) as usize
+    }
+
+    /// Returns the value the relocation stores at its target.
+    ///
+    /// `base` is the load base of the object and `symbol_address` the address
+    /// of the definition of the symbol, or zero if there is none. Returns
+    /// `None` for types that don't store a single value.
+    pub fn value(&self, base: usize, symbol_address: usize) -> Option<usize> {
+        let addend = self.addend as usize;
+
+        match self.relocation_type() {
+            R_X86_64_RELATIVE => Some(base.wrapping_add(addend)),
+            R_X86_64_64 => Some(symbol_address.wrapping_add(addend)),
+            R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => Some(symbol_address),
+            _ => None,
+        }
+    }
+}
+
+/// Computes the hash of a symbol name used in the symbol hash table.
+pub fn hash(name: &[u8]) -> u32 {
+    let mut hash: u32 = 0;
+
+    for &byte in name {
+        hash = (hash << 4).wrapping_add(byte as u32);
+
+        let high = hash & 0xf0000000;
+        if high != 0 {
+            hash ^= high >> 24;
+        }
+        hash &= !high;
+    }
+
+    hash
+}
+
+/// Represents the symbol hash table of an object.
+pub struct HashTable<'a> {
+    /// The index of the first symbol in each bucket.
+    buckets: &'a [u32],
+    /// The index of the next symbol in the same bucket for each symbol.
+    chains: &'a [u32],
+}
+
+impl<'a> HashTable<'a> {
+    /// Creates the hash table from its words.
+    ///
+    /// Returns `None` if the table has no buckets or is shorter than its
+    /// header says.
+    pub fn new(words: &'a [u32]) -> Option<HashTable<'a>> {
+        if words.len() < 2 {
+            return None;
+        }
+
+        let bucket_num = words[0] as usize;
+        let chain_num = words[1] as usize;
+
+        if bucket_num == 0 || words.len() - 2 < bucket_num + chain_num {
+            return None;
+        }
+
+        Some(HashTable {
+            buckets: &words[2..2 + bucket_num],
+            chains: &words[2 + bucket_num..2 + bucket_num + chain_num],
+        })
+    }
+
+    /// Looks up the exported symbol with the given name.
+    ///
+    /// `symbols` is the symbol table the hash table belongs to. `has_name` is
+    /// called for the symbols in the bucket of the name and returns whether
+    /// the symbol has the name.
+    pub fn lookup<'b, F>(
+        &self,
+        symbols: &'b [Symbol],
+        name: &[u8],
+        mut has_name: F,
+    ) -> Option<&'b Symbol>
+    where
+        F: FnMut(&Symbol) -> bool,
+    {
+        let mut index = self.buckets[hash(name) as usize % self.buckets.len()] as usize;
+
+        // The chain ends at the undefined symbol with index zero. Chains that
+        // are longer than the table are corrupt.
+        for _ in 0..self.chains.len() {
+            let symbol = match symbols.get(index) {
+                Some(symbol) if index != 0 => symbol,
+                _ => return None,
+            };
+
+            if symbol.is_exported() && has_name(symbol) {
+                return Some(symbol);
+            }
+
+            index = match self.chains.get(index) {
+                Some(&next) => next as usize,
+                None => return None,
+            };
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The string table of the test symbols.
+    const STRINGS: &[u8] = b"\0a\0b\0c\0";
+
+    /// Returns the null terminated string at the offset in `STRINGS`.
+    fn string_at(offset: u32) -> &'static [u8] {
+        let start = offset as usize;
+        let length = STRINGS[start..].iter().position(|&byte| byte == 0).unwrap();
+
+        &STRINGS[start..start + length]
+    }
+
+    /// Returns a symbol with the given values.
+    fn symbol(name: u32, binding: u8, section_index: u16, value: usize) -> Symbol {
+        Symbol {
+            name,
+            info: binding << 4,
+            other: 0,
+            section_index,
+            value,
+            size: 8,
+        }
+    }
+
+    /// Tests the hash function against values of the System V ABI.
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(b""), 0);
+        assert_eq!(hash(b"a"), 0x61);
+        assert_eq!(hash(b"main"), 0x737fe);
+        assert_eq!(hash(b"printf"), 0x077905a6);
+        assert_eq!(hash(b"abcdefghijklmnop"), 0x0bb9a310);
+    }
+
+    /// Tests looking up symbols through the chains of the hash table.
+    #[test]
+    fn test_lookup() {
+        let symbols = [
+            symbol(0, STB_LOCAL, SHN_UNDEF, 0),
+            symbol(1, STB_GLOBAL, 1, 0x10),
+            symbol(5, STB_LOCAL, 1, 0x20),
+            symbol(3, STB_GLOBAL, SHN_UNDEF, 0),
+            symbol(5, STB_WEAK, 1, 0x40),
+        ];
+
+        // The names with odd hashes end up in the second bucket.
+        let words = [2, 5, 3, 2, 0, 0, 4, 0, 1];
+        let hash_table = HashTable::new(&words).unwrap();
+        let lookup = |name: &[u8]| {
+            hash_table
+                .lookup(&symbols, name, |symbol| string_at(symbol.name) == name)
+                .map(|symbol| symbol.value)
+        };
+
+        assert_eq!(lookup(b"a"), Some(0x10));
+        assert_eq!(lookup(b"c"), Some(0x40));
+        assert_eq!(lookup(b"b"), None);
+        assert_eq!(lookup(b"d"), None);
+
+        // Chains with cycles or invalid indices end the lookup.
+        for words in [[1, 2, 1, 0, 1], [1, 2, 1, 0, 7]].iter() {
+            let hash_table = HashTable::new(words).unwrap();
+            assert!(hash_table.lookup(&symbols, b"e", |_| false).is_none());
+        }
+
+        assert!(HashTable::new(&[0, 0]).is_none());
+        assert!(HashTable::new(&[1, 4, 1, 0]).is_none());
+        assert!(HashTable::new(&[1]).is_none());
+    }
+
+    /// Tests the values stored for relocations.
+    #[test]
+    fn test_relocation_value() {
+        let relocation = |relocation_type: u32, addend: i64| Relocation {
+            offset: 0x20,
+            info: 1 << 32 | relocation_type as u64,
+            addend,
+        };
+        let base = 0x5555_0000_0000;
+        let symbol_address = 0x7e00_0000_1000;
+
+        let relative = relocation(R_X86_64_RELATIVE, 0x1234);
+        assert_eq!(relative.value(base, symbol_address), Some(base + 0x1234));
+        assert_eq!(relative.symbol_index(), 1);
+
+        let absolute = relocation(R_X86_64_64, -0x10);
+        assert_eq!(absolute.value(base, symbol_address), Some(symbol_address - 0x10));
+
+        let jump_slot = relocation(R_X86_64_JUMP_SLOT, 0x1234);
+        assert_eq!(jump_slot.value(base, symbol_address), Some(symbol_address));
+
+        assert_eq!(relocation(R_X86_64_COPY, 0).value(base, symbol_address), None);
+        assert_eq!(relocation(R_X86_64_NONE, 0).value(base, symbol_address), None);
+    }
+
+    /// Tests the checks of the file header.
+    #[test]
+    fn test_header() {
+        let mut identification = [0; 16];
+        identification[..4].copy_from_slice(&ELF_MAGIC);
+        identification[EI_CLASS] = ELFCLASS64;
+        identification[EI_DATA] = ELFDATA2LSB;
+        identification[EI_VERSION] = EV_CURRENT;
+
+        let mut header = Header {
+            identification,
+            elf_type: ET_DYN,
+            instruction_set: EM_X86_64,
+            version: 1,
+            program_entry: 0,
+            program_header_offset: 64,
+            section_header_offset: 0,
+            flags: 0,
+            header_size: 64,
+            program_header_entry_size: 56,
+            program_header_entry_num: 0,
+            section_header_entry_size: 0,
+            section_header_entry_num: 0,
+            name_string_table_index: 0,
+        };
+        assert!(header.is_shared_object());
+
+        header.elf_type = ET_EXEC;
+        assert!(header.is_native() && !header.is_shared_object());
+
+        header.identification[EI_DATA] = 2;
+        assert!(header.is_valid() && !header.is_native());
+
+        header.identification[0] = 0;
+        assert!(!header.is_valid());
+    }
+}
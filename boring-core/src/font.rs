@@ -0,0 +1,504 @@
+//! Bitmap fonts shared by the kernel console and the standard library.
+//!
+//! A small 8x8 font is built in. PC Screen Fonts (PSF1 and PSF2) can be used
+//! from memory, for example from a file included in the program. The font
+//! borrows the data instead of copying it.
+
+use core::{slice, str};
+
+/// The magic number of PSF1 fonts.
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+
+/// The size of the PSF1 header.
+const PSF1_HEADER_SIZE: usize = 4;
+
+/// Set in the PSF1 mode if the font has 512 glyphs instead of 256.
+const PSF1_MODE_512: u8 = 0x01;
+
+/// Set in the PSF1 mode if the font has a unicode table.
+const PSF1_MODE_HAS_TABLE: u8 = 0x02;
+
+/// Terminates the unicode entries of a glyph in PSF1 fonts.
+const PSF1_SEPARATOR: u16 = 0xffff;
+
+/// Starts a sequence of characters in PSF1 unicode tables.
+const PSF1_START_SEQUENCE: u16 = 0xfffe;
+
+/// The magic number of PSF2 fonts.
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+
+/// The minimal size of the PSF2 header.
+const PSF2_HEADER_SIZE: usize = 32;
+
+/// Set in the PSF2 flags if the font has a unicode table.
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+
+/// Terminates the unicode entries of a glyph in PSF2 fonts.
+const PSF2_SEPARATOR: u8 = 0xff;
+
+/// Starts a sequence of characters in PSF2 unicode tables.
+const PSF2_START_SEQUENCE: u8 = 0xfe;
+
+/// The largest supported glyph dimension in pixels.
+const MAX_GLYPH_SIZE: usize = 64;
+
+/// The number of characters whose glyphs are looked up when loading a font.
+const CACHED_CHARACTERS: usize = 128;
+
+/// Marks characters without a glyph in the lookup cache.
+const NO_GLYPH: u32 = 0xffff_ffff;
+
+/// The first character of the built-in font.
+const BUILTIN_FIRST_CHARACTER: usize = 0x20;
+
+/// The glyphs of the built-in 8x8 font, covering ' ' to '~'.
+///
+/// This is the public domain IBM PC font, each byte is a row with the leftmost
+/// pixel in the highest bit.
+static BUILTIN_GLYPHS: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3c, 0x3c, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x6c, 0x6c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x6c, 0x6c, 0xfe, 0x6c, 0xfe, 0x6c, 0x6c, 0x00], // '#'
+    [0x30, 0x7c, 0xc0, 0x78, 0x0c, 0xf8, 0x30, 0x00], // '$'
+    [0x00, 0xc6, 0xcc, 0x18, 0x30, 0x66, 0xc6, 0x00], // '%'
+    [0x38, 0x6c, 0x38, 0x76, 0xdc, 0xcc, 0x76, 0x00], // '&'
+    [0x60, 0x60, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00], // "'"
+    [0x18, 0x30, 0x60, 0x60, 0x60, 0x30, 0x18, 0x00], // '('
+    [0x60, 0x30, 0x18, 0x18, 0x18, 0x30, 0x60, 0x00], // ')'
+    [0x00, 0x66, 0x3c, 0xff, 0x3c, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x30, 0x30, 0xfc, 0x30, 0x30, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x30, 0x60], // ','
+    [0x00, 0x00, 0x00, 0xfc, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x30, 0x00], // '.'
+    [0x06, 0x0c, 0x18, 0x30, 0x60, 0xc0, 0x80, 0x00], // '/'
+    [0x7c, 0xc6, 0xce, 0xde, 0xf6, 0xe6, 0x7c, 0x00], // '0'
+    [0x30, 0x70, 0x30, 0x30, 0x30, 0x30, 0xfc, 0x00], // '1'
+    [0x78, 0xcc, 0x0c, 0x38, 0x60, 0xcc, 0xfc, 0x00], // '2'
+    [0x78, 0xcc, 0x0c, 0x38, 0x0c, 0xcc, 0x78, 0x00], // '3'
+    [0x1c, 0x3c, 0x6c, 0xcc, 0xfe, 0x0c, 0x1e, 0x00], // '4'
+    [0xfc, 0xc0, 0xf8, 0x0c, 0x0c, 0xcc, 0x78, 0x00], // '5'
+    [0x38, 0x60, 0xc0, 0xf8, 0xcc, 0xcc, 0x78, 0x00], // '6'
+    [0xfc, 0xcc, 0x0c, 0x18, 0x30, 0x30, 0x30, 0x00], // '7'
+    [0x78, 0xcc, 0xcc, 0x78, 0xcc, 0xcc, 0x78, 0x00], // '8'
+    [0x78, 0xcc, 0xcc, 0x7c, 0x0c, 0x18, 0x70, 0x00], // '9'
+    [0x00, 0x30, 0x30, 0x00, 0x00, 0x30, 0x30, 0x00], // ':'
+    [0x00, 0x30, 0x30, 0x00, 0x00, 0x30, 0x30, 0x60], // ';'
+    [0x18, 0x30, 0x60, 0xc0, 0x60, 0x30, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0xfc, 0x00, 0x00, 0xfc, 0x00, 0x00], // '='
+    [0x60, 0x30, 0x18, 0x0c, 0x18, 0x30, 0x60, 0x00], // '>'
+    [0x78, 0xcc, 0x0c, 0x18, 0x30, 0x00, 0x30, 0x00], // '?'
+    [0x7c, 0xc6, 0xde, 0xde, 0xde, 0xc0, 0x78, 0x00], // '@'
+    [0x30, 0x78, 0xcc, 0xcc, 0xfc, 0xcc, 0xcc, 0x00], // 'A'
+    [0xfc, 0x66, 0x66, 0x7c, 0x66, 0x66, 0xfc, 0x00], // 'B'
+    [0x3c, 0x66, 0xc0, 0xc0, 0xc0, 0x66, 0x3c, 0x00], // 'C'
+    [0xf8, 0x6c, 0x66, 0x66, 0x66, 0x6c, 0xf8, 0x00], // 'D'
+    [0xfe, 0x62, 0x68, 0x78, 0x68, 0x62, 0xfe, 0x00], // 'E'
+    [0xfe, 0x62, 0x68, 0x78, 0x68, 0x60, 0xf0, 0x00], // 'F'
+    [0x3c, 0x66, 0xc0, 0xc0, 0xce, 0x66, 0x3e, 0x00], // 'G'
+    [0xcc, 0xcc, 0xcc, 0xfc, 0xcc, 0xcc, 0xcc, 0x00], // 'H'
+    [0x78, 0x30, 0x30, 0x30, 0x30, 0x30, 0x78, 0x00], // 'I'
+    [0x1e, 0x0c, 0x0c, 0x0c, 0xcc, 0xcc, 0x78, 0x00], // 'J'
+    [0xe6, 0x66, 0x6c, 0x78, 0x6c, 0x66, 0xe6, 0x00], // 'K'
+    [0xf0, 0x60, 0x60, 0x60, 0x62, 0x66, 0xfe, 0x00], // 'L'
+    [0xc6, 0xee, 0xfe, 0xfe, 0xd6, 0xc6, 0xc6, 0x00], // 'M'
+    [0xc6, 0xe6, 0xf6, 0xde, 0xce, 0xc6, 0xc6, 0x00], // 'N'
+    [0x38, 0x6c, 0xc6, 0xc6, 0xc6, 0x6c, 0x38, 0x00], // 'O'
+    [0xfc, 0x66, 0x66, 0x7c, 0x60, 0x60, 0xf0, 0x00], // 'P'
+    [0x78, 0xcc, 0xcc, 0xcc, 0xdc, 0x78, 0x1c, 0x00], // 'Q'
+    [0xfc, 0x66, 0x66, 0x7c, 0x6c, 0x66, 0xe6, 0x00], // 'R'
+    [0x78, 0xcc, 0xe0, 0x70, 0x1c, 0xcc, 0x78, 0x00], // 'S'
+    [0xfc, 0xb4, 0x30, 0x30, 0x30, 0x30, 0x78, 0x00], // 'T'
+    [0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xfc, 0x00], // 'U'
+    [0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0x78, 0x30, 0x00], // 'V'
+    [0xc6, 0xc6, 0xc6, 0xd6, 0xfe, 0xee, 0xc6, 0x00], // 'W'
+    [0xc6, 0xc6, 0x6c, 0x38, 0x38, 0x6c, 0xc6, 0x00], // 'X'
+    [0xcc, 0xcc, 0xcc, 0x78, 0x30, 0x30, 0x78, 0x00], // 'Y'
+    [0xfe, 0xc6, 0x8c, 0x18, 0x32, 0x66, 0xfe, 0x00], // 'Z'
+    [0x78, 0x60, 0x60, 0x60, 0x60, 0x60, 0x78, 0x00], // '['
+    [0xc0, 0x60, 0x30, 0x18, 0x0c, 0x06, 0x02, 0x00], // '\\'
+    [0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0x78, 0x00], // ']'
+    [0x10, 0x38, 0x6c, 0xc6, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff], // '_'
+    [0x30, 0x30, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x78, 0x0c, 0x7c, 0xcc, 0x76, 0x00], // 'a'
+    [0xe0, 0x60, 0x60, 0x7c, 0x66, 0x66, 0xdc, 0x00], // 'b'
+    [0x00, 0x00, 0x78, 0xcc, 0xc0, 0xcc, 0x78, 0x00], // 'c'
+    [0x1c, 0x0c, 0x0c, 0x7c, 0xcc, 0xcc, 0x76, 0x00], // 'd'
+    [0x00, 0x00, 0x78, 0xcc, 0xfc, 0xc0, 0x78, 0x00], // 'e'
+    [0x38, 0x6c, 0x60, 0xf0, 0x60, 0x60, 0xf0, 0x00], // 'f'
+    [0x00, 0x00, 0x76, 0xcc, 0xcc, 0x7c, 0x0c, 0xf8], // 'g'
+    [0xe0, 0x60, 0x6c, 0x76, 0x66, 0x66, 0xe6, 0x00], // 'h'
+    [0x30, 0x00, 0x70, 0x30, 0x30, 0x30, 0x78, 0x00], // 'i'
+    [0x0c, 0x00, 0x0c, 0x0c, 0x0c, 0xcc, 0xcc, 0x78], // 'j'
+    [0xe0, 0x60, 0x66, 0x6c, 0x78, 0x6c, 0xe6, 0x00], // 'k'
+    [0x70, 0x30, 0x30, 0x30, 0x30, 0x30, 0x78, 0x00], // 'l'
+    [0x00, 0x00, 0xcc, 0xfe, 0xfe, 0xd6, 0xc6, 0x00], // 'm'
+    [0x00, 0x00, 0xf8, 0xcc, 0xcc, 0xcc, 0xcc, 0x00], // 'n'
+    [0x00, 0x00, 0x78, 0xcc, 0xcc, 0xcc, 0x78, 0x00], // 'o'
+    [0x00, 0x00, 0xdc, 0x66, 0x66, 0x7c, 0x60, 0xf0], // 'p'
+    [0x00, 0x00, 0x76, 0xcc, 0xcc, 0x7c, 0x0c, 0x1e], // 'q'
+    [0x00, 0x00, 0xdc, 0x76, 0x66, 0x60, 0xf0, 0x00], // 'r'
+    [0x00, 0x00, 0x7c, 0xc0, 0x78, 0x0c, 0xf8, 0x00], // 's'
+    [0x10, 0x30, 0x7c, 0x30, 0x30, 0x34, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0xcc, 0xcc, 0xcc, 0xcc, 0x76, 0x00], // 'u'
+    [0x00, 0x00, 0xcc, 0xcc, 0xcc, 0x78, 0x30, 0x00], // 'v'
+    [0x00, 0x00, 0xc6, 0xd6, 0xfe, 0xfe, 0x6c, 0x00], // 'w'
+    [0x00, 0x00, 0xc6, 0x6c, 0x38, 0x6c, 0xc6, 0x00], // 'x'
+    [0x00, 0x00, 0xcc, 0xcc, 0xcc, 0x7c, 0x0c, 0xf8], // 'y'
+    [0x00, 0x00, 0xfc, 0x98, 0x30, 0x64, 0xfc, 0x00], // 'z'
+    [0x1c, 0x30, 0x30, 0xe0, 0x30, 0x30, 0x1c, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0xe0, 0x30, 0x30, 0x1c, 0x30, 0x30, 0xe0, 0x00], // '}'
+    [0x76, 0xdc, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+/// The errors that can occur while loading a font.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    /// The data is neither a PSF1 nor a PSF2 font.
+    UnknownFormat,
+    /// The data ends before the font does.
+    Truncated,
+    /// The glyphs are empty or too large.
+    UnsupportedSize,
+}
+
+/// The unicode table of a font.
+#[derive(Clone, Copy)]
+enum UnicodeTable<'a> {
+    /// The font maps glyphs directly to characters.
+    None,
+    /// A PSF1 table of 16 bit entries.
+    Psf1(&'a [u8]),
+    /// A PSF2 table of UTF-8 strings.
+    Psf2(&'a [u8]),
+}
+
+/// A bitmap font.
+#[derive(Clone, Copy)]
+pub struct Font<'a> {
+    /// The width of a glyph in pixels.
+    width: usize,
+    /// The height of a glyph in pixels.
+    height: usize,
+    /// The number of bytes per glyph row.
+    bytes_per_row: usize,
+    /// The number of glyphs.
+    glyph_count: usize,
+    /// The character of the first glyph, if there is no unicode table.
+    first_character: usize,
+    /// The glyph bitmaps, each row starting with the leftmost pixel in the
+    /// highest bit.
+    glyphs: &'a [u8],
+    /// Maps characters to glyphs.
+    unicode_table: UnicodeTable<'a>,
+    /// The glyphs of the first characters, so that ASCII text doesn't search
+    /// the unicode table.
+    cache: [u32; CACHED_CHARACTERS],
+}
+
+impl Font<'static> {
+    /// Returns the built-in font.
+    pub fn builtin() -> Font<'static> {
+        let glyphs = unsafe {
+            slice::from_raw_parts(
+                BUILTIN_GLYPHS.as_ptr() as *const u8,
+                BUILTIN_GLYPHS.len() * 8,
+            )
+        };
+
+        let mut font = Font {
+            width: 8,
+            height: 8,
+            bytes_per_row: 1,
+            glyph_count: BUILTIN_GLYPHS.len(),
+            first_character: BUILTIN_FIRST_CHARACTER,
+            glyphs,
+            unicode_table: UnicodeTable::None,
+            cache: [NO_GLYPH; CACHED_CHARACTERS],
+        };
+        font.fill_cache();
+
+        font
+    }
+}
+
+impl<'a> Font<'a> {
+    /// Parses a PSF1 or PSF2 font.
+    pub fn from_psf(data: &'a [u8]) -> Result<Font<'a>, FontError> {
+        let mut font = if data.starts_with(&PSF2_MAGIC) {
+            Font::from_psf2(data)?
+        } else if data.starts_with(&PSF1_MAGIC) {
+            Font::from_psf1(data)?
+        } else {
+            return Err(FontError::UnknownFormat);
+        };
+        font.fill_cache();
+
+        Ok(font)
+    }
+
+    /// Parses a PSF1 font.
+    fn from_psf1(data: &'a [u8]) -> Result<Font<'a>, FontError> {
+        if data.len() < PSF1_HEADER_SIZE {
+            return Err(FontError::Truncated);
+        }
+
+        let mode = data[2];
+        let height = data[3] as usize;
+        let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+
+        let mut font = Font::from_bitmaps(data, PSF1_HEADER_SIZE, glyph_count, 8, height)?;
+
+        if mode & PSF1_MODE_HAS_TABLE != 0 {
+            font.unicode_table =
+                UnicodeTable::Psf1(&data[PSF1_HEADER_SIZE + font.glyphs.len()..]);
+        }
+
+        Ok(font)
+    }
+
+    /// Parses a PSF2 font.
+    fn from_psf2(data: &'a [u8]) -> Result<Font<'a>, FontError> {
+        if data.len() < PSF2_HEADER_SIZE {
+            return Err(FontError::Truncated);
+        }
+
+        let header_size = read_u32(data, 8) as usize;
+        let flags = read_u32(data, 12);
+        let glyph_count = read_u32(data, 16) as usize;
+        let glyph_size = read_u32(data, 20) as usize;
+        let height = read_u32(data, 24) as usize;
+        let width = read_u32(data, 28) as usize;
+
+        if glyph_size != (width + 7) / 8 * height {
+            return Err(FontError::UnsupportedSize);
+        }
+
+        let mut font = Font::from_bitmaps(data, header_size, glyph_count, width, height)?;
+
+        if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            font.unicode_table = UnicodeTable::Psf2(&data[header_size + font.glyphs.len()..]);
+        }
+
+        Ok(font)
+    }
+
+    /// Creates a font from the glyph bitmaps starting at `offset`.
+    fn from_bitmaps(
+        data: &'a [u8],
+        offset: usize,
+        glyph_count: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<Font<'a>, FontError> {
+        if width == 0 || height == 0 || width > MAX_GLYPH_SIZE || height > MAX_GLYPH_SIZE
+            || glyph_count == 0
+        {
+            return Err(FontError::UnsupportedSize);
+        }
+
+        let bytes_per_row = (width + 7) / 8;
+        let length = glyph_count * bytes_per_row * height;
+
+        if offset > data.len() || data.len() - offset < length {
+            return Err(FontError::Truncated);
+        }
+
+        Ok(Font {
+            width,
+            height,
+            bytes_per_row,
+            glyph_count,
+            first_character: 0,
+            glyphs: &data[offset..offset + length],
+            unicode_table: UnicodeTable::None,
+            cache: [NO_GLYPH; CACHED_CHARACTERS],
+        })
+    }
+
+    /// Looks up the glyphs of the cached characters.
+    fn fill_cache(&mut self) {
+        for index in 0..CACHED_CHARACTERS {
+            let character = index as u8 as char;
+
+            self.cache[index] = match self.find_glyph(character) {
+                Some(glyph) => glyph as u32,
+                None => NO_GLYPH,
+            };
+        }
+    }
+
+    /// The width of a glyph in pixels.
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    /// The height of a glyph in pixels.
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    /// Returns the width of the longest line of the text in pixels.
+    pub fn text_width(&self, text: &str) -> u32 {
+        text.lines()
+            .map(|line| line.chars().count() as u32 * self.width as u32)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the glyph for the given character.
+    ///
+    /// Characters without a glyph are shown as a question mark.
+    pub fn glyph(&self, character: char) -> usize {
+        let glyph = if (character as usize) < CACHED_CHARACTERS {
+            match self.cache[character as usize] {
+                NO_GLYPH => None,
+                glyph => Some(glyph as usize),
+            }
+        } else {
+            self.find_glyph(character)
+        };
+
+        match glyph {
+            Some(glyph) => glyph,
+            None if character != '?' => self.glyph('?'),
+            None => 0,
+        }
+    }
+
+    /// Searches the glyph for the given character.
+    fn find_glyph(&self, character: char) -> Option<usize> {
+        match self.unicode_table {
+            UnicodeTable::None => {
+                let index = (character as usize).checked_sub(self.first_character)?;
+                if index < self.glyph_count {
+                    Some(index)
+                } else {
+                    None
+                }
+            }
+            UnicodeTable::Psf1(table) => {
+                let mut glyph = 0;
+                let mut in_sequence = false;
+
+                for entry in table.chunks(2).filter(|entry| entry.len() == 2) {
+                    match entry[0] as u16 | (entry[1] as u16) << 8 {
+                        PSF1_SEPARATOR => {
+                            glyph += 1;
+                            in_sequence = false;
+                        }
+                        PSF1_START_SEQUENCE => in_sequence = true,
+                        code if !in_sequence && code as u32 == character as u32 => {
+                            return if glyph < self.glyph_count {
+                                Some(glyph)
+                            } else {
+                                None
+                            };
+                        }
+                        _ => (),
+                    }
+                }
+
+                None
+            }
+            UnicodeTable::Psf2(table) => {
+                for (glyph, entries) in table.split(|byte| *byte == PSF2_SEPARATOR).enumerate() {
+                    if glyph >= self.glyph_count {
+                        break;
+                    }
+
+                    // Only single characters are mapped, sequences are skipped.
+                    let sequence_start = entries
+                        .iter()
+                        .position(|byte| *byte == PSF2_START_SEQUENCE);
+                    let single_characters = match sequence_start {
+                        Some(position) => &entries[..position],
+                        None => entries,
+                    };
+
+                    if let Ok(characters) = str::from_utf8(single_characters) {
+                        if characters.chars().any(|other| other == character) {
+                            return Some(glyph);
+                        }
+                    }
+                }
+
+                None
+            }
+        }
+    }
+
+    /// Returns true if the given pixel of the glyph is set.
+    pub fn is_set(&self, glyph: usize, x: u32, y: u32) -> bool {
+        let (x, y) = (x as usize, y as usize);
+        if glyph >= self.glyph_count || x >= self.width || y >= self.height {
+            return false;
+        }
+
+        let row = (glyph * self.height + y) * self.bytes_per_row;
+
+        self.glyphs[row + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+}
+
+/// Reads the little endian u32 at the given offset.
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data[offset] as u32 | (data[offset + 1] as u32) << 8 | (data[offset + 2] as u32) << 16
+        | (data[offset + 3] as u32) << 24
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests loading a PSF2 font with a unicode table.
+    #[test]
+    fn test_psf2() {
+        let data = [
+            0x72, 0xb5, 0x4a, 0x86, // magic
+            0, 0, 0, 0, // version
+            32, 0, 0, 0, // header size
+            1, 0, 0, 0, // flags
+            2, 0, 0, 0, // glyph count
+            2, 0, 0, 0, // glyph size
+            2, 0, 0, 0, // height
+            8, 0, 0, 0, // width
+            0x00, 0x00, 0x80, 0x01, // glyphs
+            b'a', PSF2_SEPARATOR,
+            b'b', 0xc3, 0xa4, PSF2_SEPARATOR,
+        ];
+
+        let font = Font::from_psf(&data).unwrap();
+
+        assert_eq!((font.width(), font.height()), (8, 2));
+        assert_eq!(font.glyph('a'), 0);
+        assert_eq!(font.glyph('\u{e4}'), 1);
+        assert_eq!(font.glyph('z'), 0);
+        assert!(font.is_set(1, 0, 0));
+        assert!(font.is_set(1, 7, 1));
+        assert!(!font.is_set(1, 1, 0));
+        assert!(!font.is_set(2, 0, 0));
+    }
+
+    /// Tests that truncated fonts are rejected.
+    #[test]
+    fn test_truncated() {
+        let data = [0x36, 0x04, 0x00, 0x10, 0x00];
+
+        assert_eq!(Font::from_psf(&data).err(), Some(FontError::Truncated));
+        assert_eq!(Font::from_psf(b"font").err(), Some(FontError::UnknownFormat));
+    }
+
+    /// Tests the built-in font.
+    #[test]
+    fn test_builtin() {
+        let font = Font::builtin();
+
+        assert_eq!(font.glyph(' '), 0);
+        assert_eq!(font.glyph('A'), 'A' as usize - BUILTIN_FIRST_CHARACTER);
+        assert_eq!(font.glyph('\u{e4}'), font.glyph('?'));
+        assert_eq!(font.text_width("ab\nabc"), 24);
+    }
+}
@@ -2,7 +2,13 @@
 #![no_std]
 #![allow(dead_code)]
 
+#[macro_use]
+pub mod syscall;
+pub mod elf;
+pub mod font;
 pub mod io;
+pub mod pci;
+pub mod time;
 
 #[cfg(test)]
 mod tests {
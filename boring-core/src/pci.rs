@@ -0,0 +1,35 @@
+//! Defines the PCI information shared by the kernel and userspace.
+
+/// Information about a PCI function, as returned by the pci_device_info
+/// syscall.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PciDeviceInfo {
+    /// The bus the function is on.
+    pub bus: u8,
+    /// The slot of the device on the bus.
+    pub slot: u8,
+    /// The function number within the device.
+    pub function: u8,
+    /// Non-zero if a kernel driver owns the function.
+    pub owned: u8,
+    /// The vendor ID.
+    pub vendor_id: u16,
+    /// The device ID.
+    pub device_id: u16,
+    /// The class code.
+    pub class_code: u8,
+    /// The subclass.
+    pub subclass: u8,
+    /// The programming interface.
+    pub prog_if: u8,
+    /// The revision ID.
+    pub revision_id: u8,
+}
+
+impl PciDeviceInfo {
+    /// Returns true if a kernel driver owns the function.
+    pub fn is_owned(&self) -> bool {
+        self.owned != 0
+    }
+}
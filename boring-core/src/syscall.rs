@@ -0,0 +1,178 @@
+//! Defines the syscall numbers and makes syscalls to the kernel.
+
+/// The number of the print char syscall.
+pub const PRINT_CHAR_SYSCALL_NUM: u64 = 0;
+
+/// The number of the exit syscall.
+pub const EXIT_SYSCALL_NUM: u64 = 1;
+
+/// The number of the get pid syscall.
+pub const GET_PID_SYSCALL_NUM: u64 = 2;
+
+/// The number of the exec syscall.
+pub const EXEC_SYSCALL_NUM: u64 = 3;
+
+/// The number of the sleep syscall.
+pub const SLEEP_SYSCALL_NUM: u64 = 4;
+
+/// The number of the new thread syscall.
+pub const NEW_THREAD_SYSCALL_NUM: u64 = 5;
+
+/// The number of the kill thread syscall.
+pub const KILL_THREAD_SYSCALL_NUM: u64 = 6;
+
+/// The number of the serial char syscall.
+pub const SERIAL_CHAR_SYSCALL_NUM: u64 = 7;
+
+/// The number of the panic serial char syscall.
+pub const PANIC_SERIAL_CHAR_SYSCALL_NUM: u64 = 8;
+
+/// The number of the register keyboard interrupt syscall.
+pub const REGISTER_KB_INTERRUPT_SYSCALL_NUM: u64 = 9;
+
+/// The number of the pci device info syscall.
+pub const PCI_DEVICE_INFO_SYSCALL_NUM: u64 = 10;
+
+/// The number of the shutdown syscall.
+pub const SHUTDOWN_SYSCALL_NUM: u64 = 11;
+
+/// The number of the reboot syscall.
+pub const REBOOT_SYSCALL_NUM: u64 = 12;
+
+/// The number of the clock_gettime syscall.
+pub const CLOCK_GETTIME_SYSCALL_NUM: u64 = 13;
+
+/// The number of the futex wait syscall.
+pub const FUTEX_WAIT_SYSCALL_NUM: u64 = 14;
+
+/// The number of the futex wake syscall.
+pub const FUTEX_WAKE_SYSCALL_NUM: u64 = 15;
+
+/// The number of the receive syscall.
+pub const RECEIVE_SYSCALL_NUM: u64 = 16;
+
+/// The number of the timer create syscall.
+pub const TIMER_CREATE_SYSCALL_NUM: u64 = 17;
+
+/// The number of the timer wait syscall.
+pub const TIMER_WAIT_SYSCALL_NUM: u64 = 18;
+
+/// The number of the timer delete syscall.
+pub const TIMER_DELETE_SYSCALL_NUM: u64 = 19;
+
+/// The number of the map framebuffer syscall.
+pub const MAP_FRAMEBUFFER_SYSCALL_NUM: u64 = 20;
+
+/// The number of the framebuffer mode syscall.
+pub const FRAMEBUFFER_MODE_SYSCALL_NUM: u64 = 21;
+
+/// The number of the set framebuffer mode syscall.
+pub const SET_FRAMEBUFFER_MODE_SYSCALL_NUM: u64 = 22;
+
+/// The number of the flip framebuffer syscall.
+pub const FLIP_FRAMEBUFFER_SYSCALL_NUM: u64 = 23;
+
+/// The number of the scroll console syscall.
+pub const SCROLL_CONSOLE_SYSCALL_NUM: u64 = 24;
+
+/// The number of the file size syscall.
+pub const FILE_SIZE_SYSCALL_NUM: u64 = 25;
+
+/// The number of the read_file syscall.
+pub const READ_FILE_SYSCALL_NUM: u64 = 26;
+
+/// The number of the map_file syscall.
+pub const MAP_FILE_SYSCALL_NUM: u64 = 27;
+
+/// The number of the map_memory syscall.
+pub const MAP_MEMORY_SYSCALL_NUM: u64 = 28;
+
+
+/// Makes a syscall with the given arguments.
+#[macro_export]
+macro_rules! syscall {
+    ($num: expr) => {{
+        let result: u64;
+        asm!("syscall" :
+            "={rax}"(result) :
+            "{rax}"($num)
+            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
+            : "intel", "volatile");
+        result
+    }};
+    ($num: expr, $arg1: expr) => {{
+        let result: u64;
+        asm!("syscall" :
+            "={rax}"(result) :
+            "{rax}"($num),
+            "{rdi}"($arg1)
+            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
+            : "intel", "volatile");
+        result
+    }};
+    ($num: expr, $arg1: expr, $arg2: expr) => {{
+        let result: u64;
+        asm!("syscall" :
+            "={rax}"(result) :
+            "{rax}"($num),
+            "{rdi}"($arg1),
+            "{rsi}"($arg2)
+            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
+            : "intel", "volatile");
+        result
+    }};
+    ($num: expr, $arg1: expr, $arg2: expr, $arg3: expr) => {{
+        let result: u64;
+        asm!("syscall" :
+            "={rax}"(result) :
+            "{rax}"($num),
+            "{rdi}"($arg1),
+            "{rsi}"($arg2),
+            "{rdx}"($arg3)
+            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
+            : "intel", "volatile");
+        result
+    }};
+    ($num: expr, $arg1: expr, $arg2: expr, $arg3: expr, $arg4: expr) => {{
+        let result: u64;
+        asm!("syscall" :
+            "={rax}"(result) :
+            "{rax}"($num),
+            "{rdi}"($arg1),
+            "{rsi}"($arg2),
+            "{rdx}"($arg3),
+            "{r10}"($arg4)
+            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
+            : "intel", "volatile");
+        result
+    }};
+    ($num: expr, $arg1: expr, $arg2: expr, $arg3: expr, $arg4: expr, $arg5: expr) => {{
+        let result: u64;
+        asm!("syscall" :
+            "={rax}"(result) :
+            "{rax}"($num),
+            "{rdi}"($arg1),
+            "{rsi}"($arg2),
+            "{rdx}"($arg3),
+            "{r10}"($arg4),
+            "{r8}"($arg5)
+            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
+            : "intel", "volatile");
+        result
+    }};
+    ($num: expr, $arg1: expr, $arg2: expr, $arg3: expr, $arg4: expr, $arg5: expr, $arg6: expr) => {{
+        let result: u64;
+        asm!("syscall" :
+            "={rax}"(result) :
+            "{rax}"($num),
+            "{rdi}"($arg1),
+            "{rsi}"($arg2),
+            "{rdx}"($arg3),
+            "{r10}"($arg4),
+            "{r8}"($arg5),
+            "{r9}"($arg6)
+            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
+            : "intel", "volatile");
+        result
+    }};
+}
@@ -0,0 +1,135 @@
+//! Converts between calendar dates and UNIX time.
+
+use core::fmt;
+
+/// The number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A calendar date and time in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    /// The full year.
+    pub year: u32,
+    /// The month from 1 to 12.
+    pub month: u8,
+    /// The day of the month from 1 to 31.
+    pub day: u8,
+    /// The hour from 0 to 23.
+    pub hour: u8,
+    /// The minute from 0 to 59.
+    pub minute: u8,
+    /// The second from 0 to 59.
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Returns the date and time the given number of seconds after the UNIX
+    /// epoch.
+    pub fn from_unix_seconds(seconds: u64) -> DateTime {
+        let (year, month, day) = civil_from_days((seconds / SECONDS_PER_DAY) as i64);
+        let seconds_of_day = seconds % SECONDS_PER_DAY;
+
+        DateTime {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+        }
+    }
+
+    /// Returns the number of seconds since the UNIX epoch.
+    ///
+    /// Dates before the epoch return zero.
+    pub fn to_unix_seconds(&self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        let days = if days < 0 { 0 } else { days as u64 };
+
+        days * SECONDS_PER_DAY + self.hour as u64 * 3600 + self.minute as u64 * 60
+            + self.second as u64
+    }
+
+    /// Returns the day of the week, with 0 being Sunday.
+    pub fn weekday(&self) -> u8 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+
+        // The epoch was a Thursday.
+        ((days + 4) % 7 + 7) as u8 % 7
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Formats the date and time as ISO 8601, e.g. `2017-11-05T13:37:42Z`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+/// Returns the number of days between the UNIX epoch and the given date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Count years from March, so the leap day is at the end of the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// Returns the year, month and day of the given number of days since the UNIX
+/// epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the conversion between dates and UNIX time.
+    #[test]
+    fn test_unix_conversion() {
+        let time = DateTime {
+            year: 2017,
+            month: 11,
+            day: 5,
+            hour: 13,
+            minute: 37,
+            second: 42,
+        };
+
+        assert_eq!(time.to_unix_seconds(), 1509889062);
+        assert_eq!(DateTime::from_unix_seconds(1509889062), time);
+        assert_eq!(DateTime::from_unix_seconds(0).year, 1970);
+        assert_eq!(DateTime::from_unix_seconds(951782400).day, 29);
+    }
+
+    /// Tests the day of the week.
+    #[test]
+    fn test_weekday() {
+        assert_eq!(DateTime::from_unix_seconds(0).weekday(), 4);
+        assert_eq!(DateTime::from_unix_seconds(1509889062).weekday(), 0);
+    }
+}
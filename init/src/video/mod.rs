@@ -1,3 +1,15 @@
-// pub mod voxelspace;
+pub mod voxelspace;
 pub mod mandelbrot;
 pub mod raycaster;
+
+use boringos_std::timer::{Timer, TimerDelivery};
+
+/// The number of frames rendered per second.
+pub const FRAME_RATE: u64 = 60;
+
+/// Creates a timer that expires once per frame.
+///
+/// Waiting on it paces a render loop without accumulating drift.
+pub fn frame_timer() -> Timer {
+    Timer::with_frequency(FRAME_RATE, TimerDelivery::Wakeup)
+}
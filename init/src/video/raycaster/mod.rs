@@ -1,6 +1,6 @@
 // pub mod map;
 use boringos_std::screen::SCREEN;
-// use sync::time::Timestamp;
+use boringos_std::time::Instant;
 use boringos_std::math::{cosf32, normalizef32, radiansf32, sinf32};
 use core::mem;
 use core;
@@ -31,12 +31,13 @@ impl Camera {
         }
     }
 
+    /// Renders a frame and returns the time it took in nanoseconds.
     #[inline(always)]
-    pub unsafe fn render(&mut self) {
+    pub unsafe fn render(&mut self) -> u64 {
+        let frame_start = Instant::now();
         let mut buffer = SCREEN.try().unwrap().lock();
         let screen_width = buffer.width();
         let screen_height = buffer.height();
-        debugln!("width: {}, height: {}", screen_width, screen_height);
         let mut uvx: f32;
         let mut uvy: f32;
         let mut ynorm: u32;
@@ -70,15 +71,20 @@ impl Camera {
 
                     z += 1;
                 }
+                // Moving the camera scrolls the pattern.
+                xdist = xdist.wrapping_add(self.x as u32);
+                ydist = ydist.wrapping_add(self.y as u32);
                 let mut texel: u8 = (xdist ^ ydist ^ (z as u32)) as u8;
                 texel %= 16;
                 texel *= 16;
                 let pixel = mem::transmute::<[u8; 4], u32>([texel, texel, texel, 0]);
                 buffer.write(x, y, pixel);
             }
-            buffer.sync();
         }
+        buffer.sync();
         // panic_debugln!("sinang: {}, cosang: {}", sinang, cosang);
+
+        frame_start.elapsed_nanoseconds()
     }
 }
 
@@ -90,7 +96,17 @@ impl Camera {
 pub fn test() {
     let mut camera = Camera::new();
     camera.height = 160;
-    unsafe {
-        camera.render();
+
+    let frame_timer = super::frame_timer();
+    let mut total_frame_time = 0;
+    for frame in 0..super::FRAME_RATE {
+        camera.x = frame as i32;
+        total_frame_time += unsafe { camera.render() };
+        frame_timer.wait();
     }
+
+    debugln!(
+        "The raycaster rendered a frame in {} microseconds on average.",
+        total_frame_time / super::FRAME_RATE / 1000
+    );
 }
@@ -1,2 +1,91 @@
-pub mod C1W;
-pub mod D1;
+//! Loads the height and color maps of the voxel space from the initramfs.
+
+use boringos_std::image::{self, ImageError};
+use boringos_std::screen::{Color, Image};
+use spin::Once;
+
+/// The width and height of the maps.
+pub const SIZE: u32 = 1024;
+
+/// The number of pixels of a map.
+const PIXEL_COUNT: usize = (SIZE * SIZE) as usize;
+
+/// The path of the height map.
+const HEIGHT_MAP_PATH: &str = "/maps/D1.png";
+
+/// The path of the color map.
+const COLOR_MAP_PATH: &str = "/maps/C1W.png";
+
+/// The largest map file that can be read.
+const FILE_BUFFER_SIZE: usize = 1 << 20;
+
+/// The scratch space needed to decode a map, one filter byte and one sample
+/// per pixel for each line.
+const SCRATCH_SIZE: usize = (SIZE * (SIZE + 1)) as usize;
+
+static mut FILE_BUFFER: [u8; FILE_BUFFER_SIZE] = [0; FILE_BUFFER_SIZE];
+static mut SCRATCH: [u8; SCRATCH_SIZE] = [0; SCRATCH_SIZE];
+static mut HEIGHT_PIXELS: [u32; PIXEL_COUNT] = [0; PIXEL_COUNT];
+static mut COLOR_PIXELS: [u32; PIXEL_COUNT] = [0; PIXEL_COUNT];
+
+static MAP: Once<Result<Map, ImageError>> = Once::new();
+
+/// The terrain of the voxel space.
+///
+/// Both maps repeat in every direction.
+pub struct Map {
+    /// The heights, stored in the red channel.
+    heights: Image<'static>,
+    /// The colors of the terrain.
+    colors: Image<'static>,
+}
+
+impl Map {
+    /// Returns the height at the given position.
+    #[inline(always)]
+    pub fn height(&self, x: i32, y: i32) -> u8 {
+        let (x, y) = wrap(x, y);
+
+        self.heights.pixel(x, y).red
+    }
+
+    /// Returns the color at the given position.
+    #[inline(always)]
+    pub fn color(&self, x: i32, y: i32) -> Color {
+        let (x, y) = wrap(x, y);
+
+        self.colors.pixel(x, y)
+    }
+}
+
+/// Wraps the position onto the maps.
+#[inline(always)]
+fn wrap(x: i32, y: i32) -> (u32, u32) {
+    (x as u32 & (SIZE - 1), y as u32 & (SIZE - 1))
+}
+
+/// Loads a map into the given pixels and checks its size.
+fn load(path: &str, pixels: &'static mut [u32]) -> Result<Image<'static>, ImageError> {
+    let image = unsafe { image::load(path, &mut FILE_BUFFER, pixels, &mut SCRATCH)? };
+
+    if image.width() != SIZE || image.height() != SIZE {
+        return Err(ImageError::Unsupported);
+    }
+
+    Ok(image)
+}
+
+/// Returns the terrain, loading it on the first call.
+pub fn get() -> Result<&'static Map, ImageError> {
+    let map = MAP.call_once(|| unsafe {
+        Ok(Map {
+            heights: load(HEIGHT_MAP_PATH, &mut HEIGHT_PIXELS)?,
+            colors: load(COLOR_MAP_PATH, &mut COLOR_PIXELS)?,
+        })
+    });
+
+    match *map {
+        Ok(ref map) => Ok(map),
+        Err(error) => Err(error),
+    }
+}
@@ -2,8 +2,6 @@ pub mod map;
 use boringos_std::screen::SCREEN;
 // use sync::time::Timestamp;
 use boringos_std::math::{cosf32, normalizef32, radiansf32, sinf32};
-use core::mem;
-use core;
 
 // pub static mut YBUFFER: [u32; 1024] = [768; 1024];
 
@@ -29,7 +27,14 @@ impl Camera {
     }
 
     #[inline(always)]
-    pub unsafe fn render(&mut self, scale_height: f32) {
+    pub fn render(&mut self, scale_height: f32) {
+        let map = match map::get() {
+            Ok(map) => map,
+            Err(error) => {
+                debugln!("Couldn't load the voxel space maps: {:?}", error);
+                return;
+            }
+        };
         let mut buffer = SCREEN.try().unwrap().lock();
         let screen_width = buffer.width();
         let screen_height = buffer.height();
@@ -44,17 +49,16 @@ impl Camera {
 
         let mut ybuffer = [screen_height; 1024];
 
-        let mut mapoffset: usize;
         let mut invz: f32;
 
         let mut dz = 1.0;
         let mut z = 1.0;
 
         let mut pixel;
+        let mut color;
         let mut height: u8;
         let mut height_on_screen: u32;
         buffer.fill_rect(0, 0, 1023, 511, 0x007EC0EE);
-        // let mut color;
 
         // debugln!("{:?}", Timestamp::get_current());
 
@@ -74,10 +78,10 @@ impl Camera {
             for i in 0..screen_width as usize {
                 // debugln!("dx: {}, dy: {}", dx, dy);
                 // debugln!("plx: {}, ply: {}", round(plx), round(ply));
-                mapoffset = ((ply as usize) << 10) + plx as usize;
-                height = map::D1::D1[mapoffset];
+                height = map.height(plx as i32, ply as i32);
                 height_on_screen = ((self.height - height) as f32 * invz + self.horizon) as u32;
-                pixel = mem::transmute::<[u8; 4], u32>(map::C1W::C1W[mapoffset]);
+                color = map.color(plx as i32, ply as i32);
+                pixel = buffer.color(color.red, color.green, color.blue);
                 buffer.vertical_line(i as u32, height_on_screen, ybuffer[i], pixel);
                 if height_on_screen < ybuffer[i] {
                     ybuffer[i] = height_on_screen;
@@ -110,48 +114,48 @@ impl Camera {
 pub fn test() {
     let mut camera = Camera::new();
     camera.height = 160;
-    unsafe {
+    camera.render(240.0);
+    // camera.angle = 1.0;
+    // camera.render(240.0);
+    // camera.angle = 2.0;
+
+    // camera.x = 128;
+    let frame_timer = super::frame_timer();
+    for i in -90..90 {
+        camera.angle = (i * 2) as f32;
         camera.render(240.0);
-        // camera.angle = 1.0;
-        // camera.render(240.0);
-        // camera.angle = 2.0;
-
-        // camera.x = 128;
-        for i in -90..90 {
-            camera.angle = (i * 2) as f32;
-            camera.render(240.0);
-        }
-        // camera.angle = core::f32::consts::PI * (110 as f32) / 180.0;
-        //
-        // debugln!(
-        //     "angle: {}, sin: {}, cos: {}",
-        //     110,
-        //     sinf32(radiansf32(normalizef32(110.0))),
-        //     cosf32(radiansf32(normalizef32(110.0)))
-        // );
-        // camera.render(240.0);
-        // camera.angle = core::f32::consts::PI * (111 as f32) / 180.0;
-        // debugln!(
-        //     "angle: {}, sin: {}, cos: {}",
-        //     111,
-        //     math::sin32(core::f32::consts::PI * (111 as f32) / 180.0),
-        //     math::cos32(core::f32::consts::PI * (111 as f32) / 180.0)
-        // );
-        // camera.render(240.0);
-        // camera.angle = core::f32::consts::PI * (112 as f32) / 180.0;
-        // debugln!(
-        //     "angle: {}, sin: {}, cos: {}",
-        //     112,
-        //     math::sin32(core::f32::consts::PI * (112 as f32) / 180.0),
-        //     math::cos32(core::f32::consts::PI * (112 as f32) / 180.0)
-        // );
-        // camera.render(240.0);
-        // camera.draw_angle(110);
-        // for i in 512..1024 {
-        //     camera.y = i;
-        //     camera.render(240.0);
-        //     // camera.sync();
-        // }
+        frame_timer.wait();
     }
+    // camera.angle = core::f32::consts::PI * (110 as f32) / 180.0;
+    //
+    // debugln!(
+    //     "angle: {}, sin: {}, cos: {}",
+    //     110,
+    //     sinf32(radiansf32(normalizef32(110.0))),
+    //     cosf32(radiansf32(normalizef32(110.0)))
+    // );
+    // camera.render(240.0);
+    // camera.angle = core::f32::consts::PI * (111 as f32) / 180.0;
+    // debugln!(
+    //     "angle: {}, sin: {}, cos: {}",
+    //     111,
+    //     math::sin32(core::f32::consts::PI * (111 as f32) / 180.0),
+    //     math::cos32(core::f32::consts::PI * (111 as f32) / 180.0)
+    // );
+    // camera.render(240.0);
+    // camera.angle = core::f32::consts::PI * (112 as f32) / 180.0;
+    // debugln!(
+    //     "angle: {}, sin: {}, cos: {}",
+    //     112,
+    //     math::sin32(core::f32::consts::PI * (112 as f32) / 180.0),
+    //     math::cos32(core::f32::consts::PI * (112 as f32) / 180.0)
+    // );
+    // camera.render(240.0);
+    // camera.draw_angle(110);
+    // for i in 512..1024 {
+    //     camera.y = i;
+    //     camera.render(240.0);
+    //     // camera.sync();
+    // }
     // camera.render(240.0);
 }
@@ -0,0 +1,166 @@
+//! Parses the FADT describing the power management hardware.
+
+use super::{find_table, get_table, GenericAddress, SdtHeader};
+use core::cmp::min;
+use core::mem::{size_of, zeroed};
+use core::slice;
+use memory::PhysicalAddress;
+use spin::Once;
+
+/// The FADT up to the extended PM1 control blocks.
+///
+/// Older tables are shorter, missing fields are zero.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Fadt {
+    header: SdtHeader,
+    firmware_control: u32,
+    dsdt: u32,
+    reserved: u8,
+    preferred_pm_profile: u8,
+    sci_interrupt: u16,
+    smi_command_port: u32,
+    acpi_enable: u8,
+    acpi_disable: u8,
+    s4bios_request: u8,
+    pstate_control: u8,
+    pm1a_event_block: u32,
+    pm1b_event_block: u32,
+    pm1a_control_block: u32,
+    pm1b_control_block: u32,
+    pm2_control_block: u32,
+    pm_timer_block: u32,
+    gpe0_block: u32,
+    gpe1_block: u32,
+    pm1_event_length: u8,
+    pm1_control_length: u8,
+    pm2_control_length: u8,
+    pm_timer_length: u8,
+    gpe0_length: u8,
+    gpe1_length: u8,
+    gpe1_base: u8,
+    c_state_control: u8,
+    worst_c2_latency: u16,
+    worst_c3_latency: u16,
+    flush_size: u16,
+    flush_stride: u16,
+    duty_offset: u8,
+    duty_width: u8,
+    day_alarm: u8,
+    month_alarm: u8,
+    century: u8,
+    boot_architecture_flags: u16,
+    reserved2: u8,
+    flags: u32,
+    reset_register: GenericAddress,
+    reset_value: u8,
+    arm_boot_architecture_flags: u16,
+    minor_version: u8,
+    x_firmware_control: u64,
+    x_dsdt: u64,
+    x_pm1a_event_block: GenericAddress,
+    x_pm1b_event_block: GenericAddress,
+    x_pm1a_control_block: GenericAddress,
+    x_pm1b_control_block: GenericAddress,
+}
+
+/// Set in the boot architecture flags if an 8042 controller is present.
+const HAS_8042: u16 = 1 << 1;
+
+/// Set in the flags if the reset register is supported.
+const RESET_REGISTER_SUPPORTED: u32 = 1 << 10;
+
+/// Set in the flags if the PM timer is 32 bits wide.
+const PM_TIMER_32_BIT: u32 = 1 << 8;
+
+/// The information contained in the FADT.
+pub struct FadtInfo {
+    /// The physical address of the DSDT.
+    pub dsdt_address: PhysicalAddress,
+    /// The interrupt used for system control interrupts.
+    pub sci_interrupt: u16,
+    /// The port to write `acpi_enable` to, to enable ACPI mode.
+    pub smi_command_port: u16,
+    /// The value that enables ACPI mode.
+    pub acpi_enable: u8,
+    /// The port of the PM1a control block.
+    pub pm1a_control_block: u16,
+    /// The port of the PM1b control block, or 0.
+    pub pm1b_control_block: u16,
+    /// The port of the PM timer, or 0.
+    pub pm_timer_block: u16,
+    /// True if the PM timer is 32 bits wide instead of 24.
+    pub pm_timer_32_bit: bool,
+    /// The CMOS register holding the century, or 0.
+    pub century_register: u8,
+    /// True if an 8042 keyboard controller is present.
+    ///
+    /// Firmware before ACPI 2.0 doesn't report this, so it is assumed then.
+    pub has_8042: bool,
+    /// The register used to reset the system, if supported.
+    pub reset_register: Option<GenericAddress>,
+    /// The value to write to the reset register.
+    pub reset_value: u8,
+}
+
+/// The parsed FADT, if present.
+static FADT_INFO: Once<FadtInfo> = Once::new();
+
+/// Parses the FADT.
+pub fn init() {
+    assert_has_not_been_called!("The FADT should only be parsed once.");
+
+    let address = match find_table(b"FACP") {
+        Some(address) => address,
+        None => {
+            debugln!("ACPI: No FADT found.");
+            return;
+        }
+    };
+
+    let header: &SdtHeader = unsafe { get_table(address) };
+    let length = min(header.length as usize, size_of::<Fadt>());
+
+    // Copy the table, so the fields missing in old versions are zero.
+    let fadt = unsafe {
+        let mut fadt: Fadt = zeroed();
+        let source = slice::from_raw_parts(to_virtual!(address) as *const u8, length);
+        let destination = slice::from_raw_parts_mut(&mut fadt as *mut Fadt as *mut u8, length);
+        destination.copy_from_slice(source);
+        fadt
+    };
+
+    let dsdt_address = if fadt.x_dsdt != 0 {
+        fadt.x_dsdt as PhysicalAddress
+    } else {
+        fadt.dsdt as PhysicalAddress
+    };
+
+    let reset_register = if fadt.flags & RESET_REGISTER_SUPPORTED != 0 {
+        Some(fadt.reset_register)
+    } else {
+        None
+    };
+
+    let has_8042 = fadt.header.revision < 2 || fadt.boot_architecture_flags & HAS_8042 != 0;
+
+    FADT_INFO.call_once(|| FadtInfo {
+        dsdt_address,
+        sci_interrupt: fadt.sci_interrupt,
+        smi_command_port: fadt.smi_command_port as u16,
+        acpi_enable: fadt.acpi_enable,
+        pm1a_control_block: fadt.pm1a_control_block as u16,
+        pm1b_control_block: fadt.pm1b_control_block as u16,
+        pm_timer_block: fadt.pm_timer_block as u16,
+        pm_timer_32_bit: fadt.flags & PM_TIMER_32_BIT != 0,
+        century_register: fadt.century,
+        has_8042,
+        reset_register,
+        reset_value: fadt.reset_value,
+    });
+}
+
+/// Returns the information contained in the FADT, if present.
+pub fn get_info() -> Option<&'static FadtInfo> {
+    FADT_INFO.try()
+}
@@ -0,0 +1,61 @@
+//! Parses the HPET table describing the high precision event timer.
+
+use super::{find_table, get_table, GenericAddress, SdtHeader, SYSTEM_MEMORY_ADDRESS_SPACE};
+use memory::PhysicalAddress;
+use spin::Once;
+
+/// The HPET table.
+#[repr(C, packed)]
+struct Hpet {
+    header: SdtHeader,
+    event_timer_block_id: u32,
+    base_address: GenericAddress,
+    hpet_number: u8,
+    minimum_tick: u16,
+    page_protection: u8,
+}
+
+/// The information contained in the HPET table.
+pub struct HpetInfo {
+    /// The physical address of the registers.
+    pub base_address: PhysicalAddress,
+    /// The sequence number of this HPET.
+    pub number: u8,
+    /// The minimum tick in periodic mode without lost interrupts.
+    pub minimum_tick: u16,
+}
+
+/// The parsed HPET table, if present.
+static HPET_INFO: Once<HpetInfo> = Once::new();
+
+/// Parses the HPET table.
+pub fn init() {
+    assert_has_not_been_called!("The HPET table should only be parsed once.");
+
+    let address = match find_table(b"HPET") {
+        Some(address) => address,
+        None => {
+            debugln!("ACPI: No HPET found.");
+            return;
+        }
+    };
+
+    let hpet: &Hpet = unsafe { get_table(address) };
+    let base_address = hpet.base_address;
+
+    if base_address.address_space != SYSTEM_MEMORY_ADDRESS_SPACE {
+        debugln!("ACPI: The HPET is not memory mapped.");
+        return;
+    }
+
+    HPET_INFO.call_once(|| HpetInfo {
+        base_address: base_address.address as PhysicalAddress,
+        number: hpet.hpet_number,
+        minimum_tick: hpet.minimum_tick,
+    });
+}
+
+/// Returns the information contained in the HPET table, if present.
+pub fn get_info() -> Option<&'static HpetInfo> {
+    HPET_INFO.try()
+}
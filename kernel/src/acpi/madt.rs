@@ -0,0 +1,235 @@
+//! Parses the MADT describing the interrupt controllers.
+
+use super::{find_table, get_table, SdtHeader};
+use alloc::Vec;
+use core::mem::size_of;
+use memory::PhysicalAddress;
+use spin::Once;
+
+/// The MADT without its entries.
+#[repr(C, packed)]
+struct Madt {
+    /// The common table header.
+    header: SdtHeader,
+    /// The physical address of the local APICs.
+    local_apic_address: u32,
+    /// Bit 0 is set if dual 8259 PICs are installed.
+    flags: u32,
+}
+
+/// The entry type of a processor local APIC.
+const LOCAL_APIC_ENTRY: u8 = 0;
+
+/// The entry type of an I/O APIC.
+const IO_APIC_ENTRY: u8 = 1;
+
+/// The entry type of an interrupt source override.
+const INTERRUPT_OVERRIDE_ENTRY: u8 = 2;
+
+/// The entry type of a local APIC address override.
+const LOCAL_APIC_ADDRESS_OVERRIDE_ENTRY: u8 = 5;
+
+/// Set in the local APIC flags if the processor is usable.
+const PROCESSOR_ENABLED: u32 = 1 << 0;
+
+/// The processor local APIC entry.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct LocalApicEntry {
+    acpi_processor_id: u8,
+    apic_id: u8,
+    flags: u32,
+}
+
+/// The I/O APIC entry.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct IoApicEntry {
+    id: u8,
+    reserved: u8,
+    address: u32,
+    global_system_interrupt_base: u32,
+}
+
+/// The interrupt source override entry.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct InterruptOverrideEntry {
+    bus: u8,
+    source: u8,
+    global_system_interrupt: u32,
+    flags: u16,
+}
+
+/// Describes an I/O APIC.
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    /// The ID of the I/O APIC.
+    pub id: u8,
+    /// The physical address of the registers.
+    pub address: PhysicalAddress,
+    /// The first global system interrupt handled by this I/O APIC.
+    pub global_system_interrupt_base: u32,
+}
+
+/// The polarity of an interrupt input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Polarity {
+    /// The default polarity of the bus.
+    BusDefault,
+    /// The input is active when high.
+    ActiveHigh,
+    /// The input is active when low.
+    ActiveLow,
+}
+
+/// The trigger mode of an interrupt input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerMode {
+    /// The default trigger mode of the bus.
+    BusDefault,
+    /// The input is edge triggered.
+    Edge,
+    /// The input is level triggered.
+    Level,
+}
+
+/// Describes how an ISA IRQ is connected to the I/O APIC.
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    /// The ISA IRQ.
+    pub source: u8,
+    /// The global system interrupt it is connected to.
+    pub global_system_interrupt: u32,
+    /// The polarity of the input.
+    pub polarity: Polarity,
+    /// The trigger mode of the input.
+    pub trigger_mode: TriggerMode,
+}
+
+/// The information contained in the MADT.
+pub struct MadtInfo {
+    /// The physical address of the local APICs.
+    pub local_apic_address: PhysicalAddress,
+    /// The IDs of the local APICs of the usable processors.
+    pub local_apic_ids: Vec<u8>,
+    /// The I/O APICs in the system.
+    pub io_apics: Vec<IoApic>,
+    /// The interrupt source overrides.
+    pub interrupt_overrides: Vec<InterruptOverride>,
+    /// True if dual 8259 PICs are installed.
+    pub has_8259_pics: bool,
+}
+
+/// The parsed MADT, if present.
+static MADT_INFO: Once<MadtInfo> = Once::new();
+
+/// Parses the MADT.
+pub fn init() {
+    assert_has_not_been_called!("The MADT should only be parsed once.");
+
+    let address = match find_table(b"APIC") {
+        Some(address) => address,
+        None => {
+            debugln!("ACPI: No MADT found.");
+            return;
+        }
+    };
+
+    let madt: &Madt = unsafe { get_table(address) };
+
+    let mut info = MadtInfo {
+        local_apic_address: madt.local_apic_address as PhysicalAddress,
+        local_apic_ids: Vec::new(),
+        io_apics: Vec::new(),
+        interrupt_overrides: Vec::new(),
+        has_8259_pics: madt.flags & 1 != 0,
+    };
+
+    let end = to_virtual!(address + madt.header.length as usize);
+    let mut entry_address = to_virtual!(address + size_of::<Madt>());
+
+    // Each entry starts with its type and length.
+    while entry_address + 2 <= end {
+        let entry_type = unsafe { *(entry_address as *const u8) };
+        let entry_length = unsafe { *((entry_address + 1) as *const u8) } as usize;
+
+        if entry_length < 2 || entry_address + entry_length > end {
+            break;
+        }
+
+        let content = entry_address + 2;
+
+        match entry_type {
+            LOCAL_APIC_ENTRY => {
+                let entry = unsafe { (content as *const LocalApicEntry).read_unaligned() };
+
+                if entry.flags & PROCESSOR_ENABLED != 0 {
+                    info.local_apic_ids.push(entry.apic_id);
+                }
+            }
+            IO_APIC_ENTRY => {
+                let entry = unsafe { (content as *const IoApicEntry).read_unaligned() };
+
+                info.io_apics.push(IoApic {
+                    id: entry.id,
+                    address: entry.address as PhysicalAddress,
+                    global_system_interrupt_base: entry.global_system_interrupt_base,
+                });
+            }
+            INTERRUPT_OVERRIDE_ENTRY => {
+                let entry = unsafe { (content as *const InterruptOverrideEntry).read_unaligned() };
+
+                let polarity = match entry.flags & 0b11 {
+                    0b01 => Polarity::ActiveHigh,
+                    0b11 => Polarity::ActiveLow,
+                    _ => Polarity::BusDefault,
+                };
+
+                let trigger_mode = match (entry.flags >> 2) & 0b11 {
+                    0b01 => TriggerMode::Edge,
+                    0b11 => TriggerMode::Level,
+                    _ => TriggerMode::BusDefault,
+                };
+
+                info.interrupt_overrides.push(InterruptOverride {
+                    source: entry.source,
+                    global_system_interrupt: entry.global_system_interrupt,
+                    polarity,
+                    trigger_mode,
+                });
+            }
+            LOCAL_APIC_ADDRESS_OVERRIDE_ENTRY => {
+                // Two reserved bytes precede the address.
+                let address = unsafe { ((content + 2) as *const u64).read_unaligned() };
+
+                info.local_apic_address = address as PhysicalAddress;
+            }
+            _ => (),
+        }
+
+        entry_address += entry_length;
+    }
+
+    debugln!(
+        "ACPI: {} processor(s), {} I/O APIC(s).",
+        info.local_apic_ids.len(),
+        info.io_apics.len()
+    );
+
+    MADT_INFO.call_once(|| info);
+}
+
+/// Returns the information contained in the MADT, if present.
+pub fn get_info() -> Option<&'static MadtInfo> {
+    MADT_INFO.try()
+}
+
+/// Returns the override for the given ISA IRQ, if there is one.
+pub fn get_interrupt_override(irq: u8) -> Option<InterruptOverride> {
+    get_info()?
+        .interrupt_overrides
+        .iter()
+        .find(|interrupt_override| interrupt_override.source == irq)
+        .cloned()
+}
@@ -0,0 +1,78 @@
+//! Parses the MCFG table describing the PCIe configuration space.
+
+use super::{find_table, get_table, SdtHeader};
+use alloc::Vec;
+use core::mem::size_of;
+use memory::PhysicalAddress;
+
+/// The MCFG table without its entries.
+#[repr(C, packed)]
+struct Mcfg {
+    /// The common table header.
+    header: SdtHeader,
+    /// Reserved.
+    reserved: u64,
+}
+
+/// An entry of the MCFG table.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct McfgEntry {
+    /// The physical base address of the configuration space.
+    base_address: u64,
+    /// The PCI segment group number.
+    segment_group: u16,
+    /// The first bus decoded by this area.
+    start_bus: u8,
+    /// The last bus decoded by this area.
+    end_bus: u8,
+    /// Reserved.
+    reserved: u32,
+}
+
+/// An area of memory mapped PCIe configuration space.
+#[derive(Debug, Clone, Copy)]
+pub struct EcamRegion {
+    /// The physical address of the configuration space of `start_bus`.
+    pub base_address: PhysicalAddress,
+    /// The PCI segment group number.
+    pub segment_group: u16,
+    /// The first bus decoded by this area.
+    pub start_bus: u8,
+    /// The last bus decoded by this area.
+    pub end_bus: u8,
+}
+
+/// Returns the ECAM regions described by the MCFG table.
+///
+/// The list is empty if there is no MCFG table or it is too short.
+pub fn get_ecam_regions() -> Vec<EcamRegion> {
+    let mut regions = Vec::new();
+
+    if let Some(address) = find_table(b"MCFG") {
+        let mcfg: &Mcfg = unsafe { get_table(address) };
+        let entries_length = match (mcfg.header.length as usize).checked_sub(size_of::<Mcfg>()) {
+            Some(entries_length) => entries_length,
+            None => return regions,
+        };
+        let entry_count = entries_length / size_of::<McfgEntry>();
+        let entries_address = to_virtual!(address + size_of::<Mcfg>());
+
+        for i in 0..entry_count {
+            let entry = unsafe {
+                (entries_address as *const McfgEntry)
+                    .offset(i as isize)
+                    .read_unaligned()
+            };
+
+            regions.push(EcamRegion {
+                base_address: entry.base_address as PhysicalAddress,
+                segment_group: entry.segment_group,
+                start_bus: entry.start_bus,
+                end_bus: entry.end_bus,
+            });
+        }
+    }
+
+    regions
+}
@@ -0,0 +1,296 @@
+//! Parses the ACPI tables provided by the firmware.
+//!
+//! The tables are described in the ACPI specification, see
+//! http://wiki.osdev.org/ACPI for an overview.
+
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+pub mod power;
+
+use boot;
+
+use core::mem::size_of;
+use core::slice;
+use memory::{map_physical_range, PageFlags, PhysicalAddress};
+use spin::Once;
+
+/// The signature of the RSDP.
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+
+/// The root system description pointer.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Rsdp {
+    /// Should be "RSD PTR ".
+    signature: [u8; 8],
+    /// Makes the first 20 bytes sum up to zero.
+    checksum: u8,
+    /// Identifies the OEM.
+    oem_id: [u8; 6],
+    /// 0 for ACPI 1.0, 2 for ACPI 2.0 and later.
+    revision: u8,
+    /// The physical address of the RSDT.
+    rsdt_address: u32,
+    /// The length of the whole structure (ACPI 2.0+).
+    length: u32,
+    /// The physical address of the XSDT (ACPI 2.0+).
+    xsdt_address: u64,
+    /// Makes the whole structure sum up to zero (ACPI 2.0+).
+    extended_checksum: u8,
+    /// Reserved.
+    reserved: [u8; 3],
+}
+
+/// The length of the ACPI 1.0 part of the RSDP.
+const RSDP_V1_LENGTH: usize = 20;
+
+/// The header that every system description table starts with.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct SdtHeader {
+    /// The signature identifying the table.
+    pub signature: [u8; 4],
+    /// The length of the table including the header.
+    pub length: u32,
+    /// The revision of the table.
+    pub revision: u8,
+    /// Makes the whole table sum up to zero.
+    pub checksum: u8,
+    /// Identifies the OEM.
+    pub oem_id: [u8; 6],
+    /// Identifies the table of the OEM.
+    pub oem_table_id: [u8; 8],
+    /// The revision of the OEM table.
+    pub oem_revision: u32,
+    /// The vendor of the tool that created the table.
+    pub creator_id: u32,
+    /// The revision of the tool that created the table.
+    pub creator_revision: u32,
+}
+
+/// Describes the location of a register.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct GenericAddress {
+    /// The address space the register is in.
+    pub address_space: u8,
+    /// The size of the register in bits.
+    pub bit_width: u8,
+    /// The offset of the register at the given address in bits.
+    pub bit_offset: u8,
+    /// The access size.
+    pub access_size: u8,
+    /// The address of the register.
+    pub address: u64,
+}
+
+/// The generic address is in the system memory.
+pub const SYSTEM_MEMORY_ADDRESS_SPACE: u8 = 0;
+
+/// The generic address is in the system I/O space.
+pub const SYSTEM_IO_ADDRESS_SPACE: u8 = 1;
+
+/// The generic address is in the PCI configuration space.
+pub const PCI_CONFIG_ADDRESS_SPACE: u8 = 2;
+
+/// The root table listing all other tables.
+struct RootTable {
+    /// The physical address of the RSDT or XSDT.
+    address: PhysicalAddress,
+    /// The size of an entry; 4 for the RSDT and 8 for the XSDT.
+    entry_size: usize,
+}
+
+/// The root table, if one was found.
+static ROOT_TABLE: Once<RootTable> = Once::new();
+
+/// Initializes the ACPI module by locating the root table.
+pub fn init() {
+    assert_has_not_been_called!("ACPI should only be initialized once.");
+
+    let rsdp = match find_rsdp() {
+        Some(rsdp) => rsdp,
+        None => {
+            debugln!("ACPI: No RSDP found.");
+            return;
+        }
+    };
+
+    let root_table = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        RootTable {
+            address: rsdp.xsdt_address as PhysicalAddress,
+            entry_size: 8,
+        }
+    } else {
+        RootTable {
+            address: rsdp.rsdt_address as PhysicalAddress,
+            entry_size: 4,
+        }
+    };
+
+    if unsafe { map_table(root_table.address) }.is_none() {
+        debugln!("ACPI: The root table is invalid.");
+        return;
+    }
+
+    debugln!(
+        "ACPI: Found revision {} tables at {:x}.",
+        rsdp.revision,
+        root_table.address
+    );
+
+    ROOT_TABLE.call_once(|| root_table);
+
+    madt::init();
+    fadt::init();
+    hpet::init();
+}
+
+/// Returns true if ACPI tables are available.
+pub fn is_available() -> bool {
+    ROOT_TABLE.try().is_some()
+}
+
+/// Returns the physical address of the first table with the given signature.
+pub fn find_table(signature: &[u8; 4]) -> Option<PhysicalAddress> {
+    let root_table = ROOT_TABLE.try()?;
+    let header = unsafe { map_table(root_table.address)? };
+
+    let entry_count = (header.length as usize - size_of::<SdtHeader>()) / root_table.entry_size;
+    let entries_address = root_table.address + size_of::<SdtHeader>();
+
+    for i in 0..entry_count {
+        let entry_address = to_virtual!(entries_address + i * root_table.entry_size);
+
+        let table_address = unsafe {
+            if root_table.entry_size == 8 {
+                (entry_address as *const u64).read_unaligned() as PhysicalAddress
+            } else {
+                (entry_address as *const u32).read_unaligned() as PhysicalAddress
+            }
+        };
+
+        if let Some(table) = unsafe { map_table(table_address) } {
+            if &table.signature == signature {
+                return Some(table_address);
+            }
+        }
+    }
+
+    None
+}
+
+/// Maps the table at the given address and returns its header, if it is valid.
+///
+/// # Safety
+/// - The address must point to an ACPI table.
+pub unsafe fn map_table(address: PhysicalAddress) -> Option<&'static SdtHeader> {
+    map_physical_range(address, size_of::<SdtHeader>(), PageFlags::READABLE);
+    let header = &*(to_virtual!(address) as *const SdtHeader);
+
+    let length = header.length as usize;
+    if length < size_of::<SdtHeader>() {
+        return None;
+    }
+
+    map_physical_range(address, length, PageFlags::READABLE);
+    let bytes = slice::from_raw_parts(to_virtual!(address) as *const u8, length);
+
+    if checksum_is_valid(bytes) {
+        Some(header)
+    } else {
+        None
+    }
+}
+
+/// Returns a reference to the table at the given address.
+///
+/// # Safety
+/// - The table must have been mapped using `map_table`.
+/// - `T` must describe the table at the given address.
+pub unsafe fn get_table<T>(address: PhysicalAddress) -> &'static T {
+    &*(to_virtual!(address) as *const T)
+}
+
+/// Checks that the given bytes sum up to zero.
+fn checksum_is_valid(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+/// Locates the RSDP.
+///
+/// The copy of the boot loader is preferred over searching the memory.
+fn find_rsdp() -> Option<Rsdp> {
+    if let Some(rsdp_copy) = boot::get_rsdp() {
+        let rsdp = unsafe { read_rsdp(rsdp_copy.as_ptr()) };
+
+        if rsdp.is_some() {
+            return rsdp;
+        }
+    }
+
+    scan_bios_areas()
+}
+
+/// Searches the BIOS memory areas for the RSDP.
+fn scan_bios_areas() -> Option<Rsdp> {
+    // The first KiB of the EBDA, whose segment is stored at 0x40e.
+    map_physical_range(0x40e, 2, PageFlags::READABLE);
+    let ebda_start = unsafe { *(to_virtual!(0x40e) as *const u16) as PhysicalAddress } << 4;
+
+    if ebda_start != 0 {
+        if let Some(rsdp) = scan_for_rsdp(ebda_start, 0x400) {
+            return Some(rsdp);
+        }
+    }
+
+    // The BIOS read-only memory area.
+    scan_for_rsdp(0xe0000, 0x20000)
+}
+
+/// Scans the given area on 16 byte boundaries for the RSDP.
+fn scan_for_rsdp(start: PhysicalAddress, length: usize) -> Option<Rsdp> {
+    // An extended RSDP at the end of the area may reach beyond it.
+    map_physical_range(start, length + size_of::<Rsdp>(), PageFlags::READABLE);
+
+    let mut address = start;
+    while address + RSDP_V1_LENGTH <= start + length {
+        if let Some(rsdp) = unsafe { read_rsdp(to_virtual!(address) as *const u8) } {
+            return Some(rsdp);
+        }
+
+        address += 16;
+    }
+
+    None
+}
+
+/// Reads the RSDP at the given address if it is valid.
+///
+/// # Safety
+/// - The address must be readable for at least the size of an RSDP.
+unsafe fn read_rsdp(pointer: *const u8) -> Option<Rsdp> {
+    let v1_bytes = slice::from_raw_parts(pointer, RSDP_V1_LENGTH);
+
+    if &v1_bytes[0..8] != RSDP_SIGNATURE || !checksum_is_valid(v1_bytes) {
+        return None;
+    }
+
+    let mut rsdp: Rsdp = ::core::mem::zeroed();
+    let rsdp_bytes = slice::from_raw_parts_mut(&mut rsdp as *mut Rsdp as *mut u8, size_of::<Rsdp>());
+    rsdp_bytes[..RSDP_V1_LENGTH].copy_from_slice(v1_bytes);
+
+    if rsdp.revision >= 2 {
+        let full_bytes = slice::from_raw_parts(pointer, size_of::<Rsdp>());
+
+        if !checksum_is_valid(full_bytes) {
+            return None;
+        }
+
+        rsdp_bytes.copy_from_slice(full_bytes);
+    }
+
+    Some(rsdp)
+}
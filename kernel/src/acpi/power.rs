@@ -0,0 +1,180 @@
+//! Uses ACPI to power off and reset the system.
+
+use super::fadt;
+use super::{map_table, SdtHeader, PCI_CONFIG_ADDRESS_SPACE, SYSTEM_IO_ADDRESS_SPACE,
+            SYSTEM_MEMORY_ADDRESS_SPACE};
+use core::mem::size_of;
+use core::ptr;
+use core::slice;
+use memory::{map_physical_range, PageFlags};
+use x86_64::instructions::port::{inw, outb, outw};
+
+/// Set in the PM1 control register if ACPI mode is enabled.
+const SCI_ENABLE: u16 = 1 << 0;
+
+/// The position of the sleep type in the PM1 control register.
+const SLEEP_TYPE_SHIFT: u16 = 10;
+
+/// Writing this to the PM1 control register enters the sleep state.
+const SLEEP_ENABLE: u16 = 1 << 13;
+
+/// The AML opcode of a package.
+const AML_PACKAGE_OP: u8 = 0x12;
+
+/// The AML prefix of a byte constant.
+const AML_BYTE_PREFIX: u8 = 0x0a;
+
+/// Tries to enter the soft off state (S5).
+///
+/// Returns if it isn't supported.
+pub fn power_off() {
+    let fadt = match fadt::get_info() {
+        Some(fadt) => fadt,
+        None => return,
+    };
+
+    let (sleep_type_a, sleep_type_b) = match find_s5_sleep_types(fadt.dsdt_address) {
+        Some(sleep_types) => sleep_types,
+        None => {
+            debugln!("ACPI: No \\_S5 object found.");
+            return;
+        }
+    };
+
+    if fadt.pm1a_control_block == 0 {
+        return;
+    }
+
+    unsafe {
+        enable_acpi_mode(fadt);
+
+        outw(
+            fadt.pm1a_control_block,
+            sleep_type_a << SLEEP_TYPE_SHIFT | SLEEP_ENABLE,
+        );
+
+        if fadt.pm1b_control_block != 0 {
+            outw(
+                fadt.pm1b_control_block,
+                sleep_type_b << SLEEP_TYPE_SHIFT | SLEEP_ENABLE,
+            );
+        }
+    }
+}
+
+/// Tries to reset the system using the reset register.
+///
+/// Returns if it isn't supported.
+pub fn reset() {
+    let fadt = match fadt::get_info() {
+        Some(fadt) => fadt,
+        None => return,
+    };
+
+    let register = match fadt.reset_register {
+        Some(register) => register,
+        None => return,
+    };
+
+    let address = register.address;
+
+    unsafe {
+        match register.address_space {
+            SYSTEM_IO_ADDRESS_SPACE => outb(address as u16, fadt.reset_value),
+            SYSTEM_MEMORY_ADDRESS_SPACE => {
+                let virtual_address = map_physical_range(
+                    address as usize,
+                    1,
+                    PageFlags::READABLE | PageFlags::WRITABLE | PageFlags::NO_CACHE,
+                );
+                ptr::write_volatile(virtual_address as *mut u8, fadt.reset_value);
+            }
+            PCI_CONFIG_ADDRESS_SPACE => {
+                // The address is encoded as device, function and offset on bus 0.
+                let device = (address >> 32) as u8;
+                let function = (address >> 16) as u8;
+                let offset = address as u16;
+                let aligned_offset = offset & !0b11;
+                let shift = (offset & 0b11) * 8;
+
+                let value = ::drivers::pci::read_config(0, device, function, aligned_offset);
+                let value = value & !(0xff << shift) | (fadt.reset_value as u32) << shift;
+                ::drivers::pci::write_config(0, device, function, aligned_offset, value);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Enables ACPI mode, if the firmware hasn't done it yet.
+///
+/// # Safety
+/// - The FADT must describe the hardware correctly.
+unsafe fn enable_acpi_mode(fadt: &fadt::FadtInfo) {
+    if inw(fadt.pm1a_control_block) & SCI_ENABLE != 0 || fadt.smi_command_port == 0 {
+        return;
+    }
+
+    outb(fadt.smi_command_port, fadt.acpi_enable);
+
+    // Give the firmware some time to make the switch.
+    for _ in 0..1000000 {
+        if inw(fadt.pm1a_control_block) & SCI_ENABLE != 0 {
+            break;
+        }
+    }
+}
+
+/// Finds the sleep types for S5 in the `\_S5` object of the DSDT.
+///
+/// This doesn't interpret AML, but matches the usual encoding of the package.
+fn find_s5_sleep_types(dsdt_address: usize) -> Option<(u16, u16)> {
+    let header = unsafe { map_table(dsdt_address)? };
+
+    let aml = unsafe {
+        slice::from_raw_parts(
+            to_virtual!(dsdt_address + size_of::<SdtHeader>()) as *const u8,
+            header.length as usize - size_of::<SdtHeader>(),
+        )
+    };
+
+    let position = aml.windows(4).position(|name| name == b"_S5_")?;
+
+    // The name must be defined by a NameOp, optionally with a root prefix.
+    let is_definition = position >= 1
+        && (aml[position - 1] == 0x08 || (position >= 2 && aml[position - 1] == b'\\'
+            && aml[position - 2] == 0x08));
+
+    if !is_definition {
+        return None;
+    }
+
+    let mut index = position + 4;
+
+    if *aml.get(index)? != AML_PACKAGE_OP {
+        return None;
+    }
+    index += 1;
+
+    // Skip the package length, whose first byte encodes the number of bytes.
+    index += ((*aml.get(index)? as usize & 0xc0) >> 6) + 1;
+
+    // Skip the number of elements.
+    index += 1;
+
+    let mut read_element = || -> Option<u16> {
+        if *aml.get(index)? == AML_BYTE_PREFIX {
+            index += 1;
+        }
+
+        let value = *aml.get(index)? as u16;
+        index += 1;
+
+        Some(value)
+    };
+
+    let sleep_type_a = read_element()?;
+    let sleep_type_b = read_element()?;
+
+    Some((sleep_type_a, sleep_type_b))
+}
@@ -1,7 +1,9 @@
 //! Provides saving and restoring of architecture specific execution context.
 
-use super::gdt::{TSS, USER_CODE_SEGMENT, USER_DATA_SEGMENT};
+use super::gdt::{USER_CODE_SEGMENT, USER_DATA_SEGMENT};
 use super::interrupts::lapic;
+use super::isolation;
+use super::memory::prepare_page_table_switch;
 use core::mem::size_of;
 use memory::{PhysicalAddress, VirtualAddress};
 use memory::address_space::AddressSpace;
@@ -76,6 +78,23 @@ impl Context {
             page_table_address,
         }
     }
+
+    /// Creates a context for a kernel thread that runs the given function.
+    pub fn kernel_context(
+        function: fn() -> !,
+        mut stack_pointer: VirtualAddress,
+        page_table_address: PhysicalAddress,
+    ) -> Context {
+        unsafe {
+            set_kernel_thread_stack(&mut stack_pointer, function);
+        }
+
+        Context {
+            kernel_stack_pointer: stack_pointer as usize,
+            base_pointer: stack_pointer as usize,
+            page_table_address,
+        }
+    }
 }
 
 /// This is the first thing that's called by every new thread.
@@ -83,22 +102,53 @@ impl Context {
 unsafe fn enter_thread() -> ! {
     after_context_switch();
     lapic::set_priority(0x0);
-    asm!("xor r15, r15
-          xor r14, r14
-          xor r13, r13
-          xor r12, r12
-          xor r11, r11
-          xor r10, r10
-          xor r9, r9
-          xor rbp, rbp
-          xor rbx, rbx
-          xor rax, rax
-          pop rdi
-          pop rsi
-          pop rdx
-          pop rcx
-          pop r8
-          iretq" : : : : "intel", "volatile");
+    if isolation::is_enabled() {
+        // The user table has to be loaded on the way to user mode.
+        asm!("xor r15, r15
+              xor r14, r14
+              xor r13, r13
+              xor r12, r12
+              xor r11, r11
+              xor r10, r10
+              xor r9, r9
+              xor rbp, rbp
+              xor rbx, rbx
+              xor rax, rax
+              pop rdi
+              pop rsi
+              pop rdx
+              pop rcx
+              pop r8
+              jmp isolated_return_to_user" : : : : "intel", "volatile");
+    } else {
+        asm!("xor r15, r15
+              xor r14, r14
+              xor r13, r13
+              xor r12, r12
+              xor r11, r11
+              xor r10, r10
+              xor r9, r9
+              xor rbp, rbp
+              xor rbx, rbx
+              xor rax, rax
+              pop rdi
+              pop rsi
+              pop rdx
+              pop rcx
+              pop r8
+              iretq" : : : : "intel", "volatile");
+    }
+    unreachable!();
+}
+
+/// This is the first thing that's called by every new kernel thread.
+#[naked]
+unsafe fn enter_kernel_thread() -> ! {
+    after_context_switch();
+    lapic::set_priority(0x0);
+    // The function of the thread was pushed below the entry point.
+    asm!("pop rax
+          jmp rax" : : : "rax" : "intel", "volatile");
     unreachable!();
 }
 
@@ -111,6 +161,18 @@ unsafe fn set_idle_stack(stack_pointer: &mut VirtualAddress) {
     *(*stack_pointer as *mut u64) = idle as u64;
 }
 
+/// Sets the initial stack of a kernel thread that runs the given function.
+///
+/// # Safety
+/// - Make sure that the stack pointer is valid.
+unsafe fn set_kernel_thread_stack(stack_pointer: &mut VirtualAddress, function: fn() -> !) {
+    // The padding keeps the stack aligned when the function is entered.
+    for &value in &[0, function as u64, enter_kernel_thread as u64] {
+        *stack_pointer -= size_of::<u64>();
+        *(*stack_pointer as *mut u64) = value;
+    }
+}
+
 /// Sets the initial kernel stack of a thread, so that it can properly start.
 ///
 /// # Safety
@@ -149,14 +211,14 @@ pub unsafe fn switch_context(old_context: &mut Context, new_context: &Context) {
         .lock()
         .kernel_stack
         .base_stack_pointer;
-    TSS.as_mut().privilege_stack_table[0] = ::x86_64::VirtualAddress(base_sp);
+    isolation::set_kernel_stack(base_sp);
 
     switch(
         &mut old_context.kernel_stack_pointer,
         &mut old_context.base_pointer,
         new_sp,
         new_bp,
-        new_context.page_table_address,
+        prepare_page_table_switch(new_context.page_table_address) as usize,
     );
 }
 
@@ -64,17 +64,7 @@ cpu_local! {
 
 cpu_local! {
     /// The global descriptor table of the CPU.
-    pub static ref GDT: Gdt = |_| {
-        let mut gdt = Gdt::new();
-        gdt.add_entry(Descriptor::code(DescriptorFlags::DPL0));
-        gdt.add_entry(Descriptor::data(DescriptorFlags::DPL0));
-        gdt.add_entry(Descriptor::unused());
-        gdt.add_entry(Descriptor::data(DescriptorFlags::DPL3));
-        gdt.add_entry(Descriptor::code(DescriptorFlags::DPL3));
-        gdt.add_entry(Descriptor::tss(&*TSS));
-
-        gdt
-    };
+    pub static ref GDT: Gdt = |cpu_id| Gdt::with_tss(unsafe { TSS.get_specific(cpu_id) });
 }
 
 cpu_local! {
@@ -83,7 +73,7 @@ cpu_local! {
         Stack::new(DOUBLE_FAULT_STACK_MAX_SIZE,
             DOUBLE_FAULT_STACK_MAX_SIZE,
             DOUBLE_FAULT_STACK_AREA_BASE + DOUBLE_FAULT_STACK_OFFSET * cpu_id,
-            AccessType::KernelOnly, None);
+            AccessType::KernelOnly, None).unwrap();
 }
 
 impl Gdt {
@@ -96,6 +86,19 @@ impl Gdt {
         }
     }
 
+    /// Creates the global descriptor table using the given task state segment.
+    pub fn with_tss(tss: &'static TaskStateSegment) -> Gdt {
+        let mut gdt = Gdt::new();
+        gdt.add_entry(Descriptor::code(DescriptorFlags::DPL0));
+        gdt.add_entry(Descriptor::data(DescriptorFlags::DPL0));
+        gdt.add_entry(Descriptor::unused());
+        gdt.add_entry(Descriptor::data(DescriptorFlags::DPL3));
+        gdt.add_entry(Descriptor::code(DescriptorFlags::DPL3));
+        gdt.add_entry(Descriptor::tss(tss));
+
+        gdt
+    }
+
     /// Adds an entry to the GDT.
     fn add_entry(&mut self, descriptor: Descriptor) {
         match descriptor {
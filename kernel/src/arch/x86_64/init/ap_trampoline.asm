@@ -0,0 +1,125 @@
+global ap_trampoline_start
+global ap_trampoline_end
+global ap_trampoline_page_table
+global ap_trampoline_stack_pointer
+global ap_trampoline_entry
+
+;The physical address the trampoline is copied to before the APs are started.
+;NOTE: This must match AP_TRAMPOLINE_ADDRESS in arch/x86_64/memory/mod.rs.
+TRAMPOLINE_BASE equ 0x8000
+
+;The address of the given label within the copied trampoline.
+%define trampoline_address(label) (TRAMPOLINE_BASE + (label) - ap_trampoline_start)
+
+;The trampoline is only copied, so it doesn't need to be executable here.
+section .rodata.ap_trampoline progbits alloc noexec nowrite align=16
+bits 16
+ap_trampoline_start: ;the startup IPI makes the AP start here in real mode
+    cli
+    cld
+
+    xor ax, ax
+    mov ds, ax
+
+    ;load the temporary 32-bit global descriptor table
+    lgdt [trampoline_address(gdt32.pointer)]
+
+    ;enable protected mode
+    mov eax, cr0
+    or eax, 1
+    mov cr0, eax
+
+    jmp dword gdt32.code:trampoline_address(protected_mode)
+
+bits 32
+protected_mode:
+    mov ax, gdt32.data
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+
+    ;enable PAE and global pages
+    mov eax, cr4
+    or eax, 1 << 5 | 1 << 7
+    mov cr4, eax
+
+    ;use the page table of the bootstrap processor
+    mov eax, [trampoline_address(ap_trampoline_page_table)]
+    mov cr3, eax
+
+    ;set the long mode, syscall/sysret and NXE bits in EFER
+    mov ecx, 0xC0000080
+    rdmsr
+    or eax, 1 << 8 | 1 << 11 | 1
+    wrmsr
+
+    ;enable paging and read only pages
+    mov eax, cr0
+    or eax, 1 << 31 | 1 << 16
+    mov cr0, eax
+
+    lgdt [trampoline_address(gdt64.pointer)]
+
+    jmp gdt64.code:trampoline_address(long_mode)
+
+bits 64
+long_mode:
+    xor ax, ax
+    mov ss, ax
+    mov ds, ax
+    mov es, ax
+    mov fs, ax
+    mov gs, ax
+
+    ;enable the fpu
+    mov rax, cr0
+    and al, 11110011b
+    or al, 00100010b
+    mov cr0, rax
+    fninit
+
+    ;enable sse
+    mov rax, cr4
+    or ax, 0000011000000000b
+    mov cr4, rax
+
+    mov rsp, [trampoline_address(ap_trampoline_stack_pointer)]
+
+    ;jump to the higher half, call keeps the stack aligned like for any function
+    mov rax, [trampoline_address(ap_trampoline_entry)]
+    call rax
+
+    ;the entry function never returns
+.endlessLoop:
+    hlt
+    jmp .endlessLoop
+
+align 8
+gdt32:
+    dq 0 ;required
+.code: equ $ - gdt32
+    dq 0x00cf9a000000ffff ;executable, readable, present, 32-bit, 4 GiB limit
+.data: equ $ - gdt32
+    dq 0x00cf92000000ffff ;writable, present, 32-bit, 4 GiB limit
+.pointer:
+    dw .pointer - gdt32 - 1
+    dd trampoline_address(gdt32)
+
+align 8
+gdt64:
+    dq 0 ;required
+.code: equ $ - gdt64
+    dq (1 << 43) | (1 << 44) | (1 << 47) | (1 << 53) ;executable, code, present, 64-bit
+.pointer:
+    dw .pointer - gdt64 - 1
+    dq trampoline_address(gdt64)
+
+;These are filled in before each AP is started.
+align 8
+ap_trampoline_page_table: ;the physical address of the level 4 table, below 4 GiB
+    dq 0
+ap_trampoline_stack_pointer: ;the initial stack pointer of the AP
+    dq 0
+ap_trampoline_entry: ;the address of the function the AP jumps to
+    dq 0
+ap_trampoline_end:
@@ -1,22 +1,49 @@
 //! Deals with configuring the I/O APIC.
 
 use super::IRQ_INTERRUPT_NUMS;
+use super::lapic;
+use acpi::madt::{self, InterruptOverride, IoApic, Polarity, TriggerMode};
 use core::fmt;
 use memory::{map_page_at, PageFlags, PhysicalAddress, VirtualAddress};
 use x86_64::instructions::port::outb;
 
-/// The physical base address of the memory mapped I/O APIC.
-const IO_APIC_BASE: PhysicalAddress = 0xfec00000;
+/// The physical base address of the I/O APIC, if ACPI doesn't describe it.
+const DEFAULT_IO_APIC_BASE: PhysicalAddress = 0xfec00000;
+
+/// The I/O APIC that is assumed, if ACPI doesn't describe any.
+const DEFAULT_IO_APIC: IoApic = IoApic {
+    id: 0,
+    address: DEFAULT_IO_APIC_BASE,
+    global_system_interrupt_base: 0,
+};
+
+/// The offset of the version register.
+const VERSION_REGISTER: u8 = 0x01;
+
+/// The ISA IRQ of the PIT.
+const PIT_IRQ: u8 = 0;
+
+/// The ISA IRQ used to cascade the 8259 PICs.
+const CASCADE_IRQ: u8 = 2;
 
 /// Initializes the I/O APIC.
 pub fn init() {
     assert_has_not_been_called!("The I/O APIC should only be initialized once.");
 
-    map_page_at(
-        to_virtual!(IO_APIC_BASE),
-        IO_APIC_BASE,
-        PageFlags::READABLE | PageFlags::WRITABLE | PageFlags::NO_CACHE,
-    );
+    for io_apic in get_io_apics() {
+        map_page_at(
+            to_virtual!(io_apic.address),
+            io_apic.address,
+            PageFlags::READABLE | PageFlags::WRITABLE | PageFlags::NO_CACHE,
+        );
+
+        // Mask all inputs, until they are explicitly set.
+        for i in 0..get_redirection_entry_count(io_apic) {
+            let mut entry = IORedirectionEntry::new();
+            entry.set_inactive();
+            set_irq(io_apic.global_system_interrupt_base + i as u32, entry);
+        }
+    }
 
     // Disable the 8259 PIC.
     unsafe {
@@ -24,16 +51,32 @@ pub fn init() {
         outb(0xa1, 0xff);
     }
 
-    for i in 0..16 {
-        let mut irq = IORedirectionEntry::new();
-        irq.set_vector(IRQ_INTERRUPT_NUMS[i]);
-        set_irq(i as u8, irq);
-    }
+    for irq in 0..16 {
+        // The PIT is not used and the cascade never fires.
+        if irq == PIT_IRQ || irq == CASCADE_IRQ {
+            continue;
+        }
+
+        let isa_override = madt::get_interrupt_override(irq).unwrap_or(InterruptOverride {
+            source: irq,
+            global_system_interrupt: irq as u32,
+            polarity: Polarity::BusDefault,
+            trigger_mode: TriggerMode::BusDefault,
+        });
+
+        let mut entry = IORedirectionEntry::new();
+        entry.set_vector(IRQ_INTERRUPT_NUMS[irq as usize]);
+
+        // ISA interrupts are active high and edge triggered by default.
+        if isa_override.polarity == Polarity::ActiveLow {
+            entry.set_polarity(IORedirectionEntryFlags::LOW_ACTIVE_PIN_POLARITY);
+        }
+        if isa_override.trigger_mode == TriggerMode::Level {
+            entry.set_trigger_mode(IORedirectionEntryFlags::LEVEL_SENSITIVE);
+        }
 
-    // Deactivate irq2.
-    let mut irq2 = IORedirectionEntry::new();
-    irq2.set_inactive();
-    set_irq(2, irq2);
+        set_irq(isa_override.global_system_interrupt, entry);
+    }
 
     // Reroute interrupts to the IOAPIC.
     unsafe {
@@ -42,30 +85,62 @@ pub fn init() {
     }
 }
 
+/// Returns the I/O APICs of the system.
+fn get_io_apics() -> &'static [IoApic] {
+    static DEFAULT_IO_APICS: [IoApic; 1] = [DEFAULT_IO_APIC];
+
+    match madt::get_info() {
+        Some(info) if !info.io_apics.is_empty() => &info.io_apics[..],
+        _ => &DEFAULT_IO_APICS[..],
+    }
+}
+
+/// Returns the number of redirection entries of the given I/O APIC.
+fn get_redirection_entry_count(io_apic: &IoApic) -> u8 {
+    // Bits 16 - 23 contain the index of the last entry.
+    (get_register(io_apic, VERSION_REGISTER) >> 16) as u8 + 1
+}
+
 /// Writes an I/O APIC register.
-fn set_register(reg: u8, value: u32) {
+fn set_register(io_apic: &IoApic, reg: u8, value: u32) {
+    unsafe {
+        *(get_ioapic_base(io_apic) as *mut u32) = reg as u32;
+        *((get_ioapic_base(io_apic) + 0x10) as *mut u32) = value;
+    }
+}
+
+/// Reads an I/O APIC register.
+fn get_register(io_apic: &IoApic, reg: u8) -> u32 {
     unsafe {
-        *(get_ioapic_base() as *mut u32) = reg as u32;
-        *((get_ioapic_base() + 0x10) as *mut u32) = value;
+        *(get_ioapic_base(io_apic) as *mut u32) = reg as u32;
+        *((get_ioapic_base(io_apic) + 0x10) as *const u32)
     }
 }
 
-/// Sets the given IRQ number to the specified value.
-fn set_irq(number: u8, value: IORedirectionEntry) {
-    assert!(number < 24);
+/// Sets the given global system interrupt to the specified value.
+fn set_irq(global_system_interrupt: u32, value: IORedirectionEntry) {
+    let io_apic = get_io_apics()
+        .iter()
+        .find(|io_apic| {
+            io_apic.global_system_interrupt_base <= global_system_interrupt
+                && global_system_interrupt - io_apic.global_system_interrupt_base
+                    < get_redirection_entry_count(io_apic) as u32
+        })
+        .expect("No I/O APIC handles the interrupt.");
 
+    let number = (global_system_interrupt - io_apic.global_system_interrupt_base) as u8;
     let reg = 0x10 + number * 2;
 
     // Disable the entry, before setting the destination.
-    set_register(reg, IORedirectionEntryFlags::MASK.bits() as u32);
+    set_register(io_apic, reg, IORedirectionEntryFlags::MASK.bits() as u32);
 
-    set_register(reg + 1, (value.0 >> 32) as u32);
-    set_register(reg, value.0 as u32);
+    set_register(io_apic, reg + 1, (value.0 >> 32) as u32);
+    set_register(io_apic, reg, value.0 as u32);
 }
 
-/// Returns the base address for the I/O APIC.
-fn get_ioapic_base() -> VirtualAddress {
-    to_virtual!(IO_APIC_BASE)
+/// Returns the base address for the given I/O APIC.
+fn get_ioapic_base(io_apic: &IoApic) -> VirtualAddress {
+    to_virtual!(io_apic.address)
 }
 
 /// Represents an entry in the I/O APIC redirection table.
@@ -131,11 +206,10 @@ impl IORedirectionEntry {
         register.set_delivery_mode(IORedirectionEntryFlags::FIXED_DELIVERY_MODE);
         register.set_trigger_mode(IORedirectionEntryFlags::EDGE_SENSITIVE);
         register.set_polarity(IORedirectionEntryFlags::HIGH_ACTIVE_PIN_POLARITY);
-        // 0xff sends the interrupt to all processors.
-        // TODO: Don't use this ID here.
+        // Deliver to the processor that initialized the I/O APIC.
         register.set_destination(
             IORedirectionEntryFlags::PHYSICAL_DESTINATION_MODE,
-            ::multitasking::get_cpu_id() as u8,
+            lapic::get_id(),
         );
 
         register
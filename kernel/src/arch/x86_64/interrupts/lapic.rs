@@ -2,14 +2,23 @@
 //! Controller (LAPIC).
 
 use super::{IRQ8_INTERRUPT_TICKS, SPURIOUS_INTERRUPT_HANDLER_NUM, TIMER_INTERRUPT_HANDLER_NUM};
+use arch::get_cpu_id;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use drivers::rtc::CMOS_LOCK;
 use memory::{map_page_at, PageFlags, PhysicalAddress, VirtualAddress};
 use raw_cpuid::CpuId;
 use sync::{disable_preemption, restore_preemption_state};
 use x86_64::instructions::interrupts;
+use x86_64::instructions::rdtsc;
 use x86_64::instructions::port::{inb, outb};
+use x86_64::registers::msr::{rdmsr, wrmsr, IA32_APIC_BASE};
 
-/// The physical base address of the memory mapped LAPIC.
-const LAPIC_BASE: PhysicalAddress = 0xfee00000;
+/// The physical base address of the memory mapped LAPIC, if ACPI doesn't
+/// describe it.
+const DEFAULT_LAPIC_BASE: PhysicalAddress = 0xfee00000;
+
+/// The offset for the LAPIC ID register.
+const ID_REGISTER: usize = 0x20;
 
 /// The offset for the CMCI interrupt LVT register.
 const CMCI_INTERRUPT: usize = 0x2f0;
@@ -50,6 +59,24 @@ const INTERRUPT_COMMAND_REGISTER_LOW: usize = 0x300;
 /// The offset for the interrupt command register (bits 32-63).
 const INTERRUPT_COMMAND_REGISTER_HIGH: usize = 0x310;
 
+/// Set in the ICR while the interrupt hasn't been accepted yet.
+const ICR_DELIVERY_PENDING: u64 = 1 << 12;
+
+/// Set in the ICR to assert a level triggered interrupt.
+const ICR_LEVEL_ASSERT: u64 = 1 << 14;
+
+/// The position of the destination field in the ICR.
+const ICR_DESTINATION_SHIFT: u64 = 56;
+
+/// The ICR delivery mode that resets the target CPU.
+const ICR_INIT_DELIVERY_MODE: u64 = 0b101 << 8;
+
+/// The ICR delivery mode that starts an application processor.
+const ICR_STARTUP_DELIVERY_MODE: u64 = 0b110 << 8;
+
+/// The bit in the IA32_APIC_BASE MSR that is set on the bootstrap processor.
+const APIC_BASE_BSP_FLAG: u64 = 1 << 8;
+
 /// The offset for the end of interrupt register.
 const END_OF_INTERRUPT: usize = 0xb0;
 
@@ -59,22 +86,55 @@ const LOGICAL_DESTINATION_REGISTER: usize = 0xd0;
 /// The offset of the destination format register.
 const DESTINATION_FORMAT_REGISTER: usize = 0xe0;
 
+/// The MSR that holds the TSC value at which the timer fires in TSC-deadline
+/// mode.
+const IA32_TSC_DEADLINE: u32 = 0x6e0;
+
 // TODO: This assumes the LAPICS on all CPUs have the same frequency.
 /// The amount of LAPIC timer ticks per milliseconds. Measured at runtime.
 ///
 /// This value is initialized to the value that qemu uses.
 static mut TICKS_PER_MS: u32 = 1000000;
 
+/// The amount of TSC ticks per millisecond. Measured at runtime.
+static mut TSC_TICKS_PER_MS: u64 = 0;
+
+/// The delay of the periodic timer in milliseconds.
+static mut PERIODIC_TIMER_DELAY: u32 = 0;
+
+/// The ID of the bootstrap processor.
+///
+/// This is remembered, so the timer interrupt doesn't need to read an MSR.
+static BOOTSTRAP_CPU_ID: AtomicUsize = AtomicUsize::new(0);
+
 /// Initializes the LAPIC.
 pub fn init() {
     assert_has_not_been_called!("The LAPIC should only be initialized once.");
 
+    assert!(
+        unsafe { rdmsr(IA32_APIC_BASE) & APIC_BASE_BSP_FLAG != 0 },
+        "The LAPIC should be initialized on the bootstrap processor first."
+    );
+    BOOTSTRAP_CPU_ID.store(get_cpu_id(), Ordering::Release);
+
     map_page_at(
-        to_virtual!(LAPIC_BASE),
-        LAPIC_BASE,
+        get_lapic_base(),
+        get_lapic_physical_base(),
         PageFlags::READABLE | PageFlags::WRITABLE | PageFlags::NO_CACHE,
     );
 
+    configure();
+}
+
+/// Initializes the LAPIC of an application processor.
+///
+/// The mapping created by the bootstrap processor is shared by all CPUs.
+pub fn init_ap() {
+    configure();
+}
+
+/// Configures the LAPIC of the current CPU.
+fn configure() {
     let cpu_id = CpuId::new()
         .get_feature_info()
         .unwrap()
@@ -135,6 +195,8 @@ pub fn calibrate_timer() {
     let measure_accuracy_in_ms = 125;
 
     // Use the RTC to calibrate the LAPIC timer.
+    // The IRQ8 handler only touches the CMOS while this holds the lock.
+    let _lock = CMOS_LOCK.lock();
     unsafe {
         // Save the NMI enable state to restore it later.
         let nmi_bit = inb(0x70) & 0x80;
@@ -157,8 +219,9 @@ pub fn calibrate_timer() {
         // Enable interrupts.
         interrupts::enable();
 
-        // Start LAPIC timer for comparison.
+        // Start LAPIC timer and read the TSC for comparison.
         set_register(TIMER_INITIAL_COUNT, <u32>::max_value());
+        let tsc_start = rdtsc();
 
         // Wait until the specified amount of time has passed.
         while *IRQ8_INTERRUPT_TICKS.lock() < end_tick {
@@ -167,11 +230,13 @@ pub fn calibrate_timer() {
 
         // Measure LAPIC timer ticks.
         let timer_ticks_passed = <u32>::max_value() - get_register(TIMER_CURRENT_COUNT);
+        let tsc_ticks_passed = rdtsc() - tsc_start;
 
         // Disable interrupts again.
         interrupts::disable();
 
         TICKS_PER_MS = timer_ticks_passed / measure_accuracy_in_ms as u32;
+        TSC_TICKS_PER_MS = tsc_ticks_passed / measure_accuracy_in_ms;
 
         // Disable RTC interrupts after we're done.
         outb(0x70, 0x8b);
@@ -192,10 +257,85 @@ pub fn signal_eoi() {
 /// Sets the periodic lapic timer to the specified delay in milliseconds.
 pub fn set_periodic_timer(delay: u32) {
     unsafe {
+        PERIODIC_TIMER_DELAY = delay;
         set_register(TIMER_INITIAL_COUNT, delay * TICKS_PER_MS);
     }
 }
 
+/// Switches the timer from periodic to one-shot operation.
+///
+/// If `tsc_deadline` is set, the timer fires when the TSC reaches the value
+/// set with `set_tsc_deadline`, otherwise after the count set with
+/// `set_one_shot_timer`.
+pub fn enable_one_shot_timer(tsc_deadline: bool) {
+    let mut timer_register = LVTRegister::new();
+    timer_register.set_vector(TIMER_INTERRUPT_HANDLER_NUM);
+    timer_register.set_timer_mode(if tsc_deadline {
+        LVTRegisterFlags::DEADLINE_TIMER_MODE
+    } else {
+        LVTRegisterFlags::ONE_SHOT_TIMER_MODE
+    });
+
+    unsafe {
+        PERIODIC_TIMER_DELAY = 0;
+        set_register(TIMER_INITIAL_COUNT, 0);
+        set_lvt_register(TIMER_INTERRUPT, timer_register);
+
+        // The LVT write must be complete before the deadline MSR is written.
+        asm!("mfence" : : : "memory" : "intel", "volatile");
+    }
+}
+
+/// Fires the timer once after the given amount of LAPIC timer ticks.
+///
+/// A value of zero stops the timer.
+pub fn set_one_shot_timer(ticks: u32) {
+    unsafe {
+        set_register(TIMER_INITIAL_COUNT, ticks);
+    }
+}
+
+/// Fires the timer once the TSC reaches the given value.
+///
+/// A value of zero stops the timer.
+pub fn set_tsc_deadline(tsc_value: u64) {
+    unsafe {
+        wrmsr(IA32_TSC_DEADLINE, tsc_value);
+    }
+}
+
+/// Returns true if the timer supports the TSC-deadline mode.
+pub fn has_tsc_deadline() -> bool {
+    CpuId::new()
+        .get_feature_info()
+        .map_or(false, |info| info.has_tsc_deadline())
+}
+
+/// Returns the delay of the periodic timer in milliseconds.
+pub fn get_periodic_timer_delay() -> u32 {
+    unsafe { PERIODIC_TIMER_DELAY }
+}
+
+/// Returns the amount of LAPIC timer ticks per millisecond.
+pub fn get_ticks_per_ms() -> u32 {
+    unsafe { TICKS_PER_MS }
+}
+
+/// Returns the amount of TSC ticks per millisecond, measured during calibration.
+pub fn get_tsc_ticks_per_ms() -> u64 {
+    unsafe { TSC_TICKS_PER_MS }
+}
+
+/// Returns the number of ticks that passed in the current timer period.
+pub fn get_elapsed_timer_ticks() -> u32 {
+    unsafe {
+        let initial_count = get_register(TIMER_INITIAL_COUNT);
+        let current_count = get_register(TIMER_CURRENT_COUNT);
+
+        initial_count.saturating_sub(current_count)
+    }
+}
+
 /// Sets the task priority for the local APIC.
 pub fn set_priority(value: u8) {
     unsafe {
@@ -223,9 +363,27 @@ fn set_icr(value: u64) {
     }
 }
 
+/// Returns true if the current CPU is the bootstrap processor.
+pub fn is_bootstrap_processor() -> bool {
+    get_cpu_id() == BOOTSTRAP_CPU_ID.load(Ordering::Acquire)
+}
+
+/// Returns the ID of the LAPIC of this CPU.
+pub fn get_id() -> u8 {
+    unsafe { (get_register(ID_REGISTER) >> 24) as u8 }
+}
+
+/// Returns the physical base address of the LAPICs.
+fn get_lapic_physical_base() -> PhysicalAddress {
+    match ::acpi::madt::get_info() {
+        Some(info) => info.local_apic_address,
+        None => DEFAULT_LAPIC_BASE,
+    }
+}
+
 /// Returns the base address for the LAPIC of this CPU.
 fn get_lapic_base() -> VirtualAddress {
-    to_virtual!(LAPIC_BASE)
+    to_virtual!(get_lapic_physical_base())
 }
 
 /// Sets a LAPIC register.
@@ -263,6 +421,45 @@ pub fn issue_self_interrupt(vector: u8) {
     issue_interrupt(InterruptDestinationMode::SELF, vector);
 }
 
+/// Issues an interrupt to the CPU with the given LAPIC ID.
+pub fn issue_interrupt_to(apic_id: u8, vector: u8) {
+    set_icr(
+        InterruptDestinationMode::PHYSICAL.bits() | (apic_id as u64) << ICR_DESTINATION_SHIFT
+            | vector as u64,
+    );
+}
+
+/// Sends an INIT IPI to the CPU with the given LAPIC ID.
+///
+/// This puts the CPU into the wait-for-startup state.
+pub fn send_init(apic_id: u8) {
+    set_icr(
+        InterruptDestinationMode::PHYSICAL.bits() | (apic_id as u64) << ICR_DESTINATION_SHIFT
+            | ICR_INIT_DELIVERY_MODE | ICR_LEVEL_ASSERT,
+    );
+    wait_for_delivery();
+}
+
+/// Sends a startup IPI to the CPU with the given LAPIC ID.
+///
+/// The CPU starts executing in real mode at `page * 0x1000`.
+pub fn send_startup(apic_id: u8, page: u8) {
+    set_icr(
+        InterruptDestinationMode::PHYSICAL.bits() | (apic_id as u64) << ICR_DESTINATION_SHIFT
+            | ICR_STARTUP_DELIVERY_MODE | ICR_LEVEL_ASSERT | page as u64,
+    );
+    wait_for_delivery();
+}
+
+/// Waits until the last interrupt sent from this CPU has been accepted.
+fn wait_for_delivery() {
+    unsafe {
+        while get_register(INTERRUPT_COMMAND_REGISTER_LOW) as u64 & ICR_DELIVERY_PENDING != 0 {
+            asm!("pause" : : : : "intel", "volatile");
+        }
+    }
+}
+
 /// Issues the given interrupt for the given target(s).
 fn issue_interrupt(target: InterruptDestinationMode, vector: u8) {
     assert!(target.intersects(
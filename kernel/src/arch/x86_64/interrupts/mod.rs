@@ -2,8 +2,11 @@
 
 pub mod lapic;
 mod ioapic;
+pub mod vectors;
 
 pub use self::lapic::issue_self_interrupt;
+use super::isolation::original_frame;
+use super::memory::{handle_tlb_shootdown, handle_user_access_fault};
 use super::sync::CLOCK;
 use multitasking::scheduler::schedule_next_thread;
 use sync::PreemptableMutex;
@@ -15,10 +18,13 @@ use x86_64::instructions::port::{inb, outb};
 use x86_64::PrivilegeLevel;
 use memory::VirtualAddress;
 use multitasking::{get_process, ProcessID, TCB};
-use multitasking::scheduler::READY_LIST;
+use multitasking::scheduler::make_ready;
 /// The vector for the scheduling interrupt.
 pub const SCHEDULE_INTERRUPT_NUM: u8 = 0x20;
 
+/// The vector for the TLB shootdown interrupt.
+pub const TLB_SHOOTDOWN_INTERRUPT_NUM: u8 = 0xf0;
+
 /// The vectors for the IRQs.
 const IRQ_INTERRUPT_NUMS: [u8; 16] = [
     0xEC, 0xE4, 0xFF, 0x94, 0x8C, 0x84, 0x7C, 0x74, 0xD4, 0xCC, 0xC4, 0xBC, 0xB4, 0xAC, 0xA4, 0x9C
@@ -87,10 +93,16 @@ lazy_static! {
         idt[SCHEDULE_INTERRUPT_NUM as usize].set_handler_fn(schedule_interrupt)
             .disable_interrupts(false);
 
+        // Other CPUs request TLB invalidations with this interrupt.
+        idt[TLB_SHOOTDOWN_INTERRUPT_NUM as usize].set_handler_fn(tlb_shootdown_interrupt);
+
         // LAPIC specific interrupts.
         idt[SPURIOUS_INTERRUPT_HANDLER_NUM as usize].set_handler_fn(empty_handler);
         idt[TIMER_INTERRUPT_HANDLER_NUM as usize].set_handler_fn(timer_handler);
 
+        // Vectors that are allocated at runtime.
+        vectors::set_handlers(&mut idt);
+
         idt
     };
 }
@@ -110,6 +122,21 @@ pub fn init() {
     lapic::set_periodic_timer(150);
 }
 
+/// Returns the interrupt descriptor table used by the kernel.
+pub fn get_idt() -> &'static Idt {
+    &IDT
+}
+
+/// Initializes interrupts on an application processor.
+///
+/// The IDT and the I/O APIC are shared with the bootstrap processor, which
+/// also receives all external interrupts.
+pub fn init_ap() {
+    IDT.load();
+
+    lapic::init_ap();
+}
+
 macro_rules! irq_interrupt {
     ($(#[$attr: meta])* fn $name: ident $content: tt) => {
         $(#[$attr])*
@@ -134,14 +161,14 @@ macro_rules! irq_interrupt {
 /// The divide by zero exception handler of the kernel.
 extern "x86-interrupt" fn divide_by_zero_handler(stack_frame: &mut ExceptionStackFrame) {
     panic_debugln!("Divide by zero exception.");
-    panic_debugln!("{:?}", stack_frame);
+    panic_debugln!("{:?}", original_frame(stack_frame));
     loop {}
 }
 
 /// The breakpoint exception handler of the kernel.
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: &mut ExceptionStackFrame) {
     panic_debugln!("Breakpoint exception.");
-    panic_debugln!("{:?}", stack_frame);
+    panic_debugln!("{:?}", original_frame(stack_frame));
     loop {}
 }
 
@@ -151,7 +178,7 @@ extern "x86-interrupt" fn general_protection_fault_handler(
     error_code: u64,
 ) {
     panic_debugln!("GENERAL PROTECTION FAULT");
-    panic_debugln!("{:?}", stack_frame);
+    panic_debugln!("{:?}", original_frame(stack_frame));
     panic_debugln!("Error code: 0x{:x}", error_code);
     use multitasking::{CURRENT_THREAD, TCB};
     let tcb: &::sync::PreemptableMutex<TCB> = &CURRENT_THREAD;
@@ -164,6 +191,7 @@ extern "x86-interrupt" fn double_fault_handler(
     stack_frame: &mut ExceptionStackFrame,
     error_code: u64,
 ) {
+    ::interrupts::report_double_fault_cause(control_regs::cr2().0);
     panic_debugln!("DOUBLE FAULT!");
     panic_debugln!("{:?}", stack_frame);
     panic_debugln!("Error code: 0x{:x}", error_code);
@@ -178,7 +206,11 @@ extern "x86-interrupt" fn page_fault_handler(
     stack_frame: &mut ExceptionStackFrame,
     error_code: PageFaultErrorCode,
 ) {
-    ::interrupts::page_fault_handler(control_regs::cr2().0, stack_frame, error_code);
+    let address = control_regs::cr2().0;
+
+    if !handle_user_access_fault(address, stack_frame) {
+        ::interrupts::page_fault_handler(address, original_frame(stack_frame), error_code);
+    }
 }
 
 /// The software interrupt handler that invokes schedule operations.
@@ -192,6 +224,12 @@ extern "x86-interrupt" fn schedule_interrupt(_: &mut ExceptionStackFrame) {
     lapic::set_priority(0x0);
 }
 
+/// The interrupt handler for TLB shootdown requests of other CPUs.
+extern "x86-interrupt" fn tlb_shootdown_interrupt(_: &mut ExceptionStackFrame) {
+    handle_tlb_shootdown();
+    lapic::signal_eoi();
+}
+
 /// An interrupt handler that does nothing.
 #[allow(dead_code)]
 extern "x86-interrupt" fn empty_handler(_: &mut ExceptionStackFrame) {}
@@ -202,8 +240,11 @@ extern "x86-interrupt" fn empty_handler_with_error(_: &mut ExceptionStackFrame,
 irq_interrupt!(
 /// The handler for the lapic timer interrupt.
 fn timer_handler {
-    unsafe {
-        CLOCK += 150;
+    // Every CPU has its own timer, but only one of them keeps the time.
+    if lapic::is_bootstrap_processor() {
+        unsafe {
+            CLOCK += lapic::get_periodic_timer_delay() as u64;
+        }
     }
     ::interrupts::timer_interrupt();
 });
@@ -247,9 +288,12 @@ fn irq1_handler {
                     0,
                 );
 
-                pcb.add_thread(id);
+                // The process may have mapped memory where the stack should go.
+                if let Some(thread) = thread {
+                    pcb.add_thread(id);
 
-                READY_LIST.lock().push(thread);
+                    make_ready(thread);
+                }
 
                 // id as i64
             }
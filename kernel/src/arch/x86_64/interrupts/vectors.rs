@@ -0,0 +1,127 @@
+//! Allocates interrupt vectors to dynamically registered handlers.
+//!
+//! This is used for interrupts whose vector is chosen by software, like MSI.
+
+use super::lapic;
+use alloc::arc::Arc;
+use alloc::boxed::Box;
+use sync::PreemptableMutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::idt::{ExceptionStackFrame, Idt};
+
+/// The first vector that can be allocated.
+const FIRST_DYNAMIC_VECTOR: u8 = 0x40;
+
+/// The number of vectors that can be allocated.
+const DYNAMIC_VECTOR_COUNT: usize = 32;
+
+/// A handler for a dynamically allocated interrupt vector.
+pub trait InterruptHandler: Send + Sync {
+    /// Handles the interrupt with the given vector.
+    fn handle(&self, vector: u8);
+}
+
+impl<F: Fn(u8) + Send + Sync> InterruptHandler for F {
+    fn handle(&self, vector: u8) {
+        self(vector)
+    }
+}
+
+impl InterruptHandler for Box<InterruptHandler> {
+    fn handle(&self, vector: u8) {
+        (**self).handle(vector)
+    }
+}
+
+/// The handlers for the dynamic vectors.
+///
+/// A vector is allocated if it has a handler.
+static HANDLERS: PreemptableMutex<[Option<Arc<InterruptHandler>>; DYNAMIC_VECTOR_COUNT]> =
+    PreemptableMutex::new([
+        None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+        None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+        None, None,
+    ]);
+
+/// Allocates a vector and installs the given handler for it.
+///
+/// Returns `None` if all vectors are in use.
+pub fn allocate_vector<H: InterruptHandler + 'static>(handler: H) -> Option<u8> {
+    let mut handlers = HANDLERS.lock();
+
+    let index = handlers.iter().position(|handler| handler.is_none())?;
+    handlers[index] = Some(Arc::new(handler));
+
+    Some(FIRST_DYNAMIC_VECTOR + index as u8)
+}
+
+/// Frees the given vector and removes its handler.
+///
+/// # Safety
+/// - The device raising this vector must have been disabled.
+pub unsafe fn free_vector(vector: u8) {
+    assert!(
+        is_dynamic_vector(vector),
+        "The vector {:x} was not dynamically allocated.",
+        vector
+    );
+
+    HANDLERS.lock()[(vector - FIRST_DYNAMIC_VECTOR) as usize] = None;
+}
+
+/// Returns true if the vector belongs to the dynamically allocated range.
+fn is_dynamic_vector(vector: u8) -> bool {
+    vector >= FIRST_DYNAMIC_VECTOR
+        && ((vector - FIRST_DYNAMIC_VECTOR) as usize) < DYNAMIC_VECTOR_COUNT
+}
+
+/// Calls the handler of the given vector.
+fn handle_dynamic_interrupt(vector: u8) {
+    let old_priority = lapic::get_priority();
+    lapic::set_priority(0x20);
+
+    // Don't hold the lock while the handler runs.
+    let handler = HANDLERS.lock()[(vector - FIRST_DYNAMIC_VECTOR) as usize].clone();
+
+    unsafe {
+        interrupts::enable();
+    }
+
+    match handler {
+        Some(handler) => handler.handle(vector),
+        None => debugln!("Unhandled interrupt on vector {:x}.", vector),
+    }
+
+    unsafe {
+        interrupts::disable();
+    }
+    lapic::signal_eoi();
+    lapic::set_priority(old_priority);
+}
+
+macro_rules! dynamic_interrupt_handlers {
+    ($($name: ident => $vector: expr),*) => {
+        $(
+            /// The entry point for a dynamically allocated vector.
+            extern "x86-interrupt" fn $name(_: &mut ExceptionStackFrame) {
+                handle_dynamic_interrupt($vector);
+            }
+        )*
+
+        /// Installs the entry points of the dynamic vectors in the IDT.
+        pub fn set_handlers(idt: &mut Idt) {
+            $(idt[$vector as usize].set_handler_fn($name);)*
+        }
+    };
+}
+
+dynamic_interrupt_handlers!(
+    vector_0x40 => 0x40, vector_0x41 => 0x41, vector_0x42 => 0x42, vector_0x43 => 0x43,
+    vector_0x44 => 0x44, vector_0x45 => 0x45, vector_0x46 => 0x46, vector_0x47 => 0x47,
+    vector_0x48 => 0x48, vector_0x49 => 0x49, vector_0x4a => 0x4a, vector_0x4b => 0x4b,
+    vector_0x4c => 0x4c, vector_0x4d => 0x4d, vector_0x4e => 0x4e, vector_0x4f => 0x4f,
+    vector_0x50 => 0x50, vector_0x51 => 0x51, vector_0x52 => 0x52, vector_0x53 => 0x53,
+    vector_0x54 => 0x54, vector_0x55 => 0x55, vector_0x56 => 0x56, vector_0x57 => 0x57,
+    vector_0x58 => 0x58, vector_0x59 => 0x59, vector_0x5a => 0x5a, vector_0x5b => 0x5b,
+    vector_0x5c => 0x5c, vector_0x5d => 0x5d, vector_0x5e => 0x5e, vector_0x5f => 0x5f
+);
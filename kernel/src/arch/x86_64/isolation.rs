@@ -0,0 +1,427 @@
+//! Isolates the kernel page table from user mode.
+//!
+//! If the `kpti` boot option is given, user mode runs on a page table that
+//! only maps the user memory, the entry code and the entry area. Syscalls and
+//! interrupts switch to the full page table before running any other kernel
+//! code and switch back right before returning to user mode.
+//!
+//! The IDT, the GDT and the TSS have to stay accessible in user mode, so every
+//! CPU uses copies of them in the entry area. All vectors of the copied IDT
+//! point to stubs in the entry code, which call the actual handlers once the
+//! kernel page table is loaded. For interrupts from user mode, the handlers
+//! get a frame that returns to the entry code. The original frame directly
+//! follows it on the kernel stack.
+//!
+//! The double fault stack is not mapped in user mode, so double faults there
+//! can't be handled.
+
+use super::gdt::{Gdt, TSS};
+use super::interrupts;
+use super::{get_cpu_id, get_cpu_num};
+use boot;
+use core::mem::size_of;
+use core::ptr;
+use memory::{map_page, user_table_init, PageFlags, VirtualAddress, ENTRY_AREA_BASE, PAGE_SIZE};
+use spin::Once;
+use x86_64::instructions::tables::{lidt, DescriptorTablePointer};
+use x86_64::registers::control_regs;
+use x86_64::structures::idt::ExceptionStackFrame;
+use x86_64::structures::tss::TaskStateSegment;
+
+/// The distance between the entry areas of two CPUs.
+///
+/// The entry areas start one stride after the IDT.
+const ENTRY_AREA_STRIDE: usize = 4 * PAGE_SIZE;
+
+/// The number of pages mapped for the entry area of a CPU.
+const ENTRY_AREA_PAGES: usize = 2;
+
+/// The number of entries in the IDT.
+const IDT_ENTRY_COUNT: usize = 256;
+
+/// Set in IDT entries of trap gates.
+const IDT_TRAP_GATE: u64 = 1 << 40;
+
+/// Set in IDT entries that are present.
+const IDT_PRESENT: u64 = 1 << 47;
+
+/// The bits of the low half of an IDT entry that don't hold the handler.
+const IDT_OPTIONS_MASK: u64 = 0x0000ffffffff0000;
+
+/// Set if the kernel page table is isolated.
+static ENABLED: Once<bool> = Once::new();
+
+/// The handlers the interrupt stubs call, indexed by vector.
+#[no_mangle]
+static mut ISOLATED_INTERRUPT_HANDLERS: [u64; IDT_ENTRY_COUNT] = [0; IDT_ENTRY_COUNT];
+
+/// Non-zero for vectors that don't disable interrupts, indexed by vector.
+#[no_mangle]
+static mut ISOLATED_TRAP_GATES: [u8; IDT_ENTRY_COUNT] = [0; IDT_ENTRY_COUNT];
+
+/// The state a CPU needs to enter and leave the kernel.
+///
+/// NOTE: The entry code depends on the offsets of the first six fields.
+#[repr(C)]
+struct EntryArea {
+    /// The top of the kernel stack of the running thread.
+    kernel_stack_top: VirtualAddress,
+    /// The value of CR3 for the kernel page table.
+    kernel_cr3: u64,
+    /// The value of CR3 for the next switch to the user table.
+    user_cr3: u64,
+    /// The value of CR3 for the user table that keeps its translations.
+    user_cr3_keep: u64,
+    /// The value of CR3 for the user table that flushes its translations.
+    user_cr3_flush: u64,
+    /// The top of the entry stack.
+    entry_stack_top: VirtualAddress,
+    /// The GDT of the CPU.
+    gdt: Gdt,
+    /// The TSS of the CPU.
+    tss: TaskStateSegment,
+    /// The stack interrupts from user mode arrive on.
+    stack: [u8; PAGE_SIZE],
+}
+
+/// Returns the entry area of the given CPU.
+fn get_entry_area(cpu_id: usize) -> &'static mut EntryArea {
+    let address = ENTRY_AREA_BASE + (cpu_id + 1) * ENTRY_AREA_STRIDE;
+
+    unsafe { &mut *(address as *mut EntryArea) }
+}
+
+/// Returns true if the kernel page table is isolated.
+pub fn is_enabled() -> bool {
+    ENABLED.try().map_or(false, |enabled| *enabled)
+}
+
+/// Prepares the isolation of the kernel page table, if it was requested.
+///
+/// This must be called after the IDT is complete and before any process is
+/// created.
+pub fn init() {
+    assert_has_not_been_called!("The kernel page table isolation should only be initialized once.");
+
+    if !boot::has_option("kpti") {
+        ENABLED.call_once(|| false);
+        return;
+    }
+
+    assert!(size_of::<EntryArea>() <= ENTRY_AREA_PAGES * PAGE_SIZE);
+
+    let flags = PageFlags::READABLE | PageFlags::WRITABLE;
+    map_page(ENTRY_AREA_BASE, flags);
+    for cpu_id in 0..get_cpu_num() {
+        let area_address = get_entry_area(cpu_id) as *mut _ as VirtualAddress;
+
+        for i in 0..ENTRY_AREA_PAGES {
+            map_page(area_address + i * PAGE_SIZE, flags);
+        }
+    }
+
+    unsafe {
+        copy_idt();
+        user_table_init();
+    }
+
+    ENABLED.call_once(|| true);
+
+    debugln!("Isolating the kernel page table.");
+}
+
+/// Copies the IDT to the entry area, so that every vector enters a stub.
+///
+/// # Safety
+/// - The entry area must be mapped.
+unsafe fn copy_idt() {
+    let idt = &*(interrupts::get_idt() as *const _ as *const [u64; 2 * IDT_ENTRY_COUNT]);
+    let copy = &mut *(ENTRY_AREA_BASE as *mut [u64; 2 * IDT_ENTRY_COUNT]);
+    let stubs = isolated_interrupt_stubs as u64;
+
+    for vector in 0..IDT_ENTRY_COUNT {
+        let low = idt[2 * vector];
+        let high = idt[2 * vector + 1];
+
+        if low & IDT_PRESENT == 0 {
+            copy[2 * vector] = 0;
+            copy[2 * vector + 1] = 0;
+            continue;
+        }
+
+        ISOLATED_INTERRUPT_HANDLERS[vector] =
+            (low & 0xffff) | ((low >> 32) & 0xffff0000) | (high << 32);
+        ISOLATED_TRAP_GATES[vector] = (low & IDT_TRAP_GATE != 0) as u8;
+
+        // The stubs enable interrupts themselves, after switching the stack.
+        let stub = stubs + 8 * vector as u64;
+        copy[2 * vector] = (low & IDT_OPTIONS_MASK & !IDT_TRAP_GATE) | (stub & 0xffff)
+            | ((stub & 0xffff0000) << 32);
+        copy[2 * vector + 1] = stub >> 32;
+    }
+}
+
+/// Lets the current CPU use its entry area, if the kernel page table is
+/// isolated.
+///
+/// This must be called after the GDT and the IDT were loaded.
+pub fn enable() {
+    if !is_enabled() {
+        return;
+    }
+
+    let area = get_entry_area(get_cpu_id());
+
+    unsafe {
+        // Global pages would stay visible in user mode.
+        let mut cr4 = control_regs::cr4();
+        cr4.remove(control_regs::Cr4::ENABLE_GLOBAL_PAGES);
+        control_regs::cr4_write(cr4);
+
+        let kernel_cr3 = control_regs::cr3().0;
+        let stack_top = (&area.stack as *const _ as VirtualAddress + PAGE_SIZE) & !0xf;
+
+        area.kernel_stack_top = TSS.privilege_stack_table[0].0;
+        area.kernel_cr3 = kernel_cr3;
+        area.user_cr3 = kernel_cr3;
+        area.user_cr3_keep = kernel_cr3;
+        area.user_cr3_flush = kernel_cr3;
+        area.entry_stack_top = stack_top;
+
+        let mut tss = TaskStateSegment::new();
+        tss.privilege_stack_table[0] = ::x86_64::VirtualAddress(stack_top);
+        tss.interrupt_stack_table[0] = TSS.interrupt_stack_table[0];
+        ptr::write(&mut area.tss, tss);
+
+        let tss = &*(&area.tss as *const TaskStateSegment);
+        ptr::write(&mut area.gdt, Gdt::with_tss(tss));
+        (&*(&area.gdt as *const Gdt)).load();
+
+        lidt(&DescriptorTablePointer {
+            limit: (IDT_ENTRY_COUNT * 2 * size_of::<u64>() - 1) as u16,
+            base: ENTRY_AREA_BASE as u64,
+        });
+    }
+}
+
+/// Returns the address of the entry area of the current CPU.
+///
+/// The syscall entry finds it through the kernel GS base.
+pub fn get_entry_area_address() -> VirtualAddress {
+    get_entry_area(get_cpu_id()) as *mut _ as VirtualAddress
+}
+
+/// Returns the frame the interrupt arrived with, given the frame its handler
+/// got.
+///
+/// Handlers of interrupts from user mode get a frame that returns to the entry
+/// code, so diagnostics have to look at the original frame that follows it.
+pub fn original_frame(stack_frame: &ExceptionStackFrame) -> &ExceptionStackFrame {
+    extern "C" {
+        fn isolated_return_to_user();
+    }
+
+    if is_enabled() && stack_frame.instruction_pointer.0 == isolated_return_to_user as usize {
+        // The stack pointer of the entry code frame points to the original one.
+        unsafe { &*(stack_frame.stack_pointer.0 as *const ExceptionStackFrame) }
+    } else {
+        stack_frame
+    }
+}
+
+/// Sets the stack the current CPU enters the kernel on from user mode.
+pub fn set_kernel_stack(stack_top: VirtualAddress) {
+    if is_enabled() {
+        get_entry_area(get_cpu_id()).kernel_stack_top = stack_top;
+    } else {
+        unsafe {
+            TSS.as_mut().privilege_stack_table[0] = ::x86_64::VirtualAddress(stack_top);
+        }
+    }
+}
+
+/// Sets the values of CR3 the current CPU uses when entering and leaving the
+/// kernel.
+///
+/// The first switch to user mode uses `user`, all further ones use `keep`.
+pub fn set_page_table_values(kernel: u64, user: u64, keep: u64, flush: u64) {
+    let area = get_entry_area(get_cpu_id());
+
+    area.kernel_cr3 = kernel;
+    area.user_cr3 = user;
+    area.user_cr3_keep = keep;
+    area.user_cr3_flush = flush;
+}
+
+/// Makes the next switch to user mode on the current CPU flush the user
+/// translations.
+pub fn discard_user_translations() {
+    let area = get_entry_area(get_cpu_id());
+
+    area.user_cr3 = area.user_cr3_flush;
+}
+
+/// The interrupt stubs and the code that enters and leaves the kernel for
+/// interrupts.
+///
+/// There is one stub every eight bytes, in the order of the vectors. On entry,
+/// the stack is brought into the same shape for all vectors. Interrupts from
+/// user mode switch to the kernel page table and copy the frame to the kernel
+/// stack. Then the stack is brought into the shape the handler expects.
+#[naked]
+#[no_mangle]
+#[inline(never)]
+#[link_section = ".entry_text"]
+pub unsafe extern "C" fn isolated_interrupt_stubs() {
+    asm!(".rept 256
+          .align 8
+          call isolated_interrupt_common
+          .endr
+
+          isolated_interrupt_common:
+          push rax
+          push rcx
+
+          // Calculate the vector from the return address of the stub.
+          mov rax, [rsp + 16]
+          lea rcx, [rip + isolated_interrupt_stubs]
+          sub rax, rcx
+          shr rax, 3
+
+          // Only some exceptions push an error code.
+          cmp rax, 32
+          jae isolated_interrupt_without_error_code
+          mov ecx, 0x60227d00
+          bt ecx, eax
+          jnc isolated_interrupt_without_error_code
+
+          // Mark the error code as present.
+          or rax, 0x100
+          mov [rsp + 16], rax
+          jmp isolated_interrupt_from_any_mode
+
+          isolated_interrupt_without_error_code:
+          // Insert an error code of zero.
+          sub rsp, 8
+          mov rcx, [rsp + 8]
+          mov [rsp], rcx
+          mov rcx, [rsp + 16]
+          mov [rsp + 8], rcx
+          mov [rsp + 16], rax
+          mov qword ptr [rsp + 24], 0
+
+          isolated_interrupt_from_any_mode:
+          // The stack holds rcx, rax, the vector, the error code and the frame.
+          test qword ptr [rsp + 40], 3
+          jz isolated_interrupt_dispatch
+
+          // Switch to the kernel page table and find the kernel stack.
+          swapgs
+          mov rax, gs:[8]
+          mov cr3, rax
+          mov rax, gs:[0]
+          swapgs
+
+          // Copy the frame to the kernel stack.
+          mov rcx, [rsp + 32]
+          mov [rax - 40], rcx
+          mov rcx, [rsp + 40]
+          mov [rax - 32], rcx
+          mov rcx, [rsp + 48]
+          mov [rax - 24], rcx
+          mov rcx, [rsp + 56]
+          mov [rax - 16], rcx
+          mov rcx, [rsp + 64]
+          mov [rax - 8], rcx
+
+          // Below it, put a kernel frame that returns through the entry code.
+          lea rcx, [rip + isolated_return_to_user]
+          mov [rax - 80], rcx
+          mov qword ptr [rax - 72], 0x8
+          mov rcx, [rsp + 48]
+          and rcx, -0x40101
+          mov [rax - 64], rcx
+          lea rcx, [rax - 40]
+          mov [rax - 56], rcx
+          mov qword ptr [rax - 48], 0x10
+
+          // Copy the error code, the vector, rax and rcx.
+          mov rcx, [rsp + 24]
+          mov [rax - 88], rcx
+          mov rcx, [rsp + 16]
+          mov [rax - 96], rcx
+          mov rcx, [rsp + 8]
+          mov [rax - 104], rcx
+          mov rcx, [rsp]
+          mov [rax - 112], rcx
+          lea rsp, [rax - 112]
+
+          isolated_interrupt_dispatch:
+          // Trap gates run the handler with the interrupt state of the frame.
+          movzx ecx, byte ptr [rsp + 16]
+          lea rax, [rip + ISOLATED_TRAP_GATES]
+          cmp byte ptr [rax + rcx], 0
+          je isolated_interrupt_call
+          test qword ptr [rsp + 48], 0x200
+          jz isolated_interrupt_call
+          sti
+
+          isolated_interrupt_call:
+          lea rax, [rip + ISOLATED_INTERRUPT_HANDLERS]
+          mov rax, [rax + rcx * 8]
+          test qword ptr [rsp + 16], 0x100
+          jnz isolated_interrupt_call_with_error_code
+
+          // Return to the handler in place of the error code.
+          mov [rsp + 24], rax
+          pop rcx
+          pop rax
+          lea rsp, [rsp + 8]
+          ret
+
+          isolated_interrupt_call_with_error_code:
+          // Return to the handler in place of the vector.
+          mov [rsp + 16], rax
+          pop rcx
+          pop rax
+          ret
+
+          // Returns to user mode with the frame on the stack.
+          .global isolated_return_to_user
+          isolated_return_to_user:
+          cli
+          push rax
+          push rcx
+
+          // Copy the frame to the entry stack.
+          swapgs
+          mov rax, gs:[40]
+          mov rcx, [rsp]
+          mov [rax - 56], rcx
+          mov rcx, [rsp + 8]
+          mov [rax - 48], rcx
+          mov rcx, [rsp + 16]
+          mov [rax - 40], rcx
+          mov rcx, [rsp + 24]
+          mov [rax - 32], rcx
+          mov rcx, [rsp + 32]
+          mov [rax - 24], rcx
+          mov rcx, [rsp + 40]
+          mov [rax - 16], rcx
+          mov rcx, [rsp + 48]
+          mov [rax - 8], rcx
+          lea rsp, [rax - 56]
+
+          // Switch to the user table.
+          mov rax, gs:[16]
+          mov cr3, rax
+          mov rax, gs:[24]
+          mov gs:[16], rax
+          swapgs
+
+          pop rcx
+          pop rax
+          iretq"
+          : : : : "intel", "volatile");
+    unreachable!();
+}
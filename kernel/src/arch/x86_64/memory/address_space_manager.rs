@@ -1,10 +1,12 @@
 //! This is the x86_64 implementation of the `AddressSpaceManager` trait.
 
 use super::PAGE_SIZE;
-use super::paging::{convert_flags, Page, PageFrame, CURRENT_PAGE_TABLE};
+use super::paging::{allocate_contiguous_frames, convert_flags, HugePageSize, MemoryZone, Page,
+                    PageFrame, CURRENT_PAGE_TABLE};
 use super::paging::inactive_page_table::InactivePageTable;
 use super::paging::page_table_entry::*;
 use super::paging::page_table_manager::PageTableManager;
+use super::paging::shootdown::{flush_page_table, FlushBatch};
 use alloc::boxed::Box;
 use core::ptr;
 use memory::{PageFlags, PhysicalAddress, VirtualAddress};
@@ -12,18 +14,38 @@ use memory::address_space;
 
 struct AddressSpaceManager {
     table: InactivePageTable,
+    /// The unmapped pages that weren't invalidated on all CPUs yet.
+    pending: FlushBatch,
+}
+
+impl AddressSpaceManager {
+    /// Creates a manager for the given page table.
+    fn new(table: InactivePageTable) -> AddressSpaceManager {
+        let pending = FlushBatch::new(table.get_frame().get_address());
+
+        AddressSpaceManager { table, pending }
+    }
+}
+
+impl Drop for AddressSpaceManager {
+    fn drop(&mut self) {
+        self.pending.flush();
+
+        // No CPU may cache translations of the page table once it is freed.
+        flush_page_table(self.table.get_frame().get_address());
+    }
 }
 
 pub fn new_address_space_manager() -> Box<address_space::AddressSpaceManager> {
-    Box::new(AddressSpaceManager {
-        table: InactivePageTable::copy_from_current(),
-    })
+    Box::new(AddressSpaceManager::new(
+        InactivePageTable::copy_from_current(),
+    ))
 }
 
 pub fn idle_address_space_manager() -> Box<address_space::AddressSpaceManager> {
-    Box::new(AddressSpaceManager {
-        table: InactivePageTable::from_current_table(),
-    })
+    Box::new(AddressSpaceManager::new(
+        InactivePageTable::from_current_table(),
+    ))
 }
 
 impl address_space::AddressSpaceManager for AddressSpaceManager {
@@ -33,6 +55,8 @@ impl address_space::AddressSpaceManager for AddressSpaceManager {
         let start_page_num = address / PAGE_SIZE;
         let end_page_num = (address + buffer.len() - 1) / PAGE_SIZE + 1;
 
+        let mut batch = FlushBatch::new(self.table.get_frame().get_address());
+
         let mut current_offset = address % PAGE_SIZE;
         let mut current_buffer_position = 0;
 
@@ -86,9 +110,11 @@ impl address_space::AddressSpaceManager for AddressSpaceManager {
 
             // Change to the desired flags.
             entry.set_flags(flags);
+            batch.add(page_address, None);
         }
 
         self.table.unmap();
+        batch.flush();
     }
 
     unsafe fn get_page_table_address(&self) -> PhysicalAddress {
@@ -103,16 +129,103 @@ impl address_space::AddressSpaceManager for AddressSpaceManager {
         self.table.unmap();
     }
 
+    fn map_page_at(
+        &mut self,
+        page_address: VirtualAddress,
+        frame_address: PhysicalAddress,
+        flags: PageFlags,
+    ) {
+        let flags = convert_flags(flags);
+
+        self.table.map_page_at(
+            Page::from_address(page_address),
+            PageFrame::from_address(frame_address),
+            flags,
+        );
+
+        self.table.unmap();
+    }
+
+    fn map_huge_page(&mut self, page_address: VirtualAddress, flags: PageFlags) -> bool {
+        let size = HugePageSize::TwoMiB;
+        let frame_address = match allocate_contiguous_frames(size.get_order(), MemoryZone::Normal) {
+            Some(frame_address) => frame_address,
+            None => return false,
+        };
+
+        // The frames may still hold data of their previous user.
+        for i in 0..size.get_size() / PAGE_SIZE {
+            let frame = PageFrame::from_address(frame_address + i * PAGE_SIZE);
+            CURRENT_PAGE_TABLE.lock().with_temporary_page(&frame, |page| unsafe {
+                ptr::write_bytes(page.get_address() as *mut u8, 0, PAGE_SIZE);
+            });
+        }
+
+        self.table.map_huge_page_at(
+            Page::from_address(page_address),
+            PageFrame::from_address(frame_address),
+            size,
+            convert_flags(flags),
+        );
+
+        self.table.unmap();
+
+        true
+    }
+
+    fn map_huge_page_at(
+        &mut self,
+        page_address: VirtualAddress,
+        frame_address: PhysicalAddress,
+        flags: PageFlags,
+    ) {
+        self.table.map_huge_page_at(
+            Page::from_address(page_address),
+            PageFrame::from_address(frame_address),
+            HugePageSize::TwoMiB,
+            convert_flags(flags),
+        );
+
+        self.table.unmap();
+    }
+
+    unsafe fn unmap_huge_page(&mut self, page_address: VirtualAddress, deallocate: bool) -> bool {
+        let unmapped = self.table.unmap_huge_page_in_batch(
+            Page::from_address(page_address),
+            HugePageSize::TwoMiB,
+            deallocate,
+            &mut self.pending,
+        );
+
+        self.table.unmap();
+
+        unmapped
+    }
+
     unsafe fn unmap_page(&mut self, start_address: VirtualAddress) {
-        self.table.unmap_page(Page::from_address(start_address));
+        self.table
+            .unmap_page_in_batch(Page::from_address(start_address), &mut self.pending);
+
+        self.table.unmap();
+    }
+
+    unsafe fn unmap_page_without_deallocating(&mut self, start_address: VirtualAddress) {
+        self.table.unmap_page_without_deallocating_in_batch(
+            Page::from_address(start_address),
+            &mut self.pending,
+        );
 
         self.table.unmap();
     }
 
     unsafe fn unmap_page_unchecked(&mut self, start_address: VirtualAddress) {
         self.table
-            .unmap_page_unchecked(Page::from_address(start_address));
+            .unmap_page_unchecked_in_batch(Page::from_address(start_address), &mut self.pending);
 
         self.table.unmap();
     }
+
+    fn flush_unmapped(&mut self) {
+        self.pending.flush();
+    }
 }
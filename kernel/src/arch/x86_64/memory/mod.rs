@@ -1,13 +1,17 @@
 //! Handles all x86_64 memory related issues.
 
 use memory::{PageFlags, PhysicalAddress, VirtualAddress};
+use x86_64::structures::idt::ExceptionStackFrame;
 
 mod paging;
 mod address_space_manager;
+mod user_access;
 
 pub use self::address_space_manager::idle_address_space_manager;
 pub use self::address_space_manager::new_address_space_manager;
-pub use self::paging::get_free_memory_size;
+pub use self::user_access::copy_user_memory;
+pub use self::paging::{allocate_contiguous_frames, free_contiguous_frames, get_free_frame_count,
+                       get_free_memory_size, MemoryZone};
 
 /// The maximum address of the lower part of the virtual address space.
 pub const VIRTUAL_LOW_MAX_ADDRESS: VirtualAddress = 0x00007fffffffffff;
@@ -18,14 +22,39 @@ pub const VIRTUAL_HIGH_MIN_ADDRESS: VirtualAddress = 0xffff800000000000;
 /// The top of the stack after the kernel has been remapped.
 pub const FINAL_STACK_TOP: VirtualAddress = 0xfffffe8000000000;
 
+/// The start address of the entry area.
+///
+/// If the kernel page table is isolated, this area stays mapped in user mode.
+/// It holds the IDT followed by the per CPU state needed to enter the kernel.
+pub const ENTRY_AREA_BASE: VirtualAddress = 0xfffffc8000000000;
+
 /// The start address for the double fault stack area.
 pub const DOUBLE_FAULT_STACK_AREA_BASE: VirtualAddress = 0xfffffd0000000000;
 
 /// The distance between two double fault stack tops.
 pub const DOUBLE_FAULT_STACK_OFFSET: usize = 0x2000;
 
-/// The maximum size of a double fault stack.
-pub const DOUBLE_FAULT_STACK_MAX_SIZE: usize = 0x1000;
+/// The maximum size of a double fault stack, including its guard page.
+pub const DOUBLE_FAULT_STACK_MAX_SIZE: usize = 0x2000;
+
+/// The start address for the boot stacks of the application processors.
+pub const AP_STACK_AREA_BASE: VirtualAddress = 0xfffffd4000000000;
+
+/// The distance between two application processor boot stack tops.
+pub const AP_STACK_OFFSET: usize = 0x10000;
+
+/// The size of an application processor boot stack.
+pub const AP_STACK_SIZE: usize = 0x8000;
+
+/// The physical address the application processor trampoline is copied to.
+///
+/// NOTE: This must match TRAMPOLINE_BASE in init/ap_trampoline.asm.
+pub const AP_TRAMPOLINE_ADDRESS: PhysicalAddress = 0x8000;
+
+/// The size of the unmapped area at the bottom of every stack.
+///
+/// Accesses to it are recognized as stack overflows.
+pub const STACK_GUARD_SIZE: usize = PAGE_SIZE;
 
 /// The base address of the kernel stack area.
 pub const KERNEL_STACK_AREA_BASE: VirtualAddress = 0xfffffe0000000000;
@@ -36,7 +65,22 @@ pub const KERNEL_STACK_OFFSET: usize = 0x400000;
 /// The maximum size of a thread kernel stack.
 pub const KERNEL_STACK_MAX_SIZE: usize = 0x200000;
 
-/// The base address of the process stack area.
+/// The base address at which position independent executables are loaded.
+pub const USER_PIE_AREA_BASE: VirtualAddress = 0x0000555500000000;
+
+/// The base address at which program interpreters are loaded.
+pub const USER_INTERPRETER_AREA_BASE: VirtualAddress = 0x00007d0000000000;
+
+/// The base address of the area in which program interpreters load libraries.
+pub const USER_LIBRARY_AREA_BASE: VirtualAddress = 0x00007e0000000000;
+
+/// The size of the range by which user areas are randomly moved up.
+pub const USER_ASLR_RANGE: usize = 0x1000000000;
+
+/// The lowest address the framebuffer is mapped at in processes.
+pub const USER_FRAMEBUFFER_AREA_BASE: VirtualAddress = 0x00007f0000000000;
+
+/// The lowest base address of the process stack area.
 pub const USER_STACK_AREA_BASE: VirtualAddress = 0x00007f8000000000;
 
 /// The offset of the start addresses of thread stacks.
@@ -56,8 +100,13 @@ pub const HEAP_MAX_SIZE: usize = PAGE_SIZE * 512 * 512 * 512;
 /// The size of a single page.
 pub const PAGE_SIZE: usize = 0x1000;
 
+/// The size of the huge pages used for large areas.
+pub const HUGE_PAGE_SIZE: usize = 0x200000;
+
 /// The area where the initramfs will be mapped.
-const INITRAMFS_MAP_AREA_START: VirtualAddress = 0xffff800000000000 + 512 * 512 * 512;
+///
+/// This lies outside of the range `to_virtual!` maps physical memory to.
+const INITRAMFS_MAP_AREA_START: VirtualAddress = 0xfffffc0000000000;
 
 /// The run-time start address of the initramfs.
 static mut INITRAMFS_START: VirtualAddress = 0;
@@ -70,6 +119,10 @@ extern "C" {
     static KERNEL_END: PhysicalAddress;
     /// The start of the .text segment.
     static TEXT_START: PhysicalAddress;
+    /// The start of the code that stays mapped in user mode.
+    static ENTRY_TEXT_START: PhysicalAddress;
+    /// The end of the code that stays mapped in user mode.
+    static ENTRY_TEXT_END: PhysicalAddress;
     /// The start of the .rodata segment.
     static RODATA_START: PhysicalAddress;
     /// The start of the .data segment.
@@ -116,7 +169,7 @@ pub fn memory_init() {
     paging::init(physical_initramfs_start, initramfs_length);
 
     unsafe {
-        INITRAMFS_START = INITRAMFS_MAP_AREA_START + physical_initramfs_start % PAGE_SIZE;
+        INITRAMFS_START = INITRAMFS_MAP_AREA_START + physical_initramfs_start % HUGE_PAGE_SIZE;
         INITRAMFS_LENGTH = initramfs_length;
     }
 }
@@ -141,6 +194,36 @@ pub fn map_page_at(page_address: VirtualAddress, frame_address: PhysicalAddress,
     paging::map_page_at(page_address, frame_address, flags);
 }
 
+/// Maps the given physical range to its kernel virtual address.
+///
+/// Pages that are already mapped to the same frame are left untouched, so
+/// overlapping ranges can be mapped multiple times. Returns the virtual address
+/// of `start`.
+///
+/// # Panics
+/// - If a page in the range is mapped to a different frame.
+pub fn map_physical_range(start: PhysicalAddress, length: usize, flags: PageFlags) -> VirtualAddress {
+    let first_page = start / PAGE_SIZE * PAGE_SIZE;
+    let end = start + length;
+
+    let mut physical_address = first_page;
+    while physical_address < end {
+        let virtual_address = to_virtual!(physical_address);
+
+        match paging::translate_address(virtual_address) {
+            Some(mapped_address) => assert_eq!(
+                mapped_address, physical_address,
+                "The physical range at {:x} conflicts with an existing mapping.", start
+            ),
+            None => map_page_at(virtual_address, physical_address, flags),
+        }
+
+        physical_address += PAGE_SIZE;
+    }
+
+    to_virtual!(start)
+}
+
 /// Returns the flags of the given page.
 pub fn get_page_flags(page_address: VirtualAddress) -> PageFlags {
     paging::get_page_flags(page_address)
@@ -154,6 +237,69 @@ pub unsafe fn unmap_page(start_address: VirtualAddress) {
     paging::unmap_page(start_address);
 }
 
+/// Unmaps the given page without freeing the frame it was mapped to.
+///
+/// # Safety
+/// - Make sure that nothing references that page anymore.
+pub unsafe fn unmap_page_without_deallocating(start_address: VirtualAddress) {
+    paging::unmap_page_without_deallocating(start_address);
+}
+
+/// Returns the physical address of the currently active page table.
+pub fn get_current_page_table_address() -> PhysicalAddress {
+    paging::shootdown::get_current_page_table_address()
+}
+
+/// Returns the value to load into CR3 to switch to the given page table.
+///
+/// # Safety
+/// - The returned value must be loaded into CR3 before preemption is enabled.
+pub unsafe fn prepare_page_table_switch(page_table: PhysicalAddress) -> u64 {
+    paging::shootdown::prepare_switch(page_table)
+}
+
+/// Handles a TLB shootdown request of another CPU, if there is one.
+pub fn handle_tlb_shootdown() {
+    paging::shootdown::handle_pending();
+}
+
+/// Initializes the TLB shootdown for the current CPU.
+///
+/// The bootstrap processor has to call this before any other CPU does.
+pub fn tlb_init() {
+    paging::shootdown::init();
+}
+
+/// Initializes the TLB shootdown for the current application processor.
+pub fn tlb_init_ap() {
+    paging::shootdown::init_ap();
+}
+
+/// Lets new address spaces get a page table for user mode, that only maps the
+/// entry code and the entry area of the kernel.
+///
+/// # Safety
+/// - Should only be called once, after the entry area is mapped.
+pub unsafe fn user_table_init() {
+    paging::init_user_tables();
+}
+
+/// Enables the protection of user pages from the kernel on the current CPU.
+pub fn user_access_init() {
+    user_access::enable_protection();
+}
+
+/// Lets a failed access to user memory return an error, if the page fault was
+/// caused by one.
+///
+/// Returns true if the fault was handled.
+pub fn handle_user_access_fault(
+    address: VirtualAddress,
+    stack_frame: &mut ExceptionStackFrame,
+) -> bool {
+    user_access::handle_fault(address, stack_frame)
+}
+
 /// Checks if the address is a kernel or a userspace address.
 pub fn is_userspace_address(address: VirtualAddress) -> bool {
     address <= VIRTUAL_LOW_MAX_ADDRESS
@@ -5,6 +5,7 @@ use super::inactive_page_table::InactivePageTable;
 use super::page_table::{Level1, Level4, PageTable};
 use super::page_table_entry::*;
 use super::page_table_manager::PageTableManager;
+use super::shootdown::get_current_page_table_address;
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
 use core::ptr;
@@ -101,6 +102,10 @@ impl PageTableManager for CurrentPageTable {
     fn get_l4(&mut self) -> &mut PageTable<Level4> {
         unsafe { self.l4_table.as_mut() }
     }
+
+    fn get_l4_address(&self) -> PhysicalAddress {
+        get_current_page_table_address()
+    }
 }
 
 impl CurrentPageTable {
@@ -132,6 +137,10 @@ impl CurrentPageTable {
                         | PageTableEntryFlags::NO_EXECUTE,
                 )
                 .set_address(frame.get_address());
+
+            // This CPU may still cache translations of a table that was mapped
+            // here before, possibly by another CPU.
+            tlb::flush_all();
         }
 
         preemption_state
@@ -169,8 +178,11 @@ impl CurrentPageTable {
 
         let virtual_address = TEMPORARY_ADDRESS_BASE + (index << 12);
 
+        // Another CPU may have changed the entry since this CPU last used it,
+        // so the local translation can be stale either way.
+        tlb::flush(::x86_64::VirtualAddress(virtual_address));
+
         if entry.points_to() != Some(frame.get_address()) {
-            tlb::flush(::x86_64::VirtualAddress(virtual_address));
             entry.set_address(frame.get_address());
             entry.set_flags(
                 PageTableEntryFlags::PRESENT | PageTableEntryFlags::WRITABLE
@@ -217,7 +229,7 @@ impl CurrentPageTable {
     /// The old page table will not be mapped into the new one. This should be
     /// done manually.
     pub unsafe fn switch(&mut self, new_table: InactivePageTable) -> InactivePageTable {
-        let old_frame = PageFrame::from_address(control_regs::cr3().0 as PhysicalAddress);
+        let old_frame = PageFrame::from_address(get_current_page_table_address());
         let old_table = InactivePageTable::from_frame(old_frame.copy(), &new_table);
 
         let new_frame = new_table.get_frame();
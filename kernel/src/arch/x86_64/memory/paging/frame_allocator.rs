@@ -1,64 +1,303 @@
 //! Handles the allocation of physical page frames.
+//!
+//! Each memory zone is managed by a buddy allocator. A block of order `n`
+//! consists of `2^n` contiguous frames and is aligned to its size. Free blocks
+//! are linked through their first bytes, while a bitmap per order records
+//! which blocks are free, so that freed blocks can be merged with their
+//! buddies.
 
 use super::{PageFrame, PAGE_SIZE};
-use super::free_list::{FreeListIterator, FREE_LIST};
-use core::cell::Cell;
-use memory::{oom, FreeMemoryArea};
+use super::current_page_table::CURRENT_PAGE_TABLE;
+use boot;
+use core::cmp::{max, min};
+use core::ptr;
+use memory::{oom, FreeMemoryArea, PhysicalAddress};
+use sync::PreemptableMutex;
 
-/// Used to allocate page frames.
-pub struct FrameAllocator {
-    free_frames: Cell<usize>,
-}
+/// The number of block orders.
+pub const ORDER_COUNT: usize = 11;
 
-// It is save to implement sync, because access is restricted by the lock on
-// the free list. Should this change, this needs to be removed.
-unsafe impl Sync for FrameAllocator {}
+/// The size of the largest block.
+const MAX_BLOCK_SIZE: usize = PAGE_SIZE << (ORDER_COUNT - 1);
+
+/// The number of memory zones.
+const ZONE_COUNT: usize = 3;
 
 /// The page frame allocator of the kernel.
-lazy_static! {
-    /// The frame allocator used by the kernel.
-    pub static ref FRAME_ALLOCATOR: FrameAllocator = FrameAllocator {
-        free_frames: {
-            let mut number = 0;
-
-            for entry in FreeListIterator::new() {
-                number += entry.length / PAGE_SIZE;
+pub static FRAME_ALLOCATOR: FrameAllocator = FrameAllocator {
+    zones: PreemptableMutex::new([None, None, None]),
+};
+
+/// The zones physical memory is divided into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryZone {
+    /// The memory below 1 MiB.
+    Low,
+    /// The memory below 4 GiB, which 32-bit DMA can reach.
+    Dma32,
+    /// The memory above 4 GiB.
+    Normal,
+}
+
+impl MemoryZone {
+    /// Returns the zone with the given index.
+    fn from_index(index: usize) -> MemoryZone {
+        match index {
+            0 => MemoryZone::Low,
+            1 => MemoryZone::Dma32,
+            2 => MemoryZone::Normal,
+            _ => panic!("There is no memory zone with the index {}.", index),
+        }
+    }
+
+    /// Returns the zone the given address belongs to.
+    fn containing(address: PhysicalAddress) -> MemoryZone {
+        if address < MemoryZone::Dma32.start_address() {
+            MemoryZone::Low
+        } else if address < MemoryZone::Normal.start_address() {
+            MemoryZone::Dma32
+        } else {
+            MemoryZone::Normal
+        }
+    }
+
+    /// Returns the first address of the zone.
+    fn start_address(&self) -> PhysicalAddress {
+        match *self {
+            MemoryZone::Low => 0,
+            MemoryZone::Dma32 => 0x100000,
+            MemoryZone::Normal => 0x100000000,
+        }
+    }
+
+    /// Returns the first address after the zone.
+    fn end_address(&self) -> PhysicalAddress {
+        match *self {
+            MemoryZone::Low => MemoryZone::Dma32.start_address(),
+            MemoryZone::Dma32 => MemoryZone::Normal.start_address(),
+            MemoryZone::Normal => <usize>::max_value(),
+        }
+    }
+}
+
+/// The links stored at the start of a free block.
+#[derive(Clone, Copy)]
+struct FreeBlock {
+    /// The previous free block of the same order.
+    previous: Option<PhysicalAddress>,
+    /// The next free block of the same order.
+    next: Option<PhysicalAddress>,
+}
+
+/// The buddy allocator of a single zone.
+struct ZoneAllocator {
+    /// The first address covered by the bitmaps, aligned to the largest block.
+    base_address: PhysicalAddress,
+    /// The number of largest blocks covered by the bitmaps.
+    max_block_count: usize,
+    /// The physical address of the bitmaps.
+    bitmap_address: PhysicalAddress,
+    /// The first free block of each order.
+    free_lists: [Option<PhysicalAddress>; ORDER_COUNT],
+    /// The number of free frames in the zone.
+    free_frame_count: usize,
+}
+
+impl ZoneAllocator {
+    /// Creates an allocator without free blocks for the given address range.
+    fn new(
+        start_address: PhysicalAddress,
+        end_address: PhysicalAddress,
+        bitmap_address: PhysicalAddress,
+    ) -> ZoneAllocator {
+        let base_address = start_address / MAX_BLOCK_SIZE * MAX_BLOCK_SIZE;
+
+        ZoneAllocator {
+            base_address,
+            max_block_count: (end_address - base_address - 1) / MAX_BLOCK_SIZE + 1,
+            bitmap_address,
+            free_lists: [None; ORDER_COUNT],
+            free_frame_count: 0,
+        }
+    }
+
+    /// Returns the size of the bitmaps for the given address range in bytes.
+    fn get_bitmap_size(start_address: PhysicalAddress, end_address: PhysicalAddress) -> usize {
+        let base_address = start_address / MAX_BLOCK_SIZE * MAX_BLOCK_SIZE;
+        let max_block_count = (end_address - base_address - 1) / MAX_BLOCK_SIZE + 1;
+        let bit_count = max_block_count * ((1 << ORDER_COUNT) - 1);
+
+        (bit_count - 1) / 64 * 8 + 8
+    }
+
+    /// Returns the index of the bit for the block in the bitmaps.
+    fn get_bit_index(&self, address: PhysicalAddress, order: usize) -> usize {
+        let mut bitmap_start = 0;
+        for lower_order in 0..order {
+            bitmap_start += self.max_block_count << (ORDER_COUNT - 1 - lower_order);
+        }
+
+        bitmap_start + (address - self.base_address) / (PAGE_SIZE << order)
+    }
+
+    /// Checks if the block is on the free list of the order.
+    fn is_free(&self, address: PhysicalAddress, order: usize) -> bool {
+        let bit_index = self.get_bit_index(address, order);
+        let word: u64 = CURRENT_PAGE_TABLE
+            .lock()
+            .read_from_physical(self.bitmap_address + bit_index / 64 * 8);
+
+        word & (1 << (bit_index % 64)) != 0
+    }
+
+    /// Records whether the block is on the free list of the order.
+    fn set_free(&mut self, address: PhysicalAddress, order: usize, free: bool) {
+        let bit_index = self.get_bit_index(address, order);
+        let word_address = self.bitmap_address + bit_index / 64 * 8;
+
+        let mut current_page_table = CURRENT_PAGE_TABLE.lock();
+        let mut word: u64 = current_page_table.read_from_physical(word_address);
+        if free {
+            word |= 1 << (bit_index % 64);
+        } else {
+            word &= !(1 << (bit_index % 64));
+        }
+        current_page_table.write_at_physical(word_address, word);
+    }
+
+    /// Adds the block to the free list of the order.
+    fn push(&mut self, address: PhysicalAddress, order: usize) {
+        let mut current_page_table = CURRENT_PAGE_TABLE.lock();
+        let next = self.free_lists[order];
+
+        if let Some(next) = next {
+            let mut next_block: FreeBlock = current_page_table.read_from_physical(next);
+            next_block.previous = Some(address);
+            current_page_table.write_at_physical(next, next_block);
+        }
+        current_page_table.write_at_physical(
+            address,
+            FreeBlock {
+                previous: None,
+                next,
+            },
+        );
+        drop(current_page_table);
+
+        self.free_lists[order] = Some(address);
+        self.set_free(address, order, true);
+        self.free_frame_count += 1 << order;
+    }
+
+    /// Removes the block from the free list of the order.
+    fn remove(&mut self, address: PhysicalAddress, order: usize) {
+        let mut current_page_table = CURRENT_PAGE_TABLE.lock();
+        let block: FreeBlock = current_page_table.read_from_physical(address);
+
+        match block.previous {
+            Some(previous) => {
+                let mut previous_block: FreeBlock = current_page_table.read_from_physical(previous);
+                previous_block.next = block.next;
+                current_page_table.write_at_physical(previous, previous_block);
+            }
+            None => self.free_lists[order] = block.next,
+        }
+        if let Some(next) = block.next {
+            let mut next_block: FreeBlock = current_page_table.read_from_physical(next);
+            next_block.previous = block.previous;
+            current_page_table.write_at_physical(next, next_block);
+        }
+        drop(current_page_table);
+
+        self.set_free(address, order, false);
+        self.free_frame_count -= 1 << order;
+    }
+
+    /// Allocates a block of the given order, splitting a larger one if needed.
+    fn allocate(&mut self, order: usize) -> Option<PhysicalAddress> {
+        let free_order = (order..ORDER_COUNT).find(|&order| self.free_lists[order].is_some())?;
+        let address = self.free_lists[free_order].unwrap();
+        self.remove(address, free_order);
+
+        // Free the upper halves of the split blocks.
+        for split_order in (order..free_order).rev() {
+            self.push(address + (PAGE_SIZE << split_order), split_order);
+        }
+
+        Some(address)
+    }
+
+    /// Frees the block of the given order, merging it with its free buddies.
+    fn free(&mut self, address: PhysicalAddress, order: usize) {
+        let mut address = address;
+        let mut order = order;
+
+        while order < ORDER_COUNT - 1 {
+            let buddy = address ^ (PAGE_SIZE << order);
+            if !self.is_free(buddy, order) {
+                break;
             }
 
-            Cell::new(number)
+            self.remove(buddy, order);
+            address &= !(PAGE_SIZE << order);
+            order += 1;
         }
-    };
+
+        self.push(address, order);
+    }
+
+    /// Frees the page aligned address range as blocks as large as possible.
+    fn free_range(&mut self, start_address: PhysicalAddress, end_address: PhysicalAddress) {
+        let mut address = start_address;
+
+        while address < end_address {
+            let mut order = ORDER_COUNT - 1;
+            while order > 0
+                && (address % (PAGE_SIZE << order) != 0
+                    || address + (PAGE_SIZE << order) > end_address)
+            {
+                order -= 1;
+            }
+
+            self.free(address, order);
+            address += PAGE_SIZE << order;
+        }
+    }
+}
+
+/// Used to allocate page frames.
+pub struct FrameAllocator {
+    /// The allocators of the zones that contain usable memory.
+    zones: PreemptableMutex<[Option<ZoneAllocator>; ZONE_COUNT]>,
 }
 
 impl FrameAllocator {
     /// Allocates a page frame.
     pub fn allocate(&self) -> PageFrame {
-        // NOTE: The lock on the list also locks the allocator, should the inner
-        // workings of the allocator be changed, then there will also need to be a
-        // locking mechanism.
-        let list = FREE_LIST.lock();
-        let mut iterator = FreeListIterator::from_guard(list);
-        let free_area = iterator.next();
-        let mut list = iterator.finish();
-
-        if !free_area.is_none() {
-            let free_area = free_area.unwrap();
-            let page_frame = PageFrame::from_address(free_area.start_address);
-
-            let new_free_area = free_area.without_first_frame();
-
-            list.remove(free_area);
-            unsafe {
-                if new_free_area.length > 0 {
-                    list.insert(new_free_area);
+        match self.allocate_contiguous(0, MemoryZone::Normal) {
+            Some(frame) => frame,
+            None => oom(),
+        }
+    }
+
+    /// Allocates `2^order` contiguous page frames in the given zone or a lower
+    /// one.
+    ///
+    /// The returned frame is the first one and is aligned to the total size.
+    pub fn allocate_contiguous(&self, order: usize, zone: MemoryZone) -> Option<PageFrame> {
+        assert!(order < ORDER_COUNT, "The order {} is too large.", order);
+
+        let mut zones = self.zones.lock();
+
+        for zone_index in (0..zone as usize + 1).rev() {
+            if let Some(ref mut zone_allocator) = zones[zone_index] {
+                if let Some(address) = zone_allocator.allocate(order) {
+                    return Some(PageFrame::from_address(address));
                 }
             }
-            self.free_frames.set(self.free_frames.get() - 1);
-
-            page_frame
-        } else {
-            oom();
         }
+
+        None
     }
 
     /// Deallocates the page frame.
@@ -66,16 +305,199 @@ impl FrameAllocator {
     /// # Safety
     /// - Must not be called on page frames still in use.
     pub unsafe fn deallocate(&self, frame: PageFrame) {
-        // NOTE: The lock on the list also locks the allocator, should the inner
-        // workings of the allocator be changed, then there will also need to be a
-        // locking mechanism.
-        let mut list = FREE_LIST.lock();
-        self.free_frames.set(self.free_frames.get() + 1);
-        list.insert(FreeMemoryArea::new(frame.get_address(), PAGE_SIZE));
+        self.deallocate_contiguous(frame, 0);
+    }
+
+    /// Deallocates the `2^order` contiguous page frames starting at the frame.
+    ///
+    /// Orders beyond the largest block are freed as several blocks.
+    ///
+    /// # Safety
+    /// - The frames must have been allocated with the same order.
+    /// - Must not be called on page frames still in use.
+    pub unsafe fn deallocate_contiguous(&self, frame: PageFrame, order: usize) {
+        let address = frame.get_address();
+        let zone = MemoryZone::containing(address);
+
+        let mut zones = self.zones.lock();
+        let zone = zones[zone as usize]
+            .as_mut()
+            .expect("Freeing a frame outside of the usable memory.");
+
+        if order < ORDER_COUNT {
+            zone.free(address, order);
+        } else {
+            zone.free_range(address, address + (PAGE_SIZE << order));
+        }
     }
 
     /// Returns the current number of free frames.
     pub fn get_free_frame_num(&self) -> usize {
-        self.free_frames.get()
+        self.zones
+            .lock()
+            .iter()
+            .map(|zone| zone.as_ref().map_or(0, |zone| zone.free_frame_count))
+            .sum()
+    }
+
+    /// Returns the current number of free frames in the given zone.
+    pub fn get_free_frame_num_in(&self, zone: MemoryZone) -> usize {
+        self.zones.lock()[zone as usize]
+            .as_ref()
+            .map_or(0, |zone| zone.free_frame_count)
+    }
+}
+
+/// Returns the page aligned part of the memory area that lies within the zone.
+fn clip_to_zone(
+    start_address: PhysicalAddress,
+    end_address: PhysicalAddress,
+    zone: MemoryZone,
+) -> Option<(PhysicalAddress, PhysicalAddress)> {
+    let start_address = (start_address + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+    let end_address = end_address / PAGE_SIZE * PAGE_SIZE;
+
+    let start_address = max(start_address, zone.start_address());
+    let end_address = min(end_address, zone.end_address());
+
+    if start_address < end_address {
+        Some((start_address, end_address))
+    } else {
+        None
+    }
+}
+
+/// Initializes the frame allocator with the memory map.
+pub fn init() {
+    assert_has_not_been_called!("The frame allocator should only be initialized once.");
+
+    // Find the address range of each zone that needs to be managed. It starts
+    // at the zone start, so that frames outside of the memory map, like the
+    // boot page tables, can be freed later.
+    let mut zone_ranges: [Option<(PhysicalAddress, PhysicalAddress)>; ZONE_COUNT] =
+        [None; ZONE_COUNT];
+    for area in boot::get_memory_map() {
+        for zone_index in 0..ZONE_COUNT {
+            let zone = MemoryZone::from_index(zone_index);
+
+            let range = clip_to_zone(area.start_address(), area.end_address(), zone);
+
+            if let Some((_, end)) = range {
+                zone_ranges[zone_index] = Some(match zone_ranges[zone_index] {
+                    Some((zone_start, zone_end)) => (zone_start, max(zone_end, end)),
+                    None => (zone.start_address(), end),
+                });
+            }
+        }
+    }
+
+    let mut bitmap_sizes = [0; ZONE_COUNT];
+    for zone_index in 0..ZONE_COUNT {
+        if let Some((start, end)) = zone_ranges[zone_index] {
+            bitmap_sizes[zone_index] = ZoneAllocator::get_bitmap_size(start, end);
+        }
+    }
+    let bitmaps_length =
+        (bitmap_sizes.iter().sum::<usize>() - 1) / PAGE_SIZE * PAGE_SIZE + PAGE_SIZE;
+
+    // Take the bitmaps from the first area that fits, preferably above the low
+    // memory, which is scarce.
+    let get_bitmaps_start = |area: FreeMemoryArea| {
+        let start = (area.start_address() + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+
+        if start + bitmaps_length <= area.end_address() {
+            Some(start)
+        } else {
+            None
+        }
+    };
+    let bitmaps_start = boot::get_memory_map()
+        .filter_map(&get_bitmaps_start)
+        .find(|&start| start >= MemoryZone::Dma32.start_address())
+        .or_else(|| boot::get_memory_map().filter_map(&get_bitmaps_start).next())
+        .expect("There is no memory for the frame allocator.");
+    let bitmaps_end = bitmaps_start + bitmaps_length;
+
+    {
+        let mut current_page_table = CURRENT_PAGE_TABLE.lock();
+
+        for page_num in 0..bitmaps_length / PAGE_SIZE {
+            let frame = PageFrame::from_address(bitmaps_start + page_num * PAGE_SIZE);
+
+            current_page_table.with_temporary_page(&frame, |page| unsafe {
+                ptr::write_bytes(page.get_address() as *mut u8, 0, PAGE_SIZE);
+            });
+        }
+    }
+
+    let mut zones = FRAME_ALLOCATOR.zones.lock();
+
+    let mut bitmap_address = bitmaps_start;
+    for zone_index in 0..ZONE_COUNT {
+        if let Some((start, end)) = zone_ranges[zone_index] {
+            zones[zone_index] = Some(ZoneAllocator::new(start, end, bitmap_address));
+            bitmap_address += bitmap_sizes[zone_index];
+        }
+    }
+
+    for area in boot::get_memory_map() {
+        for zone_index in 0..ZONE_COUNT {
+            let zone = MemoryZone::from_index(zone_index);
+
+            let range = clip_to_zone(area.start_address(), area.end_address(), zone);
+
+            if let Some((start, end)) = range {
+                let zone_allocator = zones[zone_index].as_mut().unwrap();
+
+                // Leave out the bitmaps.
+                if start < bitmaps_start {
+                    zone_allocator.free_range(start, min(end, bitmaps_start));
+                }
+                if bitmaps_end < end {
+                    zone_allocator.free_range(max(start, bitmaps_end), end);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that memory areas are split along the zone boundaries.
+    #[test]
+    fn test_clip_to_zone() {
+        let start = 0x9f000;
+        let end = 0x100001000;
+
+        assert_eq!(clip_to_zone(start, end, MemoryZone::Low), Some((0x9f000, 0x100000)));
+        assert_eq!(
+            clip_to_zone(start, end, MemoryZone::Dma32),
+            Some((0x100000, 0x100000000))
+        );
+        assert_eq!(
+            clip_to_zone(start, end, MemoryZone::Normal),
+            Some((0x100000000, 0x100001000))
+        );
+        assert_eq!(clip_to_zone(0x1800, 0x2800, MemoryZone::Low), None);
+    }
+
+    /// Tests that the bitmaps of the orders don't overlap.
+    #[test]
+    fn test_bit_indices() {
+        let zone = ZoneAllocator::new(0x100000, 0x1000000, 0);
+        let last_block = 0x1000000 - MAX_BLOCK_SIZE;
+
+        for order in 0..ORDER_COUNT - 1 {
+            let last_address = last_block + MAX_BLOCK_SIZE - (PAGE_SIZE << order);
+            let last_index = zone.get_bit_index(last_address, order);
+
+            assert_eq!(last_index + 1, zone.get_bit_index(0, order + 1));
+        }
+        assert!(
+            zone.get_bit_index(last_block, ORDER_COUNT - 1)
+                < ZoneAllocator::get_bitmap_size(0x100000, 0x1000000) * 8
+        );
     }
 }
@@ -1,19 +1,32 @@
 //! Handles the managment of an inactive page table.
 
-use super::PageFrame;
+use super::{Page, PageFrame};
 use super::current_page_table::CURRENT_PAGE_TABLE;
-use super::frame_allocator::FRAME_ALLOCATOR;
+use super::frame_allocator::{MemoryZone, FRAME_ALLOCATOR};
 use super::page_table::{Level4, PageTable};
 use super::page_table_entry::*;
 use super::page_table_manager::PageTableManager;
+use super::shootdown::get_current_page_table_address;
 use super::super::TEMPORARY_MAP_TABLE;
 use core::ptr::Unique;
+use memory::{oom, PhysicalAddress, VirtualAddress, PAGE_SIZE};
+use spin::Once;
 use sync::PreemptionState;
-use x86_64::registers::control_regs::cr3;
 
 /// The reference to the place where the level 4 table will be mapped.
 const L4_TABLE: *mut PageTable<Level4> = 0xffffffffffffd000 as *mut PageTable<Level4>;
 
+/// The level 4 entry that maps the entry code in user tables.
+const ENTRY_TEXT_L4_INDEX: usize = 256;
+
+/// The level 4 entry that maps the entry area.
+const ENTRY_AREA_L4_INDEX: usize = 505;
+
+/// The upper half entries of the user tables, as (index, entry).
+///
+/// This is only initialized if the kernel page table is isolated.
+static USER_TABLE_ENTRIES: Once<[(usize, PageTableEntry); 2]> = Once::new();
+
 /// RePageTableEntryFlags::PRESENTs a currently inactive page table that needs to be modified.
 pub struct InactivePageTable {
     /// A reference to the level 4 table.
@@ -32,6 +45,10 @@ impl PageTableManager for InactivePageTable {
             self.l4_table.as_mut()
         }
     }
+
+    fn get_l4_address(&self) -> PhysicalAddress {
+        self.l4_frame.get_address()
+    }
 }
 
 impl Drop for InactivePageTable {
@@ -76,8 +93,33 @@ impl InactivePageTable {
 
     /// Creates a copy of the current page table kernel part as an inactive
     /// page table.
+    ///
+    /// If the kernel page table is isolated, the table is followed by its user
+    /// table.
     pub fn copy_from_current() -> InactivePageTable {
-        let frame = FRAME_ALLOCATOR.allocate();
+        let frame = match USER_TABLE_ENTRIES.try() {
+            Some(_) => FRAME_ALLOCATOR
+                .allocate_contiguous(1, MemoryZone::Normal)
+                .unwrap_or_else(|| oom()),
+            None => FRAME_ALLOCATOR.allocate(),
+        };
+
+        if let Some(entries) = USER_TABLE_ENTRIES.try() {
+            let user_frame = PageFrame::from_address(frame.get_address() + PAGE_SIZE);
+
+            CURRENT_PAGE_TABLE
+                .lock()
+                .with_temporary_page(&user_frame, |page| {
+                    let user_table =
+                        unsafe { &mut *(page.get_address() as *mut PageTable<Level4>) };
+                    user_table.zero();
+
+                    for &(index, ref entry) in entries.iter() {
+                        user_table[index] = entry.clone();
+                    }
+                });
+        }
+
         let preemption_state = unsafe { CURRENT_PAGE_TABLE.lock().map_inactive(&frame) };
 
         let table = unsafe { &mut *L4_TABLE };
@@ -85,6 +127,7 @@ impl InactivePageTable {
 
         table[256] = CURRENT_PAGE_TABLE.lock().get_l4()[256].clone();
         table[257] = CURRENT_PAGE_TABLE.lock().get_l4()[257].clone();
+        table[505] = CURRENT_PAGE_TABLE.lock().get_l4()[505].clone();
         table[506] = CURRENT_PAGE_TABLE.lock().get_l4()[506].clone();
         table[507] = CURRENT_PAGE_TABLE.lock().get_l4()[507].clone();
 
@@ -99,6 +142,11 @@ impl InactivePageTable {
                 | PageTableEntryFlags::NO_EXECUTE,
         );
 
+        if USER_TABLE_ENTRIES.try().is_some() {
+            let flags = table[511].flags() | PageTableEntryFlags::HAS_USER_TABLE;
+            table[511].set_flags(flags);
+        }
+
         CURRENT_PAGE_TABLE.lock().unmap_inactive(&preemption_state);
 
         InactivePageTable {
@@ -130,7 +178,7 @@ impl InactivePageTable {
     pub fn from_current_table() -> InactivePageTable {
         InactivePageTable {
             l4_table: unsafe { Unique::new_unchecked(L4_TABLE) },
-            l4_frame: PageFrame::from_address(cr3().0 as usize),
+            l4_frame: PageFrame::from_address(get_current_page_table_address()),
             preemption_state: None,
         }
     }
@@ -151,6 +199,50 @@ impl InactivePageTable {
         unsafe { self.l4_frame.copy() }
     }
 
+    /// Prepares the upper half of the user tables.
+    ///
+    /// The user tables only map the entry code and the entry area, which must
+    /// already be mapped in the current page table.
+    ///
+    /// # Safety
+    /// - Should only be called once during kernel setup.
+    pub unsafe fn init_user_tables(
+        entry_text_start: VirtualAddress,
+        entry_text_end: VirtualAddress,
+    ) {
+        let mut template = InactivePageTable::new();
+
+        let mut address = entry_text_start;
+        while address < entry_text_end {
+            template.map_page_at(
+                Page::from_address(address),
+                PageFrame::from_address(to_physical!(address)),
+                PageTableEntryFlags::PRESENT,
+            );
+            address += PAGE_SIZE;
+        }
+
+        let entry_text = template.get_l4()[ENTRY_TEXT_L4_INDEX].clone();
+        let template_frame = template.get_frame();
+        drop(template);
+
+        // Only the lower tables are still referenced.
+        FRAME_ALLOCATOR.deallocate(template_frame);
+
+        let entry_area = CURRENT_PAGE_TABLE.lock().get_l4()[ENTRY_AREA_L4_INDEX].clone();
+        assert!(
+            entry_area.flags().contains(PageTableEntryFlags::PRESENT),
+            "The entry area must be mapped before the user tables are prepared."
+        );
+
+        USER_TABLE_ENTRIES.call_once(|| {
+            [
+                (ENTRY_TEXT_L4_INDEX, entry_text),
+                (ENTRY_AREA_L4_INDEX, entry_area),
+            ]
+        });
+    }
+
     /// Unmaps the currently loaded inactive page table.
     pub fn unmap(&mut self) {
         if !self.preemption_state.is_none() {
@@ -3,11 +3,12 @@ mod page_table;
 pub mod page_table_entry;
 mod current_page_table;
 pub mod inactive_page_table;
-mod free_list;
 mod frame_allocator;
 pub mod page_table_manager;
+pub mod shootdown;
 
 pub use self::current_page_table::CURRENT_PAGE_TABLE;
+pub use self::frame_allocator::MemoryZone;
 use self::frame_allocator::FRAME_ALLOCATOR;
 use self::page_table_entry::*;
 use self::page_table_manager::PageTableManager;
@@ -16,12 +17,23 @@ use core::fmt;
 use memory;
 use memory::{PageFlags, PhysicalAddress, VirtualAddress};
 use boot;
+use raw_cpuid::CpuId;
+use spin::Once;
+
+/// Set if the CPU supports 1 GiB pages. Checked on first use.
+static ONE_GIB_PAGES_SUPPORTED: Once<bool> = Once::new();
 
 /// Initializes the paging.
 pub fn init(initramfs_start: PhysicalAddress, initramfs_length: usize) {
     assert_has_not_been_called!("The x86_64 paging module should only be initialized once.");
 
-    free_list::init();
+    frame_allocator::init();
+    debugln!(
+        "Free frames: {} low, {} DMA32, {} normal",
+        FRAME_ALLOCATOR.get_free_frame_num_in(MemoryZone::Low),
+        FRAME_ALLOCATOR.get_free_frame_num_in(MemoryZone::Dma32),
+        FRAME_ALLOCATOR.get_free_frame_num_in(MemoryZone::Normal)
+    );
 
     unsafe { remap_kernel() };
 
@@ -56,9 +68,7 @@ pub fn get_page_flags(page_address: VirtualAddress) -> PageFlags {
     let mut flags = PageFlags::empty();
     let mut table = CURRENT_PAGE_TABLE.lock();
 
-    if let Some(entry) = table.get_entry(Page::from_address(page_address).get_address()) {
-        let entry_flags = entry.flags();
-
+    if let Some((_, entry_flags, _)) = table.get_mapping(page_address) {
         if entry_flags.contains(PageTableEntryFlags::PRESENT) {
             flags |= PageFlags::PRESENT;
         }
@@ -83,11 +93,41 @@ pub fn get_page_flags(page_address: VirtualAddress) -> PageFlags {
     flags
 }
 
+/// Returns the physical address the given virtual address is mapped to.
+pub fn translate_address(address: VirtualAddress) -> Option<PhysicalAddress> {
+    let mut table = CURRENT_PAGE_TABLE.lock();
+
+    table
+        .get_mapping(address)
+        .map(|(frame_address, _, size)| frame_address + address % size)
+}
+
 /// Returns the size of unused physical memory.
 pub fn get_free_memory_size() -> usize {
     FRAME_ALLOCATOR.get_free_frame_num() * PAGE_SIZE
 }
 
+/// Returns the number of unused frames in the given zone.
+pub fn get_free_frame_count(zone: MemoryZone) -> usize {
+    FRAME_ALLOCATOR.get_free_frame_num_in(zone)
+}
+
+/// Allocates `2^order` physically contiguous frames in the zone or a lower one.
+pub fn allocate_contiguous_frames(order: usize, zone: MemoryZone) -> Option<PhysicalAddress> {
+    FRAME_ALLOCATOR
+        .allocate_contiguous(order, zone)
+        .map(|frame| frame.get_address())
+}
+
+/// Frees the `2^order` physically contiguous frames starting at the address.
+///
+/// # Safety
+/// - The frames must have been allocated with the same order.
+/// - Nothing may reference the frames anymore.
+pub unsafe fn free_contiguous_frames(start_address: PhysicalAddress, order: usize) {
+    FRAME_ALLOCATOR.deallocate_contiguous(PageFrame::from_address(start_address), order);
+}
+
 /// Maps the given page to the given frame using the given flags.
 pub fn map_page_at(page_address: VirtualAddress, frame_address: VirtualAddress, flags: PageFlags) {
     CURRENT_PAGE_TABLE.lock().map_page_at(
@@ -114,26 +154,95 @@ pub unsafe fn unmap_page(start_address: VirtualAddress) {
         .unmap_page(Page::from_address(start_address));
 }
 
+/// Unmaps the given page without deallocating the frame.
+///
+/// # Safety
+/// - Make sure this page isn't referenced anymore when unmapping it.
+pub unsafe fn unmap_page_without_deallocating(start_address: VirtualAddress) {
+    CURRENT_PAGE_TABLE
+        .lock()
+        .unmap_page_without_deallocating(Page::from_address(start_address));
+}
+
+/// Prepares the page tables used in user mode if the kernel page table is
+/// isolated.
+///
+/// # Safety
+/// - Should only be called once, after the entry area is mapped.
+pub unsafe fn init_user_tables() {
+    inactive_page_table::InactivePageTable::init_user_tables(
+        to_virtual!(ENTRY_TEXT_START),
+        to_virtual!(ENTRY_TEXT_END),
+    );
+}
+
+/// Maps the physical memory starting at `physical_start` to the virtual memory
+/// starting at `virtual_start`.
+///
+/// Huge pages are used wherever both addresses are aligned to them.
+fn map_range<T: PageTableManager>(
+    table: &mut T,
+    virtual_start: VirtualAddress,
+    physical_start: PhysicalAddress,
+    length: usize,
+    flags: PageTableEntryFlags,
+) {
+    let mut offset = 0;
+
+    while offset < length {
+        let virtual_address = virtual_start + offset;
+        let physical_address = physical_start + offset;
+        let huge_page_size = [HugePageSize::OneGiB, HugePageSize::TwoMiB]
+            .iter()
+            .cloned()
+            .find(|size| {
+                size.is_supported() && virtual_address % size.get_size() == 0
+                    && physical_address % size.get_size() == 0
+                    && length - offset >= size.get_size()
+            });
+
+        match huge_page_size {
+            Some(size) => {
+                table.map_huge_page_at(
+                    Page::from_address(virtual_address),
+                    PageFrame::from_address(physical_address),
+                    size,
+                    flags,
+                );
+                offset += size.get_size();
+            }
+            None => {
+                table.map_page_at(
+                    Page::from_address(virtual_address),
+                    PageFrame::from_address(physical_address),
+                    flags,
+                );
+                offset += PAGE_SIZE;
+            }
+        }
+    }
+}
+
 /// Maps the initramfs into the kernel.
 ///
+/// The initramfs keeps its offset within a huge page, so that it can be mapped
+/// with huge pages.
+///
 /// # Safety
 /// - This should only be called once.
 unsafe fn map_initramfs(initramfs_start: PhysicalAddress, initramfs_length: usize) {
     assert_has_not_been_called!("Trying to map the initramfs twice");
 
     if initramfs_length > 0 {
-        let initramfs_page_amount = (initramfs_length - 1) / PAGE_SIZE + 1;
-
-        // Map the initramfs.
-        for i in 0..initramfs_page_amount {
-            let physical_address = initramfs_start + i * PAGE_SIZE;
-            let virtual_address = INITRAMFS_MAP_AREA_START + i * PAGE_SIZE;
-            map_page_at(
-                virtual_address,
-                physical_address,
-                memory::PageFlags::READABLE,
-            );
-        }
+        let first_frame_address = initramfs_start / PAGE_SIZE * PAGE_SIZE;
+
+        map_range(
+            &mut *CURRENT_PAGE_TABLE.lock(),
+            INITRAMFS_MAP_AREA_START + first_frame_address % HUGE_PAGE_SIZE,
+            first_frame_address,
+            initramfs_start + initramfs_length - first_frame_address,
+            convert_flags(memory::PageFlags::READABLE),
+        );
     }
 }
 
@@ -149,14 +258,7 @@ unsafe fn remap_kernel() {
     {
         // Map a section.
         let mut map_section = |size: usize, start: usize, flags: PageTableEntryFlags| {
-            for i in 0..size / PAGE_SIZE {
-                let address = start + i * PAGE_SIZE;
-                new_page_table.map_page_at(
-                    Page::from_address(to_virtual!(address)),
-                    PageFrame::from_address(address),
-                    flags,
-                );
-            }
+            map_range(&mut new_page_table, to_virtual!(start), start, size, flags);
         };
 
         // Map the text section.
@@ -195,8 +297,7 @@ unsafe fn remap_kernel() {
             info.height * info.pitch as usize,
             to_physical!(info.address),
             PageTableEntryFlags::WRITABLE | PageTableEntryFlags::GLOBAL
-                | PageTableEntryFlags::NO_EXECUTE
-                | PageTableEntryFlags::USER_ACCESSIBLE,
+                | PageTableEntryFlags::NO_EXECUTE,
         )
     }
 
@@ -223,6 +324,42 @@ unsafe fn remap_kernel() {
     FRAME_ALLOCATOR.deallocate(PageFrame::from_address(STACK_L1_TABLE));
 }
 
+/// The sizes of huge pages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HugePageSize {
+    /// A 2 MiB page, mapped by a level 2 entry.
+    TwoMiB,
+    /// A 1 GiB page, mapped by a level 3 entry.
+    OneGiB,
+}
+
+impl HugePageSize {
+    /// Returns the size of such a page in bytes.
+    pub fn get_size(&self) -> usize {
+        PAGE_SIZE << self.get_order()
+    }
+
+    /// Returns the order of the frame block backing such a page.
+    pub fn get_order(&self) -> usize {
+        match *self {
+            HugePageSize::TwoMiB => 9,
+            HugePageSize::OneGiB => 18,
+        }
+    }
+
+    /// Checks if the CPU supports pages of this size.
+    pub fn is_supported(&self) -> bool {
+        match *self {
+            HugePageSize::TwoMiB => true,
+            HugePageSize::OneGiB => *ONE_GIB_PAGES_SUPPORTED.call_once(|| {
+                CpuId::new()
+                    .get_extended_function_info()
+                    .map_or(false, |info| info.has_1gib_pages())
+            }),
+        }
+    }
+}
+
 /// Represents a page.
 #[derive(Clone, Copy)]
 pub struct Page(usize);
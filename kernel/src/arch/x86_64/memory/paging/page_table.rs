@@ -1,11 +1,14 @@
 //! Contains code for dealing with page tables.
 
+use super::PageFrame;
+use super::current_page_table::CURRENT_PAGE_TABLE;
 use super::frame_allocator::FRAME_ALLOCATOR;
 use super::page_table_entry::*;
 use core::marker::PhantomData;
 use core::ops::Index;
 use core::ops::IndexMut;
-use memory::VirtualAddress;
+use memory::{PhysicalAddress, VirtualAddress, PAGE_SIZE};
+use x86_64::instructions::tlb;
 
 /// The number of entries in a page table.
 pub const ENTRY_NUMBER: usize = 512;
@@ -72,11 +75,14 @@ pub struct PageTable<T> {
 
 impl<T: ReducablePageTableLevel> PageTable<T> {
     /// Returns the address of the next page table if there is one.
+    ///
+    /// Entries that map a huge page don't point to a page table.
     fn get_next_level_address(&self, index: usize) -> Option<VirtualAddress> {
         assert!(index < ENTRY_NUMBER);
         let flags = self[index].flags();
-        debug_assert!(!flags.contains(PageTableEntryFlags::HUGE_PAGE));
-        if flags.contains(PageTableEntryFlags::PRESENT) {
+        if flags.contains(PageTableEntryFlags::PRESENT)
+            && !flags.contains(PageTableEntryFlags::HUGE_PAGE)
+        {
             Some((self as *const _ as usize | index << 3) << 9)
         } else {
             None
@@ -85,11 +91,13 @@ impl<T: ReducablePageTableLevel> PageTable<T> {
 
     /// Returns a mutable reference to the next table.
     ///
-    /// If no next table exists yet, then it is allocated and zeroed.
+    /// If no next table exists yet, then it is allocated and zeroed. A huge
+    /// page in its place is split.
     pub fn next_level_and_map(&mut self, address: VirtualAddress) -> &mut PageTable<T::NextLevel> {
+        self.split_huge_page(address);
+
         let index = PageTable::<T>::table_index(address);
         let flags = self[index].flags();
-        debug_assert!(!flags.contains(PageTableEntryFlags::HUGE_PAGE));
         // TODO: here would be the place to check whether the page is swapped out
 
         let new_table = if !flags.contains(PageTableEntryFlags::PRESENT) {
@@ -97,20 +105,92 @@ impl<T: ReducablePageTableLevel> PageTable<T> {
             let frame = FRAME_ALLOCATOR.allocate();
             self[index].set_flags(PageTableEntryFlags::PAGE_TABLE_FLAGS);
             self[index].set_address(frame.get_address());
-            true
+            Some(frame.get_address())
         } else {
-            false
+            None
         };
         let table = unsafe {
             &mut *(((self as *const _ as usize | index << 3) << 9) as *mut PageTable<T::NextLevel>)
         };
-        if new_table {
+        if let Some(table_address) = new_table {
             // zero the table out
             table.zero();
+
+            if T::get_level() == 4 && index < ENTRY_NUMBER / 2 {
+                self.share_with_user_table(index, table_address);
+            }
         }
         table
     }
 
+    /// Enters the lower half table at the index into the user table, if this
+    /// level 4 table has one.
+    ///
+    /// The user table directly follows the level 4 table.
+    fn share_with_user_table(&self, index: usize, table_address: PhysicalAddress) {
+        let recursive_entry = &self[ENTRY_NUMBER - 1];
+        if !recursive_entry
+            .flags()
+            .contains(PageTableEntryFlags::HAS_USER_TABLE)
+        {
+            return;
+        }
+
+        let user_frame = PageFrame::from_address(recursive_entry.points_to().unwrap() + PAGE_SIZE);
+        CURRENT_PAGE_TABLE
+            .lock()
+            .with_temporary_page(&user_frame, |page| {
+                let user_table = unsafe { &mut *(page.get_address() as *mut PageTable<T>) };
+                user_table[index].set(table_address, PageTableEntryFlags::PAGE_TABLE_FLAGS);
+            });
+    }
+
+    /// Splits the huge page containing the address into pages of the next level.
+    ///
+    /// The new pages map the same memory with the same flags, so translations
+    /// of the huge page that other CPUs cached stay valid.
+    pub fn split_huge_page(&mut self, address: VirtualAddress) {
+        let index = PageTable::<T>::table_index(address);
+        if !self[index].flags().contains(PageTableEntryFlags::HUGE_PAGE) {
+            return;
+        }
+
+        let preemption_state = self[index].lock();
+        let mut flags = self[index].flags();
+
+        // Another CPU may have split the page in the meantime.
+        if flags.contains(PageTableEntryFlags::PRESENT | PageTableEntryFlags::HUGE_PAGE) {
+            let start_address = self[index].points_to().unwrap();
+            let page_size = PAGE_SIZE << (9 * (T::NextLevel::get_level() - 1));
+
+            flags.remove(PageTableEntryFlags::ENTRY_LOCK);
+            if T::NextLevel::get_level() == 1 {
+                flags.remove(PageTableEntryFlags::HUGE_PAGE);
+            }
+
+            // Fill the new table before it becomes visible.
+            let frame = FRAME_ALLOCATOR.allocate();
+            CURRENT_PAGE_TABLE
+                .lock()
+                .with_temporary_page(&frame, |page| {
+                    let table =
+                        unsafe { &mut *(page.get_address() as *mut PageTable<T::NextLevel>) };
+                    for i in 0..ENTRY_NUMBER {
+                        table[i] = PageTableEntry::new();
+                        table[i].set(start_address + i * page_size, flags);
+                    }
+                });
+
+            self[index].set(frame.get_address(), PageTableEntryFlags::PAGE_TABLE_FLAGS);
+
+            // The huge page may be cached at the address of the new table.
+            let table_address = self.get_next_level_address(index).unwrap();
+            tlb::flush(::x86_64::VirtualAddress(table_address));
+        }
+
+        self[index].unlock(&preemption_state);
+    }
+
     /// Returns a reference to next page table of there is one.
     pub fn get_next_level(&self, address: VirtualAddress) -> Option<&PageTable<T::NextLevel>> {
         let index = PageTable::<T>::table_index(address);
@@ -1,7 +1,5 @@
 //! Handles page table entries.
 
-use super::PageFrame;
-use super::frame_allocator::FRAME_ALLOCATOR;
 use core::fmt;
 use core::sync::atomic::{AtomicU64, Ordering};
 use memory::PhysicalAddress;
@@ -40,6 +38,10 @@ bitflags! {
         const GLOBAL = 1 << 8;
         /// Ensures mutual exclusion for pages this entry points to.
         const ENTRY_LOCK = 1 << 9;
+        /// The level 4 table is followed by a table used in user mode.
+        ///
+        /// This is only set in the recursive entry of a level 4 table.
+        const HAS_USER_TABLE = 1 << 10;
         /// No code on this page can be executed.
         const NO_EXECUTE = 1 << 63;
 
@@ -89,6 +91,25 @@ impl PageTableEntry {
         self
     }
 
+    /// Sets the address and the flags of the entry with a single write.
+    ///
+    /// Unlike `set_address` followed by `set_flags`, this never leaves the
+    /// entry holding a mix of the old and the new mapping.
+    pub fn set(
+        &mut self,
+        address: PhysicalAddress,
+        flags: PageTableEntryFlags,
+    ) -> &mut PageTableEntry {
+        assert_eq!(address & !PHYSICAL_ADDRESS_MASK, 0);
+        let lock = if self.is_locked() {
+            PageTableEntryFlags::ENTRY_LOCK.bits()
+        } else {
+            0
+        };
+        self.0 = address as u64 | flags.bits() | lock;
+        self
+    }
+
     /// Removes the given flags from the entry.
     pub fn remove_flags(&mut self, flags: PageTableEntryFlags) -> &mut PageTableEntry {
         let mut current_flags = self.flags();
@@ -96,10 +117,11 @@ impl PageTableEntry {
         self.set_flags(current_flags)
     }
 
-    /// Unmaps and deallocates the frame this entry points to.
-    pub fn unmap(&mut self) {
-        let address = self.points_to().expect("Trying to unmap an unmapped page.");
-        unsafe { FRAME_ALLOCATOR.deallocate(PageFrame::from_address(address)) };
+    /// Unmaps this entry without deallocating the frame it points to.
+    ///
+    /// The frame has to be freed separately, after no CPU can access it
+    /// anymore.
+    pub fn clear(&mut self) {
         self.0 = 0;
     }
 
@@ -216,6 +238,17 @@ mod tests {
         assert_eq!(entry.points_to(), Some(0xcafeb000));
     }
 
+    /// Tests that setting the whole entry keeps the lock.
+    #[test]
+    fn test_set() {
+        let mut entry = PageTableEntry::new();
+        entry.set_address(0xdeadb000);
+        entry.0 |= ENTRY_LOCK.bits();
+        entry.set(0xcafeb000, PageTableEntryFlags::PRESENT | HUGE_PAGE);
+        assert_eq!(entry.points_to(), Some(0xcafeb000));
+        assert_eq!(entry.flags(), PageTableEntryFlags::PRESENT | HUGE_PAGE | ENTRY_LOCK);
+    }
+
     /// Tests that the binary rePageTableEntryFlags::PRESENTation is as expected.
     #[test]
     fn test_representation() {
@@ -1,13 +1,13 @@
 //! Uses a trait that has general page table managing functions.
 
-use super::{Page, PageFrame};
+use super::{HugePageSize, Page, PageFrame};
 use super::frame_allocator::FRAME_ALLOCATOR;
-use super::page_table::{Level1, Level2, Level4, PageTable};
+use super::page_table::{Level1, Level2, Level3, Level4, PageTable};
 use super::page_table_entry::{PageTableEntry, PageTableEntryFlags};
+use super::shootdown::FlushBatch;
 use core::ops::{Deref, DerefMut};
-use memory::{PhysicalAddress, VirtualAddress};
+use memory::{PhysicalAddress, VirtualAddress, PAGE_SIZE};
 use sync::PreemptionState;
-use x86_64::instructions::tlb;
 
 /// A reference to a locked level 1 page table.
 pub struct Level1TableReference<'a> {
@@ -69,18 +69,62 @@ pub trait PageTableManager {
     /// Returns a mutable reference to the level 4 page table.
     fn get_l4(&mut self) -> &mut PageTable<Level4>;
 
+    /// Returns the physical address of the level 4 page table.
+    fn get_l4_address(&self) -> PhysicalAddress;
+
     /// Returns the corresponding physical address to a virtual address.
     fn translate_address(&mut self, address: VirtualAddress) -> Option<PhysicalAddress> {
-        self.get_l1(address)
-            .and_then(|l1| l1[PageTable::<Level1>::table_index(address)].points_to())
-            .map(|page_address| page_address + (address & 0xfff))
+        self.get_mapping(address)
+            .map(|(frame_address, _, page_size)| frame_address + (address & (page_size - 1)))
+    }
+
+    /// Returns the frame address, the flags and the size of the page that
+    /// contains the given address.
+    ///
+    /// Unlike `get_entry` this doesn't split huge pages.
+    fn get_mapping(
+        &mut self,
+        address: VirtualAddress,
+    ) -> Option<(PhysicalAddress, PageTableEntryFlags, usize)> {
+        assert!(valid_address!(address));
+
+        let l3 = self.get_l4().get_next_level(address)?;
+        let l3_entry = &l3[PageTable::<Level3>::table_index(address)];
+        if l3_entry.flags().contains(PageTableEntryFlags::HUGE_PAGE) {
+            return l3_entry.points_to().map(|frame_address| {
+                (frame_address, l3_entry.flags(), HugePageSize::OneGiB.get_size())
+            });
+        }
+
+        let l2 = l3.get_next_level(address)?;
+        let l2_entry = &l2[PageTable::<Level2>::table_index(address)];
+        if l2_entry.flags().contains(PageTableEntryFlags::HUGE_PAGE) {
+            return l2_entry.points_to().map(|frame_address| {
+                (frame_address, l2_entry.flags(), HugePageSize::TwoMiB.get_size())
+            });
+        }
+
+        let l1 = l2.get_next_level(address)?;
+        let l1_entry = &l1[PageTable::<Level1>::table_index(address)];
+        l1_entry
+            .points_to()
+            .map(|frame_address| (frame_address, l1_entry.flags(), PAGE_SIZE))
     }
 
     /// Returns a mutable reference to the level 1 table corresponding to the
     /// given address.
+    ///
+    /// Huge pages containing the address are split on the way.
     fn get_l1(&mut self, address: VirtualAddress) -> Option<Level1TableReference> {
         assert!(valid_address!(address));
 
+        if let Some(l3) = self.get_l4().get_next_level_mut(address) {
+            l3.split_huge_page(address);
+            if let Some(l2) = l3.get_next_level_mut(address) {
+                l2.split_huge_page(address);
+            }
+        }
+
         let table_index = PageTable::<Level2>::table_index(address);
         let preemption_state = {
             let l4 = self.get_l4();
@@ -185,6 +229,47 @@ pub trait PageTableManager {
             .set_flags(flags | PageTableEntryFlags::PRESENT);
     }
 
+    /// Maps the huge page of the given size at the page to the frames starting
+    /// at the given frame.
+    fn map_huge_page_at(
+        &mut self,
+        page: Page,
+        frame: PageFrame,
+        size: HugePageSize,
+        flags: PageTableEntryFlags,
+    ) {
+        let address = page.get_address();
+        assert!(valid_address!(address));
+        assert!(size.is_supported(), "{:?} pages aren't supported.", size);
+        assert!(
+            address % size.get_size() == 0 && frame.get_address() % size.get_size() == 0,
+            "Huge page {:x} or frame {:x} is unaligned.",
+            address,
+            frame.get_address()
+        );
+
+        let l3 = self.get_l4().next_level_and_map(address);
+        let entry = match size {
+            HugePageSize::OneGiB => &mut l3[PageTable::<Level3>::table_index(address)],
+            HugePageSize::TwoMiB => {
+                let l2 = l3.next_level_and_map(address);
+                &mut l2[PageTable::<Level2>::table_index(address)]
+            }
+        };
+
+        let preemption_state = entry.lock();
+        debug_assert!(
+            !entry.flags().contains(PageTableEntryFlags::PRESENT),
+            "Trying to double map huge page {:x}",
+            address
+        );
+        entry.set(
+            frame.get_address(),
+            flags | PageTableEntryFlags::PRESENT | PageTableEntryFlags::HUGE_PAGE,
+        );
+        entry.unlock(&preemption_state);
+    }
+
     /// Maps the given page to an allocated frame with the given flags.
     fn map_page(&mut self, page: Page, flags: PageTableEntryFlags) {
         if let Some(entry) = self.get_entry(page.get_address()) {
@@ -214,6 +299,8 @@ pub trait PageTableManager {
             self.get_entry(page.get_address())
                 .unwrap()
                 .set_flags(PageTableEntryFlags::PRESENT | flags);
+
+            FlushBatch::new(self.get_l4_address()).add(page.get_address(), None);
         } else {
             self.map_page(page, flags);
         }
@@ -224,15 +311,19 @@ pub trait PageTableManager {
     /// # Safety
     /// - Make sure the page isn't referenced anywhere anymore.
     unsafe fn unmap_page(&mut self, page: Page) {
-        // TODO: Consider multiple CPUs.
-        // TODO: Consider that the page may still be in use elsewhere (don't free the
-        // frame then).
-        let entry = self.get_entry(page.get_address());
+        let mut batch = FlushBatch::new(self.get_l4_address());
 
-        entry
-            .expect("Trying to unmap a page that isn't mapped.")
-            .unmap();
-        tlb::flush(::x86_64::VirtualAddress(page.get_address()));
+        self.unmap_page_in_batch(page, &mut batch);
+    }
+
+    /// Unmaps the given page without deallocating the frame.
+    ///
+    /// # Safety
+    /// - Make sure the page isn't referenced anywhere anymore.
+    unsafe fn unmap_page_without_deallocating(&mut self, page: Page) {
+        let mut batch = FlushBatch::new(self.get_l4_address());
+
+        self.unmap_page_without_deallocating_in_batch(page, &mut batch);
     }
 
     /// Unmaps the given page, not checking if it was mapped.
@@ -240,16 +331,123 @@ pub trait PageTableManager {
     /// # Safety
     /// - Make sure the page isn't referenced anywhere anymore.
     unsafe fn unmap_page_unchecked(&mut self, page: Page) {
-        // TODO: Consider multiple CPUs.
+        let mut batch = FlushBatch::new(self.get_l4_address());
+
+        self.unmap_page_unchecked_in_batch(page, &mut batch);
+    }
+
+    /// Unmaps the given page, freeing the frame when the batch is flushed.
+    ///
+    /// # Safety
+    /// - Make sure the page isn't referenced anywhere anymore.
+    unsafe fn unmap_page_in_batch(&mut self, page: Page, batch: &mut FlushBatch) {
+        // TODO: Consider that the page may still be in use elsewhere (don't free the
+        // frame then).
+        let frame = {
+            let mut entry = self.get_entry(page.get_address())
+                .expect("Trying to unmap a page that isn't mapped.");
+            let frame = entry.points_to().expect("Trying to unmap an unmapped page.");
+            entry.clear();
+
+            frame
+        };
+
+        batch.add(page.get_address(), Some(frame));
+    }
+
+    /// Unmaps the given page without deallocating the frame as part of the
+    /// batch.
+    ///
+    /// # Safety
+    /// - Make sure the page isn't referenced anywhere anymore.
+    unsafe fn unmap_page_without_deallocating_in_batch(
+        &mut self,
+        page: Page,
+        batch: &mut FlushBatch,
+    ) {
+        let was_mapped = match self.get_entry(page.get_address()) {
+            Some(mut entry) => {
+                entry.clear();
+                true
+            }
+            None => false,
+        };
+
+        if was_mapped {
+            batch.add(page.get_address(), None);
+        }
+    }
+
+    /// Unmaps the given page, not checking if it was mapped, freeing the frame
+    /// when the batch is flushed.
+    ///
+    /// # Safety
+    /// - Make sure the page isn't referenced anywhere anymore.
+    unsafe fn unmap_page_unchecked_in_batch(&mut self, page: Page, batch: &mut FlushBatch) {
         // TODO: Consider that the page may still be in use elsewhere (don't free the
         // frame then).
-        let entry = self.get_entry(page.get_address());
+        let unmapped = match self.get_entry(page.get_address()) {
+            Some(mut entry) => {
+                let frame = entry.points_to();
+                entry.clear();
+
+                Some(frame)
+            }
+            None => None,
+        };
+
+        if let Some(frame) = unmapped {
+            batch.add(page.get_address(), frame);
+        }
+    }
+
+    /// Unmaps the huge page of the given size at the page as part of the batch.
+    ///
+    /// If `deallocate` is set, the frames are freed when the batch is flushed.
+    /// Returns false if no huge page of that size is mapped there.
+    ///
+    /// # Safety
+    /// - Make sure the page isn't referenced anywhere anymore.
+    unsafe fn unmap_huge_page_in_batch(
+        &mut self,
+        page: Page,
+        size: HugePageSize,
+        deallocate: bool,
+        batch: &mut FlushBatch,
+    ) -> bool {
+        let address = page.get_address();
+        let frame = {
+            let l3 = match self.get_l4().get_next_level_mut(address) {
+                Some(l3) => l3,
+                None => return false,
+            };
+            let entry = match size {
+                HugePageSize::OneGiB => &mut l3[PageTable::<Level3>::table_index(address)],
+                HugePageSize::TwoMiB => match l3.get_next_level_mut(address) {
+                    Some(l2) => &mut l2[PageTable::<Level2>::table_index(address)],
+                    None => return false,
+                },
+            };
+
+            if !entry.flags().contains(PageTableEntryFlags::HUGE_PAGE) {
+                return false;
+            }
+
+            let preemption_state = entry.lock();
+            let frame = entry.points_to();
+            entry.set(0, PageTableEntryFlags::empty());
+            entry.unlock(&preemption_state);
+
+            frame
+        };
 
-        if let Some(mut entry) = entry {
-            if entry.points_to().is_some() {
-                entry.unmap();
+        match frame {
+            Some(frame) => {
+                let frame = if deallocate { Some(frame) } else { None };
+                batch.add_block(address, frame, size.get_order());
+                true
             }
-            tlb::flush(::x86_64::VirtualAddress(page.get_address()));
+            None => false,
         }
     }
 }
@@ -0,0 +1,448 @@
+//! Keeps the TLBs of all CPUs consistent with the page tables.
+//!
+//! Every CPU records the page tables it may hold translations for, one per
+//! PCID. When a mapping is removed or its permissions change, the CPUs holding
+//! translations for that page table are sent an IPI and the change only
+//! completes once all of them acknowledged it.
+//!
+//! Translations cached for a PCID that isn't loaded can't be invalidated
+//! directly. Instead the PCID is forgotten, so that it is flushed the next
+//! time it is loaded.
+//!
+//! If the kernel page table is isolated, the user table of a page table uses
+//! the PCID of the page table with `USER_PCID_BIT` set. Its translations are
+//! flushed with the next switch to user mode instead.
+
+use super::PageFrame;
+use super::frame_allocator::FRAME_ALLOCATOR;
+use arch::{get_apic_id, get_cpu_id, get_cpu_num};
+use arch::isolation;
+use arch::interrupts::TLB_SHOOTDOWN_INTERRUPT_NUM;
+use arch::interrupts::lapic;
+use core::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use memory::{PhysicalAddress, VirtualAddress, PAGE_SIZE};
+use raw_cpuid::CpuId;
+use spin::Once;
+use sync::{cpu_relax, PreemptableMutex};
+use x86_64::instructions::tlb;
+use x86_64::registers::control_regs;
+
+/// The number of PCIDs used on each CPU.
+///
+/// This is kept small, because all of them are checked for every shootdown.
+const PCID_COUNT: usize = 8;
+
+/// The number of pages that are invalidated together.
+const MAX_BATCH_PAGES: usize = 32;
+
+/// The bits of CR3 that hold the PCID.
+const CR3_PCID_MASK: u64 = 0xfff;
+
+/// Set when loading CR3 to keep the translations cached for the PCID.
+const CR3_NO_FLUSH: u64 = 1 << 63;
+
+/// Set in the PCID of user tables.
+const USER_PCID_BIT: u64 = 0x800;
+
+/// The bit in CR4 that enables PCIDs.
+const CR4_PCID_ENABLE: usize = 1 << 17;
+
+/// The level 4 table entries that are shared by all address spaces.
+const SHARED_L4_ENTRIES: [usize; 6] = [256, 257, 505, 506, 507, 510];
+
+/// Set if PCIDs are used. Initialized once shootdowns are possible.
+static PCID_ENABLED: Once<bool> = Once::new();
+
+/// Set while a shootdown request waits for acknowledgements.
+static REQUEST_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The number of CPUs that haven't acknowledged the current request yet.
+static MISSING_ACKNOWLEDGEMENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The request currently being sent to other CPUs.
+static REQUEST: PreemptableMutex<Request> = PreemptableMutex::new(Request {
+    page_table: 0,
+    shared: false,
+    pages: [0; MAX_BATCH_PAGES],
+    page_count: 0,
+});
+
+cpu_local! {
+    /// The page tables the CPU may hold translations for, indexed by PCID.
+    ///
+    /// Zero marks an unused PCID.
+    static ref CACHED_PAGE_TABLES: [AtomicUsize; PCID_COUNT] = |_| [
+        AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0),
+        AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0),
+    ];
+}
+
+cpu_local! {
+    /// The PCID currently loaded on the CPU.
+    static ref CURRENT_PCID: AtomicUsize = |_| AtomicUsize::new(0);
+}
+
+cpu_local! {
+    /// The PCID that is replaced next on the CPU.
+    static ref NEXT_PCID: AtomicUsize = |_| AtomicUsize::new(1);
+}
+
+cpu_local! {
+    /// Set if the CPU participates in shootdowns.
+    static ref ONLINE: AtomicBool = |_| AtomicBool::new(false);
+}
+
+cpu_local! {
+    /// Set if the CPU still has to handle the current request.
+    static ref PENDING: AtomicBool = |_| AtomicBool::new(false);
+}
+
+/// The translations to invalidate on other CPUs.
+struct Request {
+    /// The page table the pages belong to.
+    page_table: PhysicalAddress,
+    /// Set if the pages are shared by all page tables.
+    shared: bool,
+    /// The pages to invalidate.
+    pages: [VirtualAddress; MAX_BATCH_PAGES],
+    /// The number of pages to invalidate, zero for all of them.
+    page_count: usize,
+}
+
+/// Collects pages whose translations changed to invalidate them at once.
+///
+/// Frames that were unmapped are only freed after the invalidation, when no
+/// CPU can access them anymore. The batch is flushed when it is dropped.
+pub struct FlushBatch {
+    /// The page table the pages belong to.
+    page_table: PhysicalAddress,
+    /// The pages to invalidate.
+    pages: [VirtualAddress; MAX_BATCH_PAGES],
+    /// The number of pages to invalidate.
+    page_count: usize,
+    /// The blocks of frames to free after the invalidation, with their order.
+    frames: [(PhysicalAddress, usize); MAX_BATCH_PAGES],
+    /// The number of frames to free.
+    frame_count: usize,
+}
+
+impl FlushBatch {
+    /// Creates an empty batch for the given page table.
+    pub fn new(page_table: PhysicalAddress) -> FlushBatch {
+        FlushBatch {
+            page_table,
+            pages: [0; MAX_BATCH_PAGES],
+            page_count: 0,
+            frames: [(0, 0); MAX_BATCH_PAGES],
+            frame_count: 0,
+        }
+    }
+
+    /// Adds a page whose translation changed.
+    ///
+    /// If the page was unmapped, the frame it pointed to can be passed to be
+    /// freed after the invalidation.
+    pub fn add(&mut self, page: VirtualAddress, frame: Option<PhysicalAddress>) {
+        self.add_block(page, frame, 0);
+    }
+
+    /// Adds a page whose translation changed, that may be a huge page.
+    ///
+    /// If the page was unmapped, the `2^order` frames it pointed to can be
+    /// passed to be freed after the invalidation.
+    pub fn add_block(
+        &mut self,
+        page: VirtualAddress,
+        frame: Option<PhysicalAddress>,
+        order: usize,
+    ) {
+        if self.page_count == MAX_BATCH_PAGES {
+            self.flush();
+        }
+
+        self.pages[self.page_count] = page;
+        self.page_count += 1;
+
+        if let Some(frame) = frame {
+            self.frames[self.frame_count] = (frame, order);
+            self.frame_count += 1;
+        }
+    }
+
+    /// Invalidates the collected pages on all CPUs and frees the frames.
+    pub fn flush(&mut self) {
+        if self.page_count == 0 {
+            return;
+        }
+
+        shootdown(self.page_table, &self.pages[..self.page_count]);
+        self.page_count = 0;
+
+        for &(frame, order) in &self.frames[..self.frame_count] {
+            unsafe {
+                FRAME_ALLOCATOR.deallocate_contiguous(PageFrame::from_address(frame), order);
+            }
+        }
+        self.frame_count = 0;
+    }
+}
+
+impl Drop for FlushBatch {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Enables PCIDs if possible and lets the bootstrap processor take part in
+/// shootdowns.
+pub fn init() {
+    assert_has_not_been_called!("TLB shootdowns should only be initialized once.");
+
+    let has_pcid = CpuId::new()
+        .get_feature_info()
+        .map_or(false, |info| info.has_pcid());
+
+    PCID_ENABLED.call_once(|| has_pcid);
+
+    init_ap();
+}
+
+/// Lets the current CPU take part in shootdowns.
+///
+/// This must be called before the CPU switches its page table for the first
+/// time.
+pub fn init_ap() {
+    if pcid_enabled() {
+        // The currently loaded page table uses PCID 0.
+        unsafe {
+            control_regs::cr4_write(
+                control_regs::cr4() | control_regs::Cr4::from_bits_truncate(CR4_PCID_ENABLE),
+            );
+        }
+    }
+
+    CACHED_PAGE_TABLES[0].store(get_current_page_table_address(), Ordering::SeqCst);
+    CURRENT_PCID.store(0, Ordering::SeqCst);
+    ONLINE.store(true, Ordering::SeqCst);
+}
+
+/// Returns true if PCIDs are used.
+fn pcid_enabled() -> bool {
+    PCID_ENABLED.try().map_or(false, |enabled| *enabled)
+}
+
+/// Returns the physical address of the active level 4 page table.
+pub fn get_current_page_table_address() -> PhysicalAddress {
+    (control_regs::cr3().0 & !CR3_PCID_MASK) as PhysicalAddress
+}
+
+/// Returns the value to load into CR3 to switch to the given page table.
+///
+/// The translations of the page table are kept, if they are still cached for
+/// one of the PCIDs.
+///
+/// If the kernel page table is isolated, the values for entering and leaving
+/// the kernel are prepared as well.
+///
+/// # Safety
+/// - The returned value must be loaded right away, with preemption disabled.
+pub unsafe fn prepare_switch(page_table: PhysicalAddress) -> u64 {
+    let value = select_pcid(page_table);
+
+    if isolation::is_enabled() {
+        let user_table = (page_table + PAGE_SIZE) as u64;
+
+        if pcid_enabled() {
+            let flush_value = user_table | (value & CR3_PCID_MASK) | USER_PCID_BIT;
+            let keep_value = flush_value | CR3_NO_FLUSH;
+
+            // The user translations are only valid, if the kernel ones are.
+            let next_value = if value & CR3_NO_FLUSH != 0 {
+                keep_value
+            } else {
+                flush_value
+            };
+
+            isolation::set_page_table_values(
+                value | CR3_NO_FLUSH,
+                next_value,
+                keep_value,
+                flush_value,
+            );
+        } else {
+            isolation::set_page_table_values(value, user_table, user_table, user_table);
+        }
+    }
+
+    value
+}
+
+/// Returns the value to load into CR3 for the page table, choosing a PCID.
+///
+/// # Safety
+/// - The returned value must be loaded right away, with preemption disabled.
+unsafe fn select_pcid(page_table: PhysicalAddress) -> u64 {
+    let cached_page_tables = &*CACHED_PAGE_TABLES;
+
+    if !pcid_enabled() {
+        cached_page_tables[0].store(page_table, Ordering::SeqCst);
+        return page_table as u64;
+    }
+
+    if let Some(pcid) = cached_page_tables
+        .iter()
+        .position(|cached| cached.load(Ordering::SeqCst) == page_table)
+    {
+        CURRENT_PCID.store(pcid, Ordering::SeqCst);
+        return page_table as u64 | pcid as u64 | CR3_NO_FLUSH;
+    }
+
+    // Replace the PCIDs in turn, skipping the one that is still loaded.
+    let mut pcid = NEXT_PCID.load(Ordering::SeqCst);
+    if pcid == CURRENT_PCID.load(Ordering::SeqCst) {
+        pcid = (pcid + 1) % PCID_COUNT;
+    }
+    NEXT_PCID.store((pcid + 1) % PCID_COUNT, Ordering::SeqCst);
+
+    // Other CPUs must see this before any translation of the page table can
+    // be cached.
+    cached_page_tables[pcid].store(page_table, Ordering::SeqCst);
+    CURRENT_PCID.store(pcid, Ordering::SeqCst);
+
+    page_table as u64 | pcid as u64
+}
+
+/// Invalidates all translations of the given page table on all CPUs.
+///
+/// This must be done before the frame of a page table is reused.
+pub fn flush_page_table(page_table: PhysicalAddress) {
+    shootdown(page_table, &[]);
+}
+
+/// Returns true if the page is mapped the same in all page tables.
+fn is_shared(page: VirtualAddress) -> bool {
+    SHARED_L4_ENTRIES.contains(&((page >> 39) & 0o777))
+}
+
+/// Returns true if the CPU may hold translations affected by the request.
+fn is_target(cpu_id: usize, page_table: PhysicalAddress, shared: bool) -> bool {
+    if shared {
+        ONLINE.get_specific(cpu_id).load(Ordering::SeqCst)
+    } else {
+        CACHED_PAGE_TABLES
+            .get_specific(cpu_id)
+            .iter()
+            .any(|cached| cached.load(Ordering::SeqCst) == page_table)
+    }
+}
+
+/// Invalidates the pages of the page table on all CPUs.
+///
+/// An empty list of pages invalidates the whole page table.
+fn shootdown(page_table: PhysicalAddress, pages: &[VirtualAddress]) {
+    assert!(pages.len() <= MAX_BATCH_PAGES);
+
+    let shared = pages.iter().any(|&page| is_shared(page));
+
+    // The page table changes must be visible before checking which CPUs use
+    // the page table.
+    fence(Ordering::SeqCst);
+
+    invalidate_local(page_table, pages, shared);
+
+    if PCID_ENABLED.try().is_none() {
+        // Only this CPU is running yet.
+        return;
+    }
+
+    let cpu_id = get_cpu_id();
+    let mut request = REQUEST.lock();
+
+    request.page_table = page_table;
+    request.shared = shared;
+    request.pages[..pages.len()].copy_from_slice(pages);
+    request.page_count = pages.len();
+
+    let mut target_count = 0;
+    for other_cpu_id in 0..get_cpu_num() {
+        if other_cpu_id != cpu_id && is_target(other_cpu_id, page_table, shared) {
+            PENDING
+                .get_specific(other_cpu_id)
+                .store(true, Ordering::SeqCst);
+            target_count += 1;
+        }
+    }
+
+    if target_count == 0 {
+        return;
+    }
+
+    MISSING_ACKNOWLEDGEMENTS.store(target_count, Ordering::SeqCst);
+    REQUEST_ACTIVE.store(true, Ordering::SeqCst);
+
+    for other_cpu_id in 0..get_cpu_num() {
+        if PENDING.get_specific(other_cpu_id).load(Ordering::SeqCst) {
+            lapic::issue_interrupt_to(get_apic_id(other_cpu_id), TLB_SHOOTDOWN_INTERRUPT_NUM);
+        }
+    }
+
+    while MISSING_ACKNOWLEDGEMENTS.load(Ordering::SeqCst) > 0 {
+        cpu_relax();
+    }
+
+    REQUEST_ACTIVE.store(false, Ordering::SeqCst);
+}
+
+/// Handles the shootdown request for the current CPU, if there is one.
+///
+/// This is called by the shootdown interrupt and while spinning, so that
+/// CPUs waiting with interrupts disabled can't block each other.
+pub fn handle_pending() {
+    if !REQUEST_ACTIVE.load(Ordering::SeqCst) || !PENDING.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    {
+        // The requesting CPU holds the lock until all CPUs acknowledged.
+        let request = unsafe { REQUEST.without_locking() };
+
+        invalidate_local(
+            request.page_table,
+            &request.pages[..request.page_count],
+            request.shared,
+        );
+    }
+
+    MISSING_ACKNOWLEDGEMENTS.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Invalidates the pages of the page table on the current CPU.
+///
+/// An empty list of pages invalidates the whole page table.
+fn invalidate_local(page_table: PhysicalAddress, pages: &[VirtualAddress], shared: bool) {
+    if shared || get_current_page_table_address() == page_table {
+        if pages.is_empty() {
+            tlb::flush_all();
+        } else {
+            for &page in pages {
+                tlb::flush(::x86_64::VirtualAddress(page));
+            }
+        }
+
+        // This only invalidated the translations of the kernel PCID.
+        if isolation::is_enabled() {
+            isolation::discard_user_translations();
+        }
+    }
+
+    if PCID_ENABLED.try().is_none() {
+        return;
+    }
+
+    let current_pcid = CURRENT_PCID.load(Ordering::SeqCst);
+    for (pcid, cached) in CACHED_PAGE_TABLES.iter().enumerate() {
+        if pcid != current_pcid && (shared || cached.load(Ordering::SeqCst) == page_table) {
+            cached.store(0, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Lets the kernel access user memory in a controlled way.
+//!
+//! With SMEP the kernel can't execute user pages and with SMAP it can only
+//! access them between `stac` and `clac`. Page faults while copying from or to
+//! user memory don't panic, but make the copy fail.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use memory::{is_userspace_address, VirtualAddress};
+use raw_cpuid::CpuId;
+use x86_64::registers::control_regs;
+use x86_64::structures::idt::ExceptionStackFrame;
+
+/// The bit in CR4 that enables SMEP.
+const CR4_SMEP_ENABLE: usize = 1 << 20;
+
+/// The bit in CR4 that enables SMAP.
+const CR4_SMAP_ENABLE: usize = 1 << 21;
+
+/// Set if SMAP is enabled, so that user accesses have to be allowed first.
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    /// The instruction in `copy_bytes` that accesses user memory.
+    static user_copy_instruction: u8;
+    /// The instruction in `copy_bytes` that follows a fault.
+    static user_copy_fixup: u8;
+}
+
+/// Enables SMEP and SMAP on the current CPU, as far as it supports them.
+pub fn enable_protection() {
+    let (has_smep, has_smap) = CpuId::new()
+        .get_extended_feature_info()
+        .map_or((false, false), |features| {
+            (features.has_smep(), features.has_smap())
+        });
+
+    let mut cr4_bits = 0;
+    if has_smep {
+        cr4_bits |= CR4_SMEP_ENABLE;
+    }
+    if has_smap {
+        cr4_bits |= CR4_SMAP_ENABLE;
+    }
+
+    unsafe {
+        control_regs::cr4_write(
+            control_regs::cr4() | control_regs::Cr4::from_bits_truncate(cr4_bits),
+        );
+    }
+
+    SMAP_ENABLED.store(has_smap, Ordering::SeqCst);
+}
+
+/// Copies `length` bytes from `source` to `destination`, one of which is user
+/// memory.
+///
+/// Returns false if accessing the user memory faulted.
+///
+/// # Safety
+/// - The kernel side of the copy must be valid.
+/// - The user side must be checked to be accessible to the current process.
+pub unsafe fn copy_user_memory(destination: *mut u8, source: *const u8, length: usize) -> bool {
+    let smap_enabled = SMAP_ENABLED.load(Ordering::Relaxed);
+
+    if smap_enabled {
+        asm!("stac" : : : "memory" : "volatile");
+    }
+
+    let remaining = copy_bytes(destination, source, length);
+
+    if smap_enabled {
+        asm!("clac" : : : "memory" : "volatile");
+    }
+
+    remaining == 0
+}
+
+/// Copies `length` bytes from `source` to `destination`.
+///
+/// Returns the number of bytes that weren't copied, because of a page fault.
+#[naked]
+#[inline(never)]
+unsafe extern "C" fn copy_bytes(
+    _destination: *mut u8,
+    _source: *const u8,
+    _length: usize,
+) -> usize {
+    asm!("mov rcx, rdx
+          .global user_copy_instruction
+          user_copy_instruction:
+          rep movsb
+          .global user_copy_fixup
+          user_copy_fixup:
+          mov rax, rcx
+          ret"
+          : : : : "intel", "volatile");
+    unreachable!();
+}
+
+/// Makes a copy that caused a page fault on user memory return early.
+///
+/// Returns true if the fault was caused by such a copy.
+pub fn handle_fault(address: VirtualAddress, stack_frame: &mut ExceptionStackFrame) -> bool {
+    let (instruction, fixup) = unsafe {
+        (
+            &user_copy_instruction as *const u8 as usize,
+            &user_copy_fixup as *const u8 as usize,
+        )
+    };
+
+    if stack_frame.instruction_pointer.0 == instruction && is_userspace_address(address) {
+        stack_frame.instruction_pointer = ::x86_64::VirtualAddress(fixup);
+        true
+    } else {
+        false
+    }
+}
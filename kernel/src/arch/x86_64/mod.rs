@@ -8,20 +8,24 @@ pub mod sync;
 pub mod interrupts;
 pub mod context;
 pub mod syscalls;
+pub mod time;
 pub mod gdt;
+pub mod isolation;
 pub mod device;
+pub mod power;
+pub mod smp;
 // pub mod video;
 
 pub use self::context::Context;
-use self::gdt::{GDT, TSS};
+use self::gdt::GDT;
 use self::interrupts::SCHEDULE_INTERRUPT_NUM;
-use self::interrupts::issue_self_interrupt;
+use self::interrupts::{issue_self_interrupt, lapic};
 use multitasking::{StackType, CURRENT_THREAD};
 use raw_cpuid::CpuId;
-use x86_64::VirtualAddress;
-use x86_64::instructions::{rdmsr, wrmsr};
+use x86_64::instructions::{rdmsr, rdtsc, wrmsr};
 use x86_64::registers::*;
 use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
 
 /// The stack type used for the x86_64 architecture.
 pub const STACK_TYPE: StackType = StackType::FullDescending;
@@ -62,6 +66,9 @@ pub fn early_init() {
         let cr0_flags = control_regs::cr0() | control_regs::Cr0::WRITE_PROTECT;
         control_regs::cr0_write(cr0_flags);
     }
+
+    // Keep the kernel from executing or silently accessing user pages.
+    memory::user_access_init();
 }
 
 /// Initializes the machine state for the x86_64 architecture to the final
@@ -69,28 +76,147 @@ pub fn early_init() {
 pub fn init() {
     assert_has_not_been_called!("x86_64 specific initialization code should only be called once.");
 
+    cache_cpu_id();
+
     unsafe {
         GDT.load();
     }
 
-    syscalls::init();
     interrupts::init();
+    isolation::init();
+    isolation::enable();
+    syscalls::init();
+    time::init();
+    memory::tlb_init();
+
+    smp::init();
+}
+
+/// Initializes the machine state of an application processor.
+///
+/// The trampoline already did what `early_init` does on the bootstrap
+/// processor.
+fn init_ap() {
+    cache_cpu_id();
+
+    unsafe {
+        GDT.load();
+    }
+
+    interrupts::init_ap();
+    isolation::enable();
+    syscalls::init();
+    time::init_ap();
+    memory::tlb_init_ap();
+    memory::user_access_init();
 }
 
+/// The MSR that `rdtscp` reads into `ecx`.
+const IA32_TSC_AUX: u32 = 0xc0000103;
+
+/// Whether the ID of each CPU is cached in its IA32_TSC_AUX MSR.
+static CPU_ID_CACHED: AtomicBool = ATOMIC_BOOL_INIT;
+
 /// Returns the ID of the currently running CPU.
+///
+/// The IDs are numbered in the order of the MADT, if it is available.
 pub fn get_cpu_id() -> usize {
-    CpuId::new()
+    if CPU_ID_CACHED.load(Ordering::Acquire) {
+        // The cached value is offset by one, so an AP that hasn't cached its
+        // ID yet reads zero.
+        let cached_id: u32;
+        unsafe {
+            asm!("rdtscp" : "={ecx}"(cached_id) : : "rax", "rdx" : "intel", "volatile");
+        }
+
+        if cached_id != 0 {
+            return cached_id as usize - 1;
+        }
+    }
+
+    lookup_cpu_id()
+}
+
+/// Looks up the ID of the currently running CPU by its LAPIC ID.
+fn lookup_cpu_id() -> usize {
+    let apic_id = CpuId::new()
         .get_feature_info()
-        .unwrap()
-        .initial_local_apic_id() as usize
+        .map_or(0, |features| features.initial_local_apic_id());
+
+    match ::acpi::madt::get_info() {
+        Some(info) => info.local_apic_ids
+            .iter()
+            .position(|id| *id == apic_id)
+            .unwrap_or(apic_id as usize),
+        None => apic_id as usize,
+    }
+}
+
+/// Caches the ID of the currently running CPU, so that `get_cpu_id` doesn't
+/// have to look it up on every call.
+///
+/// This has to be called on the bootstrap processor after the MADT was parsed
+/// and before the application processors are started.
+fn cache_cpu_id() {
+    let has_rdtscp = CpuId::new()
+        .get_extended_function_info()
+        .map_or(false, |function_info| function_info.has_rdtscp());
+
+    if has_rdtscp {
+        unsafe {
+            wrmsr(IA32_TSC_AUX, lookup_cpu_id() as u64 + 1);
+        }
+        CPU_ID_CACHED.store(true, Ordering::Release);
+    }
+}
+
+/// Returns the LAPIC ID of the given CPU.
+pub fn get_apic_id(cpu_id: usize) -> u8 {
+    match ::acpi::madt::get_info() {
+        Some(info) if !info.local_apic_ids.is_empty() => info.local_apic_ids[cpu_id],
+        _ => cpu_id as u8,
+    }
 }
 
 /// Returns the number of addressable CPUs.
 pub fn get_cpu_num() -> usize {
-    CpuId::new()
+    match ::acpi::madt::get_info() {
+        Some(info) if !info.local_apic_ids.is_empty() => info.local_apic_ids.len(),
+        _ => CpuId::new()
+            .get_feature_info()
+            .unwrap()
+            .max_logical_processor_ids() as usize,
+    }
+}
+
+/// Returns a value that is hard to predict to seed random number generators.
+///
+/// This uses `rdrand` if it is supported and mixes in the time stamp counter.
+pub fn get_random_seed() -> u64 {
+    let mut seed = rdtsc();
+
+    let has_rdrand = CpuId::new()
         .get_feature_info()
-        .unwrap()
-        .max_logical_processor_ids() as usize
+        .map_or(false, |features| features.has_rdrand());
+
+    if has_rdrand {
+        for _ in 0..10 {
+            let value: u64;
+            let success: u8;
+            unsafe {
+                asm!("rdrand $0
+                      setc $1"
+                      : "=r"(value), "=r"(success) : : "cc" : "intel", "volatile");
+            }
+
+            if success != 0 {
+                seed ^= value;
+                break;
+            }
+        }
+    }
+
+    seed
 }
 
 /// This is called once per processor to enter the first user mode thread.
@@ -103,7 +229,7 @@ pub unsafe fn enter_first_thread() -> ! {
         .without_locking()
         .context
         .kernel_stack_pointer;
-    TSS.as_mut().privilege_stack_table[0] = VirtualAddress(stack_pointer);
+    isolation::set_kernel_stack(stack_pointer);
     asm!("mov rsp, $0
           ret"
           : : "r"(stack_pointer) : : "intel", "volatile");
@@ -115,13 +241,20 @@ pub fn schedule() {
     issue_self_interrupt(SCHEDULE_INTERRUPT_NUM);
 }
 
+/// This function starts a scheduling operation on the given CPU.
+pub fn schedule_on(cpu_id: usize) {
+    lapic::issue_interrupt_to(get_apic_id(cpu_id), SCHEDULE_INTERRUPT_NUM);
+}
+
 
 /// Writes the formatted arguments.
 ///
 /// This takes arguments as dictated by `core::fmt` and prints the to the
 /// screen using the printing method relevant for the current architecture.
 pub fn write_fmt(args: fmt::Arguments) {
-    if cfg!(target_arch = "x86_64") {
+    if ::drivers::console::is_selected() {
+        ::drivers::console::write_fmt(args);
+    } else if cfg!(target_arch = "x86_64") {
         use core::fmt::Write;
         vga_buffer::WRITER.lock().write_fmt(args).unwrap();
     }
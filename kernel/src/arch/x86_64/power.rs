@@ -0,0 +1,84 @@
+//! Powers off and resets the machine.
+
+use super::sync::cpu_halt;
+use acpi;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::{inb, outb, outl};
+
+/// The port of the QEMU `isa-debug-exit` device.
+///
+/// QEMU exits with `(value << 1) | 1` when a value is written to it.
+const DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// The command port of the 8042 controller.
+const KEYBOARD_CONTROLLER_COMMAND_PORT: u16 = 0x64;
+
+/// Set in the status register of the 8042 while its input buffer is full.
+const KEYBOARD_CONTROLLER_INPUT_FULL: u8 = 1 << 1;
+
+/// Pulses the reset line of the CPU.
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xfe;
+
+/// Powers the machine off.
+///
+/// A non-zero status is reported through the QEMU `isa-debug-exit` device
+/// first, so that test runs can exit with it.
+pub fn shutdown(status: u8) -> ! {
+    unsafe {
+        interrupts::disable();
+    }
+
+    if status != 0 {
+        debug_exit(status);
+    }
+
+    acpi::power::power_off();
+
+    debug_exit(status);
+
+    halt()
+}
+
+/// Resets the machine.
+pub fn reboot() -> ! {
+    unsafe {
+        interrupts::disable();
+    }
+
+    acpi::power::reset();
+
+    if acpi::fadt::get_info().map_or(true, |fadt| fadt.has_8042) {
+        unsafe {
+            // Wait for the controller to accept commands.
+            for _ in 0..100000 {
+                if inb(KEYBOARD_CONTROLLER_COMMAND_PORT) & KEYBOARD_CONTROLLER_INPUT_FULL == 0 {
+                    break;
+                }
+            }
+
+            outb(KEYBOARD_CONTROLLER_COMMAND_PORT, KEYBOARD_CONTROLLER_RESET);
+        }
+    }
+
+    debug_exit(0);
+
+    halt()
+}
+
+/// Exits QEMU with the given status, if the `isa-debug-exit` device exists.
+fn debug_exit(status: u8) {
+    unsafe {
+        outl(DEBUG_EXIT_PORT, status as u32);
+    }
+}
+
+/// Halts the CPU forever.
+fn halt() -> ! {
+    panic_debugln!("The machine could not be powered off or reset.");
+
+    loop {
+        unsafe {
+            cpu_halt();
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Starts the application processors.
+//!
+//! The application processors (APs) are started with the INIT-SIPI-SIPI
+//! sequence. They begin in real mode at a trampoline below 1 MiB, which
+//! switches them to long mode using the page table of the bootstrap processor
+//! and jumps to `ap_main` on a boot stack of their own.
+
+use super::interrupts::lapic;
+use super::memory::{get_current_page_table_address, AP_STACK_AREA_BASE, AP_STACK_OFFSET,
+                    AP_STACK_SIZE, AP_TRAMPOLINE_ADDRESS};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use memory::{map_page_at, unmap_page_without_deallocating, PageFlags, PAGE_SIZE};
+use multitasking::{Stack, CURRENT_THREAD};
+use multitasking::stack::AccessType;
+use x86_64::instructions::rdtsc;
+
+/// The time to wait after the INIT IPI in microseconds.
+const INIT_DELAY: u64 = 10000;
+
+/// The time to wait after each startup IPI in microseconds.
+const STARTUP_DELAY: u64 = 200;
+
+/// The time an AP gets to finish its initialization in microseconds.
+const START_TIMEOUT: u64 = 100000;
+
+extern "C" {
+    /// The start of the trampoline code.
+    static ap_trampoline_start: u8;
+    /// The end of the trampoline code.
+    static ap_trampoline_end: u8;
+    /// The page table field within the trampoline.
+    static ap_trampoline_page_table: u8;
+    /// The stack pointer field within the trampoline.
+    static ap_trampoline_stack_pointer: u8;
+    /// The entry point field within the trampoline.
+    static ap_trampoline_entry: u8;
+}
+
+cpu_local! {
+    /// The stack each AP uses until it enters its idle thread.
+    static ref BOOT_STACK: Stack = |cpu_id|
+        Stack::new(if cpu_id == 0 { 0 } else { AP_STACK_SIZE },
+            AP_STACK_SIZE,
+            AP_STACK_AREA_BASE + AP_STACK_OFFSET * cpu_id,
+            AccessType::KernelOnly, None).unwrap();
+}
+
+/// The number of APs that finished their initialization.
+static STARTED_APS: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts all application processors listed in the MADT.
+///
+/// This must be called on the bootstrap processor after the rest of the
+/// architecture was initialized.
+pub fn init() {
+    assert_has_not_been_called!("The application processors should only be started once.");
+
+    let apic_ids = match ::acpi::madt::get_info() {
+        Some(info) if info.local_apic_ids.len() > 1 => info.local_apic_ids.clone(),
+        _ => return,
+    };
+
+    let page_table = get_current_page_table_address() as u64;
+    assert!(
+        page_table < 1 << 32,
+        "The trampoline can only load page tables below 4 GiB."
+    );
+
+    // The idle threads of all CPUs need to be created in the idle address space.
+    CURRENT_THREAD.get_specific(0);
+
+    let bootstrap_cpu_id = super::get_cpu_id();
+
+    unsafe {
+        let trampoline_start = &ap_trampoline_start as *const u8 as usize;
+        let trampoline_length = &ap_trampoline_end as *const u8 as usize - trampoline_start;
+        assert!(trampoline_length <= PAGE_SIZE);
+
+        // The AP starts with paging disabled, so the trampoline needs to be
+        // identity mapped while it switches to long mode.
+        map_page_at(
+            AP_TRAMPOLINE_ADDRESS,
+            AP_TRAMPOLINE_ADDRESS,
+            PageFlags::READABLE | PageFlags::WRITABLE | PageFlags::EXECUTABLE,
+        );
+        ptr::copy_nonoverlapping(
+            trampoline_start as *const u8,
+            AP_TRAMPOLINE_ADDRESS as *mut u8,
+            trampoline_length,
+        );
+
+        set_trampoline_field(&ap_trampoline_page_table, page_table);
+        set_trampoline_field(&ap_trampoline_entry, ap_main as u64);
+
+        for (cpu_id, &apic_id) in apic_ids.iter().enumerate() {
+            if cpu_id == bootstrap_cpu_id {
+                continue;
+            }
+
+            let started_aps = STARTED_APS.load(Ordering::SeqCst);
+
+            set_trampoline_field(
+                &ap_trampoline_stack_pointer,
+                BOOT_STACK.get_specific(cpu_id).base_stack_pointer as u64,
+            );
+
+            if !start_ap(apic_id, started_aps) {
+                // Put the AP back into its wait state, so that it can't start
+                // late with the parameters of the next one.
+                lapic::send_init(apic_id);
+                delay(INIT_DELAY);
+                debugln!("SMP: The CPU with LAPIC ID {} did not start.", apic_id);
+                continue;
+            }
+        }
+
+        unmap_page_without_deallocating(AP_TRAMPOLINE_ADDRESS);
+    }
+
+    debugln!(
+        "SMP: {} of {} CPUs are running.",
+        STARTED_APS.load(Ordering::SeqCst) + 1,
+        apic_ids.len()
+    );
+}
+
+/// Starts the AP with the given LAPIC ID.
+///
+/// Returns true if the AP finished its initialization in time.
+fn start_ap(apic_id: u8, started_aps: usize) -> bool {
+    let has_started = || STARTED_APS.load(Ordering::SeqCst) > started_aps;
+    let startup_page = (AP_TRAMPOLINE_ADDRESS / PAGE_SIZE) as u8;
+
+    lapic::send_init(apic_id);
+    delay(INIT_DELAY);
+
+    lapic::send_startup(apic_id, startup_page);
+    delay(STARTUP_DELAY);
+
+    // The second startup IPI is only needed if the first one was lost.
+    if !has_started() {
+        lapic::send_startup(apic_id, startup_page);
+        delay(STARTUP_DELAY);
+    }
+
+    let timeout = rdtsc() + START_TIMEOUT * lapic::get_tsc_ticks_per_ms() / 1000;
+    while !has_started() {
+        if rdtsc() > timeout {
+            return false;
+        }
+        unsafe {
+            asm!("pause" : : : : "intel", "volatile");
+        }
+    }
+
+    true
+}
+
+/// Writes the given value to a parameter field in the copied trampoline.
+///
+/// # Safety
+/// - The trampoline must be mapped and copied.
+unsafe fn set_trampoline_field(field: &u8, value: u64) {
+    let offset = field as *const u8 as usize - &ap_trampoline_start as *const u8 as usize;
+
+    ptr::write_volatile((AP_TRAMPOLINE_ADDRESS + offset) as *mut u64, value);
+}
+
+/// Busy waits for the given amount of microseconds.
+fn delay(microseconds: u64) {
+    let end = rdtsc() + microseconds * lapic::get_tsc_ticks_per_ms() / 1000;
+
+    while rdtsc() < end {
+        unsafe {
+            asm!("pause" : : : : "intel", "volatile");
+        }
+    }
+}
+
+/// The entry point of the APs after the trampoline.
+extern "C" fn ap_main() -> ! {
+    super::init_ap();
+
+    STARTED_APS.fetch_add(1, Ordering::SeqCst);
+
+    unsafe { super::enter_first_thread() }
+}
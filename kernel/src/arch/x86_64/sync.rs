@@ -1,6 +1,6 @@
 //! Handles architecture specific synchronization.
 
-use sync::time::Timestamp;
+use sync::time::ClockSource;
 use x86_64::instructions::interrupts;
 use x86_64::registers::flags::*;
 
@@ -11,6 +11,9 @@ pub static mut CLOCK: u64 = 0;
 /// a platform-specific method of lightening CPU load in spinlocks.
 #[inline(always)]
 pub fn cpu_relax() {
+    // Spinning with interrupts disabled must not block TLB shootdowns.
+    super::memory::handle_tlb_shootdown();
+
     // This instruction is meant for usage in spinlock loops
     // (see Intel x86 manual, III, 4.2)
     unsafe {
@@ -28,6 +31,19 @@ pub unsafe fn cpu_halt() {
     asm!("hlt" :::: "volatile");
 }
 
+/// Enables interrupts and halts the cpu, until it is woken again.
+///
+/// # Safety
+/// - Don't use this function directly, rather use the interface through the
+/// sync module.
+#[inline(always)]
+pub unsafe fn enable_interrupts_and_halt() {
+    // Interrupts are only recognized after the instruction following sti, so
+    // none can be handled before the halt.
+    asm!("sti
+          hlt" :::: "volatile");
+}
+
 /// Disables interrupts.
 ///
 /// # Safety
@@ -54,7 +70,7 @@ pub fn interrupts_enabled() -> bool {
     flags().contains(Flags::IF)
 }
 
-/// Returns the current timestamp.
-pub fn get_current_timestamp() -> Timestamp {
-    Timestamp::from_microseconds(unsafe { CLOCK * 1000 })
+/// Returns the clock source used before a better one is selected.
+pub fn get_fallback_clock_source() -> &'static ClockSource {
+    &super::time::LAPIC_CLOCK_SOURCE
 }
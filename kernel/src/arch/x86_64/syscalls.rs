@@ -1,6 +1,7 @@
 //! Serves to accept syscalls.
 
 use super::gdt::{USER_32BIT_CODE_SEGMENT, KERNEL_CODE_SEGMENT, TSS};
+use super::isolation;
 use syscalls::syscall_handler;
 use x86_64::registers::flags::Flags;
 use x86_64::registers::msr::{wrmsr, IA32_FMASK, IA32_KERNEL_GS_BASE, IA32_LSTAR, IA32_STAR};
@@ -11,9 +12,18 @@ pub fn init() {
     let syscall_cs = KERNEL_CODE_SEGMENT.0 as u64;
 
     let star_value = sysret_cs << 48 | syscall_cs << 32;
-    let lstar_value = syscall_entry as u64;
-    let fmask_value = Flags::IF.bits() as u64;
-    let gs_base_value = unsafe { &TSS.privilege_stack_table[0] as *const _ as u64 };
+    // Clearing AC keeps user code from turning off SMAP for the kernel.
+    let fmask_value = (Flags::IF | Flags::AC).bits() as u64;
+    let (lstar_value, gs_base_value) = if isolation::is_enabled() {
+        (
+            isolated_syscall_entry as u64,
+            isolation::get_entry_area_address() as u64,
+        )
+    } else {
+        (syscall_entry as u64, unsafe {
+            &TSS.privilege_stack_table[0] as *const _ as u64
+        })
+    };
 
     unsafe {
         wrmsr(IA32_LSTAR, lstar_value);
@@ -23,32 +33,33 @@ pub fn init() {
     }
 }
 
+/// Passes the syscall in the registers to the handler.
+extern "C" fn syscall_inner() -> i64 {
+    let num;
+    let arg1;
+    let arg2;
+    let arg3;
+    let arg4;
+    let arg5;
+    let arg6;
+    unsafe {
+        asm!("" :
+             "={rax}"(num),
+             "={rdi}"(arg1),
+             "={rsi}"(arg2),
+             "={rdx}"(arg3),
+             "={r10}"(arg4),
+             "={r8}"(arg5),
+             "={r9}"(arg6)
+             : : : "intel", "volatile");
+    }
+
+    syscall_handler(num, arg1, arg2, arg3, arg4, arg5, arg6)
+}
+
 /// The entry point for all syscalls.
 #[naked]
 extern "C" fn syscall_entry() {
-    extern "C" fn syscall_inner() -> i64 {
-        let num;
-        let arg1;
-        let arg2;
-        let arg3;
-        let arg4;
-        let arg5;
-        let arg6;
-        unsafe {
-            asm!("" :
-                 "={rax}"(num),
-                 "={rdi}"(arg1),
-                 "={rsi}"(arg2),
-                 "={rdx}"(arg3),
-                 "={r10}"(arg4),
-                 "={r8}"(arg5),
-                 "={r9}"(arg6)
-                 : : : "intel", "volatile");
-        }
-
-        syscall_handler(num, arg1, arg2, arg3, arg4, arg5, arg6)
-    }
-
     unsafe {
         asm!("// Load the gs base to point to the stack pointer.
               swapgs
@@ -84,3 +95,59 @@ extern "C" fn syscall_entry() {
               : : "i"(syscall_inner as extern "C" fn() -> i64) : : "intel", "volatile");
     }
 }
+
+/// The entry point for all syscalls if the kernel page table is isolated.
+///
+/// The kernel GS base points to the entry area of the CPU.
+#[naked]
+#[inline(never)]
+#[link_section = ".entry_text"]
+extern "C" fn isolated_syscall_entry() {
+    unsafe {
+        asm!("// Load the gs base to point to the entry area.
+              swapgs
+
+              // Save the old stack pointer.
+              mov r12, rsp
+              // Switch to the kernel page table.
+              mov rsp, gs:[8]
+              mov cr3, rsp
+              // Load the new stack pointer.
+              mov rsp, gs:[0]
+
+              // Restore the gs base.
+              swapgs
+
+              // Now that the stack pointer is a kernel stack pointer, enable interrupts.
+              sti
+
+              // Save some context.
+              push r12 //The old stack pointer
+              push r11 //The flags register
+              push rcx //The program counter
+
+              // Call the actual handler.
+              call $0
+
+              // Restore the context.
+              pop rcx
+              pop r11
+              pop r12
+
+              cli
+              swapgs
+
+              // Switch to the user table.
+              mov rsp, gs:[16]
+              mov cr3, rsp
+              mov rsp, gs:[24]
+              mov gs:[16], rsp
+
+              swapgs
+
+              // Restore the old stack pointer.
+              mov rsp, r12
+              sysret"
+              : : "i"(syscall_inner as extern "C" fn() -> i64) : : "intel", "volatile");
+    }
+}
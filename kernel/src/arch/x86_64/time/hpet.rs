@@ -0,0 +1,105 @@
+//! The high precision event timer as a clock source.
+
+use acpi;
+use core::ptr;
+use memory::{map_physical_range, PageFlags, VirtualAddress, PAGE_SIZE};
+use spin::Once;
+use sync::time::ClockSource;
+
+/// The offset of the general capabilities and ID register.
+const CAPABILITIES_REGISTER: usize = 0x000;
+
+/// The offset of the general configuration register.
+const CONFIGURATION_REGISTER: usize = 0x010;
+
+/// The offset of the main counter register.
+const MAIN_COUNTER_REGISTER: usize = 0x0f0;
+
+/// Set in the capabilities if the main counter is 64 bits wide.
+const COUNTER_64_BIT: u64 = 1 << 13;
+
+/// Starts the main counter when set in the configuration register.
+const ENABLE: u64 = 1 << 0;
+
+/// The number of femtoseconds in a second.
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+/// The HPET clock source.
+pub struct Hpet {
+    /// The virtual address of the registers.
+    base: VirtualAddress,
+    /// The frequency of the main counter.
+    frequency: u64,
+}
+
+impl Hpet {
+    /// Reads the given register.
+    fn read_register(&self, offset: usize) -> u64 {
+        unsafe { ptr::read_volatile((self.base + offset) as *const u64) }
+    }
+
+    /// Writes the given register.
+    fn write_register(&self, offset: usize, value: u64) {
+        unsafe { ptr::write_volatile((self.base + offset) as *mut u64, value) }
+    }
+}
+
+impl ClockSource for Hpet {
+    fn name(&self) -> &'static str {
+        "HPET"
+    }
+
+    fn read(&self) -> u64 {
+        self.read_register(MAIN_COUNTER_REGISTER)
+    }
+
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+}
+
+/// The HPET, if it is usable.
+static HPET: Once<Hpet> = Once::new();
+
+/// Initializes the HPET, if ACPI describes one.
+pub fn init() {
+    assert_has_not_been_called!("The HPET should only be initialized once.");
+
+    let info = match acpi::hpet::get_info() {
+        Some(info) => info,
+        None => return,
+    };
+
+    let base = map_physical_range(
+        info.base_address,
+        PAGE_SIZE,
+        PageFlags::READABLE | PageFlags::WRITABLE | PageFlags::NO_CACHE,
+    );
+
+    let mut hpet = Hpet { base, frequency: 0 };
+
+    let capabilities = hpet.read_register(CAPABILITIES_REGISTER);
+
+    // A 32-bit counter would wrap around every few minutes.
+    if capabilities & COUNTER_64_BIT == 0 {
+        debugln!("The HPET only has a 32-bit counter and is not used.");
+        return;
+    }
+
+    // The upper half contains the period in femtoseconds.
+    let period = capabilities >> 32;
+    if period == 0 {
+        return;
+    }
+    hpet.frequency = FEMTOSECONDS_PER_SECOND / period;
+
+    let configuration = hpet.read_register(CONFIGURATION_REGISTER);
+    hpet.write_register(CONFIGURATION_REGISTER, configuration | ENABLE);
+
+    HPET.call_once(|| hpet);
+}
+
+/// Returns the HPET clock source, if it is usable.
+pub fn get_clock_source() -> Option<&'static Hpet> {
+    HPET.try()
+}
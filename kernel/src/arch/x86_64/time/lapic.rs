@@ -0,0 +1,55 @@
+//! The LAPIC timer as a clock source.
+//!
+//! This counts the periods of the periodic timer and interpolates within the
+//! current period. It is only used if no better clock source is available.
+
+use super::super::interrupts::lapic;
+use super::super::sync::CLOCK;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use sync::time::ClockSource;
+
+/// The LAPIC timer clock source.
+pub struct LapicClockSource {
+    /// The highest value read so far.
+    ///
+    /// The counter may reload before the interrupt increments the period
+    /// count, so values are never allowed to decrease.
+    last_value: AtomicUsize,
+}
+
+/// The LAPIC timer clock source.
+pub static LAPIC_CLOCK_SOURCE: LapicClockSource = LapicClockSource {
+    last_value: ATOMIC_USIZE_INIT,
+};
+
+impl ClockSource for LapicClockSource {
+    fn name(&self) -> &'static str {
+        "LAPIC timer"
+    }
+
+    fn read(&self) -> u64 {
+        let ticks_per_ms = lapic::get_ticks_per_ms() as u64;
+        let value = unsafe { CLOCK } * ticks_per_ms + lapic::get_elapsed_timer_ticks() as u64;
+
+        let mut last_value = self.last_value.load(Ordering::Relaxed);
+        loop {
+            if value as usize <= last_value {
+                return last_value as u64;
+            }
+
+            let previous =
+                self.last_value
+                    .compare_and_swap(last_value, value as usize, Ordering::Relaxed);
+
+            if previous == last_value {
+                return value;
+            }
+
+            last_value = previous;
+        }
+    }
+
+    fn frequency(&self) -> u64 {
+        lapic::get_ticks_per_ms() as u64 * 1000
+    }
+}
@@ -0,0 +1,133 @@
+//! Provides the clock sources and timers of the x86_64 architecture.
+
+mod hpet;
+mod lapic;
+mod tsc;
+
+pub use self::lapic::LAPIC_CLOCK_SOURCE;
+
+use super::interrupts::lapic as local_apic;
+use spin::Once;
+use sync::time::{set_clock_source, Timestamp, NANOSECONDS_PER_SECOND};
+use x86_64::instructions::rdtsc;
+
+/// The way the LAPIC timer is operated.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TimerMode {
+    /// The timer fires periodically, the LAPIC timer is the clock source.
+    Periodic,
+    /// The timer is programmed with a count for the next deadline.
+    OneShot,
+    /// The timer fires when the TSC reaches the next deadline.
+    TscDeadline,
+}
+
+/// The selected timer mode.
+static TIMER_MODE: Once<TimerMode> = Once::new();
+
+/// The number of nanoseconds in a millisecond.
+const NANOSECONDS_PER_MILLISECOND: u64 = NANOSECONDS_PER_SECOND / 1000;
+
+/// Selects the best available clock source.
+///
+/// The invariant TSC is preferred, followed by the HPET and the LAPIC timer.
+/// If the LAPIC timer isn't needed to keep time, it is switched to one-shot
+/// operation.
+pub fn init() {
+    assert_has_not_been_called!("The clock sources should only be initialized once.");
+
+    hpet::init();
+
+    let mode = if let Some(tsc) = tsc::init() {
+        set_clock_source(tsc);
+
+        if local_apic::has_tsc_deadline() {
+            TimerMode::TscDeadline
+        } else {
+            TimerMode::OneShot
+        }
+    } else if let Some(hpet) = hpet::get_clock_source() {
+        set_clock_source(hpet);
+        TimerMode::OneShot
+    } else {
+        set_clock_source(&LAPIC_CLOCK_SOURCE);
+        TimerMode::Periodic
+    };
+
+    if mode != TimerMode::Periodic {
+        local_apic::enable_one_shot_timer(mode == TimerMode::TscDeadline);
+    }
+
+    TIMER_MODE.call_once(|| mode);
+}
+
+/// Sets up the timer of an application processor in the selected mode.
+pub fn init_ap() {
+    match *TIMER_MODE.try().expect("The timer mode wasn't selected yet.") {
+        TimerMode::Periodic => {
+            local_apic::set_periodic_timer(local_apic::get_periodic_timer_delay())
+        }
+        mode => local_apic::enable_one_shot_timer(mode == TimerMode::TscDeadline),
+    }
+}
+
+/// Returns true if the timer only fires at programmed deadlines.
+pub fn is_tickless() -> bool {
+    TIMER_MODE
+        .try()
+        .map_or(false, |mode| *mode != TimerMode::Periodic)
+}
+
+/// Programs the timer interrupt of the current CPU to fire at the given time.
+///
+/// If no time is given, the timer is stopped. This does nothing if the timer
+/// is periodic.
+pub fn set_timer_deadline(deadline: Option<Timestamp>) {
+    let mode = match TIMER_MODE.try() {
+        Some(mode) => *mode,
+        None => return,
+    };
+
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => {
+            match mode {
+                TimerMode::Periodic => (),
+                TimerMode::OneShot => local_apic::set_one_shot_timer(0),
+                TimerMode::TscDeadline => local_apic::set_tsc_deadline(0),
+            }
+            return;
+        }
+    };
+
+    let now = Timestamp::get_current();
+    let delay = deadline.as_nanoseconds().saturating_sub(now.as_nanoseconds());
+
+    match mode {
+        TimerMode::Periodic => (),
+        TimerMode::OneShot => {
+            let ticks_per_ms = local_apic::get_ticks_per_ms() as u64;
+            let ticks = delay / NANOSECONDS_PER_MILLISECOND * ticks_per_ms
+                + delay % NANOSECONDS_PER_MILLISECOND * ticks_per_ms / NANOSECONDS_PER_MILLISECOND;
+
+            // A count of zero would stop the timer.
+            let ticks = if ticks > <u32>::max_value() as u64 {
+                <u32>::max_value()
+            } else if ticks == 0 {
+                1
+            } else {
+                ticks as u32
+            };
+
+            local_apic::set_one_shot_timer(ticks);
+        }
+        TimerMode::TscDeadline => {
+            let frequency = tsc::get_tsc_frequency().unwrap();
+            let ticks = delay / NANOSECONDS_PER_SECOND * frequency
+                + delay % NANOSECONDS_PER_SECOND * frequency / NANOSECONDS_PER_SECOND;
+
+            // A deadline in the past fires immediately.
+            local_apic::set_tsc_deadline(rdtsc().saturating_add(ticks));
+        }
+    }
+}
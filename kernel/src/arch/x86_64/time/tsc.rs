@@ -0,0 +1,109 @@
+//! The invariant time stamp counter as a clock source.
+
+use super::hpet;
+use super::super::interrupts::lapic;
+use raw_cpuid::CpuId;
+use spin::Once;
+use sync::time::ClockSource;
+use x86_64::instructions::rdtsc;
+
+/// The time in milliseconds the TSC is measured against the HPET.
+const CALIBRATION_TIME_MS: u64 = 50;
+
+/// The TSC clock source.
+pub struct Tsc {
+    /// The frequency of the TSC.
+    frequency: u64,
+}
+
+impl ClockSource for Tsc {
+    fn name(&self) -> &'static str {
+        "TSC"
+    }
+
+    fn read(&self) -> u64 {
+        rdtsc()
+    }
+
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+}
+
+/// The TSC, if it is usable.
+static TSC: Once<Tsc> = Once::new();
+
+/// Initializes the TSC, if it is invariant.
+pub fn init() -> Option<&'static Tsc> {
+    assert_has_not_been_called!("The TSC should only be initialized once.");
+
+    let invariant = CpuId::new()
+        .get_extended_function_info()
+        .map_or(false, |info| info.has_invariant_tsc());
+
+    if !invariant {
+        return None;
+    }
+
+    let frequency = match get_frequency() {
+        0 => return None,
+        frequency => frequency,
+    };
+
+    Some(TSC.call_once(|| Tsc { frequency }))
+}
+
+/// Returns the frequency of the TSC, if it is usable.
+pub fn get_tsc_frequency() -> Option<u64> {
+    TSC.try().map(|tsc| tsc.frequency)
+}
+
+/// Determines the frequency of the TSC.
+fn get_frequency() -> u64 {
+    // Leaf 0x15 reports the ratio to the crystal clock on newer CPUs.
+    let (denominator, numerator, crystal_frequency) = cpuid_0x15();
+    if denominator != 0 && numerator != 0 && crystal_frequency != 0 {
+        return crystal_frequency as u64 * numerator as u64 / denominator as u64;
+    }
+
+    // Measure against the HPET if possible.
+    if let Some(hpet) = hpet::get_clock_source() {
+        let hpet_ticks = hpet.frequency() * CALIBRATION_TIME_MS / 1000;
+
+        let hpet_start = hpet.read();
+        let tsc_start = rdtsc();
+
+        while hpet.read() - hpet_start < hpet_ticks {}
+
+        let tsc_ticks = rdtsc() - tsc_start;
+        let hpet_elapsed = hpet.read() - hpet_start;
+
+        return tsc_ticks * hpet.frequency() / hpet_elapsed;
+    }
+
+    // Otherwise use the measurement from the LAPIC calibration.
+    lapic::get_tsc_ticks_per_ms() * 1000
+}
+
+/// Executes CPUID leaf 0x15, returning eax, ebx and ecx.
+fn cpuid_0x15() -> (u32, u32, u32) {
+    let max_leaf: u32;
+    unsafe {
+        asm!("cpuid" : "={eax}"(max_leaf) : "{eax}"(0) : "ebx", "ecx", "edx" : "volatile");
+    }
+
+    if max_leaf < 0x15 {
+        return (0, 0, 0);
+    }
+
+    let (eax, ebx, ecx): (u32, u32, u32);
+    unsafe {
+        asm!("cpuid"
+            : "={eax}"(eax), "={ebx}"(ebx), "={ecx}"(ecx)
+            : "{eax}"(0x15), "{ecx}"(0)
+            : "edx"
+            : "volatile");
+    }
+
+    (eax, ebx, ecx)
+}
@@ -6,13 +6,15 @@
 use boot;
 use core::fmt;
 use core::ptr::Unique;
+use drivers::console::ansi::{Action, Parameters, Parser};
 use sync::PreemptableMutex;
 use volatile::Volatile;
+use x86_64::instructions::port::{inb, outb};
 
 /// Represents a color in the buffer.
 #[allow(dead_code)]
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Color {
     Black = 0,
     Blue = 1,
@@ -92,14 +94,82 @@ impl Buffer {
     }
 }
 
+/// The port used to select a CRT controller register.
+const CRTC_ADDRESS_PORT: u16 = 0x3d4;
+
+/// The port used to access the selected CRT controller register.
+const CRTC_DATA_PORT: u16 = 0x3d5;
+
+/// The CRT controller register holding the first scan line of the cursor.
+const CURSOR_START_REGISTER: u8 = 0x0a;
+
+/// The CRT controller register holding the high byte of the cursor position.
+const CURSOR_LOCATION_HIGH_REGISTER: u8 = 0x0e;
+
+/// The CRT controller register holding the low byte of the cursor position.
+const CURSOR_LOCATION_LOW_REGISTER: u8 = 0x0f;
+
+/// Set in the cursor start register to hide the cursor.
+const CURSOR_DISABLE: u8 = 0x20;
+
+/// The distance between two tab stops.
+const TAB_WIDTH: usize = 8;
+
+/// The VGA colors for the eight ANSI colors.
+const ANSI_COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+];
+
+/// The VGA colors for the eight bright ANSI colors.
+const BRIGHT_ANSI_COLORS: [Color; 8] = [
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::Yellow,
+    Color::LightBlue,
+    Color::Pink,
+    Color::LightCyan,
+    Color::White,
+];
+
+/// The default foreground color.
+const DEFAULT_FOREGROUND: Color = Color::LightGray;
+
+/// The default background color.
+const DEFAULT_BACKGROUND: Color = Color::Black;
+
 /// The writer is used to write to a legacy VGA display buffer.
+///
+/// It understands ANSI escape sequences for colors, cursor movement and
+/// erasing.
 pub struct Writer {
     /// The current column position.
     column_position: usize,
     /// The current row position.
     row_position: usize,
-    /// The color code used throughout the buffer.
+    /// The color code used for new characters.
     color_code: ColorCode,
+    /// The selected foreground color.
+    foreground: Color,
+    /// The selected background color.
+    background: Color,
+    /// Set if bright foreground colors are used.
+    bold: bool,
+    /// Set if foreground and background are swapped.
+    reverse: bool,
+    /// The cursor position saved by an escape sequence.
+    saved_position: (usize, usize),
+    /// Set if the hardware cursor is shown.
+    cursor_visible: bool,
+    /// The parser for escape sequences.
+    parser: Parser,
     /// Access to the buffer itself.
     buffer: Buffer,
 }
@@ -108,35 +178,244 @@ pub struct Writer {
 impl Writer {
     /// Writes the given character to the buffer.
     pub fn write_char(&mut self, byte: u8) {
-        match byte {
-            b'\n' => self.new_line(),
-            byte => {
-                if self.column_position >= self.buffer.width {
-                    self.new_line();
+        self.process_byte(byte);
+        self.update_cursor();
+    }
+
+    /// Writes the given string to the buffer.
+    pub fn write_string(&mut self, string: &str) {
+        for byte in string.bytes() {
+            self.process_byte(byte);
+        }
+
+        self.update_cursor();
+    }
+
+    /// Passes the byte through the escape sequence parser and executes the
+    /// result.
+    fn process_byte(&mut self, byte: u8) {
+        match self.parser.advance(byte as char) {
+            Some(Action::Print(character)) => self.put_char(character as u8),
+            Some(Action::Control(character)) => self.control(character),
+            Some(Action::ControlSequence {
+                parameters,
+                private,
+                command,
+            }) => self.control_sequence(&parameters, private, command),
+            None => (),
+        }
+    }
+
+    /// Prints the byte at the cursor position.
+    fn put_char(&mut self, byte: u8) {
+        if self.column_position >= self.buffer.width {
+            self.new_line();
+        }
+
+        let column_position = self.column_position;
+        let row_position = self.row_position;
+        let color_code = self.color_code;
+
+        self.buffer.write_char(
+            row_position,
+            column_position,
+            ScreenChar {
+                character: byte,
+                color_code: color_code,
+            },
+        );
+
+        self.column_position += 1;
+    }
+
+    /// Executes the given control character.
+    fn control(&mut self, character: char) {
+        match character {
+            '\n' => self.new_line(),
+            '\r' => self.column_position = 0,
+            '\t' => {
+                let next_stop = (self.column_position / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.column_position = if next_stop < self.buffer.width {
+                    next_stop
+                } else {
+                    self.buffer.width - 1
+                };
+            }
+            '\x08' => self.column_position = self.column_position.saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    /// Executes the given control sequence.
+    fn control_sequence(&mut self, parameters: &Parameters, private: bool, command: char) {
+        let count = parameters.get(0, 1) as usize;
+
+        if private {
+            // Only showing and hiding the cursor is supported.
+            match (parameters.get(0, 0), command) {
+                (25, 'h') => self.set_cursor_visible(true),
+                (25, 'l') => self.set_cursor_visible(false),
+                _ => (),
+            }
+            return;
+        }
+
+        match command {
+            'A' => self.row_position = self.row_position.saturating_sub(count),
+            'B' => self.row_position = self.row_position.saturating_add(count),
+            'C' => self.column_position = self.column_position.saturating_add(count),
+            'D' => self.column_position = self.column_position.saturating_sub(count),
+            'E' => {
+                self.row_position = self.row_position.saturating_add(count);
+                self.column_position = 0;
+            }
+            'F' => {
+                self.row_position = self.row_position.saturating_sub(count);
+                self.column_position = 0;
+            }
+            'G' => self.column_position = count - 1,
+            'H' | 'f' => {
+                self.row_position = parameters.get(0, 1) as usize - 1;
+                self.column_position = parameters.get(1, 1) as usize - 1;
+            }
+            'J' => self.erase_in_display(parameters.get(0, 0)),
+            'K' => self.erase_in_line(parameters.get(0, 0)),
+            'm' => self.select_graphic_rendition(parameters),
+            's' => self.saved_position = (self.row_position, self.column_position),
+            'u' => {
+                let (row, column) = self.saved_position;
+                self.row_position = row;
+                self.column_position = column;
+            }
+            _ => (),
+        }
+
+        self.clamp_position();
+    }
+
+    /// Keeps the cursor within the screen after it was moved.
+    fn clamp_position(&mut self) {
+        if self.row_position >= self.buffer.height {
+            self.row_position = self.buffer.height - 1;
+        }
+
+        if self.column_position >= self.buffer.width {
+            self.column_position = self.buffer.width - 1;
+        }
+    }
+
+    /// Erases parts of the screen.
+    ///
+    /// Mode 0 erases from the cursor to the end, mode 1 from the start to the
+    /// cursor and mode 2 the whole screen.
+    fn erase_in_display(&mut self, mode: u16) {
+        let row = self.row_position;
+
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                for line in row + 1..self.buffer.height {
+                    self.clear_line(line);
+                }
+            }
+            1 => {
+                for line in 0..row {
+                    self.clear_line(line);
                 }
+                self.erase_in_line(1);
+            }
+            2 => {
+                for line in 0..self.buffer.height {
+                    self.clear_line(line);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Erases parts of the current line.
+    ///
+    /// Mode 0 erases from the cursor to the end, mode 1 from the start to the
+    /// cursor and mode 2 the whole line.
+    fn erase_in_line(&mut self, mode: u16) {
+        let width = self.buffer.width;
+        let column = if self.column_position < width {
+            self.column_position
+        } else {
+            width - 1
+        };
+
+        let (start, end) = match mode {
+            0 => (column, width),
+            1 => (0, column + 1),
+            2 => (0, width),
+            _ => return,
+        };
 
-                let column_position = self.column_position;
-                let row_position = self.row_position;
-                let color_code = self.color_code;
+        let row = self.row_position;
+        let space = self.blank();
+        for i in start..end {
+            self.buffer.write_char(row, i, space);
+        }
+    }
 
-                self.buffer.write_char(
-                    row_position,
-                    column_position,
-                    ScreenChar {
-                        character: byte,
-                        color_code: color_code,
-                    },
-                );
+    /// Applies the parameters of an SGR sequence to the colors.
+    fn select_graphic_rendition(&mut self, parameters: &Parameters) {
+        if parameters.len() == 0 {
+            self.reset_attributes();
+        }
 
-                self.column_position += 1;
+        for index in 0..parameters.len() {
+            match parameters.get(index, 0) {
+                0 => self.reset_attributes(),
+                1 => self.bold = true,
+                7 => self.reverse = true,
+                22 => self.bold = false,
+                27 => self.reverse = false,
+                code @ 30...37 => self.foreground = ANSI_COLORS[code as usize - 30],
+                39 => self.foreground = DEFAULT_FOREGROUND,
+                code @ 40...47 => self.background = ANSI_COLORS[code as usize - 40],
+                49 => self.background = DEFAULT_BACKGROUND,
+                code @ 90...97 => self.foreground = BRIGHT_ANSI_COLORS[code as usize - 90],
+                code @ 100...107 => self.background = BRIGHT_ANSI_COLORS[code as usize - 100],
+                _ => (),
             }
         }
+
+        self.update_color_code();
     }
 
-    /// Writes the given string to the buffer.
-    pub fn write_string(&mut self, string: &str) {
-        for byte in string.bytes() {
-            self.write_char(byte);
+    /// Resets the colors to the defaults.
+    fn reset_attributes(&mut self) {
+        self.foreground = DEFAULT_FOREGROUND;
+        self.background = DEFAULT_BACKGROUND;
+        self.bold = false;
+        self.reverse = false;
+    }
+
+    /// Computes the color code from the selected colors.
+    fn update_color_code(&mut self) {
+        let mut foreground = self.foreground;
+
+        // Bold text uses the bright variant of the normal colors.
+        if self.bold {
+            if let Some(index) = ANSI_COLORS.iter().position(|&color| color == foreground) {
+                foreground = BRIGHT_ANSI_COLORS[index];
+            }
+        }
+
+        self.color_code = if self.reverse {
+            ColorCode::new(self.background, foreground)
+        } else {
+            ColorCode::new(foreground, self.background)
+        };
+    }
+
+    /// Returns an empty character with the current colors.
+    fn blank(&self) -> ScreenChar {
+        ScreenChar {
+            character: b' ',
+            color_code: self.color_code,
         }
     }
 
@@ -166,12 +445,8 @@ impl Writer {
 
     /// Clears the given line.
     fn clear_line(&mut self, line: usize) {
-        let color_code = self.color_code;
         let width = self.buffer.width;
-        let space = ScreenChar {
-            character: b' ',
-            color_code: color_code,
-        };
+        let space = self.blank();
 
         for i in 0..width {
             self.buffer.write_char(line, i, space);
@@ -186,6 +461,43 @@ impl Writer {
 
         self.column_position = 0;
         self.row_position = 0;
+        self.update_cursor();
+    }
+
+    /// Shows or hides the hardware cursor.
+    fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+
+        unsafe {
+            let cursor_start = read_crtc_register(CURSOR_START_REGISTER);
+
+            let cursor_start = if visible {
+                cursor_start & !CURSOR_DISABLE
+            } else {
+                cursor_start | CURSOR_DISABLE
+            };
+
+            write_crtc_register(CURSOR_START_REGISTER, cursor_start);
+        }
+    }
+
+    /// Moves the hardware cursor to the cursor position.
+    fn update_cursor(&mut self) {
+        if !self.cursor_visible {
+            return;
+        }
+
+        let column = if self.column_position < self.buffer.width {
+            self.column_position
+        } else {
+            self.buffer.width - 1
+        };
+        let position = (self.row_position * self.buffer.width + column) as u16;
+
+        unsafe {
+            write_crtc_register(CURSOR_LOCATION_LOW_REGISTER, position as u8);
+            write_crtc_register(CURSOR_LOCATION_HIGH_REGISTER, (position >> 8) as u8);
+        }
     }
 
     /// Initializes the buffer.
@@ -195,6 +507,7 @@ impl Writer {
         self.buffer.height = info.height;
         self.buffer.width = info.width;
         self.buffer.address = unsafe { Unique::new_unchecked(info.address as *mut _) };
+        self.update_cursor();
     }
 }
 
@@ -206,12 +519,38 @@ impl fmt::Write for Writer {
     }
 }
 
+/// Reads the given CRT controller register.
+///
+/// # Safety
+/// - The caller must hold the writer lock.
+unsafe fn read_crtc_register(register: u8) -> u8 {
+    outb(CRTC_ADDRESS_PORT, register);
+    inb(CRTC_DATA_PORT)
+}
+
+/// Writes the given CRT controller register.
+///
+/// # Safety
+/// - The caller must hold the writer lock.
+/// - Wrong values can make the display unusable.
+unsafe fn write_crtc_register(register: u8, value: u8) {
+    outb(CRTC_ADDRESS_PORT, register);
+    outb(CRTC_DATA_PORT, value);
+}
+
 /// The Writer that is used to print to the screen.
 pub static WRITER: PreemptableMutex<Writer> = PreemptableMutex::new(Writer {
     column_position: 0,
     row_position: 0,
-    color_code: ColorCode::new(Color::LightGray, Color::Black),
-    buffer: Buffer::new(to_virtual!(0xb8000), 25, 80),
+    color_code: ColorCode::new(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+    foreground: DEFAULT_FOREGROUND,
+    background: DEFAULT_BACKGROUND,
+    bold: false,
+    reverse: false,
+    saved_position: (0, 0),
+    cursor_visible: true,
+    parser: Parser::new(),
+    buffer: Buffer::new(to_virtual!(0xb8000), 80, 25),
 });
 
 /// Contains basic buffer information.
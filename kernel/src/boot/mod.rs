@@ -4,8 +4,11 @@ mod multiboot;
 
 #[cfg(target_arch = "x86_64")]
 use arch::vga_buffer;
+use core::cmp::min;
+use core::str;
 use memory::{get_kernel_end_address, get_kernel_start_address, FreeMemoryArea, PhysicalAddress,
-             PAGE_SIZE};
+             AP_TRAMPOLINE_ADDRESS, PAGE_SIZE};
+use spin::Once;
 
 /// Lists possiblities for boot sources.
 enum BootMethod {
@@ -17,6 +20,88 @@ enum BootMethod {
     Multiboot2,
 }
 
+/// The position and size of a color channel within a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorField {
+    /// The position of the lowest bit of the channel.
+    pub position: u8,
+    /// The number of bits of the channel.
+    pub size: u8,
+}
+
+impl ColorField {
+    /// Places the given 8 bit channel value in the field.
+    pub fn place(&self, value: u8) -> u32 {
+        let value = if self.size >= 8 {
+            (value as u32) << (self.size - 8)
+        } else {
+            value as u32 >> (8 - self.size)
+        };
+
+        value << self.position
+    }
+}
+
+/// Describes the linear framebuffer set up by the boot loader.
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferInfo {
+    /// The physical address of the framebuffer.
+    pub address: PhysicalAddress,
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// The number of bytes per line.
+    pub pitch: u32,
+    /// The number of bits per pixel.
+    pub bpp: u8,
+    /// The red channel of a pixel.
+    pub red: ColorField,
+    /// The green channel of a pixel.
+    pub green: ColorField,
+    /// The blue channel of a pixel.
+    pub blue: ColorField,
+}
+
+impl FramebufferInfo {
+    /// Creates the framebuffer information from the fields shared by both
+    /// multiboot versions.
+    ///
+    /// `color_info` holds the position and size of the red, green and blue
+    /// channels.
+    fn from_multiboot(
+        address: PhysicalAddress,
+        pitch: u32,
+        width: u32,
+        height: u32,
+        bpp: u8,
+        color_info: [u8; 6],
+    ) -> FramebufferInfo {
+        FramebufferInfo {
+            address,
+            width,
+            height,
+            pitch,
+            bpp,
+            red: ColorField {
+                position: color_info[0],
+                size: color_info[1],
+            },
+            green: ColorField {
+                position: color_info[2],
+                size: color_info[3],
+            },
+            blue: ColorField {
+                position: color_info[4],
+                size: color_info[5],
+            },
+        }
+    }
+}
+
+/// The multiboot framebuffer type for direct RGB color.
+const DIRECT_RGB_FRAMEBUFFER_TYPE: u8 = 1;
+
 /// Represents an area to be excluded from the available memory map.
 struct MemoryMapExcludeArea {
     /// The start address of the area to be excluded.
@@ -52,6 +137,14 @@ impl MemoryMapExcludeArea {
         }
     }
 
+    /// The memory area the application processor trampoline is copied to.
+    fn ap_trampoline() -> MemoryMapExcludeArea {
+        MemoryMapExcludeArea {
+            start: AP_TRAMPOLINE_ADDRESS,
+            length: PAGE_SIZE,
+        }
+    }
+
     /// Checks if the area is contained within another area.
     fn is_contained_in(&self, area: FreeMemoryArea) -> bool {
         area.start_address() <= self.start && self.start + self.length <= area.end_address()
@@ -67,7 +160,7 @@ impl MemoryMapExcludeArea {
 pub struct MemoryMapIterator {
     multiboot_iterator: Option<multiboot::MemoryMapIterator>,
     multiboot2_iterator: Option<multiboot2::MemoryMapIterator>,
-    to_exclude: [MemoryMapExcludeArea; 2],
+    to_exclude: [MemoryMapExcludeArea; 3],
     current_entry: Option<FreeMemoryArea>,
     exclude_index: usize,
 }
@@ -75,14 +168,20 @@ pub struct MemoryMapIterator {
 impl MemoryMapIterator {
     /// Creates a new memory map iterator.
     fn new() -> MemoryMapIterator {
-        let kernel_area = MemoryMapExcludeArea::kernel();
-        let initramfs_area = MemoryMapExcludeArea::initramfs();
-
-        let to_exclude = if kernel_area.start <= initramfs_area.start {
-            [kernel_area, initramfs_area]
-        } else {
-            [initramfs_area, kernel_area]
-        };
+        let mut to_exclude = [
+            MemoryMapExcludeArea::ap_trampoline(),
+            MemoryMapExcludeArea::kernel(),
+            MemoryMapExcludeArea::initramfs(),
+        ];
+
+        // Sort the areas by their start addresses.
+        for i in 1..to_exclude.len() {
+            let mut j = i;
+            while j > 0 && to_exclude[j - 1].start > to_exclude[j].start {
+                to_exclude.swap(j - 1, j);
+                j -= 1;
+            }
+        }
 
         let exclude_index = 0;
 
@@ -122,7 +221,8 @@ impl Iterator for MemoryMapIterator {
         // - The to_exclude list must be ordered by the start addresses.
         // - The to_exclude entries must not overlap.
         // - The memory areas must not overlap.
-        // - A to_exclude entry must lie completely within a memory area.
+        // - A to_exclude entry must lie completely within a memory area, or
+        //   outside of all of them.
 
         let get_next_entry = |iterator: &mut MemoryMapIterator| match *get_boot_method() {
             BootMethod::Multiboot => match iterator.multiboot_iterator {
@@ -147,7 +247,14 @@ impl Iterator for MemoryMapIterator {
                 } else {
                     // Handle the exclude areas.
 
-                    if self.to_exclude[self.exclude_index].is_contained_in(current_entry) {
+                    if self.to_exclude[self.exclude_index].end_address()
+                        <= current_entry.start_address()
+                    {
+                        // The area to exclude isn't part of any free area.
+                        self.exclude_index += 1;
+
+                        continue;
+                    } else if self.to_exclude[self.exclude_index].is_contained_in(current_entry) {
                         // The area to exclude is contained in the current free entry.
                         let (entry_before, entry_after) = {
                             let exclude_area = &self.to_exclude[self.exclude_index];
@@ -190,6 +297,14 @@ impl Iterator for MemoryMapIterator {
     }
 }
 
+/// The maximum length of the kernel command line that is kept.
+const COMMAND_LINE_MAX_LENGTH: usize = 256;
+
+/// The copy of the kernel command line and its length.
+///
+/// It is copied, because the information structure is not mapped later.
+static COMMAND_LINE: Once<([u8; COMMAND_LINE_MAX_LENGTH], usize)> = Once::new();
+
 /// The method that the system was booted with.
 // This will only be set once very early. After that it can be assumed to be
 // static.
@@ -208,6 +323,40 @@ pub fn init(magic_number: u32, information_structure_address: usize) {
     };
 }
 
+/// Keeps a copy of the kernel command line passed by the boot loader.
+///
+/// The command line ends at the first null byte and is cut off if it is too
+/// long.
+fn set_command_line(command_line: &[u8]) {
+    let length = command_line
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(command_line.len());
+    let length = min(length, COMMAND_LINE_MAX_LENGTH);
+
+    let mut buffer = [0; COMMAND_LINE_MAX_LENGTH];
+    buffer[..length].copy_from_slice(&command_line[..length]);
+
+    COMMAND_LINE.call_once(|| (buffer, length));
+}
+
+/// Returns the kernel command line given by the boot loader.
+pub fn get_command_line() -> &'static str {
+    COMMAND_LINE
+        .try()
+        .and_then(|&(ref buffer, length)| str::from_utf8(&buffer[..length]).ok())
+        .unwrap_or("")
+}
+
+/// Checks if the option was given on the kernel command line.
+///
+/// Options are separated by whitespace.
+pub fn has_option(option: &str) -> bool {
+    get_command_line()
+        .split_whitespace()
+        .any(|word| word == option)
+}
+
 /// Identifies the boot method.
 fn set_boot_method(magic_number: u32) {
     unsafe {
@@ -234,6 +383,17 @@ pub fn get_vga_info() -> vga_buffer::Info {
     }
 }
 
+/// Returns information about the linear framebuffer, if there is one.
+///
+/// Text mode and indexed color framebuffers are not reported.
+pub fn get_framebuffer_info() -> Option<FramebufferInfo> {
+    match *get_boot_method() {
+        BootMethod::Multiboot => multiboot::get_framebuffer_info(),
+        BootMethod::Multiboot2 => multiboot2::get_framebuffer_info(),
+        _ => unimplemented!(),
+    }
+}
+
 /// Returns the name of the boot loader.
 pub fn get_bootloader_name() -> &'static str {
     match *get_boot_method() {
@@ -261,6 +421,14 @@ pub fn get_initramfs_length() -> usize {
     }
 }
 
+/// Returns the copy of the ACPI RSDP given by the boot loader, if any.
+pub fn get_rsdp() -> Option<&'static [u8]> {
+    match *get_boot_method() {
+        BootMethod::Multiboot2 => multiboot2::get_rsdp(),
+        _ => None,
+    }
+}
+
 /// Returns an iterator for the map of usable memory.
 pub fn get_memory_map() -> MemoryMapIterator {
     MemoryMapIterator::new()
@@ -3,6 +3,7 @@
 use core::mem::size_of;
 use memory::{FreeMemoryArea, PhysicalAddress};
 use arch::vga_buffer;
+use super::{FramebufferInfo, DIRECT_RGB_FRAMEBUFFER_TYPE};
 
 /// Represents the multiboot information structure.
 #[repr(C)]
@@ -115,6 +116,11 @@ pub fn init(information_structure_address: usize) {
     };
 
     assert!(!get_flags().contains(MultibootFlags::A_OUT | MultibootFlags::ELF));
+
+    if get_flags().contains(MultibootFlags::CMDLINE) {
+        let command_line = from_c_str!(to_virtual!(get_info().cmdline)).unwrap_or("");
+        super::set_command_line(command_line.as_bytes());
+    }
 }
 
 /// Returns the VGA buffer information requested.
@@ -140,6 +146,26 @@ pub fn get_vga_info() -> vga_buffer::Info {
     }
 }
 
+/// Returns information about the linear framebuffer, if there is one.
+pub fn get_framebuffer_info() -> Option<FramebufferInfo> {
+    let info = get_info();
+
+    if get_flags().contains(MultibootFlags::FRAMEBUFFER)
+        && info.framebuffer_type == DIRECT_RGB_FRAMEBUFFER_TYPE
+    {
+        Some(FramebufferInfo::from_multiboot(
+            info.framebuffer_addr as PhysicalAddress,
+            info.framebuffer_pitch,
+            info.framebuffer_width,
+            info.framebuffer_height,
+            info.framebuffer_bpp,
+            info.color_info,
+        ))
+    } else {
+        None
+    }
+}
+
 /// Returns the start address of the initramfs.
 pub fn get_initramfs_start() -> PhysicalAddress {
     get_initramfs_module_entry().mod_start as usize
@@ -4,13 +4,187 @@ use memory::{FreeMemoryArea, PhysicalAddress};
 use spin::Once;
 use multiboot2;
 use arch::vga_buffer;
+use super::{FramebufferInfo, DIRECT_RGB_FRAMEBUFFER_TYPE};
 
 static BOOT_INFO: Once<&multiboot2::BootInformation> = Once::new();
 
+/// The type of the tag containing the kernel command line.
+const COMMAND_LINE_TAG_TYPE: u32 = 1;
+
+/// The type of the tag containing a copy of the ACPI 1.0 RSDP.
+const OLD_RSDP_TAG_TYPE: u32 = 14;
+
+/// The type of the tag containing a copy of the ACPI 2.0+ RSDP.
+const NEW_RSDP_TAG_TYPE: u32 = 15;
+
+/// The type of the framebuffer tag.
+const FRAMEBUFFER_TAG_TYPE: u32 = 8;
+
+/// The offset of the color info within the framebuffer tag.
+const FRAMEBUFFER_COLOR_INFO_OFFSET: usize = 32;
+
+/// The maximum size of an RSDP.
+const RSDP_MAX_SIZE: usize = 36;
+
+/// The copy of the RSDP passed by the boot loader.
+///
+/// It is copied, because the information structure is not mapped later.
+static RSDP: Once<[u8; RSDP_MAX_SIZE]> = Once::new();
+
+/// The framebuffer information passed by the boot loader.
+static FRAMEBUFFER: Once<FramebufferInfo> = Once::new();
+
 /// Initializes the multiboot module.
 pub fn init(information_structure_address: usize) {
     assert_has_not_been_called!("The multiboot2 module should only be initialized once.");
     BOOT_INFO.call_once(|| unsafe { multiboot2::load(information_structure_address) });
+
+    unsafe {
+        copy_rsdp(information_structure_address);
+        copy_framebuffer_info(information_structure_address);
+        copy_command_line(information_structure_address);
+    }
+}
+
+/// Returns the tags of the information structure as (type, address, size).
+///
+/// This is used for tags that the multiboot2 crate doesn't know about.
+///
+/// # Safety
+/// - The information structure must still be identity mapped.
+unsafe fn raw_tags(information_structure_address: usize) -> RawTagIterator {
+    RawTagIterator {
+        // The tags start after the total size and the reserved field.
+        current: information_structure_address + 8,
+        end: information_structure_address + *(information_structure_address as *const u32) as usize,
+    }
+}
+
+/// Iterates over the raw tags of the information structure.
+struct RawTagIterator {
+    /// The address of the next tag.
+    current: usize,
+    /// The end of the information structure.
+    end: usize,
+}
+
+impl Iterator for RawTagIterator {
+    type Item = (u32, usize, usize);
+
+    fn next(&mut self) -> Option<(u32, usize, usize)> {
+        if self.current + 8 > self.end {
+            return None;
+        }
+
+        let (tag_type, size) = unsafe {
+            (
+                *(self.current as *const u32),
+                *((self.current + 4) as *const u32) as usize,
+            )
+        };
+
+        // Type 0 marks the end of the tags.
+        if tag_type == 0 || size < 8 {
+            return None;
+        }
+
+        let tag = (tag_type, self.current, size);
+
+        // Tags are 8 byte aligned.
+        self.current += (size + 7) & !7;
+
+        Some(tag)
+    }
+}
+
+/// Copies the RSDP from the information structure, if present.
+///
+/// # Safety
+/// - The information structure must still be identity mapped.
+unsafe fn copy_rsdp(information_structure_address: usize) {
+    let mut rsdp_tag = None;
+
+    for (tag_type, address, size) in raw_tags(information_structure_address) {
+        match tag_type {
+            // Prefer the newer RSDP if both are present.
+            NEW_RSDP_TAG_TYPE => rsdp_tag = Some((address, size)),
+            OLD_RSDP_TAG_TYPE if rsdp_tag.is_none() => rsdp_tag = Some((address, size)),
+            _ => (),
+        }
+    }
+
+    if let Some((address, size)) = rsdp_tag {
+        let mut rsdp = [0; RSDP_MAX_SIZE];
+        let length = ::core::cmp::min(size - 8, RSDP_MAX_SIZE);
+
+        for (i, byte) in rsdp.iter_mut().take(length).enumerate() {
+            *byte = *((address + 8 + i) as *const u8);
+        }
+
+        RSDP.call_once(|| rsdp);
+    }
+}
+
+/// Copies the framebuffer information, if it describes a direct RGB
+/// framebuffer.
+///
+/// # Safety
+/// - The information structure must still be identity mapped.
+unsafe fn copy_framebuffer_info(information_structure_address: usize) {
+    let tag = raw_tags(information_structure_address)
+        .find(|&(tag_type, _, _)| tag_type == FRAMEBUFFER_TAG_TYPE);
+
+    if let Some((_, address, size)) = tag {
+        if size < FRAMEBUFFER_COLOR_INFO_OFFSET + 6 {
+            return;
+        }
+
+        let framebuffer_type = *((address + 29) as *const u8);
+        if framebuffer_type != DIRECT_RGB_FRAMEBUFFER_TYPE {
+            return;
+        }
+
+        let mut color_info = [0; 6];
+        for (i, byte) in color_info.iter_mut().enumerate() {
+            *byte = *((address + FRAMEBUFFER_COLOR_INFO_OFFSET + i) as *const u8);
+        }
+
+        let info = FramebufferInfo::from_multiboot(
+            *((address + 8) as *const u64) as PhysicalAddress,
+            *((address + 16) as *const u32),
+            *((address + 20) as *const u32),
+            *((address + 24) as *const u32),
+            *((address + 28) as *const u8),
+            color_info,
+        );
+
+        FRAMEBUFFER.call_once(|| info);
+    }
+}
+
+/// Copies the kernel command line from the information structure, if present.
+///
+/// # Safety
+/// - The information structure must still be identity mapped.
+unsafe fn copy_command_line(information_structure_address: usize) {
+    let tag = raw_tags(information_structure_address)
+        .find(|&(tag_type, _, _)| tag_type == COMMAND_LINE_TAG_TYPE);
+
+    if let Some((_, address, size)) = tag {
+        let command_line = ::core::slice::from_raw_parts((address + 8) as *const u8, size - 8);
+
+        super::set_command_line(command_line);
+    }
+}
+
+/// Returns the framebuffer information given by the boot loader.
+pub fn get_framebuffer_info() -> Option<FramebufferInfo> {
+    FRAMEBUFFER.try().cloned()
+}
+
+/// Returns the copy of the RSDP given by the boot loader.
+pub fn get_rsdp() -> Option<&'static [u8]> {
+    RSDP.try().map(|rsdp| &rsdp[..])
 }
 
 /// Returns the VGA buffer information requested.
@@ -0,0 +1,381 @@
+//! A driver for the Bochs Graphics Adapter.
+//!
+//! This is the standard VGA device of Bochs and QEMU. It is programmed
+//! through the DISPI registers and displays a linear framebuffer in BAR 0.
+//! Pages are stacked vertically in the virtual screen and flipped by changing
+//! the Y offset.
+
+use super::framebuffer::{self, Framebuffer, FramebufferDevice, FramebufferError, Mode};
+use super::pci::{register_driver, PciDeviceFunction, PciDeviceId, PciDriver, PciError};
+use alloc::Vec;
+use boot::{ColorField, FramebufferInfo};
+use memory::PhysicalAddress;
+use sync::PreemptableMutex;
+use x86_64::instructions::port::{inw, outw};
+
+/// The port used to select a DISPI register.
+const DISPI_INDEX_PORT: u16 = 0x01ce;
+
+/// The port used to access the selected DISPI register.
+const DISPI_DATA_PORT: u16 = 0x01cf;
+
+/// The register holding the version of the interface.
+const ID_REGISTER: u16 = 0x0;
+
+/// The register holding the horizontal resolution.
+const X_RESOLUTION_REGISTER: u16 = 0x1;
+
+/// The register holding the vertical resolution.
+const Y_RESOLUTION_REGISTER: u16 = 0x2;
+
+/// The register holding the number of bits per pixel.
+const BPP_REGISTER: u16 = 0x3;
+
+/// The register enabling the display.
+const ENABLE_REGISTER: u16 = 0x4;
+
+/// The register holding the width of the virtual screen.
+const VIRTUAL_WIDTH_REGISTER: u16 = 0x6;
+
+/// The register holding the height of the virtual screen.
+const VIRTUAL_HEIGHT_REGISTER: u16 = 0x7;
+
+/// The register holding the displayed column of the virtual screen.
+const X_OFFSET_REGISTER: u16 = 0x8;
+
+/// The register holding the displayed line of the virtual screen.
+const Y_OFFSET_REGISTER: u16 = 0x9;
+
+/// The register holding the size of the video memory in 64KiB units.
+const VIDEO_MEMORY_REGISTER: u16 = 0xa;
+
+/// The first version supporting 32 bits per pixel.
+const MIN_VERSION: u16 = 0xb0c2;
+
+/// The highest known version.
+const MAX_VERSION: u16 = 0xb0c5;
+
+/// Set in the enable register to enable the display.
+const ENABLED: u16 = 0x01;
+
+/// Set in the enable register to read the maximum resolution and depth.
+const GET_CAPABILITIES: u16 = 0x02;
+
+/// Set in the enable register to use the linear framebuffer.
+const LINEAR_FRAMEBUFFER_ENABLED: u16 = 0x40;
+
+/// Set in the enable register to keep the memory contents on mode switches.
+const NO_CLEAR_MEMORY: u16 = 0x80;
+
+/// The size of the video memory if the device doesn't report it.
+const DEFAULT_MEMORY_SIZE: usize = 16 * 1024 * 1024;
+
+/// The maximum number of pages that are set up for page flipping.
+const MAX_PAGES: u32 = 2;
+
+/// The bits per pixel of all offered modes.
+const MODE_BPP: u8 = 32;
+
+/// The resolutions that are offered if they fit in the video memory.
+const RESOLUTIONS: [(u32, u32); 9] = [
+    (640, 480),
+    (800, 600),
+    (1024, 768),
+    (1280, 720),
+    (1280, 1024),
+    (1366, 768),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+];
+
+/// The devices handled by this driver.
+static BGA_IDS: [PciDeviceId; 1] = [PciDeviceId::device(0x1234, 0x1111)];
+
+/// The state of an initialized adapter.
+struct BgaState {
+    /// The physical address of the video memory.
+    address: PhysicalAddress,
+    /// The size of the video memory in bytes.
+    memory_size: usize,
+    /// The maximum horizontal resolution.
+    max_width: u32,
+    /// The maximum vertical resolution.
+    max_height: u32,
+    /// The current mode, if the display is enabled.
+    mode: Option<Mode>,
+    /// The number of pages in the current mode.
+    pages: u32,
+}
+
+/// The Bochs Graphics Adapter driver.
+struct Bga {
+    /// The state of the adapter, once it was probed.
+    state: PreemptableMutex<Option<BgaState>>,
+}
+
+/// The driver instance.
+static BGA: Bga = Bga {
+    state: PreemptableMutex::new(None),
+};
+
+/// Registers the driver with the PCI subsystem.
+pub fn init() {
+    assert_has_not_been_called!("The BGA driver should only be initialized once.");
+
+    register_driver(&BGA);
+}
+
+impl PciDriver for Bga {
+    fn name(&self) -> &'static str {
+        "bga"
+    }
+
+    fn id_table(&self) -> &'static [PciDeviceId] {
+        &BGA_IDS
+    }
+
+    fn probe(&self, device: &PciDeviceFunction) -> Result<(), PciError> {
+        let mut state = self.state.lock();
+
+        // Only one adapter is supported.
+        if state.is_some() {
+            return Err(PciError::ProbeFailed);
+        }
+
+        let address = device.memory_bar(0).ok_or(PciError::ProbeFailed)?;
+
+        let version = unsafe { read_register(ID_REGISTER) };
+        if version < MIN_VERSION || version > MAX_VERSION {
+            debugln!("Unsupported BGA version {:#x}.", version);
+            return Err(PciError::ProbeFailed);
+        }
+
+        let memory_size = match unsafe { read_register(VIDEO_MEMORY_REGISTER) } {
+            0 => DEFAULT_MEMORY_SIZE,
+            size => size as usize * 64 * 1024,
+        };
+
+        let (max_width, max_height) = unsafe { read_capabilities() };
+
+        let mut new_state = BgaState {
+            address,
+            memory_size,
+            max_width,
+            max_height,
+            mode: None,
+            pages: 0,
+        };
+
+        // Keep the mode of the boot loader, but set up the pages for flipping.
+        let (enable, current_mode) = unsafe {
+            (
+                read_register(ENABLE_REGISTER),
+                Mode {
+                    width: read_register(X_RESOLUTION_REGISTER) as u32,
+                    height: read_register(Y_RESOLUTION_REGISTER) as u32,
+                    bpp: read_register(BPP_REGISTER) as u8,
+                },
+            )
+        };
+
+        if enable & ENABLED != 0 && current_mode.bpp == MODE_BPP {
+            new_state
+                .set_mode(current_mode, NO_CLEAR_MEMORY)
+                .map_err(|_| PciError::ProbeFailed)?;
+        }
+
+        debugln!(
+            "BGA version {:#x} with {}KiB of video memory at {:#x}, up to {}x{}.",
+            version,
+            memory_size / 1024,
+            address,
+            max_width,
+            max_height
+        );
+
+        *state = Some(new_state);
+        drop(state);
+
+        framebuffer::register_device(&BGA);
+
+        Ok(())
+    }
+}
+
+impl FramebufferDevice for Bga {
+    fn name(&self) -> &'static str {
+        "Bochs Graphics Adapter"
+    }
+
+    fn modes(&self) -> Vec<Mode> {
+        let state = self.state.lock();
+        let state = match *state {
+            Some(ref state) => state,
+            None => return Vec::new(),
+        };
+
+        RESOLUTIONS
+            .iter()
+            .map(|&(width, height)| Mode {
+                width,
+                height,
+                bpp: MODE_BPP,
+            })
+            .filter(|mode| state.supports(mode))
+            .collect()
+    }
+
+    fn set_mode(&self, mode: Mode) -> Result<(), FramebufferError> {
+        match *self.state.lock() {
+            Some(ref mut state) => state.set_mode(mode, 0),
+            None => Err(FramebufferError::NoDevice),
+        }
+    }
+
+    fn framebuffer(&self) -> Option<Framebuffer> {
+        let state = self.state.lock();
+        let state = state.as_ref()?;
+        let mode = state.mode?;
+
+        Some(Framebuffer {
+            info: FramebufferInfo {
+                address: state.address,
+                width: mode.width,
+                height: mode.height,
+                pitch: mode.width * mode.bpp as u32 / 8,
+                bpp: mode.bpp,
+                red: ColorField {
+                    position: 16,
+                    size: 8,
+                },
+                green: ColorField {
+                    position: 8,
+                    size: 8,
+                },
+                blue: ColorField {
+                    position: 0,
+                    size: 8,
+                },
+            },
+            memory_size: state.memory_size,
+            pages: state.pages,
+        })
+    }
+
+    fn flip(&self, page: u32) -> Result<(), FramebufferError> {
+        let state = self.state.lock();
+        let (mode, pages) = match *state {
+            Some(BgaState {
+                mode: Some(mode),
+                pages,
+                ..
+            }) => (mode, pages),
+            _ => return Err(FramebufferError::InvalidPage),
+        };
+
+        if page >= pages {
+            return Err(FramebufferError::InvalidPage);
+        }
+
+        unsafe {
+            write_register(Y_OFFSET_REGISTER, (page * mode.height) as u16);
+        }
+
+        Ok(())
+    }
+}
+
+impl BgaState {
+    /// Returns true if the given mode is supported.
+    fn supports(&self, mode: &Mode) -> bool {
+        let page_size = mode.width as usize * mode.height as usize * mode.bpp as usize / 8;
+
+        mode.bpp == MODE_BPP
+            && mode.width > 0
+            && mode.height > 0
+            && mode.width <= self.max_width
+            && mode.height <= self.max_height
+            && page_size <= self.memory_size
+    }
+
+    /// Programs the given mode with as many pages as fit in the video memory.
+    ///
+    /// `flags` are additional flags for the enable register.
+    fn set_mode(&mut self, mode: Mode, flags: u16) -> Result<(), FramebufferError> {
+        if !self.supports(&mode) {
+            return Err(FramebufferError::UnsupportedMode);
+        }
+
+        let page_size = mode.width as usize * mode.height as usize * mode.bpp as usize / 8;
+        let mut pages = (self.memory_size / page_size) as u32;
+        if pages > MAX_PAGES {
+            pages = MAX_PAGES;
+        }
+
+        // The virtual height register has 16 bits.
+        while pages > 1 && mode.height * pages > 0xffff {
+            pages -= 1;
+        }
+
+        unsafe {
+            write_register(ENABLE_REGISTER, 0);
+            write_register(X_RESOLUTION_REGISTER, mode.width as u16);
+            write_register(Y_RESOLUTION_REGISTER, mode.height as u16);
+            write_register(BPP_REGISTER, mode.bpp as u16);
+            write_register(ENABLE_REGISTER, ENABLED | LINEAR_FRAMEBUFFER_ENABLED | flags);
+
+            write_register(VIRTUAL_WIDTH_REGISTER, mode.width as u16);
+            write_register(VIRTUAL_HEIGHT_REGISTER, (mode.height * pages) as u16);
+            write_register(X_OFFSET_REGISTER, 0);
+            write_register(Y_OFFSET_REGISTER, 0);
+
+            // QEMU derives the virtual height from the memory size, so the
+            // number of usable pages is based on the value it reports.
+            let virtual_height = read_register(VIRTUAL_HEIGHT_REGISTER) as u32;
+            if virtual_height / mode.height < pages {
+                pages = virtual_height / mode.height;
+            }
+        }
+
+        self.mode = Some(mode);
+        self.pages = if pages == 0 { 1 } else { pages };
+
+        Ok(())
+    }
+}
+
+/// Reads the maximum resolution of the adapter.
+///
+/// # Safety
+/// - Must only be called once the adapter was found.
+unsafe fn read_capabilities() -> (u32, u32) {
+    let enable = read_register(ENABLE_REGISTER);
+
+    // Keeping the enabled bit doesn't change the current mode.
+    write_register(ENABLE_REGISTER, enable | GET_CAPABILITIES);
+    let max_width = read_register(X_RESOLUTION_REGISTER) as u32;
+    let max_height = read_register(Y_RESOLUTION_REGISTER) as u32;
+    write_register(ENABLE_REGISTER, enable);
+
+    (max_width, max_height)
+}
+
+/// Reads the given DISPI register.
+///
+/// # Safety
+/// - Must only be called by the driver of the adapter.
+unsafe fn read_register(register: u16) -> u16 {
+    outw(DISPI_INDEX_PORT, register);
+    inw(DISPI_DATA_PORT)
+}
+
+/// Writes the given DISPI register.
+///
+/// # Safety
+/// - Must only be called by the driver of the adapter.
+/// - Invalid values can leave the display in an unusable state.
+unsafe fn write_register(register: u16, value: u16) {
+    outw(DISPI_INDEX_PORT, register);
+    outw(DISPI_DATA_PORT, value);
+}
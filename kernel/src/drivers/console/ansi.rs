@@ -0,0 +1,237 @@
+//! Parses ANSI escape sequences.
+//!
+//! The parser is fed one character at a time and returns the actions the
+//! output device should perform. Only control sequences (`ESC [`) are
+//! reported, other escape sequences are ignored.
+
+/// The maximum number of parameters of a control sequence.
+///
+/// Further parameters are ignored.
+const MAX_PARAMETERS: usize = 16;
+
+/// The escape character.
+const ESCAPE: char = '\x1b';
+
+/// The delete character, which is ignored.
+const DELETE: char = '\x7f';
+
+/// The state of the parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Characters are printed.
+    Ground,
+    /// An escape character was read.
+    Escape,
+    /// The parameters of a control sequence are read.
+    ControlSequence,
+}
+
+/// The numeric parameters of a control sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parameters {
+    /// The parameter values, omitted parameters are zero.
+    values: [u16; MAX_PARAMETERS],
+    /// The number of parameters.
+    count: usize,
+}
+
+impl Parameters {
+    /// Creates an empty parameter list.
+    const fn new() -> Parameters {
+        Parameters {
+            values: [0; MAX_PARAMETERS],
+            count: 0,
+        }
+    }
+
+    /// Returns the number of parameters.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the parameter at the given index.
+    ///
+    /// Omitted and zero parameters are replaced by the default.
+    pub fn get(&self, index: usize, default: u16) -> u16 {
+        if index < self.count && self.values[index] != 0 {
+            self.values[index]
+        } else {
+            default
+        }
+    }
+
+    /// Adds a digit to the current parameter.
+    fn push_digit(&mut self, digit: u16) {
+        if self.count == 0 {
+            self.count = 1;
+        }
+
+        let value = &mut self.values[self.count - 1];
+        *value = value.saturating_mul(10).saturating_add(digit);
+    }
+
+    /// Starts the next parameter.
+    fn next(&mut self) {
+        if self.count == 0 {
+            self.count = 1;
+        }
+
+        if self.count < MAX_PARAMETERS {
+            self.count += 1;
+        }
+    }
+}
+
+/// An action the output device should perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// The character should be printed.
+    Print(char),
+    /// The control character, like a line feed, should be executed.
+    Control(char),
+    /// The control sequence should be executed.
+    ControlSequence {
+        /// The numeric parameters.
+        parameters: Parameters,
+        /// Set for private sequences starting with `?`.
+        private: bool,
+        /// The final character, which selects the function.
+        command: char,
+    },
+}
+
+/// A parser for ANSI escape sequences.
+pub struct Parser {
+    /// The current state.
+    state: State,
+    /// The parameters of the current control sequence.
+    parameters: Parameters,
+    /// Set if the current control sequence is private.
+    private: bool,
+}
+
+impl Parser {
+    /// Creates a new parser.
+    pub const fn new() -> Parser {
+        Parser {
+            state: State::Ground,
+            parameters: Parameters::new(),
+            private: false,
+        }
+    }
+
+    /// Feeds the next character to the parser.
+    ///
+    /// Returns the action for the character, if there is one.
+    pub fn advance(&mut self, character: char) -> Option<Action> {
+        if character == ESCAPE {
+            self.state = State::Escape;
+            return None;
+        }
+
+        match self.state {
+            State::Ground => match character {
+                DELETE => None,
+                '\x00'...'\x1f' => Some(Action::Control(character)),
+                _ => Some(Action::Print(character)),
+            },
+            State::Escape => {
+                if character == '[' {
+                    self.state = State::ControlSequence;
+                    self.parameters = Parameters::new();
+                    self.private = false;
+                } else {
+                    self.state = State::Ground;
+                }
+
+                None
+            }
+            State::ControlSequence => match character {
+                // Control characters are executed within sequences.
+                '\x00'...'\x1f' => Some(Action::Control(character)),
+                '0'...'9' => {
+                    self.parameters.push_digit(character as u16 - '0' as u16);
+                    None
+                }
+                ';' => {
+                    self.parameters.next();
+                    None
+                }
+                '?' => {
+                    self.private = true;
+                    None
+                }
+                '@'...'~' => {
+                    self.state = State::Ground;
+
+                    Some(Action::ControlSequence {
+                        parameters: self.parameters,
+                        private: self.private,
+                        command: character,
+                    })
+                }
+                // Intermediate characters aren't used by any supported sequence.
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the string to a new parser and returns the last action.
+    fn parse(string: &str) -> Option<Action> {
+        let mut parser = Parser::new();
+        let mut action = None;
+
+        for character in string.chars() {
+            action = parser.advance(character);
+        }
+
+        action
+    }
+
+    /// Tests parsing control sequences with parameters.
+    #[test]
+    fn test_control_sequence() {
+        match parse("\x1b[1;;32m") {
+            Some(Action::ControlSequence {
+                parameters,
+                private,
+                command,
+            }) => {
+                assert_eq!(parameters.len(), 3);
+                assert_eq!(parameters.get(0, 0), 1);
+                assert_eq!(parameters.get(1, 7), 7);
+                assert_eq!(parameters.get(2, 0), 32);
+                assert!(!private);
+                assert_eq!(command, 'm');
+            }
+            action => panic!("Unexpected action {:?}.", action),
+        }
+
+        match parse("\x1b[?25l") {
+            Some(Action::ControlSequence {
+                parameters,
+                private,
+                command,
+            }) => {
+                assert_eq!(parameters.get(0, 0), 25);
+                assert!(private);
+                assert_eq!(command, 'l');
+            }
+            action => panic!("Unexpected action {:?}.", action),
+        }
+    }
+
+    /// Tests that plain characters and control characters are passed on.
+    #[test]
+    fn test_ground() {
+        assert_eq!(parse("a"), Some(Action::Print('a')));
+        assert_eq!(parse("\x1b[2Jb"), Some(Action::Print('b')));
+        assert_eq!(parse("\n"), Some(Action::Control('\n')));
+        assert_eq!(parse("\x1bc"), None);
+    }
+}
@@ -0,0 +1,19 @@
+//! Bitmap fonts for the framebuffer console.
+//!
+//! The fonts are parsed by `boring_core::font`, which is shared with the
+//! standard library.
+
+use alloc::Vec;
+use alloc::boxed::Box;
+
+pub use boring_core::font::{Font, FontError};
+
+/// Parses a PSF1 or PSF2 font that stays loaded for the rest of the runtime.
+///
+/// The data is leaked, so it should only be used for fonts that are loaded
+/// once.
+pub fn load_psf(data: Vec<u8>) -> Result<Font<'static>, FontError> {
+    let data: &'static [u8] = unsafe { &*Box::into_raw(data.into_boxed_slice()) };
+
+    Font::from_psf(data)
+}
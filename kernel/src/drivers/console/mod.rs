@@ -0,0 +1,776 @@
+//! A text console on the linear framebuffer.
+//!
+//! The console replaces the VGA text buffer when the boot loader sets up a
+//! graphics mode. Text is drawn with a bitmap font, colored with ANSI escape
+//! sequences and kept in a scrollback buffer.
+
+pub mod ansi;
+pub mod font;
+
+use self::ansi::{Action, Parameters, Parser};
+use self::font::Font;
+use alloc::Vec;
+use alloc::vec_deque::VecDeque;
+use boot::{get_framebuffer_info, ColorField};
+use core::fmt;
+use core::ptr::{copy, write_volatile};
+use core::str;
+use core::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use drivers::framebuffer::get_framebuffer;
+use file_handle::FileHandle;
+use initramfs;
+use memory::{map_physical_range, PageFlags, VirtualAddress};
+use sync::PreemptableMutex;
+
+/// The path of the font that replaces the built-in font.
+const FONT_PATH: &str = "/fonts/console.psf";
+
+/// The number of lines kept above the screen.
+const SCROLLBACK_LINES: usize = 1000;
+
+/// The size of the buffer for output before the console is initialized.
+const EARLY_BUFFER_SIZE: usize = 4096;
+
+/// The distance between two tab stops.
+const TAB_WIDTH: usize = 8;
+
+/// The height of the cursor in pixels.
+const CURSOR_HEIGHT: usize = 2;
+
+/// The 16 ANSI colors as RGB values, the second half are the bright colors.
+const PALETTE: [u32; 16] = [
+    0x000000, 0xaa0000, 0x00aa00, 0xaa5500, 0x0000aa, 0xaa00aa, 0x00aaaa, 0xaaaaaa, 0x555555,
+    0xff5555, 0x55ff55, 0xffff55, 0x5555ff, 0xff55ff, 0x55ffff, 0xffffff,
+];
+
+/// The index of the default foreground color in the palette.
+const DEFAULT_FOREGROUND: u8 = 7;
+
+/// The index of the default background color in the palette.
+const DEFAULT_BACKGROUND: u8 = 0;
+
+/// A color selected by escape sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    /// One of the 256 indexed colors.
+    Indexed(u8),
+    /// A direct RGB color.
+    Rgb(u32),
+}
+
+impl Color {
+    /// Returns the RGB value of the color.
+    ///
+    /// Bold text uses the bright variant of the first eight colors.
+    fn to_rgb(&self, bold: bool) -> u32 {
+        match *self {
+            Color::Indexed(index) if bold && index < 8 => PALETTE[index as usize + 8],
+            Color::Indexed(index) => indexed_color(index),
+            Color::Rgb(rgb) => rgb,
+        }
+    }
+}
+
+/// The text attributes selected by escape sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Attributes {
+    /// The foreground color.
+    foreground: Color,
+    /// The background color.
+    background: Color,
+    /// Set for bold text.
+    bold: bool,
+    /// Set if foreground and background are swapped.
+    reverse: bool,
+}
+
+impl Attributes {
+    /// The attributes after a reset.
+    const DEFAULT: Attributes = Attributes {
+        foreground: Color::Indexed(DEFAULT_FOREGROUND),
+        background: Color::Indexed(DEFAULT_BACKGROUND),
+        bold: false,
+        reverse: false,
+    };
+
+    /// Returns a cell showing the character with these attributes.
+    fn cell(&self, character: char) -> Cell {
+        let foreground = self.foreground.to_rgb(self.bold);
+        let background = self.background.to_rgb(false);
+
+        if self.reverse {
+            Cell {
+                character,
+                foreground: background,
+                background: foreground,
+            }
+        } else {
+            Cell {
+                character,
+                foreground,
+                background,
+            }
+        }
+    }
+}
+
+/// A character on the console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    /// The character shown.
+    character: char,
+    /// The RGB foreground color.
+    foreground: u32,
+    /// The RGB background color.
+    background: u32,
+}
+
+/// The framebuffer the console draws on.
+struct Screen {
+    /// The virtual address of the first pixel.
+    address: VirtualAddress,
+    /// The width in pixels.
+    width: usize,
+    /// The height in pixels.
+    height: usize,
+    /// The number of bytes per line.
+    pitch: usize,
+    /// The number of bytes per pixel.
+    bytes_per_pixel: usize,
+    /// The red channel of a pixel.
+    red: ColorField,
+    /// The green channel of a pixel.
+    green: ColorField,
+    /// The blue channel of a pixel.
+    blue: ColorField,
+}
+
+impl Screen {
+    /// Maps the current framebuffer into the kernel.
+    ///
+    /// Returns `None` if there is no framebuffer with a supported depth.
+    fn from_framebuffer() -> Option<Screen> {
+        let info = get_framebuffer()?.info;
+        let bytes_per_pixel = (info.bpp as usize + 7) / 8;
+
+        if bytes_per_pixel < 2 || bytes_per_pixel > 4 {
+            return None;
+        }
+
+        let address = map_physical_range(
+            info.address,
+            info.pitch as usize * info.height as usize,
+            PageFlags::READABLE | PageFlags::WRITABLE,
+        );
+
+        Some(Screen {
+            address,
+            width: info.width as usize,
+            height: info.height as usize,
+            pitch: info.pitch as usize,
+            bytes_per_pixel,
+            red: info.red,
+            green: info.green,
+            blue: info.blue,
+        })
+    }
+
+    /// Converts the RGB value to a pixel value.
+    fn pixel(&self, rgb: u32) -> u32 {
+        self.red.place((rgb >> 16) as u8) | self.green.place((rgb >> 8) as u8)
+            | self.blue.place(rgb as u8)
+    }
+
+    /// Returns the address of the given pixel.
+    fn pixel_address(&self, x: usize, y: usize) -> VirtualAddress {
+        self.address + y * self.pitch + x * self.bytes_per_pixel
+    }
+
+    /// Writes the pixel value at the given address.
+    ///
+    /// # Safety
+    /// - The address must belong to a pixel of the screen.
+    unsafe fn write_pixel(&self, address: VirtualAddress, value: u32) {
+        match self.bytes_per_pixel {
+            4 => write_volatile(address as *mut u32, value),
+            3 => {
+                write_volatile(address as *mut u16, value as u16);
+                write_volatile((address + 2) as *mut u8, (value >> 16) as u8);
+            }
+            _ => write_volatile(address as *mut u16, value as u16),
+        }
+    }
+
+    /// Fills the rectangle with the RGB color.
+    fn fill_rect(&self, x: usize, y: usize, width: usize, height: usize, rgb: u32) {
+        let value = self.pixel(rgb);
+
+        for line in y..y + height {
+            let mut address = self.pixel_address(x, line);
+            for _ in 0..width {
+                unsafe { self.write_pixel(address, value) };
+                address += self.bytes_per_pixel;
+            }
+        }
+    }
+
+    /// Moves the lines from `source` to `target` upwards.
+    fn move_up(&self, target: usize, source: usize, height: usize) {
+        unsafe {
+            copy(
+                self.pixel_address(0, source) as *const u8,
+                self.pixel_address(0, target) as *mut u8,
+                height * self.pitch,
+            );
+        }
+    }
+}
+
+/// A text console on the framebuffer.
+pub struct Console {
+    /// The framebuffer the console draws on.
+    screen: Screen,
+    /// The font used to draw characters.
+    font: Font<'static>,
+    /// The number of characters per line.
+    columns: usize,
+    /// The number of lines on the screen.
+    rows: usize,
+    /// The scrollback buffer, the last `rows` lines are on the screen.
+    lines: VecDeque<Vec<Cell>>,
+    /// The row of the cursor on the screen.
+    cursor_row: usize,
+    /// The column of the cursor.
+    cursor_column: usize,
+    /// The attributes for new characters.
+    attributes: Attributes,
+    /// The parser for escape sequences.
+    parser: Parser,
+    /// The number of lines the view is scrolled back into the history.
+    view_offset: usize,
+}
+
+impl Console {
+    /// Creates a console drawing on the given screen.
+    fn new(screen: Screen, font: Font<'static>) -> Console {
+        let mut console = Console {
+            screen,
+            font,
+            columns: 0,
+            rows: 0,
+            lines: VecDeque::new(),
+            cursor_row: 0,
+            cursor_column: 0,
+            attributes: Attributes::DEFAULT,
+            parser: Parser::new(),
+            view_offset: 0,
+        };
+
+        console.update_size();
+        console.cursor_row = 0;
+        console.redraw();
+
+        console
+    }
+
+    /// Writes the given string to the console.
+    pub fn write_string(&mut self, string: &str) {
+        self.hide_cursor();
+
+        // New output scrolls back to the bottom.
+        if self.view_offset != 0 {
+            self.view_offset = 0;
+            self.redraw();
+        }
+
+        for character in string.chars() {
+            match self.parser.advance(character) {
+                Some(Action::Print(character)) => self.put_char(character),
+                Some(Action::Control(character)) => self.control(character),
+                Some(Action::ControlSequence {
+                    parameters,
+                    private: false,
+                    command: 'm',
+                }) => self.select_graphic_rendition(&parameters),
+                _ => (),
+            }
+        }
+
+        self.show_cursor();
+    }
+
+    /// Scrolls the view the given number of lines back into the history.
+    ///
+    /// Negative numbers scroll towards the newest output.
+    pub fn scroll_view(&mut self, lines: isize) {
+        let max_offset = self.lines.len() - self.rows;
+
+        let offset = self.view_offset as isize + lines;
+        self.view_offset = if offset < 0 {
+            0
+        } else if offset as usize > max_offset {
+            max_offset
+        } else {
+            offset as usize
+        };
+
+        self.redraw();
+    }
+
+    /// The width of a character in pixels.
+    fn cell_width(&self) -> usize {
+        self.font.width() as usize
+    }
+
+    /// The height of a character in pixels.
+    fn cell_height(&self) -> usize {
+        self.font.height() as usize
+    }
+
+    /// Replaces the font of the console.
+    pub fn set_font(&mut self, font: Font<'static>) {
+        self.font = font;
+        self.update_size();
+        self.redraw();
+    }
+
+    /// Draws on the given screen, after the framebuffer changed.
+    fn set_screen(&mut self, screen: Screen) {
+        self.screen = screen;
+        self.update_size();
+        self.redraw();
+    }
+
+    /// Adapts the number of rows and columns to the screen and font.
+    fn update_size(&mut self) {
+        let lines_below_cursor = if self.cursor_row < self.rows {
+            self.rows - self.cursor_row - 1
+        } else {
+            0
+        };
+
+        self.columns = self.screen.width / self.cell_width();
+        self.rows = self.screen.height / self.cell_height();
+
+        while self.lines.len() < self.rows {
+            self.lines.push_front(Vec::new());
+        }
+
+        // Keep the cursor on the same line.
+        self.cursor_row = self.rows.saturating_sub(lines_below_cursor + 1);
+        if self.cursor_column >= self.columns {
+            self.cursor_column = self.columns.saturating_sub(1);
+        }
+        self.view_offset = 0;
+    }
+
+    /// Prints the character at the cursor position.
+    fn put_char(&mut self, character: char) {
+        if self.cursor_column >= self.columns {
+            self.new_line();
+        }
+
+        let cell = self.attributes.cell(character);
+        let blank = self.attributes.cell(' ');
+        let index = self.line_index(self.cursor_row);
+        let column = self.cursor_column;
+
+        {
+            let line = &mut self.lines[index];
+            while line.len() <= column {
+                line.push(blank);
+            }
+            line[column] = cell;
+        }
+
+        self.draw_cell(self.cursor_row, column, cell);
+        self.cursor_column += 1;
+    }
+
+    /// Executes the given control character.
+    fn control(&mut self, character: char) {
+        match character {
+            '\n' => self.new_line(),
+            '\r' => self.cursor_column = 0,
+            '\t' => {
+                let next_stop = (self.cursor_column / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.cursor_column = if next_stop < self.columns {
+                    next_stop
+                } else {
+                    self.columns
+                };
+            }
+            '\x08' => self.cursor_column = self.cursor_column.saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    /// Moves the cursor to the start of the next line, scrolling if needed.
+    fn new_line(&mut self) {
+        self.cursor_column = 0;
+
+        // A screen that is smaller than a glyph has no rows to scroll.
+        if self.rows == 0 {
+            return;
+        }
+
+        if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+            return;
+        }
+
+        self.lines.push_back(Vec::new());
+        while self.lines.len() > self.rows + SCROLLBACK_LINES {
+            self.lines.pop_front();
+        }
+
+        let font_height = self.cell_height();
+        self.screen.move_up(0, font_height, (self.rows - 1) * font_height);
+
+        let background = self.attributes.cell(' ').background;
+        self.screen.fill_rect(
+            0,
+            (self.rows - 1) * font_height,
+            self.columns * self.cell_width(),
+            font_height,
+            background,
+        );
+    }
+
+    /// Applies the parameters of an SGR sequence to the attributes.
+    fn select_graphic_rendition(&mut self, parameters: &Parameters) {
+        if parameters.len() == 0 {
+            self.attributes = Attributes::DEFAULT;
+            return;
+        }
+
+        let mut index = 0;
+        while index < parameters.len() {
+            match parameters.get(index, 0) {
+                0 => self.attributes = Attributes::DEFAULT,
+                1 => self.attributes.bold = true,
+                7 => self.attributes.reverse = true,
+                22 => self.attributes.bold = false,
+                27 => self.attributes.reverse = false,
+                code @ 30...37 => self.attributes.foreground = Color::Indexed(code as u8 - 30),
+                38 => {
+                    let (color, used) = extended_color(parameters, index + 1);
+                    if let Some(color) = color {
+                        self.attributes.foreground = color;
+                    }
+                    index += used;
+                }
+                39 => self.attributes.foreground = Color::Indexed(DEFAULT_FOREGROUND),
+                code @ 40...47 => self.attributes.background = Color::Indexed(code as u8 - 40),
+                48 => {
+                    let (color, used) = extended_color(parameters, index + 1);
+                    if let Some(color) = color {
+                        self.attributes.background = color;
+                    }
+                    index += used;
+                }
+                49 => self.attributes.background = Color::Indexed(DEFAULT_BACKGROUND),
+                code @ 90...97 => self.attributes.foreground = Color::Indexed(code as u8 - 90 + 8),
+                code @ 100...107 => {
+                    self.attributes.background = Color::Indexed(code as u8 - 100 + 8)
+                }
+                _ => (),
+            }
+
+            index += 1;
+        }
+    }
+
+    /// Returns the index in the scrollback buffer of the given screen row.
+    fn line_index(&self, row: usize) -> usize {
+        self.lines.len() - self.rows + row
+    }
+
+    /// Returns the cell shown at the given position of the view.
+    fn visible_cell(&self, row: usize, column: usize) -> Cell {
+        let index = self.line_index(row) - self.view_offset;
+
+        match self.lines[index].get(column) {
+            Some(cell) => *cell,
+            None => Attributes::DEFAULT.cell(' '),
+        }
+    }
+
+    /// Draws the whole view.
+    fn redraw(&mut self) {
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let cell = self.visible_cell(row, column);
+                self.draw_cell(row, column, cell);
+            }
+        }
+
+        // Clear the area that is too small for a character.
+        let text_width = self.columns * self.cell_width();
+        let text_height = self.rows * self.cell_height();
+        let background = PALETTE[DEFAULT_BACKGROUND as usize];
+        self.screen.fill_rect(
+            text_width,
+            0,
+            self.screen.width - text_width,
+            self.screen.height,
+            background,
+        );
+        self.screen.fill_rect(
+            0,
+            text_height,
+            text_width,
+            self.screen.height - text_height,
+            background,
+        );
+    }
+
+    /// Draws the cell at the given position.
+    fn draw_cell(&self, row: usize, column: usize, cell: Cell) {
+        let (width, height) = (self.cell_width(), self.cell_height());
+        let glyph = self.font.glyph(cell.character);
+        let foreground = self.screen.pixel(cell.foreground);
+        let background = self.screen.pixel(cell.background);
+
+        for y in 0..height {
+            let mut address = self.screen
+                .pixel_address(column * width, row * height + y);
+
+            for x in 0..width {
+                let value = if self.font.is_set(glyph, x as u32, y as u32) {
+                    foreground
+                } else {
+                    background
+                };
+
+                unsafe { self.screen.write_pixel(address, value) };
+                address += self.screen.bytes_per_pixel;
+            }
+        }
+    }
+
+    /// Draws the cursor below the character at the cursor position.
+    fn show_cursor(&self) {
+        if self.view_offset != 0 || self.cursor_column >= self.columns {
+            return;
+        }
+
+        let height = if self.cell_height() > CURSOR_HEIGHT {
+            CURSOR_HEIGHT
+        } else {
+            self.cell_height()
+        };
+
+        let foreground = self.attributes.cell(' ').foreground;
+        self.screen.fill_rect(
+            self.cursor_column * self.cell_width(),
+            (self.cursor_row + 1) * self.cell_height() - height,
+            self.cell_width(),
+            height,
+            foreground,
+        );
+    }
+
+    /// Removes the cursor by drawing the character below it again.
+    fn hide_cursor(&self) {
+        if self.view_offset != 0 || self.cursor_column >= self.columns {
+            return;
+        }
+
+        let cell = self.visible_cell(self.cursor_row, self.cursor_column);
+        self.draw_cell(self.cursor_row, self.cursor_column, cell);
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.write_string(string);
+
+        Ok(())
+    }
+}
+
+/// Stores the output until the console is initialized.
+struct EarlyOutput {
+    /// The stored output.
+    buffer: [u8; EARLY_BUFFER_SIZE],
+    /// The number of used bytes.
+    length: usize,
+}
+
+impl fmt::Write for EarlyOutput {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        // Output that doesn't fit is dropped.
+        for byte in string.bytes() {
+            if self.length < EARLY_BUFFER_SIZE {
+                self.buffer[self.length] = byte;
+                self.length += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Set if the kernel output goes to the framebuffer console.
+static SELECTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// The console, once it is initialized.
+static CONSOLE: PreemptableMutex<Option<Console>> = PreemptableMutex::new(None);
+
+/// The output before the console was initialized.
+static EARLY_OUTPUT: PreemptableMutex<EarlyOutput> = PreemptableMutex::new(EarlyOutput {
+    buffer: [0; EARLY_BUFFER_SIZE],
+    length: 0,
+});
+
+/// Selects the console for the kernel output if the boot loader set up a
+/// graphics mode.
+///
+/// Output is buffered until the console is initialized. Returns true if the
+/// console was selected.
+pub fn select() -> bool {
+    let selected = get_framebuffer_info().is_some();
+
+    SELECTED.store(selected, Ordering::SeqCst);
+
+    selected
+}
+
+/// Returns true if the kernel output goes to the console.
+pub fn is_selected() -> bool {
+    SELECTED.load(Ordering::SeqCst)
+}
+
+/// Initializes the console, if it was selected.
+///
+/// This needs the heap, the initramfs and the framebuffer drivers.
+pub fn init() {
+    assert_has_not_been_called!("The console should only be initialized once.");
+
+    if !is_selected() {
+        return;
+    }
+
+    let screen = match Screen::from_framebuffer() {
+        Some(screen) => screen,
+        None => {
+            debugln!("The framebuffer isn't usable for the console.");
+            SELECTED.store(false, Ordering::SeqCst);
+            return;
+        }
+    };
+
+    let mut console = Console::new(screen, load_font());
+
+    {
+        let early_output = EARLY_OUTPUT.lock();
+        let output = &early_output.buffer[..early_output.length];
+
+        // The buffer could end within a character.
+        let valid_output = match str::from_utf8(output) {
+            Ok(string) => string,
+            Err(error) => unsafe { str::from_utf8_unchecked(&output[..error.valid_up_to()]) },
+        };
+
+        console.write_string(valid_output);
+    }
+
+    *CONSOLE.lock() = Some(console);
+}
+
+/// Loads the console font from the initramfs, or the built-in font.
+fn load_font() -> Font<'static> {
+    let mut file = match initramfs::open(FONT_PATH) {
+        Ok(file) => file,
+        Err(_) => return Font::builtin(),
+    };
+
+    let mut data = Vec::new();
+    data.resize(file.len() as usize, 0);
+
+    let font = file.read(&mut data)
+        .map_err(|_| font::FontError::Truncated)
+        .and_then(|_| font::load_psf(data));
+
+    match font {
+        Ok(font) => font,
+        Err(error) => {
+            debugln!("Could not load {}: {:?}", FONT_PATH, error);
+            Font::builtin()
+        }
+    }
+}
+
+/// Writes the formatted arguments to the console.
+pub fn write_fmt(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    let mut console = CONSOLE.lock();
+    match *console {
+        Some(ref mut console) => console.write_fmt(args).unwrap(),
+        None => EARLY_OUTPUT.lock().write_fmt(args).unwrap(),
+    }
+}
+
+/// Scrolls the view the given number of lines back into the history.
+///
+/// Negative numbers scroll towards the newest output.
+pub fn scroll(lines: isize) {
+    if let Some(ref mut console) = *CONSOLE.lock() {
+        console.scroll_view(lines);
+    }
+}
+
+/// Replaces the font of the console.
+pub fn set_font(font: Font<'static>) {
+    if let Some(ref mut console) = *CONSOLE.lock() {
+        console.set_font(font);
+    }
+}
+
+/// Adapts the console to a new framebuffer mode.
+pub fn framebuffer_changed() {
+    if let Some(ref mut console) = *CONSOLE.lock() {
+        if let Some(screen) = Screen::from_framebuffer() {
+            console.set_screen(screen);
+        }
+    }
+}
+
+/// Returns the RGB value of one of the 256 indexed colors.
+///
+/// These are the 16 ANSI colors, a 6x6x6 color cube and 24 shades of gray.
+fn indexed_color(index: u8) -> u32 {
+    match index {
+        0...15 => PALETTE[index as usize],
+        16...231 => {
+            let index = index as u32 - 16;
+            let level = |value: u32| if value == 0 { 0 } else { 55 + value * 40 };
+
+            level(index / 36) << 16 | level(index / 6 % 6) << 8 | level(index % 6)
+        }
+        _ => {
+            let gray = 8 + (index as u32 - 232) * 10;
+
+            gray << 16 | gray << 8 | gray
+        }
+    }
+}
+
+/// Parses the color of an extended SGR color sequence.
+///
+/// Returns the color and the number of parameters used.
+fn extended_color(parameters: &Parameters, index: usize) -> (Option<Color>, usize) {
+    match parameters.get(index, 0) {
+        5 => (Some(Color::Indexed(parameters.get(index + 1, 0) as u8)), 2),
+        2 => {
+            let red = parameters.get(index + 1, 0) as u32 & 0xff;
+            let green = parameters.get(index + 2, 0) as u32 & 0xff;
+            let blue = parameters.get(index + 3, 0) as u32 & 0xff;
+
+            (Some(Color::Rgb(red << 16 | green << 8 | blue)), 4)
+        }
+        _ => (None, 0),
+    }
+}
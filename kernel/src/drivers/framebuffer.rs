@@ -0,0 +1,116 @@
+//! Provides a common interface to the linear framebuffer.
+//!
+//! Without a driver for the graphics device, the framebuffer set up by the
+//! boot loader is used. A driver can register itself as the framebuffer
+//! device to support mode switching and page flipping.
+
+use alloc::Vec;
+use boot::{get_framebuffer_info, FramebufferInfo};
+use spin::Once;
+
+/// A display mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// The number of bits per pixel.
+    pub bpp: u8,
+}
+
+/// The current state of the framebuffer.
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer {
+    /// The layout of the first page.
+    pub info: FramebufferInfo,
+    /// The size of the memory holding all pages in bytes.
+    pub memory_size: usize,
+    /// The number of pages, each `pitch * height` bytes after the previous.
+    pub pages: u32,
+}
+
+/// The errors that can occur while using the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramebufferError {
+    /// The framebuffer can't be changed without a device driver.
+    NoDevice,
+    /// The device doesn't support the mode.
+    UnsupportedMode,
+    /// The page doesn't exist in the current mode.
+    InvalidPage,
+}
+
+/// A graphics device that displays a linear framebuffer.
+pub trait FramebufferDevice: Sync {
+    /// The name of the device.
+    fn name(&self) -> &'static str;
+
+    /// Returns the modes the device supports.
+    fn modes(&self) -> Vec<Mode>;
+
+    /// Switches to the given mode, displaying the first page.
+    fn set_mode(&self, mode: Mode) -> Result<(), FramebufferError>;
+
+    /// Returns the current framebuffer, or `None` if no mode is set.
+    fn framebuffer(&self) -> Option<Framebuffer>;
+
+    /// Displays the given page.
+    fn flip(&self, page: u32) -> Result<(), FramebufferError>;
+}
+
+/// The registered framebuffer device.
+static DEVICE: Once<&'static FramebufferDevice> = Once::new();
+
+/// Makes the given device the framebuffer device.
+///
+/// Only the first registered device is used.
+pub fn register_device(device: &'static FramebufferDevice) {
+    DEVICE.call_once(|| device);
+
+    debugln!("Using {} as the framebuffer device.", device.name());
+}
+
+/// Returns the current framebuffer.
+///
+/// Returns `None` if there is no linear framebuffer.
+pub fn get_framebuffer() -> Option<Framebuffer> {
+    if let Some(framebuffer) = DEVICE.try().and_then(|device| device.framebuffer()) {
+        return Some(framebuffer);
+    }
+
+    get_framebuffer_info().map(|info| Framebuffer {
+        info,
+        memory_size: info.pitch as usize * info.height as usize,
+        pages: 1,
+    })
+}
+
+/// Returns the modes the framebuffer device supports.
+pub fn modes() -> Vec<Mode> {
+    match DEVICE.try() {
+        Some(device) => device.modes(),
+        None => Vec::new(),
+    }
+}
+
+/// Switches the framebuffer device to the given mode.
+pub fn set_mode(mode: Mode) -> Result<(), FramebufferError> {
+    match DEVICE.try() {
+        Some(device) => device.set_mode(mode)?,
+        None => return Err(FramebufferError::NoDevice),
+    }
+
+    super::console::framebuffer_changed();
+
+    Ok(())
+}
+
+/// Displays the given page of the framebuffer.
+pub fn flip(page: u32) -> Result<(), FramebufferError> {
+    match DEVICE.try() {
+        Some(device) => device.flip(page),
+        None if page == 0 => Ok(()),
+        None => Err(FramebufferError::InvalidPage),
+    }
+}
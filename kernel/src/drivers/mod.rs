@@ -1 +1,18 @@
+//! This module contains the device drivers.
+
 //pub mod serial;
+pub mod bga;
+pub mod console;
+pub mod framebuffer;
+pub mod pci;
+pub mod rtc;
+
+/// Initializes the drivers.
+pub fn init() {
+    assert_has_not_been_called!("The drivers should only be initialized once.");
+
+    pci::init_pci();
+    bga::init();
+    console::init();
+    rtc::init();
+}
@@ -0,0 +1,167 @@
+//! Provides the mechanisms to access the PCI configuration space.
+
+use acpi::mcfg::EcamRegion;
+use alloc::Vec;
+use core::ptr;
+use cpuio;
+use memory::{map_physical_range, PageFlags, VirtualAddress};
+
+/// The size of the legacy configuration space of a function.
+pub const LEGACY_CONFIG_SPACE_SIZE: usize = 0x100;
+
+/// The size of the extended configuration space of a function.
+pub const EXTENDED_CONFIG_SPACE_SIZE: usize = 0x1000;
+
+/// A mechanism to access the configuration space.
+pub trait ConfigAccess: Send {
+    /// Reads a 32-bit aligned word from the configuration space.
+    ///
+    /// # Safety
+    /// - Out-of-range parameters may do horrible things to the hardware.
+    unsafe fn read(&mut self, bus: u8, slot: u8, function: u8, offset: u16) -> u32;
+
+    /// Writes a 32-bit aligned word to the configuration space.
+    ///
+    /// # Safety
+    /// - Out-of-range parameters may do horrible things to the hardware.
+    unsafe fn write(&mut self, bus: u8, slot: u8, function: u8, offset: u16, value: u32);
+
+    /// The size of the configuration space of each function.
+    fn config_space_size(&self) -> usize;
+}
+
+/// Configuration space access through the ports 0xCF8 and 0xCFC.
+pub struct PortAccess {
+    address: cpuio::Port<u32>,
+    data: cpuio::Port<u32>,
+}
+
+impl PortAccess {
+    /// Creates the port based access mechanism.
+    pub fn new() -> PortAccess {
+        PortAccess {
+            address: unsafe { cpuio::Port::new(0xCF8) },
+            data: unsafe { cpuio::Port::new(0xCFC) },
+        }
+    }
+
+    /// Selects the given register.
+    fn select(&mut self, bus: u8, slot: u8, function: u8, offset: u16) {
+        // The bus number occupies bits 16 - 23
+        // The slot number occupies bits 11 - 15
+        // The function number occupies bits 8 - 10
+        // The two least signifigant bits must be 0
+        let address = 0x80000000 | (bus as u32) << 16 | (slot as u32) << 11
+            | (function as u32) << 8 | (offset & 0b1111_1100) as u32;
+
+        self.address.write(address);
+    }
+}
+
+impl ConfigAccess for PortAccess {
+    unsafe fn read(&mut self, bus: u8, slot: u8, function: u8, offset: u16) -> u32 {
+        if offset as usize >= LEGACY_CONFIG_SPACE_SIZE {
+            return 0xffffffff;
+        }
+
+        self.select(bus, slot, function, offset);
+        self.data.read()
+    }
+
+    unsafe fn write(&mut self, bus: u8, slot: u8, function: u8, offset: u16, value: u32) {
+        if offset as usize >= LEGACY_CONFIG_SPACE_SIZE {
+            return;
+        }
+
+        self.select(bus, slot, function, offset);
+        self.data.write(value);
+    }
+
+    fn config_space_size(&self) -> usize {
+        LEGACY_CONFIG_SPACE_SIZE
+    }
+}
+
+/// Memory mapped configuration space access as described by the MCFG table.
+pub struct EcamAccess {
+    regions: Vec<MappedRegion>,
+    /// Used for buses that no region covers.
+    fallback: PortAccess,
+}
+
+/// An ECAM region mapped into the kernel address space.
+struct MappedRegion {
+    /// The first bus decoded by the region.
+    start_bus: u8,
+    /// The last bus decoded by the region.
+    end_bus: u8,
+    /// The virtual address of the configuration space of `start_bus`.
+    base: VirtualAddress,
+}
+
+impl EcamAccess {
+    /// Creates the memory mapped access mechanism for the given regions.
+    ///
+    /// The configuration space of the regions is mapped here once and stays
+    /// mapped.
+    pub fn new(regions: Vec<EcamRegion>) -> EcamAccess {
+        // Only segment group 0 is reachable through the bus numbers used here.
+        let regions = regions
+            .iter()
+            .filter(|region| region.segment_group == 0 && region.start_bus <= region.end_bus)
+            .map(|region| {
+                // Each bus has 1 MiB of configuration space.
+                let length = ((region.end_bus - region.start_bus) as usize + 1) << 20;
+                let base = map_physical_range(
+                    region.base_address,
+                    length,
+                    PageFlags::READABLE | PageFlags::WRITABLE | PageFlags::NO_CACHE,
+                );
+
+                MappedRegion {
+                    start_bus: region.start_bus,
+                    end_bus: region.end_bus,
+                    base,
+                }
+            })
+            .collect();
+
+        EcamAccess {
+            regions,
+            fallback: PortAccess::new(),
+        }
+    }
+
+    /// Returns a pointer to the given register, if a region covers the bus.
+    fn register(&self, bus: u8, slot: u8, function: u8, offset: u16) -> Option<*mut u32> {
+        let region = self.regions
+            .iter()
+            .find(|region| region.start_bus <= bus && bus <= region.end_bus)?;
+
+        let address = region.base
+            + (((bus - region.start_bus) as usize) << 20 | (slot as usize) << 15
+                | (function as usize) << 12 | (offset as usize & 0xffc));
+
+        Some(address as *mut u32)
+    }
+}
+
+impl ConfigAccess for EcamAccess {
+    unsafe fn read(&mut self, bus: u8, slot: u8, function: u8, offset: u16) -> u32 {
+        match self.register(bus, slot, function, offset) {
+            Some(register) => ptr::read_volatile(register),
+            None => self.fallback.read(bus, slot, function, offset),
+        }
+    }
+
+    unsafe fn write(&mut self, bus: u8, slot: u8, function: u8, offset: u16, value: u32) {
+        match self.register(bus, slot, function, offset) {
+            Some(register) => ptr::write_volatile(register, value),
+            None => self.fallback.write(bus, slot, function, offset, value),
+        }
+    }
+
+    fn config_space_size(&self) -> usize {
+        EXTENDED_CONFIG_SPACE_SIZE
+    }
+}
@@ -33,7 +33,7 @@ pub enum DeviceClass {
 impl DeviceClass {
     pub fn from_u8(c: u8) -> DeviceClass {
         if c <= DeviceClass::DataAndSignalProcessing as u8 {
-            unsafe { ::core::intrinsics::transmute(c) }
+            unsafe { ::core::mem::transmute(c) }
         } else {
             DeviceClass::Unknown
         }
@@ -54,7 +54,7 @@ pub enum HeaderType {
 impl HeaderType {
     fn from_u8(c: u8) -> HeaderType {
         if c <= HeaderType::CardBusBridge as u8 {
-            unsafe { ::core::intrinsics::transmute(c) }
+            unsafe { ::core::mem::transmute(c) }
         } else {
             debugln!("unknown type: {}", c);
             HeaderType::Unknown
@@ -64,6 +64,7 @@ impl HeaderType {
 
 #[allow(dead_code)]
 #[repr(packed)]
+#[derive(Clone, Copy)]
 pub struct Header {
     // Common Header Fields
     pub vendor_id: u16,
@@ -146,7 +147,10 @@ impl Header {
                 min_grant = (registers[15] >> 16) as u8;
                 max_latency = (registers[15] >> 24) as u8;
             }
-            _ => unimplemented!("Header {:?} not implemented", header_enum),
+            _ => {
+                // Only the common fields are known for other header types.
+                debugln!("PCI header {:?} is only partially parsed", header_enum);
+            }
         };
 
         Header {
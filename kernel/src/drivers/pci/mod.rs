@@ -4,23 +4,23 @@
 
 use core::fmt;
 use core::iter::Iterator;
-use spin::PreemptableMutex;
-use cpuio;
+use sync::PreemptableMutex;
+use self::config::{ConfigAccess, EcamAccess, PortAccess};
 use self::headers::Header;
-use alloc::vec::Vec;
-
-// lazy_static! {
-//     static ref PCI_DEVICES: PreemptableMutex<Vec<Box<PciDeviceFunction>>> = PreemptableMutex::new(Vec::new());
-// }
-
+use acpi;
+use alloc::Vec;
+use memory::PhysicalAddress;
+use alloc::boxed::Box;
 
+pub mod config;
 pub mod headers;
+pub mod msi;
 
 struct Pci {
-    address: cpuio::Port<u32>,
-    data: cpuio::Port<u32>,
-    // devices: Vec<PciDeviceFunction<'a>>,
+    access: Box<ConfigAccess>,
     devices: Vec<PciDeviceFunction>,
+    /// Whether the bus has been enumerated yet.
+    enumerated: bool,
 }
 
 impl Pci {
@@ -28,15 +28,22 @@ impl Pci {
     /// This is marked as `unsafe` because passing in out-of-range
     /// parameters probably does excitingly horrible things to the
     /// hardware.
-    unsafe fn read_config_register(&mut self, bus: u8, slot: u8, function: u8, offset: u8) -> u32 {
-        // The bus number occupies bits 16 - 23
-        // The slot number occupies bits 11 - 15
-        // The function number occupies bits 8 - 10
-        // The two least signifigant bits must be 0
-        let address: u32 = 0x80000000 | (bus as u32) << 16 | (slot as u32) << 11
-            | (function as u32) << 8 | (offset & 0b1111_1100) as u32;
-        self.address.write(address);
-        self.data.read()
+    unsafe fn read_config_register(&mut self, bus: u8, slot: u8, function: u8, offset: u16) -> u32 {
+        self.access.read(bus, slot, function, offset)
+    }
+
+    /// Write a 32-bit aligned word to PCI Configuration Address Space.
+    ///
+    /// This is `unsafe` for the same reasons as `read_config_register`.
+    unsafe fn write_config_register(
+        &mut self,
+        bus: u8,
+        slot: u8,
+        function: u8,
+        offset: u16,
+        value: u32,
+    ) {
+        self.access.write(bus, slot, function, offset, value);
     }
 
     /// Check for a PCI device, and return information about it if present.
@@ -47,7 +54,7 @@ impl Pci {
 
         let mut registers: [u32; 18] = [0; 18];
         for (i, reg) in registers.iter_mut().enumerate() {
-            *reg = self.read_config_register(bus, slot, function, (i as u8) * 0x4);
+            *reg = self.read_config_register(bus, slot, function, (i as u16) * 0x4);
         }
 
         Some(PciDeviceFunction {
@@ -55,17 +62,101 @@ impl Pci {
             device_id: slot,
             function: function,
             header: Header::new(registers),
-            owned: false,
+            owner: None,
         })
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct PciDeviceFunction {
     pub bus: u8,
     pub device_id: u8,
     pub function: u8,
     pub header: Header,
-    pub owned: bool,
+    /// The name of the driver that owns this function, if any.
+    pub owner: Option<&'static str>,
+}
+
+impl PciDeviceFunction {
+    /// Returns true if a driver has claimed this function.
+    pub fn is_owned(&self) -> bool {
+        self.owner.is_some()
+    }
+
+    /// Returns true if this function is at the given location.
+    fn is_at(&self, bus: u8, slot: u8, function: u8) -> bool {
+        self.bus == bus && self.device_id == slot && self.function == function
+    }
+
+    /// Reads a configuration register of this function.
+    pub fn read_config(&self, offset: u16) -> u32 {
+        read_config(self.bus, self.device_id, self.function, offset)
+    }
+
+    /// Writes a configuration register of this function.
+    ///
+    /// # Safety
+    /// - Only the owner of the function should change its configuration.
+    pub unsafe fn write_config(&self, offset: u16, value: u32) {
+        write_config(self.bus, self.device_id, self.function, offset, value);
+    }
+
+    /// Returns the offset of the capability with the given ID, if present.
+    pub fn find_capability(&self, id: u8) -> Option<u16> {
+        // Bit 4 of the status register signals a capability list.
+        if self.header.status & 1 << 4 == 0 {
+            return None;
+        }
+
+        let mut offset = self.header.capabilities_pointer & 0b1111_1100;
+
+        // The list can't be longer than the legacy configuration space.
+        for _ in 0..48 {
+            if offset == 0 {
+                break;
+            }
+
+            let capability = self.read_config(offset as u16);
+
+            if capability as u8 == id {
+                return Some(offset as u16);
+            }
+
+            offset = (capability >> 8) as u8 & 0b1111_1100;
+        }
+
+        None
+    }
+
+    /// Returns the physical address of the given memory BAR.
+    ///
+    /// Returns `None` for I/O BARs and unused BARs.
+    pub fn memory_bar(&self, index: usize) -> Option<PhysicalAddress> {
+        let bars = self.header.base_addresses;
+        let bar = *bars.get(index)?;
+
+        // Bit 0 is set for I/O space BARs.
+        if bar & 1 != 0 {
+            return None;
+        }
+
+        let address = match (bar >> 1) & 0b11 {
+            // 32-bit BAR.
+            0b00 => (bar & !0b1111) as PhysicalAddress,
+            // 64-bit BAR using the next BAR as the upper half.
+            0b10 => {
+                let upper = *bars.get(index + 1)? as PhysicalAddress;
+                upper << 32 | (bar & !0b1111) as PhysicalAddress
+            }
+            _ => return None,
+        };
+
+        if address == 0 {
+            None
+        } else {
+            Some(address)
+        }
+    }
 }
 
 impl fmt::Display for PciDeviceFunction {
@@ -80,34 +171,121 @@ impl fmt::Display for PciDeviceFunction {
         )
     }
 }
+
 lazy_static! {
     static ref PCI: PreemptableMutex<Pci> = PreemptableMutex::new(Pci {
-        address: unsafe { cpuio::Port::new(0xCF8) },
-        data: unsafe { cpuio::Port::new(0xCFC) },
+        access: Box::new(PortAccess::new()),
         devices: Vec::new(),
+        enumerated: false,
     });
 }
 
-// lazy_static! {
-//     static ref PCI_DEVICES: PreemptableMutex<Vec<PciDeviceFunction>> = PreemptableMutex::new(Vec::new());
-// }
+lazy_static! {
+    /// The drivers that have been registered so far.
+    static ref PCI_DRIVERS: PreemptableMutex<Vec<&'static PciDriver>> =
+        PreemptableMutex::new(Vec::new());
+}
+
+/// The possible errors for PCI driver operations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PciError {
+    /// No device exists at the given location.
+    NoSuchDevice,
+    /// The device is already owned by another driver.
+    AlreadyOwned(&'static str),
+    /// The driver could not initialize the device.
+    ProbeFailed,
+    /// The device doesn't have the required capability.
+    NoCapability,
+    /// A BAR doesn't describe a usable memory region.
+    InvalidBar,
+    /// No interrupt vector is free anymore.
+    NoFreeVector,
+    /// The MSI-X table of the device has no entry with the given index.
+    InvalidVector(u16),
+}
 
-// lazy_static! {
-//     static ref PCI: PreemptableMutex<Pci<'static>> = PreemptableMutex::new(Pci {
-//             address: unsafe { cpuio::Port::new(0xCF8) },
-//             data: unsafe { cpuio::Port::new(0xCFC) },
-//             devices: PciDeviceFunctionIterator {
-//                     done: false,
-//                     bus: 0,
-//                     device: 0,
-//                     multifunction: false,
-//                     function: 0,
-//                 }.collect()
-//                 // }.map(move |e| TakeCell::new(&mut e)).collect()
-//     });
-// }
+/// Describes a set of devices a driver can handle.
+///
+/// Fields that are `None` match any value.
+#[derive(Debug, Clone, Copy)]
+pub struct PciDeviceId {
+    pub vendor_id: Option<u16>,
+    pub device_id: Option<u16>,
+    pub class_code: Option<u8>,
+    pub subclass: Option<u8>,
+    pub prog_if: Option<u8>,
+}
 
+impl PciDeviceId {
+    /// Matches a specific vendor and device ID.
+    pub const fn device(vendor_id: u16, device_id: u16) -> PciDeviceId {
+        PciDeviceId {
+            vendor_id: Some(vendor_id),
+            device_id: Some(device_id),
+            class_code: None,
+            subclass: None,
+            prog_if: None,
+        }
+    }
 
+    /// Matches a class and subclass with any programming interface.
+    pub const fn class(class_code: u8, subclass: u8) -> PciDeviceId {
+        PciDeviceId {
+            vendor_id: None,
+            device_id: None,
+            class_code: Some(class_code),
+            subclass: Some(subclass),
+            prog_if: None,
+        }
+    }
+
+    /// Matches a class, subclass and programming interface.
+    pub const fn class_with_prog_if(class_code: u8, subclass: u8, prog_if: u8) -> PciDeviceId {
+        PciDeviceId {
+            vendor_id: None,
+            device_id: None,
+            class_code: Some(class_code),
+            subclass: Some(subclass),
+            prog_if: Some(prog_if),
+        }
+    }
+
+    /// Returns true if the given header is matched by this ID.
+    pub fn matches(&self, header: &Header) -> bool {
+        fn field_matches<T: PartialEq>(wanted: Option<T>, actual: T) -> bool {
+            wanted.map_or(true, |wanted| wanted == actual)
+        }
+
+        field_matches(self.vendor_id, header.vendor_id)
+            && field_matches(self.device_id, header.device_id)
+            && field_matches(self.class_code, header.class_code)
+            && field_matches(self.subclass, header.subclass)
+            && field_matches(self.prog_if, header.prog_if)
+    }
+}
+
+/// Every PCI device driver implements this.
+pub trait PciDriver: Sync {
+    /// The name of the driver, which is also recorded as the device owner.
+    fn name(&self) -> &'static str;
+
+    /// The devices this driver is able to handle.
+    fn id_table(&self) -> &'static [PciDeviceId];
+
+    /// Initializes the given device.
+    ///
+    /// The device is owned by the driver while this is called. Returning an
+    /// error releases it again.
+    fn probe(&self, device: &PciDeviceFunction) -> Result<(), PciError>;
+
+    /// Returns true if the driver can handle the given device.
+    fn matches(&self, device: &PciDeviceFunction) -> bool {
+        self.id_table()
+            .iter()
+            .any(|id| id.matches(&device.header))
+    }
+}
 
 const MAX_BUS: u8 = 255;
 const MAX_DEVICE: u8 = 31;
@@ -157,10 +335,6 @@ impl Iterator for PciDeviceFunctionIterator {
             }
 
             // Check for something at the current bus/device/function.
-            // let radio_capsule = static_init!(
-            //     capsules::ieee802154::RadioDriver<'static>,
-            //    capsules::ieee802154::RadioDriver::new(radio_mac));
-
             if let Some(result) = unsafe { pci.probe(self.bus, self.device, self.function) } {
                 // Something was found
                 // Check to see if function 0 is multifunction
@@ -174,7 +348,6 @@ impl Iterator for PciDeviceFunctionIterator {
 
                 // Return our result
                 return Some(result);
-            // return Some(TakeCell::new(&mut result));
             } else {
                 // Nothing was found, update our state and continue
                 self.update_state();
@@ -195,27 +368,151 @@ pub fn pci_iter() -> PciDeviceFunctionIterator {
     }
 }
 
+/// Enumerates the PCI bus and probes the registered drivers.
 pub fn init_pci() {
-    PCI.lock().devices = pci_iter().collect();
-    // PCI.lock().devices = pci_iter().map(move |e| TakeCell::new(&mut e)).collect();
+    assert_has_not_been_called!("The PCI bus should only be enumerated once.");
+
+    let ecam_regions = acpi::mcfg::get_ecam_regions();
+    if !ecam_regions.is_empty() {
+        debugln!("PCI: Using ECAM for {} bus range(s).", ecam_regions.len());
+        PCI.lock().access = Box::new(EcamAccess::new(ecam_regions));
+    }
+
+    let devices: Vec<PciDeviceFunction> = pci_iter().collect();
+    {
+        let mut pci = PCI.lock();
+        pci.devices = devices;
+        pci.enumerated = true;
+    }
+
+    probe_drivers();
+}
+
+/// Registers a driver.
+///
+/// If the bus has already been enumerated, the driver is probed against all
+/// devices that are not owned yet.
+pub fn register_driver(driver: &'static PciDriver) {
+    PCI_DRIVERS.lock().push(driver);
+
+    let enumerated = PCI.lock().enumerated;
+    if enumerated {
+        probe_driver(driver);
+    }
+}
+
+/// Probes every registered driver against the unowned devices.
+fn probe_drivers() {
+    let drivers: Vec<&'static PciDriver> = PCI_DRIVERS.lock().clone();
+
+    for driver in drivers {
+        probe_driver(driver);
+    }
+}
+
+/// Probes a single driver against all unowned devices.
+fn probe_driver(driver: &'static PciDriver) {
+    let device_num = PCI.lock().devices.len();
+
+    for index in 0..device_num {
+        let device = PCI.lock().devices[index];
+
+        if device.is_owned() || !driver.matches(&device) {
+            continue;
+        }
+
+        // Claim first, so the device can't be handed out twice while the
+        // driver is probing without the lock held.
+        if claim_device(device.bus, device.device_id, device.function, driver.name()).is_err() {
+            continue;
+        }
+
+        match driver.probe(&device) {
+            Ok(()) => debugln!("PCI: {} owns {}", driver.name(), device),
+            Err(error) => {
+                debugln!("PCI: {} failed to probe {} ({:?})", driver.name(), device, error);
+                release_device(device.bus, device.device_id, device.function);
+            }
+        }
+    }
+}
+
+/// Marks the function at the given location as owned by `owner`.
+pub fn claim_device(bus: u8, slot: u8, function: u8, owner: &'static str) -> Result<(), PciError> {
+    let mut pci = PCI.lock();
+
+    match pci.devices
+        .iter_mut()
+        .find(|device| device.is_at(bus, slot, function))
+    {
+        Some(device) => match device.owner {
+            Some(current_owner) => Err(PciError::AlreadyOwned(current_owner)),
+            None => {
+                device.owner = Some(owner);
+                Ok(())
+            }
+        },
+        None => Err(PciError::NoSuchDevice),
+    }
+}
+
+/// Releases the ownership of the function at the given location.
+pub fn release_device(bus: u8, slot: u8, function: u8) {
+    let mut pci = PCI.lock();
+
+    if let Some(device) = pci.devices
+        .iter_mut()
+        .find(|device| device.is_at(bus, slot, function))
+    {
+        device.owner = None;
+    }
+}
+
+/// Returns the first unowned device with the given IDs.
+pub fn get_pci_device(vendor_id: u16, device_id: u16) -> Option<PciDeviceFunction> {
+    let id = PciDeviceId::device(vendor_id, device_id);
+
+    PCI.lock()
+        .devices
+        .iter()
+        .find(|device| !device.is_owned() && id.matches(&device.header))
+        .map(|device| *device)
+}
+
+/// Returns the number of enumerated functions.
+pub fn device_count() -> usize {
+    PCI.lock().devices.len()
+}
+
+/// Returns a copy of the function with the given index in the device list.
+pub fn get_device_by_index(index: usize) -> Option<PciDeviceFunction> {
+    PCI.lock().devices.get(index).map(|device| *device)
+}
+
+/// Returns the size of the configuration space of each function.
+pub fn config_space_size() -> usize {
+    PCI.lock().access.config_space_size()
+}
+
+/// Reads a configuration register of the given function.
+pub fn read_config(bus: u8, slot: u8, function: u8, offset: u16) -> u32 {
+    unsafe { PCI.lock().read_config_register(bus, slot, function, offset) }
+}
+
+/// Writes a configuration register of the given function.
+///
+/// # Safety
+/// - Writing configuration registers can change how the device decodes memory.
+pub unsafe fn write_config(bus: u8, slot: u8, function: u8, offset: u16, value: u32) {
+    PCI.lock()
+        .write_config_register(bus, slot, function, offset, value);
 }
 
 pub fn print_devices() {
     for device in PCI.lock().devices.iter() {
-        debugln!("{}", device.header)
+        debugln!("{}", device)
     }
 }
-//
-// pub fn get_pci_device(device_id: u16, vendor_id: u16) -> Option<PciDeviceFunction> {
-//     for device in PCI.lock().devices.iter() {
-//         if device.header.device_id == device_id && device.header.vendor_id == vendor_id &&
-//             !device.owned
-//         {
-//             return Some(device);
-//         }
-//     }
-//     None
-// }
 
 // Running under QEMU, and checking against http://pcidatabase.com/ , we have:
 //
@@ -223,3 +520,75 @@ pub fn print_devices() {
 // 0.1: 8086 7000 Intel 82371SB PIIX3 PCI-to-ISA Bridge (Triton II)
 // 0.2: 1013 00b8 Cirrus Logic CL-GD5446 64-bit VisualMedia Accelerator
 // 0.3: 8086 100e Intel 02000 Intel Pro 1000/MT
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a function with a standard header and the given BARs.
+    fn function(vendor_id: u16, device_id: u16, class: u32, bars: [u32; 6]) -> PciDeviceFunction {
+        let mut registers = [0; 18];
+        registers[0] = (device_id as u32) << 16 | vendor_id as u32;
+        registers[2] = class << 8;
+        registers[4..10].copy_from_slice(&bars);
+
+        PciDeviceFunction {
+            bus: 0,
+            device_id: 3,
+            function: 0,
+            header: Header::new(registers),
+            owner: None,
+        }
+    }
+
+    /// Tests that fields that are `None` match any value.
+    #[test]
+    fn test_matches() {
+        // An IDE controller with programming interface 0x80.
+        let header = function(0x8086, 0x7010, 0x01_01_80, [0; 6]).header;
+
+        assert!(PciDeviceId::device(0x8086, 0x7010).matches(&header));
+        assert!(!PciDeviceId::device(0x8086, 0x7011).matches(&header));
+        assert!(!PciDeviceId::device(0x1234, 0x7010).matches(&header));
+
+        assert!(PciDeviceId::class(0x01, 0x01).matches(&header));
+        assert!(!PciDeviceId::class(0x01, 0x06).matches(&header));
+        assert!(!PciDeviceId::class(0x02, 0x01).matches(&header));
+
+        assert!(PciDeviceId::class_with_prog_if(0x01, 0x01, 0x80).matches(&header));
+        assert!(!PciDeviceId::class_with_prog_if(0x01, 0x01, 0x8a).matches(&header));
+
+        let any = PciDeviceId {
+            vendor_id: None,
+            device_id: None,
+            class_code: None,
+            subclass: None,
+            prog_if: None,
+        };
+        assert!(any.matches(&header));
+    }
+
+    /// Tests decoding 32-bit, 64-bit, I/O and unused BARs.
+    #[test]
+    fn test_memory_bar() {
+        let bars = [
+            0xfebf_0008,
+            0xfe00_000c,
+            0x0000_0001,
+            0x0000_c041,
+            0,
+            0x0000_0004,
+        ];
+        let device = function(0x1234, 0x1111, 0x03_00_00, bars);
+
+        // Prefetchable 32-bit BAR.
+        assert_eq!(device.memory_bar(0), Some(0xfebf_0000));
+        // 64-bit BAR using BAR 2 as the upper half.
+        assert_eq!(device.memory_bar(1), Some(0x1_fe00_0000));
+        assert_eq!(device.memory_bar(3), None);
+        assert_eq!(device.memory_bar(4), None);
+        // A 64-bit BAR can't use the last BAR as its lower half.
+        assert_eq!(device.memory_bar(5), None);
+        assert_eq!(device.memory_bar(6), None);
+    }
+}
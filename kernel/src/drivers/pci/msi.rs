@@ -0,0 +1,230 @@
+//! Configures message signaled interrupts (MSI and MSI-X).
+//!
+//! See http://wiki.osdev.org/PCI#Message_Signaled_Interrupts for details.
+
+use super::{PciDeviceFunction, PciError};
+use arch::interrupts::vectors::{allocate_vector, free_vector, InterruptHandler};
+use core::ptr;
+use memory::{map_physical_range, PageFlags};
+
+/// The capability ID of MSI.
+const MSI_CAPABILITY_ID: u8 = 0x05;
+
+/// The capability ID of MSI-X.
+const MSIX_CAPABILITY_ID: u8 = 0x11;
+
+/// The offset of the command register.
+const COMMAND_REGISTER: u16 = 0x04;
+
+/// Disables legacy INTx interrupts when set in the command register.
+const INTERRUPT_DISABLE: u32 = 1 << 10;
+
+/// Enables MSI when set in the MSI message control register.
+const MSI_ENABLE: u32 = 1 << 16;
+
+/// Set in the MSI message control register if 64-bit addresses are supported.
+const MSI_64_BIT: u32 = 1 << 23;
+
+/// The multiple message enable field of the MSI message control register.
+const MSI_MULTIPLE_MESSAGE_ENABLE: u32 = 0b111 << 20;
+
+/// Enables MSI-X when set in the MSI-X message control register.
+const MSIX_ENABLE: u32 = 1 << 31;
+
+/// Masks all MSI-X vectors when set in the MSI-X message control register.
+const MSIX_FUNCTION_MASK: u32 = 1 << 30;
+
+/// Masks a single MSI-X table entry when set in its vector control field.
+const MSIX_ENTRY_MASKED: u32 = 1;
+
+/// The size of an MSI-X table entry.
+const MSIX_ENTRY_SIZE: usize = 16;
+
+/// Returns the message address that targets the LAPIC with the given ID.
+fn message_address(lapic_id: u8) -> u32 {
+    0xfee00000 | (lapic_id as u32) << 12
+}
+
+/// Returns the message data for a fixed, edge triggered interrupt.
+fn message_data(vector: u8) -> u32 {
+    vector as u32
+}
+
+/// Disables the legacy INTx interrupts of the device.
+///
+/// # Safety
+/// - The caller must own the device.
+unsafe fn disable_legacy_interrupts(device: &PciDeviceFunction) {
+    // The upper half is the status register, whose bits are cleared by
+    // writing ones, so only the command register is written back.
+    let command = device.read_config(COMMAND_REGISTER) & 0xffff;
+    device.write_config(COMMAND_REGISTER, command | INTERRUPT_DISABLE);
+}
+
+/// Enables MSI for the device, delivering a single vector to the given LAPIC.
+///
+/// Returns the vector that was allocated for the handler.
+///
+/// # Safety
+/// - The caller must own the device.
+pub unsafe fn enable_msi<H: InterruptHandler + 'static>(
+    device: &PciDeviceFunction,
+    handler: H,
+    lapic_id: u8,
+) -> Result<u8, PciError> {
+    let capability = device
+        .find_capability(MSI_CAPABILITY_ID)
+        .ok_or(PciError::NoCapability)?;
+
+    let vector = allocate_vector(handler).ok_or(PciError::NoFreeVector)?;
+
+    let control = device.read_config(capability);
+    let data_offset = if control & MSI_64_BIT != 0 {
+        device.write_config(capability + 8, 0);
+        capability + 12
+    } else {
+        capability + 8
+    };
+
+    device.write_config(capability + 4, message_address(lapic_id));
+
+    // Only the message data is in the lower half, the upper half is reserved
+    // or used for per-vector masking.
+    let data = device.read_config(data_offset) & 0xffff0000;
+    device.write_config(data_offset, data | message_data(vector));
+
+    disable_legacy_interrupts(device);
+
+    // Use a single message and enable MSI.
+    device.write_config(
+        capability,
+        (control & !MSI_MULTIPLE_MESSAGE_ENABLE) | MSI_ENABLE,
+    );
+
+    Ok(vector)
+}
+
+/// Disables MSI for the device and frees the given vector.
+///
+/// # Safety
+/// - The caller must own the device.
+/// - The vector must be the one returned by `enable_msi`.
+pub unsafe fn disable_msi(device: &PciDeviceFunction, vector: u8) {
+    if let Some(capability) = device.find_capability(MSI_CAPABILITY_ID) {
+        let control = device.read_config(capability);
+        device.write_config(capability, control & !MSI_ENABLE);
+    }
+
+    free_vector(vector);
+}
+
+/// Describes the MSI-X table of a device.
+struct MsixTable {
+    /// The offset of the MSI-X capability.
+    capability: u16,
+    /// The virtual address of the table.
+    address: usize,
+    /// The number of entries in the table.
+    size: u16,
+}
+
+impl MsixTable {
+    /// Locates and maps the MSI-X table of the given device.
+    fn new(device: &PciDeviceFunction) -> Result<MsixTable, PciError> {
+        let capability = device
+            .find_capability(MSIX_CAPABILITY_ID)
+            .ok_or(PciError::NoCapability)?;
+
+        let control = device.read_config(capability);
+        let size = ((control >> 16) & 0x7ff) as u16 + 1;
+
+        let table_register = device.read_config(capability + 4);
+        let bar = (table_register & 0b111) as usize;
+        let offset = (table_register & !0b111) as usize;
+
+        let bar_address = device.memory_bar(bar).ok_or(PciError::InvalidBar)?;
+        let address = map_physical_range(
+            bar_address + offset,
+            size as usize * MSIX_ENTRY_SIZE,
+            PageFlags::READABLE | PageFlags::WRITABLE | PageFlags::NO_CACHE,
+        );
+
+        Ok(MsixTable {
+            capability,
+            address,
+            size,
+        })
+    }
+
+    /// Returns a pointer to the given dword of the given entry.
+    fn entry_register(&self, entry: u16, dword: usize) -> *mut u32 {
+        (self.address + entry as usize * MSIX_ENTRY_SIZE + dword * 4) as *mut u32
+    }
+}
+
+/// Enables MSI-X for the device and routes the given table entry to the given
+/// LAPIC.
+///
+/// Returns the vector that was allocated for the handler.
+///
+/// # Safety
+/// - The caller must own the device.
+pub unsafe fn enable_msix<H: InterruptHandler + 'static>(
+    device: &PciDeviceFunction,
+    entry: u16,
+    handler: H,
+    lapic_id: u8,
+) -> Result<u8, PciError> {
+    let table = MsixTable::new(device)?;
+
+    if entry >= table.size {
+        return Err(PciError::InvalidVector(entry));
+    }
+
+    let vector = allocate_vector(handler).ok_or(PciError::NoFreeVector)?;
+
+    // Enable MSI-X with all vectors masked while the entry is changed.
+    let control = device.read_config(table.capability);
+    device.write_config(
+        table.capability,
+        control | MSIX_ENABLE | MSIX_FUNCTION_MASK,
+    );
+
+    ptr::write_volatile(table.entry_register(entry, 0), message_address(lapic_id));
+    ptr::write_volatile(table.entry_register(entry, 1), 0);
+    ptr::write_volatile(table.entry_register(entry, 2), message_data(vector));
+
+    let vector_control = ptr::read_volatile(table.entry_register(entry, 3));
+    ptr::write_volatile(
+        table.entry_register(entry, 3),
+        vector_control & !MSIX_ENTRY_MASKED,
+    );
+
+    disable_legacy_interrupts(device);
+
+    device.write_config(
+        table.capability,
+        (control | MSIX_ENABLE) & !MSIX_FUNCTION_MASK,
+    );
+
+    Ok(vector)
+}
+
+/// Masks the given MSI-X table entry and frees its vector.
+///
+/// # Safety
+/// - The caller must own the device.
+/// - The vector must be the one returned by `enable_msix` for this entry.
+pub unsafe fn disable_msix(device: &PciDeviceFunction, entry: u16, vector: u8) {
+    if let Ok(table) = MsixTable::new(device) {
+        if entry < table.size {
+            let vector_control = ptr::read_volatile(table.entry_register(entry, 3));
+            ptr::write_volatile(
+                table.entry_register(entry, 3),
+                vector_control | MSIX_ENTRY_MASKED,
+            );
+        }
+    }
+
+    free_vector(vector);
+}
@@ -0,0 +1,190 @@
+//! A driver for the CMOS real time clock.
+//!
+//! The RTC keeps the date and time while the machine is powered off. It is
+//! read once at boot to seed the realtime clock.
+
+use acpi::fadt;
+use boring_core::time::DateTime;
+use sync::PreemptableMutex;
+use sync::time::{set_realtime, NANOSECONDS_PER_SECOND};
+use x86_64::instructions::port::{inb, outb};
+
+/// The port used to select a CMOS register.
+const CMOS_ADDRESS_PORT: u16 = 0x70;
+
+/// The port used to access the selected CMOS register.
+const CMOS_DATA_PORT: u16 = 0x71;
+
+/// The bit of the address port that disables NMIs.
+const NMI_DISABLE_BIT: u8 = 0x80;
+
+/// The CMOS register holding the seconds.
+const SECONDS_REGISTER: u8 = 0x00;
+
+/// The CMOS register holding the minutes.
+const MINUTES_REGISTER: u8 = 0x02;
+
+/// The CMOS register holding the hours.
+const HOURS_REGISTER: u8 = 0x04;
+
+/// The CMOS register holding the day of the month.
+const DAY_REGISTER: u8 = 0x07;
+
+/// The CMOS register holding the month.
+const MONTH_REGISTER: u8 = 0x08;
+
+/// The CMOS register holding the year within the century.
+const YEAR_REGISTER: u8 = 0x09;
+
+/// Status register A, which indicates running updates.
+const STATUS_REGISTER_A: u8 = 0x0a;
+
+/// Status register B, which holds the data format.
+const STATUS_REGISTER_B: u8 = 0x0b;
+
+/// Set in status register A while the RTC updates its registers.
+const UPDATE_IN_PROGRESS: u8 = 0x80;
+
+/// Set in status register B if the hours use the 24 hour format.
+const HOUR_FORMAT_24: u8 = 0x02;
+
+/// Set in status register B if the values are binary instead of BCD.
+const BINARY_MODE: u8 = 0x04;
+
+/// Set in the hours register for PM times in the 12 hour format.
+const PM_BIT: u8 = 0x80;
+
+/// The century that is assumed if the CMOS doesn't store it.
+const DEFAULT_CENTURY: u32 = 20;
+
+/// Serializes accesses to the CMOS registers.
+pub static CMOS_LOCK: PreemptableMutex<()> = PreemptableMutex::new(());
+
+/// Reads the RTC and seeds the realtime clock.
+pub fn init() {
+    assert_has_not_been_called!("The RTC should only be initialized once.");
+
+    let time = read_time();
+
+    set_realtime(time.to_unix_seconds() * NANOSECONDS_PER_SECOND);
+
+    debugln!(
+        "RTC time: {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        time.year,
+        time.month,
+        time.day,
+        time.hour,
+        time.minute,
+        time.second
+    );
+}
+
+/// Reads the current date and time from the RTC.
+pub fn read_time() -> DateTime {
+    let _lock = CMOS_LOCK.lock();
+    let century_register = get_century_register();
+
+    // Read until two consecutive reads match, to avoid reading during an update.
+    let mut time = read_raw_time(century_register);
+    loop {
+        let next_time = read_raw_time(century_register);
+        if next_time == time {
+            break;
+        }
+        time = next_time;
+    }
+
+    let status_b = unsafe { read_register(STATUS_REGISTER_B) };
+    let binary = status_b & BINARY_MODE != 0;
+    let convert = |value: u8| if binary { value } else { from_bcd(value) };
+
+    let pm = time.hours & PM_BIT != 0;
+    let mut hour = convert(time.hours & !PM_BIT);
+    if status_b & HOUR_FORMAT_24 == 0 {
+        // 12 AM is midnight and 12 PM is noon.
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    let century = match time.century {
+        Some(century) => convert(century) as u32,
+        None => DEFAULT_CENTURY,
+    };
+
+    DateTime {
+        year: century * 100 + convert(time.year) as u32,
+        month: convert(time.month),
+        day: convert(time.day),
+        hour,
+        minute: convert(time.minutes),
+        second: convert(time.seconds),
+    }
+}
+
+/// The unconverted values of the time registers.
+#[derive(PartialEq, Eq)]
+struct RawTime {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: Option<u8>,
+}
+
+/// Reads the time registers once no update is in progress.
+fn read_raw_time(century_register: Option<u8>) -> RawTime {
+    unsafe {
+        while read_register(STATUS_REGISTER_A) & UPDATE_IN_PROGRESS != 0 {
+            asm!("pause" : : : : "intel", "volatile");
+        }
+
+        RawTime {
+            seconds: read_register(SECONDS_REGISTER),
+            minutes: read_register(MINUTES_REGISTER),
+            hours: read_register(HOURS_REGISTER),
+            day: read_register(DAY_REGISTER),
+            month: read_register(MONTH_REGISTER),
+            year: read_register(YEAR_REGISTER),
+            century: century_register.map(|register| read_register(register)),
+        }
+    }
+}
+
+/// Returns the CMOS register holding the century, if the FADT describes one.
+fn get_century_register() -> Option<u8> {
+    match fadt::get_info() {
+        Some(info) if info.century_register != 0 => Some(info.century_register),
+        _ => None,
+    }
+}
+
+/// Reads the given CMOS register.
+///
+/// # Safety
+/// - The caller must hold the CMOS lock.
+unsafe fn read_register(register: u8) -> u8 {
+    let nmi_bit = inb(CMOS_ADDRESS_PORT) & NMI_DISABLE_BIT;
+    outb(CMOS_ADDRESS_PORT, nmi_bit | register);
+    inb(CMOS_DATA_PORT)
+}
+
+/// Converts a BCD value to binary.
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the BCD conversion.
+    #[test]
+    fn test_bcd() {
+        assert_eq!(from_bcd(0x59), 59);
+        assert_eq!(from_bcd(0x12), 12);
+    }
+}
@@ -1,15 +1,24 @@
 //! Handles ELF files.
 
+use alloc::String;
 use alloc::boxed::Box;
-use core::fmt;
+use boring_core::elf::{DynamicEntry, Header, ProgramHeader, Relocation, DT_NULL, DT_RELA,
+                       DT_RELAENT, DT_RELASZ, EI_ABIVERSION, EI_OSABI, EI_VERSION, ET_DYN,
+                       ET_EXEC, EV_CURRENT, PF_R, PF_W, PF_X, PT_DYNAMIC, PT_INTERP, PT_LOAD,
+                       R_X86_64_NONE, R_X86_64_RELATIVE};
+use core::cmp::{max, min};
 use core::mem;
 use core::mem::size_of;
+use core::slice;
+use core::str;
 use file_handle::FileHandle;
 use initramfs;
-use memory::{PageFlags, PhysicalAddress, VirtualAddress, PAGE_SIZE};
+use memory::{aslr, PageFlags, VirtualAddress, HUGE_PAGE_SIZE, PAGE_SIZE,
+             USER_ASLR_RANGE, USER_INTERPRETER_AREA_BASE, USER_LIBRARY_AREA_BASE,
+             USER_PIE_AREA_BASE};
 use memory::address_space;
 use memory::address_space::{AddressSpace, Segment};
-use multitasking::{create_process, ProcessID};
+use multitasking::{create_process, create_process_with_arguments, ProcessID};
 
 /// Represents an ELF file.
 struct ElfFile {
@@ -23,7 +32,7 @@ impl ElfFile {
     /// Reads an ELF file from the initramfs.
     fn from_initramfs(name: &str) -> Result<ElfFile, ElfError> {
         if let Ok(mut file_handle) = initramfs::open(name) {
-            Header::from_file_handle(&mut *file_handle).and_then(|header| {
+            read_header(&mut *file_handle).and_then(|header| {
                 let file_size = file_handle.len();
 
                 // Check if the program header is fully contained in the file.
@@ -46,7 +55,7 @@ impl ElfFile {
                     };
 
                     for program_header in program_header_iterator {
-                        if !program_header.is_fully_contained(file_size) {
+                        if !is_fully_contained(&program_header, file_size) {
                             return Err(ElfError::InvalidFile);
                         }
                     }
@@ -93,286 +102,58 @@ pub enum ElfError {
     WrongType,
     /// The file is not a valid ELF file.
     InvalidFile,
-    /// The segments within the ELF file overlapped.
+    /// The segments within the ELF file overlapped each other or the stack.
     OverlappingSegments,
+    /// The file contains a relocation of a type the kernel can't handle.
+    UnsupportedRelocation,
+    /// The program interpreter requested by the file can't be used.
+    InvalidInterpreter,
 }
 
-/// Differentiates the endianness (byte order).
-#[repr(u8)]
-#[derive(Debug, PartialEq)]
-enum Endianness {
-    /// Least significant byte first.
-    Little = 1,
-    /// Most significant byte first.
-    Big = 2,
-}
+/// Reads the ELF header from the file handle.
+fn read_header(file_handle: &mut FileHandle) -> Result<Header, ElfError> {
+    let file_size = file_handle.len();
 
-impl Endianness {
-    /// Returns true if the endianness is the same as the one of the kernel.
-    fn is_native(&self) -> bool {
-        if cfg!(target_endian = "little") {
-            *self == Endianness::Little
-        } else if cfg!(target_endian = "big") {
-            *self == Endianness::Big
-        } else {
-            false
-        }
+    if file_size < size_of::<Header>() as u64 {
+        return Err(ElfError::NotAnElfFile);
     }
-}
-
-/// Differentiates between 32 and 64 bit executables.
-#[repr(u8)]
-#[derive(Debug, PartialEq)]
-enum ELFClass {
-    /// A 32 bit exectuable.
-    Bit32 = 1,
-    /// A 64 bit executable.
-    Bit64 = 2,
-}
 
-impl ELFClass {
-    /// Checks if the bus width for the binary is the same as in the kernel.
-    fn is_native(&self) -> bool {
-        if cfg!(target_pointer_width = "64") {
-            *self == ELFClass::Bit64
-        } else if cfg!(target_pointer_width = "32") {
-            *self == ELFClass::Bit32
-        } else {
-            false
-        }
-    }
-}
+    let header: Header = unsafe {
+        let mut header_buffer: [u8; size_of::<Header>()] = mem::uninitialized();
 
-/// The different types of ELF files.
-#[repr(u16)]
-#[derive(Debug, PartialEq)]
-#[allow(dead_code)]
-enum ElfType {
-    /// No file type is specified.
-    NoFileType = 0,
-    /// The ELF file is suitable for further linking.
-    Relocatable = 1,
-    /// The ELF file may be executed.
-    Executable = 2,
-    /// The ELF file is a shared object.
-    Shared = 3,
-    /// File contents are unspecified (reserved).
-    Core = 4,
-}
+        file_handle.read(&mut header_buffer).unwrap();
 
-/// The instruction set of the executable file.
-#[repr(u16)]
-#[derive(Debug, PartialEq)]
-#[allow(dead_code)]
-enum InstructionSet {
-    /// No instruction set was given.
-    NoSpecific = 0,
-    /// The ELF file uses the x86 instruction set.
-    #[allow(non_camel_case_types)]
-    x86 = 3,
-    /// The ELF file uses the ARM instruction set.
-    ARM = 0x28,
-    /// The ELF file uses the x86_64 instruction set.
-    #[allow(non_camel_case_types)]
-    x86_64 = 0x3e,
-}
+        mem::transmute(header_buffer)
+    };
 
-impl InstructionSet {
-    /// Returns true if the instruction set corresponds to the instruction set
-    /// of the machine.
-    fn is_native(&self) -> bool {
-        if cfg!(target_arch = "x86_64") {
-            *self == InstructionSet::x86_64
-        } else {
-            false
-        }
+    if !header.is_valid() {
+        return Err(ElfError::NotAnElfFile);
     }
-}
 
-/// Represents the header at the beginning of an ELF file.
-#[repr(C, packed)]
-struct Header {
-    /// The magic number: [0x7f, 'E', 'L', 'F'].
-    magic: [u8; 4],
-    /// The class of ELF file.
-    elf_class: ELFClass,
-    /// The endianness of the ELF file.
-    endianness: Endianness,
-    /// The ELF version.
-    version: u8,
-    /// The ABI of the file.
-    abi: u8,
-    /// The ABI version of the file.
-    abi_version: u8,
-    /// Unused padding bytes.
-    padding: [u8; 7],
-    /// The type of ELF file.
-    elf_type: ElfType,
-    /// The instruction set used by the ELF file.
-    instruction_set: InstructionSet,
-    /// The version of the ELF file.
-    elf_version: u32,
-    /// The entry address for the program.
-    program_entry: VirtualAddress,
-    /// The offset from file start to the program header.
-    program_header_offset: usize,
-    /// The offset from file start to the section header.
-    section_header_offset: usize,
-    /// Architecture specific flags.
-    flags: u32,
-    /// The size of the ELF header.
-    header_size: u16,
-    /// The size of a program header entry.
-    program_header_entry_size: u16,
-    /// The amount of program header entries.
-    program_header_entry_num: u16,
-    /// The size of a section header entry.
-    section_header_entry_size: u16,
-    /// The amount of section header entries.
-    section_header_entry_num: u16,
-    /// The index of the section header to the name strings.
-    name_string_table_index: u16,
-}
-
-impl fmt::Debug for Header {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if !self.is_valid() {
-            write!(f, "Invalid ELF header.")
-        } else {
-            unsafe {
-                write!(
-                    f,
-                    "Magic: ['\\x{:x}', '{}', '{}', '{}'],
-{}: {:?}, {}: {:?}, {}: {}, {}: {:?}, {}: {}, {}: {:x}",
-                    self.magic[0],
-                    self.magic[1] as char,
-                    self.magic[2] as char,
-                    self.magic[3] as char,
-                    "ELFClass",
-                    self.elf_class,
-                    "Endianness",
-                    self.endianness,
-                    "ABI",
-                    self.abi,
-                    "Type",
-                    self.elf_type,
-                    "Version",
-                    self.elf_version,
-                    "Entry address",
-                    self.program_entry
-                )
-            }
-        }
+    if header.identification[EI_VERSION] != EV_CURRENT {
+        return Err(ElfError::UnknownVersion);
     }
-}
-
-impl Header {
-    /// Creates a ELF header from the file handle.
-    fn from_file_handle(file_handle: &mut FileHandle) -> Result<Header, ElfError> {
-        let file_size = file_handle.len();
-
-        if file_size < size_of::<Header>() as u64 {
-            return Err(ElfError::NotAnElfFile);
-        }
 
-        let header: Header = unsafe {
-            let mut header_buffer: [u8; size_of::<Header>()] = mem::uninitialized();
-
-            file_handle.read(&mut header_buffer).unwrap();
-
-            mem::transmute(header_buffer)
-        };
-
-        if !header.is_valid() {
-            return Err(ElfError::NotAnElfFile);
-        }
-
-        if header.version != 1 {
-            return Err(ElfError::UnknownVersion);
-        }
-
-        if !header.is_executable() {
-            return Err(ElfError::WrongType);
-        }
-        debugln!("{:?}", header);
-
-        Ok(header)
-    }
-
-    /// Returns true if the header is valid and can be understood by the kernel.
-    fn is_valid(&self) -> bool {
-        self.magic == [0x7f, 'E' as u8, 'L' as u8, 'F' as u8]
+    if !is_executable(&header) {
+        return Err(ElfError::WrongType);
     }
+    debugln!("{:?}", header);
 
-    /// Returns true if the file can be executed.
-    fn is_executable(&self) -> bool {
-        unsafe {
-            self.endianness.is_native() && self.instruction_set.is_native() && self.abi == 0
-                && self.abi_version == 0 && self.elf_type == ElfType::Executable
-                && self.program_header_offset != 0 && self.elf_class.is_native()
-        }
-    }
+    Ok(header)
 }
 
-/// Represents the different segment types in the program header.
-#[repr(u32)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-#[allow(dead_code)]
-enum SegmentType {
-    /// An unused entry.
-    Null = 0,
-    /// A loadable segment.
-    Load = 1,
-    /// Dynamic linking tables.
-    Dynamic = 2,
-    /// The path to a program interpreter.
-    Interpreter = 3,
-    /// Note sections.
-    Note = 4,
+/// Returns true if the file can be executed.
+fn is_executable(header: &Header) -> bool {
+    header.is_native() && header.identification[EI_OSABI] == 0
+        && header.identification[EI_ABIVERSION] == 0
+        && (header.elf_type == ET_EXEC || header.elf_type == ET_DYN)
+        && header.program_header_offset != 0
 }
 
-bitflags! {
-    /// The different flags a segment can have.
-    struct SegmentFlags: u32 {
-        /// The segment may be executed.
-        const EXECUTABLE = 0x1;
-        /// The segment is writable.
-        const WRITABLE = 0x2;
-        /// The segment is readable.
-        const READABLE = 0x4;
-    }
-}
-
-/// Represents the program header of an ELF file.
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug)]
-struct ProgramHeader {
-    /// The type of the segment.
-    segment_type: SegmentType,
-    /// The flags of the segment.
-    flags: SegmentFlags,
-    /// The offset from the beginning of the file.
-    offset: usize,
-    /// The virtual address at which the segment should be mapped.
-    virtual_address: VirtualAddress,
-    /// The physical address at which the segment should be mapped.
-    ///
-    /// # Note
-    /// Reserved for systems with physical addressing.
-    physical_address: PhysicalAddress,
-    /// The size of the segment within the file.
-    size_in_file: usize,
-    /// The size of the segment within memory.
-    size_in_memory: usize,
-    /// The alignment of the segment.
-    align: usize,
-}
-
-impl ProgramHeader {
-    fn is_fully_contained(&self, file_size: u64) -> bool {
-        file_size >= (self.offset as u64).saturating_add(self.size_in_file as u64)
-            || self.size_in_file == 0
-    }
+/// Returns true if the data of the segment is within a file of the given size.
+fn is_fully_contained(program_header: &ProgramHeader, file_size: u64) -> bool {
+    file_size >= (program_header.offset as u64).saturating_add(program_header.size_in_file as u64)
+        || program_header.size_in_file == 0
 }
 
 /// Provides an iterator for the program headers.
@@ -417,41 +198,172 @@ impl<'a> Iterator for ProgramHeaderIterator<'a> {
     }
 }
 
-/// Creates a new process from the given file on the initramfs.
-pub fn process_from_initramfs_file(name: &str) -> Result<ProcessID, ElfError> {
-    ElfFile::from_initramfs(name).and_then(|file| process_from_elf_file(file))
+/// The maximum length of the path to a program interpreter.
+const MAX_INTERPRETER_PATH_LENGTH: usize = 256;
+
+/// Returns the address to write to and the value to write there for the
+/// relocation of a file loaded at `load_base`, unless the relocation does
+/// nothing.
+fn apply_relocation(
+    relocation: &Relocation,
+    load_base: VirtualAddress,
+) -> Result<Option<(VirtualAddress, VirtualAddress)>, ElfError> {
+    match relocation.relocation_type() {
+        R_X86_64_NONE => Ok(None),
+        R_X86_64_RELATIVE => {
+            let address = load_base
+                .checked_add(relocation.offset)
+                .ok_or(ElfError::InvalidFile)?;
+            let value = relocation
+                .value(load_base, 0)
+                .ok_or(ElfError::UnsupportedRelocation)?;
+
+            Ok(Some((address, value)))
+        }
+        _ => Err(ElfError::UnsupportedRelocation),
+    }
 }
 
-/// Creates a new process from the given ELF file handle.
-fn process_from_elf_file(mut file: ElfFile) -> Result<ProcessID, ElfError> {
-    let mut address_space = AddressSpace::new();
+/// The location of the relocation table with addends in the file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RelocationTable {
+    /// The file offset of the table.
+    offset: u64,
+    /// The number of relocations in the table.
+    count: usize,
+    /// The size of a single entry.
+    entry_size: usize,
+}
 
-    {
-        let mut iterator = file.program_headers();
+impl ElfFile {
+    /// Reads a value of type `T` at the given offset in the file.
+    fn read_value<T: Copy>(&mut self, offset: u64) -> Result<T, ElfError> {
+        unsafe {
+            let mut value: T = mem::uninitialized();
+            let buffer = slice::from_raw_parts_mut(&mut value as *mut T as *mut u8, size_of::<T>());
+
+            self.file_handle
+                .read_at(buffer, offset)
+                .map_err(|_| ElfError::InvalidFile)?;
+
+            Ok(value)
+        }
+    }
+
+    /// Returns the file offset of the data linked at the given address.
+    ///
+    /// All `length` bytes after the address have to be backed by the file.
+    fn file_offset_of(&mut self, address: VirtualAddress, length: usize) -> Option<u64> {
+        self.program_headers()
+            .filter(|program_header| program_header.segment_type == PT_LOAD)
+            .find(|program_header| {
+                let start = program_header.virtual_address;
+                let end = start.saturating_add(program_header.size_in_file);
+
+                start <= address && address.saturating_add(length) <= end
+            })
+            .map(|program_header| {
+                (program_header.offset + address - program_header.virtual_address) as u64
+            })
+    }
+
+    /// Returns the first program header of the given type.
+    fn find_program_header(&mut self, segment_type: u32) -> Option<ProgramHeader> {
+        self.program_headers()
+            .find(|program_header| program_header.segment_type == segment_type)
+    }
+
+    /// Returns the largest alignment the loadable segments need, but at least
+    /// the page size.
+    fn load_align(&mut self) -> usize {
+        self.program_headers()
+            .filter(|program_header| program_header.segment_type == PT_LOAD)
+            .map(|program_header| program_header.align)
+            .filter(|align| align.is_power_of_two())
+            .fold(PAGE_SIZE, max)
+    }
+
+    /// Returns the address at which the file should be loaded.
+    ///
+    /// Position independent files are loaded at a random address above
+    /// `area_base`, all others at the address they were linked at.
+    fn choose_load_base(&mut self, area_base: VirtualAddress) -> VirtualAddress {
+        if self.header.elf_type != ET_DYN {
+            return 0;
+        }
+
+        let align = self.load_align();
+
+        area_base + aslr::random_offset(USER_ASLR_RANGE, align)
+    }
+
+    /// Returns the entry address of the file loaded at `load_base`.
+    fn entry_address(&self, load_base: VirtualAddress) -> Result<VirtualAddress, ElfError> {
+        load_base
+            .checked_add(self.header.program_entry)
+            .ok_or(ElfError::InvalidFile)
+    }
+
+    /// Reads the path of the program interpreter the file requests.
+    fn interpreter_path(&mut self, interpreter: &ProgramHeader) -> Result<String, ElfError> {
+        if interpreter.size_in_file > MAX_INTERPRETER_PATH_LENGTH {
+            return Err(ElfError::InvalidInterpreter);
+        }
+
+        let mut buffer = [0; MAX_INTERPRETER_PATH_LENGTH];
+        let path = &mut buffer[..interpreter.size_in_file];
+        self.file_handle
+            .read_at(path, interpreter.offset as u64)
+            .map_err(|_| ElfError::InvalidFile)?;
+
+        // The path is terminated by a null byte.
+        let length = path.iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(path.len());
+
+        str::from_utf8(&path[..length])
+            .map(String::from)
+            .map_err(|_| ElfError::InvalidInterpreter)
+    }
+
+    /// Maps all loadable segments of the file into the address space.
+    ///
+    /// Returns the load base, which is added to all addresses in the file.
+    fn load(
+        &mut self,
+        area_base: VirtualAddress,
+        address_space: &mut AddressSpace,
+    ) -> Result<VirtualAddress, ElfError> {
+        let load_base = self.choose_load_base(area_base);
+        let mut iterator = self.program_headers();
 
         // For each segment.
         while let Some(program_header) = iterator.next() {
-            if { program_header.segment_type } != SegmentType::Load {
+            if program_header.segment_type != PT_LOAD {
                 continue;
             }
 
+            let virtual_address = load_base
+                .checked_add(program_header.virtual_address)
+                .ok_or(ElfError::InvalidFile)?;
+
             // Convert the flags to page flags.
             let mut flags = PageFlags::USER_ACCESSIBLE;
 
-            if { program_header.flags }.contains(SegmentFlags::READABLE) {
+            if program_header.flags & PF_R != 0 {
                 flags |= PageFlags::READABLE;
             }
 
-            if { program_header.flags }.contains(SegmentFlags::WRITABLE) {
+            if program_header.flags & PF_W != 0 {
                 flags |= PageFlags::WRITABLE;
             }
 
-            if { program_header.flags }.contains(SegmentFlags::EXECUTABLE) {
+            if program_header.flags & PF_X != 0 {
                 flags |= PageFlags::EXECUTABLE;
             }
 
             let segment = Segment::new(
-                program_header.virtual_address,
+                virtual_address,
                 program_header.size_in_memory,
                 flags,
                 address_space::SegmentType::FromFile,
@@ -485,28 +397,397 @@ fn process_from_elf_file(mut file: ElfFile) -> Result<ProcessID, ElfError> {
                     return Err(ElfError::InvalidFile);
                 }
 
-                address_space
-                    .write_to(segment_data, program_header.virtual_address + i * PAGE_SIZE);
+                address_space.write_to(segment_data, virtual_address + i * PAGE_SIZE);
             }
 
-            let last_mapped_page =
-                (program_header.virtual_address + program_header.size_in_file - 1) / PAGE_SIZE + 1;
-            let last_page_to_map = (program_header.virtual_address + program_header.size_in_memory
-                - 1) / PAGE_SIZE + 1;
-            let page_aligned_start_address = program_header.virtual_address / PAGE_SIZE * PAGE_SIZE;
+            if program_header.size_in_file < program_header.size_in_memory {
+                let file_end = virtual_address + program_header.size_in_file;
+                let memory_end = virtual_address + program_header.size_in_memory;
+                let next_page_address = (file_end - 1) / PAGE_SIZE * PAGE_SIZE + PAGE_SIZE;
+
+                // Zero the rest of the last page read from the file.
+                if file_end < min(next_page_address, memory_end) {
+                    address_space
+                        .zero_mapped_area(file_end, min(next_page_address, memory_end) - file_end);
+                }
 
-            for i in 0..last_page_to_map - last_mapped_page {
-                address_space.map_page(page_aligned_start_address + (i + 1) * PAGE_SIZE);
+                // The pages after it don't need to be written to.
+                if next_page_address < memory_end {
+                    address_space
+                        .map_anonymous_area(next_page_address, memory_end - next_page_address);
+                }
             }
+        }
 
-            if program_header.size_in_file < program_header.size_in_memory {
-                address_space.zero_mapped_area(
-                    program_header.virtual_address + program_header.size_in_file,
-                    program_header.size_in_memory - program_header.size_in_file,
-                );
+        Ok(load_base)
+    }
+
+    /// Finds the relocation table with addends listed in the dynamic segment.
+    fn relocation_table(&mut self) -> Result<Option<RelocationTable>, ElfError> {
+        let dynamic = match self.find_program_header(PT_DYNAMIC) {
+            Some(dynamic) => dynamic,
+            None => return Ok(None),
+        };
+
+        let mut table_address = None;
+        let mut table_size = 0;
+        let mut entry_size = size_of::<Relocation>();
+
+        let entry_num = dynamic.size_in_file / size_of::<DynamicEntry>();
+        for i in 0..entry_num {
+            let entry: DynamicEntry =
+                self.read_value((dynamic.offset + i * size_of::<DynamicEntry>()) as u64)?;
+
+            match entry.tag {
+                DT_NULL => break,
+                DT_RELA => table_address = Some(entry.value as VirtualAddress),
+                DT_RELASZ => table_size = entry.value as usize,
+                DT_RELAENT => entry_size = entry.value as usize,
+                _ => (),
             }
         }
+
+        let table_address = match table_address {
+            Some(table_address) => table_address,
+            None => return Ok(None),
+        };
+
+        if entry_size < size_of::<Relocation>() {
+            return Err(ElfError::InvalidFile);
+        }
+
+        let offset = self.file_offset_of(table_address, table_size)
+            .ok_or(ElfError::InvalidFile)?;
+
+        Ok(Some(RelocationTable {
+            offset,
+            count: table_size / entry_size,
+            entry_size,
+        }))
     }
 
-    Ok(create_process(address_space, file.header.program_entry))
+    /// Applies the relocations listed in the dynamic segment, if there is one.
+    ///
+    /// Only relative relocations are supported, which is enough for position
+    /// independent files that don't need any libraries.
+    fn relocate(
+        &mut self,
+        load_base: VirtualAddress,
+        address_space: &mut AddressSpace,
+    ) -> Result<(), ElfError> {
+        let table = match self.relocation_table()? {
+            Some(table) => table,
+            None => return Ok(()),
+        };
+
+        for i in 0..table.count {
+            let relocation: Relocation =
+                self.read_value(table.offset + (i * table.entry_size) as u64)?;
+
+            if let Some((address, value)) = apply_relocation(&relocation, load_base)? {
+                if !address_space.contains_range(address, size_of::<VirtualAddress>()) {
+                    return Err(ElfError::InvalidFile);
+                }
+
+                unsafe {
+                    address_space.write_val(value, address);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Creates a new process from the given file on the initramfs.
+pub fn process_from_initramfs_file(name: &str) -> Result<ProcessID, ElfError> {
+    ElfFile::from_initramfs(name).and_then(|file| process_from_elf_file(file))
+}
+
+/// Creates a new process from the given ELF file handle.
+///
+/// If the file requests a program interpreter, the interpreter is loaded as
+/// well and started instead of the file. It is responsible for relocating the
+/// file and gets the following arguments:
+///
+/// 1. The load base of the file.
+/// 2. The address of the dynamic section of the file or zero.
+/// 3. The entry address of the file.
+/// 4. The load base of the interpreter.
+/// 5. The base address of the area to load libraries into.
+fn process_from_elf_file(mut file: ElfFile) -> Result<ProcessID, ElfError> {
+    let mut address_space = AddressSpace::new();
+
+    let load_base = file.load(USER_PIE_AREA_BASE, &mut address_space)?;
+    let entry_address = file.entry_address(load_base)?;
+
+    let interpreter = match file.find_program_header(PT_INTERP) {
+        Some(interpreter) => interpreter,
+        None => {
+            file.relocate(load_base, &mut address_space)?;
+
+            return create_process(address_space, entry_address)
+                .ok_or(ElfError::OverlappingSegments);
+        }
+    };
+
+    let mut interpreter_file = ElfFile::from_initramfs(&file.interpreter_path(&interpreter)?)?;
+
+    // The interpreter is relocated here and loaded at a random address, so
+    // it has to be position independent and can't request an interpreter.
+    if interpreter_file.header.elf_type != ET_DYN
+        || interpreter_file.find_program_header(PT_INTERP).is_some()
+    {
+        return Err(ElfError::InvalidInterpreter);
+    }
+
+    let interpreter_base = interpreter_file.load(USER_INTERPRETER_AREA_BASE, &mut address_space)?;
+    interpreter_file.relocate(interpreter_base, &mut address_space)?;
+
+    let dynamic_address = match file.find_program_header(PT_DYNAMIC) {
+        Some(dynamic) => load_base + dynamic.virtual_address,
+        None => 0,
+    };
+    let library_area_base =
+        USER_LIBRARY_AREA_BASE + aslr::random_offset(USER_ASLR_RANGE, HUGE_PAGE_SIZE);
+
+    create_process_with_arguments(
+        address_space,
+        interpreter_file.entry_address(interpreter_base)?,
+        load_base as u64,
+        dynamic_address as u64,
+        entry_address as u64,
+        interpreter_base as u64,
+        library_area_base as u64,
+    ).ok_or(ElfError::OverlappingSegments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::Vec;
+    use boring_core::elf::{EI_CLASS, EI_DATA, ELFCLASS64, ELFDATA2LSB, ELF_MAGIC, EM_X86_64};
+    use file_handle::{FileError, SeekFrom};
+
+    /// A file held in memory.
+    struct MemoryFile {
+        /// The contents of the file.
+        data: Vec<u8>,
+        /// The current offset within the file.
+        position: u64,
+    }
+
+    impl FileHandle for MemoryFile {
+        fn seek(&mut self, position: SeekFrom) -> ::file_handle::Result<u64> {
+            let position = match position {
+                SeekFrom::Start(offset) => offset as i64,
+                SeekFrom::Current(offset) => self.position as i64 + offset,
+                SeekFrom::End(offset) => self.data.len() as i64 + offset,
+            };
+
+            if position < 0 {
+                Err(FileError::SeekBeforeStart)
+            } else if position as usize > self.data.len() {
+                Err(FileError::SeekPastEnd)
+            } else {
+                self.position = position as u64;
+                Ok(self.position)
+            }
+        }
+
+        fn read(&mut self, buffer: &mut [u8]) -> ::file_handle::Result<()> {
+            let start = self.position as usize;
+            if start + buffer.len() > self.data.len() {
+                return Err(FileError::SeekPastEnd);
+            }
+
+            buffer.copy_from_slice(&self.data[start..start + buffer.len()]);
+            Ok(())
+        }
+    }
+
+    /// Writes the bytes of the value at the given offset of the file.
+    fn write<T: Copy>(data: &mut Vec<u8>, offset: usize, value: T) {
+        let bytes =
+            unsafe { slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+
+        if data.len() < offset + bytes.len() {
+            data.resize(offset + bytes.len(), 0);
+        }
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Returns a program header with the given values.
+    fn program_header(
+        segment_type: u32,
+        offset: usize,
+        virtual_address: VirtualAddress,
+        size_in_file: usize,
+        align: usize,
+    ) -> ProgramHeader {
+        ProgramHeader {
+            segment_type,
+            flags: PF_R,
+            offset,
+            virtual_address,
+            physical_address: 0,
+            size_in_file,
+            size_in_memory: size_in_file,
+            align,
+        }
+    }
+
+    /// Builds a file with two loadable segments and the given dynamic entries.
+    ///
+    /// The first segment holds the headers and the dynamic segment at 0x800,
+    /// the second is linked at 0x3000 and holds a relocation table at 0x3010.
+    fn elf_file(elf_type: u16, dynamic: &[(i64, u64)]) -> ElfFile {
+        let dynamic_size = dynamic.len() * size_of::<DynamicEntry>();
+        let program_headers = [
+            program_header(PT_LOAD, 0, 0, 0x1000, HUGE_PAGE_SIZE),
+            program_header(PT_DYNAMIC, 0x800, 0x800, dynamic_size, 8),
+            program_header(PT_LOAD, 0x1000, 0x3000, 0x100, 0x1001),
+        ];
+
+        let mut data = Vec::new();
+        for (index, program_header) in program_headers.iter().enumerate() {
+            write(&mut data, 0x40 + index * size_of::<ProgramHeader>(), *program_header);
+        }
+        for (index, &(tag, value)) in dynamic.iter().enumerate() {
+            let offset = 0x800 + index * size_of::<DynamicEntry>();
+            write(&mut data, offset, DynamicEntry { tag, value });
+        }
+        write(&mut data, 0x1010, Relocation {
+            offset: 0x20,
+            info: R_X86_64_RELATIVE as u64,
+            addend: 0x1234,
+        });
+        write(&mut data, 0x1028, Relocation {
+            offset: 0,
+            info: R_X86_64_NONE as u64,
+            addend: 0,
+        });
+        data.resize(0x1100, 0);
+
+        let mut identification = [0; 16];
+        identification[..4].copy_from_slice(&ELF_MAGIC);
+        identification[EI_CLASS] = ELFCLASS64;
+        identification[EI_DATA] = ELFDATA2LSB;
+        identification[EI_VERSION] = EV_CURRENT;
+
+        let header = Header {
+            identification,
+            elf_type,
+            instruction_set: EM_X86_64,
+            version: 1,
+            program_entry: 0x10,
+            program_header_offset: 0x40,
+            section_header_offset: 0,
+            flags: 0,
+            header_size: size_of::<Header>() as u16,
+            program_header_entry_size: size_of::<ProgramHeader>() as u16,
+            program_header_entry_num: program_headers.len() as u16,
+            section_header_entry_size: 0,
+            section_header_entry_num: 0,
+            name_string_table_index: 0,
+        };
+
+        ElfFile {
+            file_handle: Box::new(MemoryFile { data, position: 0 }),
+            header,
+        }
+    }
+
+    /// The dynamic entries describing the relocation table.
+    const DYNAMIC: [(i64, u64); 4] = [
+        (DT_RELA, 0x3010),
+        (DT_RELASZ, 48),
+        (DT_RELAENT, 24),
+        (DT_NULL, 0),
+    ];
+
+    /// Tests that addresses are translated to offsets of the loadable
+    /// segments that back them.
+    #[test]
+    fn test_file_offset_of() {
+        let mut file = elf_file(ET_DYN, &DYNAMIC);
+
+        assert_eq!(file.file_offset_of(0x10, 8), Some(0x10));
+        assert_eq!(file.file_offset_of(0x3010, 48), Some(0x1010));
+        assert_eq!(file.file_offset_of(0x30f8, 16), None);
+        assert_eq!(file.file_offset_of(0x2000, 8), None);
+    }
+
+    /// Tests that the relocation table is found through the dynamic segment.
+    #[test]
+    fn test_relocation_table() {
+        let mut file = elf_file(ET_DYN, &DYNAMIC);
+        assert_eq!(
+            file.relocation_table().unwrap(),
+            Some(RelocationTable {
+                offset: 0x1010,
+                count: 2,
+                entry_size: 24,
+            })
+        );
+
+        let relocation: Relocation = file.read_value(0x1010).unwrap();
+        assert_eq!(relocation.addend, 0x1234);
+
+        // Entries after the end of the dynamic section are ignored.
+        let mut file = elf_file(ET_DYN, &[(DT_NULL, 0), (DT_RELA, 0x3010)]);
+        assert_eq!(file.relocation_table().unwrap(), None);
+
+        let mut file = elf_file(ET_DYN, &[(DT_RELA, 0x3010), (DT_RELAENT, 8)]);
+        assert!(file.relocation_table().is_err());
+
+        // The table has to be backed by the file.
+        let mut file = elf_file(ET_DYN, &[(DT_RELA, 0x3010), (DT_RELASZ, 0x1000)]);
+        assert!(file.relocation_table().is_err());
+    }
+
+    /// Tests the values written for relocations.
+    #[test]
+    fn test_apply_relocation() {
+        let base = 0x5555_0000_0000;
+
+        let relative = Relocation {
+            offset: 0x20,
+            info: R_X86_64_RELATIVE as u64,
+            addend: 0x1234,
+        };
+        assert_eq!(apply_relocation(&relative, base).unwrap(), Some((base + 0x20, base + 0x1234)));
+
+        let none = Relocation {
+            offset: 0x20,
+            info: R_X86_64_NONE as u64,
+            addend: 0,
+        };
+        assert_eq!(apply_relocation(&none, base).unwrap(), None);
+
+        let symbol = Relocation {
+            offset: 0x20,
+            info: 1 << 32 | 1,
+            addend: 0,
+        };
+        assert!(apply_relocation(&symbol, base).is_err());
+
+        let overflow = Relocation {
+            offset: usize::max_value(),
+            info: R_X86_64_RELATIVE as u64,
+            addend: 0,
+        };
+        assert!(apply_relocation(&overflow, base).is_err());
+    }
+
+    /// Tests the load base alignment and that other files aren't moved.
+    #[test]
+    fn test_load_base() {
+        let mut file = elf_file(ET_DYN, &DYNAMIC);
+        assert_eq!(file.load_align(), HUGE_PAGE_SIZE);
+
+        let mut file = elf_file(ET_EXEC, &DYNAMIC);
+        assert_eq!(file.choose_load_base(USER_PIE_AREA_BASE), 0);
+        assert_eq!(file.entry_address(0x1000).unwrap(), 0x1010);
+    }
 }
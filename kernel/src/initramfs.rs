@@ -5,7 +5,7 @@ use arch::memory::{get_initramfs_length, get_initramfs_start};
 use core::{ptr, slice, str};
 use core::mem::size_of;
 use file_handle::{FileError, FileHandle, Result, SeekFrom};
-use memory::VirtualAddress;
+use memory::{PhysicalAddress, VirtualAddress};
 
 /// The magic number that identifies a BoringOS initramfs.
 const MAGIC: [u8; 8] = [
@@ -245,3 +245,22 @@ pub fn open(name: &str) -> Result<Box<FileHandle>> {
 
     Err(FileError::FileNotFound)
 }
+
+/// Returns the physical address and the length of the file with the given name.
+pub fn get_physical_area(name: &str) -> Result<(PhysicalAddress, usize)> {
+    for file in get_file_iterator()? {
+        if file.name == name {
+            let physical_start =
+                ::boot::get_initramfs_start() + (file.start - get_initramfs_start());
+
+            return Ok((physical_start, file.length));
+        }
+    }
+
+    Err(FileError::FileNotFound)
+}
+
+/// Returns the physical address right after the end of the initramfs.
+pub fn get_physical_end() -> PhysicalAddress {
+    ::boot::get_initramfs_start() + get_initramfs_length()
+}
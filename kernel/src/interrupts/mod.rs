@@ -7,36 +7,50 @@
 use arch::schedule;
 use memory::VirtualAddress;
 use multitasking::CURRENT_THREAD;
-use multitasking::scheduler::{READY_LIST, SLEEPING_LIST};
+use multitasking::scheduler::{make_ready, SLEEPING_LIST};
 use sync::time::Timestamp;
 use x86_64::structures::idt::PageFaultErrorCode;
 
 /// The timer interrupt handler for the system.
 pub fn timer_interrupt() {
-    {
-        let mut sleeping_list = SLEEPING_LIST.lock();
-        loop {
-            if sleeping_list.peek().is_some() {
-                if sleeping_list.peek().unwrap().get_sleep_time() <= Timestamp::get_current() {
-                    READY_LIST.lock().push(sleeping_list.pop().unwrap().0);
-                } else {
-                    break;
-                }
+    // The list isn't locked while a thread is made ready, because that
+    // reprograms the timer.
+    loop {
+        let thread = {
+            let mut sleeping_list = SLEEPING_LIST.lock();
+            let has_expired = sleeping_list
+                .peek()
+                .map_or(false, |thread| thread.get_sleep_time() <= Timestamp::get_current());
+
+            if has_expired {
+                sleeping_list.pop()
             } else {
-                break;
+                None
             }
+        };
+
+        match thread {
+            Some(thread) => make_ready(thread.0),
+            None => break,
         }
     }
 
+    ::sync::timer::run_expired_timers();
+    ::sync::timer::reprogram();
+
     schedule();
 }
 
 /// The page fault handler.
+///
+/// `stack_frame` is the frame the fault arrived with.
 pub fn page_fault_handler(
     address: VirtualAddress,
-    stack_frame: &mut ::x86_64::structures::idt::ExceptionStackFrame,
+    stack_frame: &::x86_64::structures::idt::ExceptionStackFrame,
     error_code: PageFaultErrorCode,
 ) {
+    handle_stack_overflow(address);
+
     unsafe { ::sync::disable_preemption() };
     let current_thread = CURRENT_THREAD.lock();
 
@@ -55,3 +69,67 @@ pub fn page_fault_handler(
     debugln!("Page flags: {:?}", ::memory::get_page_flags(address));
     loop {}
 }
+
+/// Handles a fault at the address, if it means that the current thread
+/// overflowed one of its stacks.
+///
+/// A thread that overflowed its user stack is killed. An overflow of a kernel
+/// stack may have left the kernel in an inconsistent state, so it is fatal.
+fn handle_stack_overflow(address: VirtualAddress) {
+    let killed = {
+        let mut current_thread = CURRENT_THREAD.lock();
+
+        if current_thread.kernel_stack.is_overflow_address(address) {
+            panic_debugln!(
+                "Kernel stack overflow in thread {} of process {}.",
+                current_thread.id,
+                current_thread.pid
+            );
+            loop {}
+        }
+
+        if current_thread.user_stack.is_overflow_address(address) {
+            debugln!(
+                "Stack overflow in thread {} of process {}.",
+                current_thread.id,
+                current_thread.pid
+            );
+            current_thread.kill();
+            true
+        } else {
+            false
+        }
+    };
+
+    if killed {
+        schedule();
+
+        // The scheduler never returns to a dead thread.
+        unsafe {
+            ::sync::enable_preemption();
+        }
+        loop {
+            unsafe {
+                ::sync::cpu_halt();
+            }
+        }
+    }
+}
+
+/// Reports an overflow of the kernel stack of the current thread, if it
+/// caused the double fault.
+///
+/// An overflowing kernel stack can't take the frame of the page fault, which
+/// turns it into a double fault. The current thread isn't locked, because it
+/// may have overflowed while it was locked.
+pub fn report_double_fault_cause(address: VirtualAddress) {
+    let current_thread = unsafe { CURRENT_THREAD.without_locking() };
+
+    if current_thread.kernel_stack.is_overflow_address(address) {
+        panic_debugln!(
+            "Kernel stack overflow in thread {} of process {}.",
+            current_thread.id,
+            current_thread.pid
+        );
+    }
+}
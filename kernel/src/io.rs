@@ -9,7 +9,9 @@ pub fn init() {
     unsafe {
         serial::init();
     }
-    if cfg!(target_arch = "x86_64") {
+    // The framebuffer console is used instead of the VGA text buffer in
+    // graphics modes.
+    if !::drivers::console::select() && cfg!(target_arch = "x86_64") {
         ::arch::vga_buffer::init();
     }
 }
@@ -44,6 +44,7 @@ extern crate x86_64;
 mod macros;
 #[macro_use]
 mod io;
+mod acpi;
 mod arch;
 mod boot;
 mod cpuio;
@@ -91,7 +92,11 @@ pub extern "C" fn main(magic_number: u32, information_structure_address: usize)
         boot::get_bootloader_name()
     );
     memory::init();
+    memory::aslr::init();
+    acpi::init();
     arch::init();
+    drivers::init();
+    sync::timer::init();
 
     let extended_info = raw_cpuid::CpuId::new().get_extended_function_info();
     let unwrapped_info = extended_info.unwrap();
@@ -103,6 +108,12 @@ pub extern "C" fn main(magic_number: u32, information_structure_address: usize)
         "The available amount of memory is {}MiB.",
         arch::memory::get_free_memory_size() / 1024 / 1024
     );
+    let heap_statistics = memory::allocator::get_statistics();
+    debugln!(
+        "The kernel heap holds {} allocations in {}KiB.",
+        heap_statistics.allocation_count,
+        (heap_statistics.slab_pages + heap_statistics.span_pages) * arch::memory::PAGE_SIZE / 1024
+    );
 
     // video::voxelspace::test();
 
@@ -6,7 +6,7 @@ use alloc::boxed::Box;
 use arch::memory::{idle_address_space_manager, new_address_space_manager};
 use core::mem::size_of_val;
 use core::slice;
-use memory::{is_userspace_address, PAGE_SIZE};
+use memory::{is_userspace_address, HUGE_PAGE_SIZE, PAGE_SIZE};
 
 /// Represents an address space
 pub struct AddressSpace {
@@ -21,6 +21,8 @@ impl Drop for AddressSpace {
         for segment in &mut self.segments {
             segment.unmap(&mut self.manager);
         }
+
+        self.manager.flush_unmapped();
     }
 }
 
@@ -143,12 +145,101 @@ impl AddressSpace {
         }
     }
 
+    /// Maps zeroed pages for the given area, which must lie within a segment.
+    ///
+    /// The parts of the area that are aligned to huge pages are mapped with
+    /// huge pages, as long as there is enough contiguous physical memory. The
+    /// last page is mapped completely, even if the area ends before it.
+    pub fn map_anonymous_area(&mut self, start: VirtualAddress, length: usize) {
+        assert_eq!(start % PAGE_SIZE, 0);
+
+        let segment_flags = { self.get_segment(start, length).map(|segment| segment.flags) };
+
+        if let Some(segment_flags) = segment_flags {
+            let end = start + length;
+            let mut address = start;
+
+            while address < end {
+                if address % HUGE_PAGE_SIZE == 0 && end - address >= HUGE_PAGE_SIZE
+                    && self.manager.map_huge_page(address, segment_flags)
+                {
+                    address += HUGE_PAGE_SIZE;
+                } else {
+                    self.manager.zero(address, PAGE_SIZE, segment_flags);
+                    address += PAGE_SIZE;
+                }
+            }
+        } else {
+            self.handle_out_of_segment(start, length);
+        }
+    }
+
+    /// Maps the physical memory area starting at `physical_start` at `start`.
+    ///
+    /// Huge pages are used where both addresses are aligned to them. The
+    /// frames are not freed when the area is unmapped. Returns false if the
+    /// area overlaps another segment.
+    pub fn map_physical_area(
+        &mut self,
+        start: VirtualAddress,
+        physical_start: PhysicalAddress,
+        length: usize,
+        flags: PageFlags,
+    ) -> bool {
+        assert_eq!(start % PAGE_SIZE, 0);
+        assert_eq!(physical_start % PAGE_SIZE, 0);
+
+        let segment = Segment::new(start, length, flags, SegmentType::PhysicalMemory);
+        if !self.add_segment(segment) {
+            return false;
+        }
+
+        let end = start + length;
+        let mut address = start;
+
+        while address < end {
+            let physical_address = physical_start + (address - start);
+
+            if address % HUGE_PAGE_SIZE == 0 && physical_address % HUGE_PAGE_SIZE == 0
+                && end - address >= HUGE_PAGE_SIZE
+            {
+                self.manager
+                    .map_huge_page_at(address, physical_address, flags);
+                address += HUGE_PAGE_SIZE;
+            } else {
+                self.manager.map_page_at(address, physical_address, flags);
+                address += PAGE_SIZE;
+            }
+        }
+
+        true
+    }
+
+    /// Unmaps the segment starting at the given address and removes it.
+    ///
+    /// Returns false if no segment starts there.
+    ///
+    /// # Safety
+    /// - Nothing should reference the unmapped pages.
+    pub unsafe fn remove_segment(&mut self, start: VirtualAddress) -> bool {
+        match self.segments.iter().position(|segment| segment.start == start) {
+            Some(index) => {
+                let segment = self.segments.remove(index);
+                segment.unmap(&mut self.manager);
+                self.manager.flush_unmapped();
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Unmaps the given page in the address space.
     ///
     /// # Safety
     /// - Nothing should reference the unmapped pages.
     pub unsafe fn unmap_page(&mut self, start_address: VirtualAddress) {
         self.manager.unmap_page(start_address);
+        self.manager.flush_unmapped();
     }
 }
 
@@ -159,6 +250,9 @@ pub enum SegmentType {
     FromFile,
     /// The content of the segment is only in memory.
     MemoryOnly,
+    /// The segment maps physical memory that isn't managed by the frame
+    /// allocator, such as device memory.
+    PhysicalMemory,
 }
 
 /// Represents a segment of memory in the address space.
@@ -196,6 +290,11 @@ impl Segment {
         self.start
     }
 
+    /// Returns the flags the segment is mapped with.
+    pub fn flags(&self) -> PageFlags {
+        self.flags
+    }
+
     /// Checks if the address is contained within the segment.
     fn contains(&self, address: VirtualAddress) -> bool {
         self.start <= address && address < self.end()
@@ -214,17 +313,45 @@ impl Segment {
     /// Unmaps this segment.
     fn unmap(&self, manager: &mut Box<AddressSpaceManager>) {
         let pages_in_segment = (self.length - 1) / PAGE_SIZE + 1;
-        for page_num in 0..pages_in_segment {
+        let mut page_num = 0;
+
+        while page_num < pages_in_segment {
+            if self.unmap_huge_page(manager, self.start + page_num * PAGE_SIZE) {
+                page_num += HUGE_PAGE_SIZE / PAGE_SIZE;
+                continue;
+            }
+
             unsafe {
                 match self.segment_type {
                     SegmentType::FromFile => manager.unmap_page(self.start + page_num * PAGE_SIZE),
                     SegmentType::MemoryOnly => {
                         manager.unmap_page_unchecked(self.start + page_num * PAGE_SIZE)
                     }
+                    SegmentType::PhysicalMemory => {
+                        manager.unmap_page_without_deallocating(self.start + page_num * PAGE_SIZE)
+                    }
                 }
             }
+
+            page_num += 1;
         }
     }
+
+    /// Unmaps the huge page at the address, if one within the segment is
+    /// mapped there.
+    fn unmap_huge_page(
+        &self,
+        manager: &mut Box<AddressSpaceManager>,
+        address: VirtualAddress,
+    ) -> bool {
+        let deallocate = match self.segment_type {
+            SegmentType::PhysicalMemory => false,
+            _ => true,
+        };
+
+        address % HUGE_PAGE_SIZE == 0 && address + HUGE_PAGE_SIZE <= self.end()
+            && unsafe { manager.unmap_huge_page(address, deallocate) }
+    }
 }
 
 /// This trait should be implemented by any architecture specific address space
@@ -243,18 +370,61 @@ pub trait AddressSpaceManager: Send {
     /// Maps the given page in the managed address space.
     fn map_page(&mut self, page_address: VirtualAddress, flags: PageFlags);
 
+    /// Maps the given page to the given frame in the managed address space.
+    fn map_page_at(
+        &mut self,
+        page_address: VirtualAddress,
+        frame_address: PhysicalAddress,
+        flags: PageFlags,
+    );
+
+    /// Maps the given huge page to zeroed memory in the managed address space.
+    ///
+    /// Returns false if there isn't enough contiguous physical memory.
+    fn map_huge_page(&mut self, page_address: VirtualAddress, flags: PageFlags) -> bool;
+
+    /// Maps the given huge page to the frames starting at the given frame in
+    /// the managed address space.
+    fn map_huge_page_at(
+        &mut self,
+        page_address: VirtualAddress,
+        frame_address: PhysicalAddress,
+        flags: PageFlags,
+    );
+
+    /// Unmaps the huge page at the given address in the managed address space.
+    ///
+    /// The frames are only freed if `deallocate` is set. Returns false if no
+    /// huge page is mapped there.
+    ///
+    /// # Safety
+    /// - Nothing should reference the unmapped pages.
+    unsafe fn unmap_huge_page(&mut self, page_address: VirtualAddress, deallocate: bool) -> bool;
+
     /// Unmaps the given page in the managed address space.
     ///
     /// # Safety
     /// - Nothing should reference the unmapped pages.
     unsafe fn unmap_page(&mut self, start_address: VirtualAddress);
 
+    /// Unmaps the given page in the managed address space without
+    /// deallocating the frame it points to.
+    ///
+    /// # Safety
+    /// - Nothing should reference the unmapped pages.
+    unsafe fn unmap_page_without_deallocating(&mut self, start_address: VirtualAddress);
+
     /// Unmaps the given page in the managed address space not checking if it was mapped.
     ///
     /// # Safety
     /// - Nothing should reference the unmapped pages.
     unsafe fn unmap_page_unchecked(&mut self, start_address: VirtualAddress);
 
+    /// Invalidates the pages unmapped so far on all CPUs and frees their frames.
+    ///
+    /// Unmapped pages may stay accessible until this is called.
+    fn flush_unmapped(&mut self);
+
     /// Zeroes the given area in the managed address space.
     fn zero(&mut self, start: VirtualAddress, length: usize, flags: PageFlags) {
         let zero: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
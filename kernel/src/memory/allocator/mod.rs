@@ -1,32 +1,91 @@
 //! Provides the heap allocator for the kernel.
+//!
+//! Small allocations are served from slabs of equally sized objects, which
+//! each CPU caches in magazines. Larger allocations get spans of whole pages.
 
-mod linked_list_allocator;
+mod slab;
+mod span;
 
-use self::linked_list_allocator::LinkedListAllocator;
 use alloc::allocator::{Alloc, AllocErr, Layout};
 use arch::memory::{HEAP_MAX_SIZE, HEAP_START};
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use memory::VirtualAddress;
-use sync::PreemptableMutex;
+
+/// The end of the part of the heap that holds the slabs.
+///
+/// The rest of the heap holds the spans of large allocations.
+const SLAB_AREA_END: VirtualAddress = HEAP_START + HEAP_MAX_SIZE / 2;
+
+/// The number of bytes that are currently allocated.
+static ALLOCATED_BYTES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The number of allocations that weren't freed yet.
+static ALLOCATION_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The number of allocations that failed.
+static FAILED_ALLOCATIONS: AtomicUsize = ATOMIC_USIZE_INIT;
 
 pub struct Allocator;
 
 unsafe impl<'a> Alloc for &'a Allocator {
-    // TODO: Read more on this trait and possibly make it more efficient.
     unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
-        Ok(ALLOCATOR
-            .lock()
-            .allocate_first_fit(layout.size(), layout.align()))
+        let ptr = match slab::get_size_class(layout.size(), layout.align()) {
+            Some(class_index) => slab::allocate(class_index),
+            None => span::allocate(layout.size(), layout.align()),
+        };
+
+        match ptr {
+            Some(ptr) => {
+                ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+                ALLOCATION_COUNT.fetch_add(1, Ordering::Relaxed);
+                Ok(ptr)
+            }
+            None => {
+                FAILED_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+                Err(AllocErr::Exhausted { request: layout })
+            }
+        }
     }
 
     unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
-        ALLOCATOR.lock().free(ptr, layout.size(), layout.align());
+        match slab::get_size_class(layout.size(), layout.align()) {
+            Some(class_index) => slab::free(class_index, ptr),
+            None => span::free(ptr, layout.size()),
+        }
+
+        ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        ALLOCATION_COUNT.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn oom(&mut self, err: AllocErr) -> ! {
+        panic!("The kernel heap is out of memory: {:?}", err);
     }
 }
 
-lazy_static! {
-    /// The kernel heap allocator.
-    static ref ALLOCATOR: PreemptableMutex<LinkedListAllocator> =
-        PreemptableMutex::new(LinkedListAllocator::new(HEAP_START, HEAP_START + HEAP_MAX_SIZE));
+/// Statistics about the kernel heap.
+#[derive(Debug, Clone, Copy)]
+pub struct Statistics {
+    /// The number of bytes that are currently allocated.
+    pub allocated_bytes: usize,
+    /// The number of allocations that weren't freed yet.
+    pub allocation_count: usize,
+    /// The number of allocations that failed.
+    pub failed_allocations: usize,
+    /// The number of pages mapped for slabs.
+    pub slab_pages: usize,
+    /// The number of pages mapped for large allocations.
+    pub span_pages: usize,
+}
+
+/// Returns the current statistics of the kernel heap.
+pub fn get_statistics() -> Statistics {
+    Statistics {
+        allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        allocation_count: ALLOCATION_COUNT.load(Ordering::Relaxed),
+        failed_allocations: FAILED_ALLOCATIONS.load(Ordering::Relaxed),
+        slab_pages: slab::get_mapped_page_count(),
+        span_pages: span::get_mapped_page_count(),
+    }
 }
 
 /// Aligns the given address to the given alignment.
@@ -0,0 +1,401 @@
+//! Provides slabs of equally sized objects for small allocations.
+//!
+//! Every CPU keeps a magazine of free objects per size class, so most
+//! allocations and frees don't touch the shared slabs. Only when a magazine
+//! runs empty or full, half a magazine is exchanged with the slabs.
+
+use super::SLAB_AREA_END;
+use super::span::{map_pages, unmap_pages};
+use arch::get_cpu_id;
+use arch::memory::{HEAP_START, PAGE_SIZE};
+use core::mem::size_of;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use memory::VirtualAddress;
+use sync::PreemptableMutex;
+
+/// The number of size classes.
+const SIZE_CLASS_COUNT: usize = 8;
+
+/// The object sizes of the size classes.
+const SIZE_CLASSES: [usize; SIZE_CLASS_COUNT] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// The size of a slab.
+///
+/// Slabs are aligned to their size, so the slab of an object can be found
+/// from its address.
+const SLAB_SIZE: usize = 4 * PAGE_SIZE;
+
+/// The number of pages in a slab.
+const SLAB_PAGE_COUNT: usize = SLAB_SIZE / PAGE_SIZE;
+
+/// The number of empty slabs that stay mapped for reuse.
+const MAX_CACHED_SLABS: usize = 8;
+
+/// The number of objects a magazine can hold.
+const MAGAZINE_SIZE: usize = 32;
+
+/// The number of CPUs that have magazines.
+///
+/// Other CPUs use the slabs directly.
+const MAX_CPUS: usize = 16;
+
+/// A size class without any slabs.
+const EMPTY_SIZE_CLASS: PreemptableMutex<SizeClass> = PreemptableMutex::new(SizeClass {
+    partial_slabs: ptr::null_mut(),
+});
+
+/// The magazines of a CPU that doesn't hold any objects yet.
+const EMPTY_MAGAZINES: PreemptableMutex<[Magazine; SIZE_CLASS_COUNT]> =
+    PreemptableMutex::new([Magazine {
+        objects: [0; MAGAZINE_SIZE],
+        count: 0,
+    }; SIZE_CLASS_COUNT]);
+
+/// The slabs of all size classes.
+static SIZE_CLASS_SLABS: [PreemptableMutex<SizeClass>; SIZE_CLASS_COUNT] = [
+    EMPTY_SIZE_CLASS, EMPTY_SIZE_CLASS, EMPTY_SIZE_CLASS, EMPTY_SIZE_CLASS, EMPTY_SIZE_CLASS,
+    EMPTY_SIZE_CLASS, EMPTY_SIZE_CLASS, EMPTY_SIZE_CLASS,
+];
+
+/// The magazines of all CPUs.
+static MAGAZINES: [PreemptableMutex<[Magazine; SIZE_CLASS_COUNT]>; MAX_CPUS] = [
+    EMPTY_MAGAZINES, EMPTY_MAGAZINES, EMPTY_MAGAZINES, EMPTY_MAGAZINES, EMPTY_MAGAZINES,
+    EMPTY_MAGAZINES, EMPTY_MAGAZINES, EMPTY_MAGAZINES, EMPTY_MAGAZINES, EMPTY_MAGAZINES,
+    EMPTY_MAGAZINES, EMPTY_MAGAZINES, EMPTY_MAGAZINES, EMPTY_MAGAZINES, EMPTY_MAGAZINES,
+    EMPTY_MAGAZINES,
+];
+
+/// The slabs that don't belong to a size class.
+static SLAB_POOL: PreemptableMutex<SlabPool> = PreemptableMutex::new(SlabPool {
+    cached_slabs: ptr::null_mut(),
+    cached_slab_count: 0,
+    released_slabs: ptr::null_mut(),
+    end_address: HEAP_START,
+});
+
+/// The number of pages mapped for slabs.
+static MAPPED_PAGES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The header at the start of every slab.
+struct Slab {
+    /// The previous slab in the list of partial slabs.
+    previous: *mut Slab,
+    /// The next slab in the list of partial slabs or the slab pool.
+    next: *mut Slab,
+    /// The first free object of the slab.
+    free_objects: *mut FreeObject,
+    /// The number of free objects in the slab.
+    free_count: usize,
+}
+
+/// A free object in a slab.
+struct FreeObject {
+    /// The next free object in the same slab.
+    next: *mut FreeObject,
+}
+
+/// Caches free objects of a size class for one CPU.
+#[derive(Clone, Copy)]
+struct Magazine {
+    /// The addresses of the free objects.
+    objects: [VirtualAddress; MAGAZINE_SIZE],
+    /// The number of free objects.
+    count: usize,
+}
+
+/// The slabs of a size class.
+struct SizeClass {
+    /// The slabs that have free objects.
+    partial_slabs: *mut Slab,
+}
+
+// The size classes are locked, so this is okay.
+unsafe impl Send for SizeClass {}
+
+/// Manages the slabs that aren't used by a size class.
+struct SlabPool {
+    /// Empty slabs that are still mapped.
+    cached_slabs: *mut Slab,
+    /// The number of cached slabs.
+    cached_slab_count: usize,
+    /// Slabs of which only the first page is still mapped.
+    released_slabs: *mut Slab,
+    /// The start of the part of the slab area that was never used.
+    end_address: VirtualAddress,
+}
+
+// The slab pool is locked, so this is okay.
+unsafe impl Send for SlabPool {}
+
+impl SizeClass {
+    /// Takes a free object from the slabs of the size class.
+    ///
+    /// Returns `None` if no new slab could be mapped.
+    unsafe fn allocate_object(&mut self, class_index: usize) -> Option<VirtualAddress> {
+        if self.partial_slabs.is_null() {
+            let slab = SLAB_POOL.lock().get_slab()?;
+            initialize_slab(slab, class_index);
+            self.push(slab);
+        }
+
+        let slab = &mut *self.partial_slabs;
+        let object = slab.free_objects;
+        slab.free_objects = (*object).next;
+        slab.free_count -= 1;
+
+        if slab.free_count == 0 {
+            self.remove(slab);
+        }
+
+        Some(object as VirtualAddress)
+    }
+
+    /// Returns the object to its slab.
+    ///
+    /// Slabs that become empty are returned to the slab pool.
+    unsafe fn free_object(&mut self, class_index: usize, object: VirtualAddress) {
+        let slab = &mut *((object & !(SLAB_SIZE - 1)) as *mut Slab);
+        let was_full = slab.free_count == 0;
+
+        let object = object as *mut FreeObject;
+        (*object).next = slab.free_objects;
+        slab.free_objects = object;
+        slab.free_count += 1;
+
+        if slab.free_count == get_object_count(class_index) {
+            if !was_full {
+                self.remove(slab);
+            }
+            SLAB_POOL.lock().return_slab(slab);
+        } else if was_full {
+            self.push(slab);
+        }
+    }
+
+    /// Adds the slab to the partial slabs.
+    unsafe fn push(&mut self, slab: *mut Slab) {
+        (*slab).previous = ptr::null_mut();
+        (*slab).next = self.partial_slabs;
+
+        if !self.partial_slabs.is_null() {
+            (*self.partial_slabs).previous = slab;
+        }
+        self.partial_slabs = slab;
+    }
+
+    /// Removes the slab from the partial slabs.
+    unsafe fn remove(&mut self, slab: *mut Slab) {
+        let previous = (*slab).previous;
+        let next = (*slab).next;
+
+        if previous.is_null() {
+            self.partial_slabs = next;
+        } else {
+            (*previous).next = next;
+        }
+
+        if !next.is_null() {
+            (*next).previous = previous;
+        }
+    }
+}
+
+impl SlabPool {
+    /// Returns an unused, fully mapped slab.
+    ///
+    /// Returns `None` if the slab couldn't be mapped.
+    unsafe fn get_slab(&mut self) -> Option<*mut Slab> {
+        if !self.cached_slabs.is_null() {
+            let slab = self.cached_slabs;
+            self.cached_slabs = (*slab).next;
+            self.cached_slab_count -= 1;
+
+            return Some(slab);
+        }
+
+        if !self.released_slabs.is_null() {
+            let slab = self.released_slabs;
+
+            if !map_pages(slab as VirtualAddress + PAGE_SIZE, SLAB_PAGE_COUNT - 1) {
+                return None;
+            }
+            MAPPED_PAGES.fetch_add(SLAB_PAGE_COUNT - 1, Ordering::Relaxed);
+
+            self.released_slabs = (*slab).next;
+            return Some(slab);
+        }
+
+        if self.end_address == SLAB_AREA_END || !map_pages(self.end_address, SLAB_PAGE_COUNT) {
+            return None;
+        }
+        MAPPED_PAGES.fetch_add(SLAB_PAGE_COUNT, Ordering::Relaxed);
+
+        let slab = self.end_address as *mut Slab;
+        self.end_address += SLAB_SIZE;
+
+        Some(slab)
+    }
+
+    /// Takes back a slab that is no longer used by a size class.
+    ///
+    /// Only a few empty slabs are kept mapped, the others only keep the page
+    /// with their header.
+    unsafe fn return_slab(&mut self, slab: *mut Slab) {
+        if self.cached_slab_count < MAX_CACHED_SLABS {
+            (*slab).next = self.cached_slabs;
+            self.cached_slabs = slab;
+            self.cached_slab_count += 1;
+        } else {
+            unmap_pages(slab as VirtualAddress + PAGE_SIZE, SLAB_PAGE_COUNT - 1);
+            MAPPED_PAGES.fetch_sub(SLAB_PAGE_COUNT - 1, Ordering::Relaxed);
+
+            (*slab).next = self.released_slabs;
+            self.released_slabs = slab;
+        }
+    }
+}
+
+/// Returns the index of the size class for the given size and alignment.
+///
+/// Returns `None` if the allocation is too large for the slabs.
+pub fn get_size_class(size: usize, alignment: usize) -> Option<usize> {
+    // The objects are aligned to their size.
+    let size = if size > alignment { size } else { alignment };
+
+    SIZE_CLASSES
+        .iter()
+        .position(|&object_size| size <= object_size)
+}
+
+/// Returns the offset of the first object within a slab of the size class.
+fn get_first_object_offset(class_index: usize) -> usize {
+    let object_size = SIZE_CLASSES[class_index];
+
+    (size_of::<Slab>() - 1) / object_size * object_size + object_size
+}
+
+/// Returns the number of objects in a slab of the size class.
+fn get_object_count(class_index: usize) -> usize {
+    (SLAB_SIZE - get_first_object_offset(class_index)) / SIZE_CLASSES[class_index]
+}
+
+/// Initializes the header and free objects of a slab for the size class.
+unsafe fn initialize_slab(slab: *mut Slab, class_index: usize) {
+    let object_size = SIZE_CLASSES[class_index];
+    let first_object = slab as VirtualAddress + get_first_object_offset(class_index);
+    let object_count = get_object_count(class_index);
+
+    for i in 0..object_count {
+        let object = (first_object + i * object_size) as *mut FreeObject;
+        (*object).next = if i + 1 < object_count {
+            (first_object + (i + 1) * object_size) as *mut FreeObject
+        } else {
+            ptr::null_mut()
+        };
+    }
+
+    (*slab).free_objects = first_object as *mut FreeObject;
+    (*slab).free_count = object_count;
+}
+
+/// Allocates an object of the given size class.
+pub fn allocate(class_index: usize) -> Option<*mut u8> {
+    // If the thread moves to another CPU after this, the magazine of the
+    // previous CPU is used, which is just slower.
+    let cpu_id = get_cpu_id();
+
+    if cpu_id >= MAX_CPUS {
+        let object = unsafe { SIZE_CLASS_SLABS[class_index].lock().allocate_object(class_index) };
+        return object.map(|object| object as *mut u8);
+    }
+
+    let mut magazines = MAGAZINES[cpu_id].lock();
+    let magazine = &mut magazines[class_index];
+
+    if magazine.count == 0 {
+        let mut size_class = SIZE_CLASS_SLABS[class_index].lock();
+
+        while magazine.count < MAGAZINE_SIZE / 2 {
+            match unsafe { size_class.allocate_object(class_index) } {
+                Some(object) => {
+                    magazine.objects[magazine.count] = object;
+                    magazine.count += 1;
+                }
+                None => break,
+            }
+        }
+
+        if magazine.count == 0 {
+            return None;
+        }
+    }
+
+    magazine.count -= 1;
+    Some(magazine.objects[magazine.count] as *mut u8)
+}
+
+/// Frees an object of the given size class.
+///
+/// # Safety
+/// - The object must have been allocated from the size class.
+/// - The object must not be used anymore.
+pub unsafe fn free(class_index: usize, ptr: *mut u8) {
+    let cpu_id = get_cpu_id();
+
+    if cpu_id >= MAX_CPUS {
+        SIZE_CLASS_SLABS[class_index]
+            .lock()
+            .free_object(class_index, ptr as VirtualAddress);
+        return;
+    }
+
+    let mut magazines = MAGAZINES[cpu_id].lock();
+    let magazine = &mut magazines[class_index];
+
+    if magazine.count == MAGAZINE_SIZE {
+        let mut size_class = SIZE_CLASS_SLABS[class_index].lock();
+
+        while magazine.count > MAGAZINE_SIZE / 2 {
+            magazine.count -= 1;
+            size_class.free_object(class_index, magazine.objects[magazine.count]);
+        }
+    }
+
+    magazine.objects[magazine.count] = ptr as VirtualAddress;
+    magazine.count += 1;
+}
+
+/// Returns the number of pages mapped for slabs.
+pub fn get_mapped_page_count() -> usize {
+    MAPPED_PAGES.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that allocations get the smallest size class that fits.
+    #[test]
+    fn test_size_class() {
+        assert_eq!(get_size_class(0, 1), Some(0));
+        assert_eq!(get_size_class(16, 8), Some(0));
+        assert_eq!(get_size_class(17, 8), Some(1));
+        assert_eq!(get_size_class(8, 256), Some(4));
+        assert_eq!(get_size_class(2048, 8), Some(7));
+        assert_eq!(get_size_class(2049, 8), None);
+        assert_eq!(get_size_class(8, 4096), None);
+    }
+
+    /// Tests that the objects fit behind the slab header.
+    #[test]
+    fn test_object_layout() {
+        for class_index in 0..SIZE_CLASS_COUNT {
+            let offset = get_first_object_offset(class_index);
+
+            assert!(offset >= size_of::<Slab>());
+            assert_eq!(offset % SIZE_CLASSES[class_index], 0);
+            assert!(offset + get_object_count(class_index) * SIZE_CLASSES[class_index] <= SLAB_SIZE);
+        }
+    }
+}
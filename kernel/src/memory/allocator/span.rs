@@ -0,0 +1,262 @@
+//! Provides spans of whole pages for large allocations.
+//!
+//! The pages of a span are mapped when it is allocated and unmapped when it is
+//! freed. Freed address ranges are kept in a sorted list to be reused.
+
+use super::{align, SLAB_AREA_END};
+use arch::memory::{HEAP_MAX_SIZE, HEAP_START, PAGE_SIZE};
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use memory::{get_free_memory_size, map_page, unmap_page, PageFlags, VirtualAddress};
+use sync::PreemptableMutex;
+
+/// The maximum number of free address ranges that are remembered.
+const MAX_FREE_RANGES: usize = 64;
+
+/// The number of frames kept free for page tables when mapping pages.
+const PAGE_TABLE_RESERVE: usize = 4;
+
+/// The address ranges of the large allocations.
+static SPANS: PreemptableMutex<AddressRangeAllocator> = PreemptableMutex::new(
+    AddressRangeAllocator::new(SLAB_AREA_END, HEAP_START + HEAP_MAX_SIZE),
+);
+
+/// The number of pages mapped for large allocations.
+static MAPPED_PAGES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Represents a range of virtual addresses.
+#[derive(Clone, Copy)]
+struct AddressRange {
+    /// The start address of the range.
+    start: VirtualAddress,
+    /// The length of the range.
+    length: usize,
+}
+
+impl AddressRange {
+    /// Returns the end address (exclusive) of the range.
+    fn end(&self) -> VirtualAddress {
+        self.start + self.length
+    }
+}
+
+/// Hands out ranges of an area of virtual addresses.
+struct AddressRangeAllocator {
+    /// The free ranges below `end_address`, sorted by their start address.
+    free_ranges: [AddressRange; MAX_FREE_RANGES],
+    /// The number of free ranges.
+    free_range_count: usize,
+    /// The start of the part of the area that was never used.
+    end_address: VirtualAddress,
+    /// The end of the area.
+    max_address: VirtualAddress,
+}
+
+impl AddressRangeAllocator {
+    /// Creates an allocator for the area between the given addresses.
+    const fn new(start_address: VirtualAddress, max_address: VirtualAddress) -> AddressRangeAllocator {
+        AddressRangeAllocator {
+            free_ranges: [AddressRange {
+                start: 0,
+                length: 0,
+            }; MAX_FREE_RANGES],
+            free_range_count: 0,
+            end_address: start_address,
+            max_address,
+        }
+    }
+
+    /// Reserves a range of the given length and alignment.
+    fn reserve(&mut self, length: usize, alignment: usize) -> Option<VirtualAddress> {
+        for index in 0..self.free_range_count {
+            let range = self.free_ranges[index];
+            let start = align(range.start, alignment);
+
+            if start + length <= range.end() {
+                self.remove(index);
+
+                // Return the parts before and after the reserved range.
+                if start > range.start {
+                    self.release(range.start, start - range.start);
+                }
+                if range.end() > start + length {
+                    self.release(start + length, range.end() - start - length);
+                }
+
+                return Some(start);
+            }
+        }
+
+        let start = align(self.end_address, alignment);
+        if start > self.max_address || self.max_address - start < length {
+            return None;
+        }
+
+        let old_end_address = self.end_address;
+        self.end_address = start + length;
+        if start > old_end_address {
+            self.release(old_end_address, start - old_end_address);
+        }
+
+        Some(start)
+    }
+
+    /// Releases the given range to be reused.
+    ///
+    /// If there are too many free ranges already, the range is not reused.
+    fn release(&mut self, start: VirtualAddress, length: usize) {
+        if start + length == self.end_address {
+            self.end_address = start;
+
+            // The last free range may now reach the unused part.
+            if self.free_range_count > 0 {
+                let last_range = self.free_ranges[self.free_range_count - 1];
+                if last_range.end() == self.end_address {
+                    self.end_address = last_range.start;
+                    self.free_range_count -= 1;
+                }
+            }
+            return;
+        }
+
+        let count = self.free_range_count;
+        let index = self.free_ranges[..count]
+            .iter()
+            .position(|range| range.start > start)
+            .unwrap_or(count);
+
+        let merges_previous = index > 0 && self.free_ranges[index - 1].end() == start;
+        let merges_next = index < count && start + length == self.free_ranges[index].start;
+
+        match (merges_previous, merges_next) {
+            (true, true) => {
+                self.free_ranges[index - 1].length += length + self.free_ranges[index].length;
+                self.remove(index);
+            }
+            (true, false) => self.free_ranges[index - 1].length += length,
+            (false, true) => {
+                self.free_ranges[index].start = start;
+                self.free_ranges[index].length += length;
+            }
+            (false, false) => {
+                if count == MAX_FREE_RANGES {
+                    return;
+                }
+
+                for i in (index..count).rev() {
+                    self.free_ranges[i + 1] = self.free_ranges[i];
+                }
+                self.free_ranges[index] = AddressRange { start, length };
+                self.free_range_count += 1;
+            }
+        }
+    }
+
+    /// Removes the free range at the given index.
+    fn remove(&mut self, index: usize) {
+        for i in index..self.free_range_count - 1 {
+            self.free_ranges[i] = self.free_ranges[i + 1];
+        }
+        self.free_range_count -= 1;
+    }
+}
+
+/// Allocates a span that fits the given size and alignment.
+pub fn allocate(size: usize, alignment: usize) -> Option<*mut u8> {
+    let page_count = (size - 1) / PAGE_SIZE + 1;
+    let alignment = if alignment > PAGE_SIZE {
+        alignment
+    } else {
+        PAGE_SIZE
+    };
+
+    let mut spans = SPANS.lock();
+    let start = spans.reserve(page_count * PAGE_SIZE, alignment)?;
+
+    if map_pages(start, page_count) {
+        MAPPED_PAGES.fetch_add(page_count, Ordering::Relaxed);
+        Some(start as *mut u8)
+    } else {
+        spans.release(start, page_count * PAGE_SIZE);
+        None
+    }
+}
+
+/// Frees the span allocated with the given size.
+///
+/// # Safety
+/// - The span must not be used anymore.
+pub unsafe fn free(ptr: *mut u8, size: usize) {
+    let page_count = (size - 1) / PAGE_SIZE + 1;
+    let start = ptr as VirtualAddress;
+
+    let mut spans = SPANS.lock();
+    unmap_pages(start, page_count);
+    spans.release(start, page_count * PAGE_SIZE);
+
+    MAPPED_PAGES.fetch_sub(page_count, Ordering::Relaxed);
+}
+
+/// Returns the number of pages mapped for large allocations.
+pub fn get_mapped_page_count() -> usize {
+    MAPPED_PAGES.load(Ordering::Relaxed)
+}
+
+/// Maps the given number of pages starting at `start`.
+///
+/// Returns false without mapping anything if there aren't enough free frames.
+pub fn map_pages(start: VirtualAddress, page_count: usize) -> bool {
+    // The frame allocator can't fail, so the check has to be done before.
+    if get_free_memory_size() < (page_count + PAGE_TABLE_RESERVE) * PAGE_SIZE {
+        return false;
+    }
+
+    for i in 0..page_count {
+        map_page(start + i * PAGE_SIZE, PageFlags::READABLE | PageFlags::WRITABLE);
+    }
+
+    true
+}
+
+/// Unmaps the given number of pages starting at `start`.
+///
+/// # Safety
+/// - The pages must not be used anymore.
+pub unsafe fn unmap_pages(start: VirtualAddress, page_count: usize) {
+    for i in 0..page_count {
+        unmap_page(start + i * PAGE_SIZE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that released ranges are merged and reused.
+    #[test]
+    fn test_reuse() {
+        let mut ranges = AddressRangeAllocator::new(0x10000, 0x100000);
+
+        let first = ranges.reserve(0x1000, 0x1000).unwrap();
+        let second = ranges.reserve(0x2000, 0x1000).unwrap();
+        let third = ranges.reserve(0x1000, 0x1000).unwrap();
+        assert_eq!((first, second, third), (0x10000, 0x11000, 0x13000));
+
+        ranges.release(first, 0x1000);
+        ranges.release(second, 0x2000);
+        assert_eq!(ranges.free_range_count, 1);
+        assert_eq!(ranges.reserve(0x3000, 0x1000), Some(0x10000));
+
+        ranges.release(third, 0x1000);
+        assert_eq!(ranges.end_address, 0x13000);
+    }
+
+    /// Tests that the alignment is respected and the area isn't exceeded.
+    #[test]
+    fn test_alignment() {
+        let mut ranges = AddressRangeAllocator::new(0x11000, 0x40000);
+
+        assert_eq!(ranges.reserve(0x1000, 0x10000), Some(0x20000));
+        assert_eq!(ranges.reserve(0x1000, 0x1000), Some(0x11000));
+        assert_eq!(ranges.reserve(0x20000, 0x1000), None);
+    }
+}
@@ -0,0 +1,66 @@
+//! Randomizes the layout of user address spaces.
+//!
+//! The random numbers are generated from a seed that is chosen once per boot.
+//! Passing `noaslr` on the kernel command line disables the randomization, so
+//! that every process gets the same layout, which helps when debugging.
+
+use arch::get_random_seed;
+use boot;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use memory::VirtualAddress;
+
+/// The state of the random number generator.
+static STATE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Initializes the random number generator with the seed for this boot.
+pub fn init() {
+    assert_has_not_been_called!("The ASLR seed should only be chosen once.");
+
+    if !is_enabled() {
+        debugln!("Address space layout randomization is disabled.");
+        return;
+    }
+
+    // Xorshift never leaves the zero state.
+    let seed = get_random_seed() as usize;
+    STATE.store(if seed == 0 { 1 } else { seed }, Ordering::Relaxed);
+}
+
+/// Returns true if address space layouts should be randomized.
+pub fn is_enabled() -> bool {
+    !boot::has_option("noaslr")
+}
+
+/// Returns the next random number.
+fn next_random() -> usize {
+    let mut state = STATE.load(Ordering::Relaxed);
+
+    loop {
+        let next = xorshift(state);
+
+        let previous = STATE.compare_and_swap(state, next, Ordering::Relaxed);
+        if previous == state {
+            return next;
+        }
+        state = previous;
+    }
+}
+
+/// Advances the xorshift generator by one step.
+fn xorshift(mut state: usize) -> usize {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+}
+
+/// Returns a random offset below `range` that is a multiple of `align`.
+///
+/// If randomization is disabled, the offset is always zero.
+pub fn random_offset(range: usize, align: usize) -> VirtualAddress {
+    if !is_enabled() || range < align {
+        0
+    } else {
+        next_random() % (range / align) * align
+    }
+}
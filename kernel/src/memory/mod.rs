@@ -1,7 +1,9 @@
 //! Handles all memory related things.
 
 pub mod allocator;
+pub mod aslr;
 pub mod address_space;
+pub mod user;
 
 pub use arch::memory::*;
 
@@ -58,14 +60,6 @@ impl FreeMemoryArea {
     pub fn end_address(&self) -> PhysicalAddress {
         self.start_address + self.length
     }
-
-    /// Returns the same area except for the first frame.
-    pub fn without_first_frame(&self) -> FreeMemoryArea {
-        FreeMemoryArea {
-            start_address: self.start_address + PAGE_SIZE,
-            length: self.length - PAGE_SIZE,
-        }
-    }
 }
 
 impl fmt::Debug for FreeMemoryArea {
@@ -0,0 +1,185 @@
+//! Provides access to the memory of the current process for syscalls.
+//!
+//! User addresses are checked against the segments of the process before they
+//! are accessed. If accessing them faults anyway, an error is returned instead
+//! of panicking.
+
+use super::{copy_user_memory, PageFlags, VirtualAddress};
+use alloc::Vec;
+use core::marker::PhantomData;
+use core::mem;
+use core::mem::size_of;
+use core::slice;
+use multitasking::get_current_process;
+
+/// The error returned if user memory can't be accessed.
+///
+/// The memory is either outside of a segment permitting the access or
+/// accessing it faulted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserAccessError;
+
+/// Checks if the current process permits accesses with the given flags to the
+/// range.
+fn is_accessible(address: VirtualAddress, length: usize, flags: PageFlags) -> bool {
+    if length == 0 {
+        return true;
+    }
+
+    if address.checked_add(length).is_none() {
+        return false;
+    }
+
+    get_current_process()
+        .address_space
+        .get_segment(address, length)
+        .map_or(false, |segment| {
+            segment
+                .flags()
+                .contains(flags | PageFlags::USER_ACCESSIBLE)
+        })
+}
+
+/// Copies the user memory at `source` into the buffer.
+pub fn copy_from_user(buffer: &mut [u8], source: VirtualAddress) -> Result<(), UserAccessError> {
+    if !is_accessible(source, buffer.len(), PageFlags::READABLE) {
+        return Err(UserAccessError);
+    }
+
+    unsafe { copy_from_user_unchecked(buffer, source) }
+}
+
+/// Copies the buffer to the user memory at `destination`.
+pub fn copy_to_user(destination: VirtualAddress, buffer: &[u8]) -> Result<(), UserAccessError> {
+    if !is_accessible(destination, buffer.len(), PageFlags::WRITABLE) {
+        return Err(UserAccessError);
+    }
+
+    let copied = unsafe { copy_user_memory(destination as *mut u8, buffer.as_ptr(), buffer.len()) };
+
+    if copied {
+        Ok(())
+    } else {
+        Err(UserAccessError)
+    }
+}
+
+/// Copies the user memory at `source` into the buffer without checking the
+/// segments.
+///
+/// # Safety
+/// - The range must have been checked to be readable by the process.
+unsafe fn copy_from_user_unchecked(
+    buffer: &mut [u8],
+    source: VirtualAddress,
+) -> Result<(), UserAccessError> {
+    if copy_user_memory(buffer.as_mut_ptr(), source as *const u8, buffer.len()) {
+        Ok(())
+    } else {
+        Err(UserAccessError)
+    }
+}
+
+/// A pointer to a value in the memory of the current process.
+pub struct UserPtr<T> {
+    /// The address of the value.
+    address: VirtualAddress,
+    /// The type of the value.
+    value_type: PhantomData<T>,
+}
+
+impl<T> UserPtr<T> {
+    /// Creates a pointer to the value at the given address.
+    pub fn new(address: VirtualAddress) -> UserPtr<T> {
+        UserPtr {
+            address,
+            value_type: PhantomData,
+        }
+    }
+
+    /// Checks if the process permits reading the value.
+    pub fn is_readable(&self) -> bool {
+        is_accessible(self.address, size_of::<T>(), PageFlags::READABLE)
+    }
+
+    /// Checks if the process permits writing the value.
+    pub fn is_writable(&self) -> bool {
+        is_accessible(self.address, size_of::<T>(), PageFlags::WRITABLE)
+    }
+
+    /// Reads the value.
+    pub fn read(&self) -> Result<T, UserAccessError> {
+        if !self.is_readable() {
+            return Err(UserAccessError);
+        }
+
+        unsafe { self.read_unchecked() }
+    }
+
+    /// Reads the value without checking the segments.
+    ///
+    /// This still fails instead of panicking, if reading faults.
+    ///
+    /// # Safety
+    /// - `is_readable` must have returned true for the pointer.
+    pub unsafe fn read_unchecked(&self) -> Result<T, UserAccessError> {
+        let mut value: T = mem::uninitialized();
+
+        let result = {
+            let value_ptr = &mut value as *mut T as *mut u8;
+            let buffer = slice::from_raw_parts_mut(value_ptr, size_of::<T>());
+            copy_from_user_unchecked(buffer, self.address)
+        };
+
+        match result {
+            Ok(()) => Ok(value),
+            Err(error) => {
+                mem::forget(value);
+                Err(error)
+            }
+        }
+    }
+
+    /// Writes the value.
+    pub fn write(&self, value: T) -> Result<(), UserAccessError> {
+        let value_ptr = &value as *const T as *const u8;
+        let buffer = unsafe { slice::from_raw_parts(value_ptr, size_of::<T>()) };
+
+        copy_to_user(self.address, buffer)
+    }
+}
+
+/// A buffer in the memory of the current process.
+#[derive(Debug, Clone, Copy)]
+pub struct UserSlice {
+    /// The start address of the buffer.
+    address: VirtualAddress,
+    /// The length of the buffer in bytes.
+    length: usize,
+}
+
+impl UserSlice {
+    /// Creates a buffer of the given length at the given address.
+    pub fn new(address: VirtualAddress, length: usize) -> UserSlice {
+        UserSlice { address, length }
+    }
+
+    /// Reads the whole buffer.
+    pub fn read_to_vec(&self) -> Result<Vec<u8>, UserAccessError> {
+        let mut buffer = Vec::with_capacity(self.length);
+        buffer.resize(self.length, 0);
+
+        copy_from_user(&mut buffer, self.address)?;
+
+        Ok(buffer)
+    }
+
+    /// Writes the data to the buffer, starting at the given offset.
+    pub fn write_at(&self, offset: usize, data: &[u8]) -> Result<(), UserAccessError> {
+        if offset > self.length || data.len() > self.length - offset {
+            return Err(UserAccessError);
+        }
+
+        copy_to_user(self.address + offset, data)
+    }
+}
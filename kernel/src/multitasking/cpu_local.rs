@@ -57,6 +57,14 @@ impl<T> CPULocalMut<T> {
         CPULocalMut(UnsafeCell::new(vec))
     }
 
+    /// Gets the local value of the given cpu.
+    ///
+    /// # Safety
+    /// - Make sure the value isn't changed while the reference is in use.
+    pub unsafe fn get_specific(&self, cpu_id: usize) -> &T {
+        &(*self.0.get())[cpu_id]
+    }
+
     /// Sets the value to the specified type.
     ///
     /// # Safety
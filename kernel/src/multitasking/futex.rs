@@ -0,0 +1,134 @@
+//! Implements futexes, which let threads wait on a value in user memory.
+
+use super::{ProcessID, CURRENT_THREAD};
+use super::wait::{block, prepare_to_block, wake, WakeReason, Waiter};
+use alloc::btree_map::BTreeMap;
+use alloc::Vec;
+use core::mem::size_of;
+use memory::VirtualAddress;
+use memory::user::UserPtr;
+use sync::PreemptableMutex;
+use sync::time::Timestamp;
+
+/// The errors that can occur while waiting on a futex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The address isn't a valid aligned address in the process.
+    InvalidAddress,
+    /// The futex didn't hold the expected value.
+    ValueChanged,
+    /// The timeout expired.
+    TimedOut,
+}
+
+lazy_static! {
+    /// The threads waiting on each futex.
+    static ref FUTEXES: PreemptableMutex<BTreeMap<(ProcessID, VirtualAddress), Vec<Waiter>>> =
+        PreemptableMutex::new(BTreeMap::new());
+}
+
+/// Blocks the current thread while the futex at the given address holds the
+/// expected value, until it is woken up or the timeout expires.
+pub fn futex_wait(
+    address: VirtualAddress,
+    expected: u32,
+    timeout: Option<Timestamp>,
+) -> Result<(), FutexError> {
+    let pid = CURRENT_THREAD.lock().pid;
+    let key = (pid, address);
+
+    if address % size_of::<u32>() != 0 {
+        return Err(FutexError::InvalidAddress);
+    }
+
+    let futex = UserPtr::<u32>::new(address);
+
+    let waiter = prepare_to_block();
+
+    {
+        // Holding the lock makes the check and registration atomic to wakers.
+        let mut futexes = FUTEXES.lock();
+
+        let value = match futex.read() {
+            Ok(value) => value,
+            Err(_) => return Err(FutexError::InvalidAddress),
+        };
+        if value != expected {
+            return Err(FutexError::ValueChanged);
+        }
+
+        futexes.entry(key).or_insert_with(Vec::new).push(waiter);
+    }
+
+    match block(waiter, timeout) {
+        WakeReason::Woken => Ok(()),
+        WakeReason::TimedOut => {
+            remove_waiter(key, waiter);
+            Err(FutexError::TimedOut)
+        }
+        WakeReason::ProcessDied => unreachable!(),
+    }
+}
+
+/// Wakes up to `count` threads waiting on the futex at the given address.
+///
+/// Returns the number of woken threads.
+pub fn futex_wake(address: VirtualAddress, count: usize) -> usize {
+    let pid = CURRENT_THREAD.lock().pid;
+    let key = (pid, address);
+
+    let mut futexes = FUTEXES.lock();
+    let mut woken = 0;
+
+    let remove_entry = match futexes.get_mut(&key) {
+        Some(waiters) => {
+            // Wake the threads in the order they started waiting.
+            while woken < count && !waiters.is_empty() {
+                if wake(waiters.remove(0), WakeReason::Woken) {
+                    woken += 1;
+                }
+            }
+
+            waiters.is_empty()
+        }
+        None => false,
+    };
+
+    if remove_entry {
+        futexes.remove(&key);
+    }
+
+    woken
+}
+
+/// Removes all futex waiters of the given process.
+pub fn remove_process_futexes(pid: ProcessID) {
+    let mut futexes = FUTEXES.lock();
+
+    let keys: Vec<(ProcessID, VirtualAddress)> = futexes
+        .keys()
+        .filter(|&&(futex_pid, _)| futex_pid == pid)
+        .cloned()
+        .collect();
+
+    for key in keys {
+        futexes.remove(&key);
+    }
+}
+
+/// Removes the given waiter from the futex, if it is still waiting.
+fn remove_waiter(key: (ProcessID, VirtualAddress), waiter: Waiter) {
+    let mut futexes = FUTEXES.lock();
+
+    let remove_entry = match futexes.get_mut(&key) {
+        Some(waiters) => {
+            waiters.retain(|other| *other != waiter);
+            waiters.is_empty()
+        }
+        None => false,
+    };
+
+    if remove_entry {
+        futexes.remove(&key);
+    }
+}
@@ -0,0 +1,109 @@
+//! Provides per-process message queues.
+//!
+//! Messages are sent by the kernel, for example by expiring timers, and
+//! received by any thread of the process.
+
+use super::ProcessID;
+use super::wait::{block, prepare_to_block, wake, WakeReason, Waiter};
+use alloc::btree_map::BTreeMap;
+use alloc::vec_deque::VecDeque;
+use alloc::Vec;
+use sync::PreemptableMutex;
+use sync::time::Timestamp;
+
+/// The maximum number of queued messages per process.
+///
+/// Further messages are dropped until the process receives messages again.
+const MAX_QUEUED_MESSAGES: usize = 256;
+
+/// The kind of a message sent when a timer expires.
+pub const TIMER_MESSAGE: u64 = 1;
+
+/// A message as it is passed to userspace.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    /// The kind of the message.
+    pub kind: u64,
+    /// The first argument, depending on the kind.
+    pub arg1: u64,
+    /// The second argument, depending on the kind.
+    pub arg2: u64,
+}
+
+/// The message queue of a process.
+struct MessageQueue {
+    /// The messages that were not received yet.
+    messages: VecDeque<Message>,
+    /// The threads waiting for a message.
+    receivers: Vec<Waiter>,
+}
+
+lazy_static! {
+    /// The message queues of all processes that were sent messages.
+    static ref MESSAGE_QUEUES: PreemptableMutex<BTreeMap<ProcessID, MessageQueue>> =
+        PreemptableMutex::new(BTreeMap::new());
+}
+
+/// Sends the message to the given process.
+///
+/// Returns false if the queue of the process is full.
+pub fn send_message(pid: ProcessID, message: Message) -> bool {
+    let mut queues = MESSAGE_QUEUES.lock();
+    let queue = queues.entry(pid).or_insert_with(|| MessageQueue {
+        messages: VecDeque::new(),
+        receivers: Vec::new(),
+    });
+
+    if queue.messages.len() >= MAX_QUEUED_MESSAGES {
+        return false;
+    }
+
+    queue.messages.push_back(message);
+
+    // Wake one receiver that is still waiting.
+    while !queue.receivers.is_empty() {
+        if wake(queue.receivers.remove(0), WakeReason::Woken) {
+            break;
+        }
+    }
+
+    true
+}
+
+/// Receives the next message of the current process.
+///
+/// Blocks until a message arrives. Returns `None` if the timeout expires
+/// first.
+pub fn receive_message(pid: ProcessID, timeout: Option<Timestamp>) -> Option<Message> {
+    loop {
+        let waiter = prepare_to_block();
+
+        {
+            let mut queues = MESSAGE_QUEUES.lock();
+            let queue = queues.entry(pid).or_insert_with(|| MessageQueue {
+                messages: VecDeque::new(),
+                receivers: Vec::new(),
+            });
+
+            if let Some(message) = queue.messages.pop_front() {
+                return Some(message);
+            }
+
+            queue.receivers.push(waiter);
+        }
+
+        if block(waiter, timeout) == WakeReason::TimedOut {
+            if let Some(queue) = MESSAGE_QUEUES.lock().get_mut(&pid) {
+                queue.receivers.retain(|other| *other != waiter);
+            }
+
+            return None;
+        }
+    }
+}
+
+/// Removes the message queue of the given process.
+pub fn remove_message_queue(pid: ProcessID) {
+    MESSAGE_QUEUES.lock().remove(&pid);
+}
@@ -5,6 +5,10 @@ pub mod stack;
 pub mod scheduler;
 mod cpu_local;
 mod pcb;
+pub mod wait;
+pub mod futex;
+pub mod message;
+pub mod user_timer;
 
 pub use self::cpu_local::{CPULocal, CPULocalMut};
 pub use self::pcb::{get_current_process, get_process, PCB};
@@ -21,6 +25,9 @@ use sync::preemptable_mutex::PreemptableMutexGuard;
 /// The type of a process ID.
 pub type ProcessID = usize;
 
+/// The process ID of the first process, which is started from `/bin/init`.
+pub const INIT_PROCESS_ID: ProcessID = 1;
+
 /// The type of a thread ID.
 type ThreadID = u16;
 
@@ -44,15 +51,55 @@ fn find_pid(list: &PreemptableMutexGuard<BTreeMap<ProcessID, PCB>>) -> ProcessID
 }
 
 /// Creates a new process.
-pub fn create_process(address_space: AddressSpace, entry_address: VirtualAddress) -> ProcessID {
+///
+/// Returns `None` if the stacks of the first thread overlap segments of the
+/// address space.
+pub fn create_process(
+    address_space: AddressSpace,
+    entry_address: VirtualAddress,
+) -> Option<ProcessID> {
+    create_process_with_arguments(address_space, entry_address, 0, 0, 0, 0, 0)
+}
+
+/// Creates a new process whose first thread gets the given arguments.
+///
+/// Returns `None` if the stacks of the first thread overlap segments of the
+/// address space.
+pub fn create_process_with_arguments(
+    address_space: AddressSpace,
+    entry_address: VirtualAddress,
+    arg1: u64,
+    arg2: u64,
+    arg3: u64,
+    arg4: u64,
+    arg5: u64,
+) -> Option<ProcessID> {
     let mut pcb = PCB::new(address_space);
 
     let mut process_list = PROCESS_LIST.lock();
     let id = find_pid(&process_list);
 
-    let first_tcb = TCB::in_process(id, 0, entry_address, &mut pcb);
+    let first_tcb = TCB::in_process_with_arguments(
+        id,
+        0,
+        entry_address,
+        &mut pcb,
+        arg1,
+        arg2,
+        arg3,
+        arg4,
+        arg5,
+    );
+    let first_tcb = match first_tcb {
+        Some(first_tcb) => first_tcb,
+        None => {
+            // The thread was never started, so the process can be dropped.
+            pcb.thread_count = 0;
+            return None;
+        }
+    };
 
-    scheduler::READY_LIST.lock().push(first_tcb);
+    scheduler::make_ready(first_tcb);
 
     assert!(
         process_list.insert(id, pcb).is_none(),
@@ -60,5 +107,33 @@ pub fn create_process(address_space: AddressSpace, entry_address: VirtualAddress
         id
     );
 
-    id
+    Some(id)
+}
+
+/// Creates a thread in the idle process that runs the given function in the
+/// kernel.
+///
+/// This has to be called while the idle address space is active.
+pub fn create_kernel_thread(function: fn() -> !, priority: i32) {
+    let id = {
+        let mut process_list = PROCESS_LIST.lock();
+        let idle_pcb = process_list.get_mut(&0).unwrap();
+        let id = idle_pcb
+            .find_thread_id()
+            .expect("The idle process has no thread IDs left.");
+        idle_pcb.add_thread(id);
+
+        id
+    };
+
+    scheduler::make_ready(TCB::kernel_tcb(id, function, priority));
+}
+
+/// Releases everything that still refers to the given process after it was
+/// dropped.
+fn release_process_resources(pid: ProcessID) {
+    user_timer::remove_process_timers(pid);
+    message::remove_message_queue(pid);
+    futex::remove_process_futexes(pid);
+    wait::remove_process_wakeups(pid);
 }
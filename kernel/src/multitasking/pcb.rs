@@ -5,6 +5,8 @@ use arch::{get_cpu_num, schedule};
 use core::cmp::max;
 use core::ops::{Deref, DerefMut};
 use memory::address_space::AddressSpace;
+use memory::{aslr, VirtualAddress, HUGE_PAGE_SIZE, USER_ASLR_RANGE, USER_FRAMEBUFFER_AREA_BASE,
+             USER_STACK_AREA_BASE};
 use multitasking::{ProcessID, ThreadID, CURRENT_THREAD, PROCESS_LIST};
 use sync::preemptable_mutex::PreemptableMutexGuard;
 
@@ -21,6 +23,10 @@ enum ProcessState {
 pub struct PCB {
     /// The address space of the process.
     pub address_space: AddressSpace,
+    /// The base address of the thread stack area of the process.
+    pub stack_area_base: VirtualAddress,
+    /// The address the framebuffer is mapped at in the process.
+    pub framebuffer_area_base: VirtualAddress,
     /// The amount of currently existing threads within this process.
     pub thread_count: u16,
     /// The state of the process.
@@ -40,6 +46,11 @@ impl PCB {
     pub fn new(address_space: AddressSpace) -> PCB {
         PCB {
             address_space,
+            stack_area_base: USER_STACK_AREA_BASE
+                + aslr::random_offset(USER_ASLR_RANGE, HUGE_PAGE_SIZE),
+            // The alignment lets the framebuffer be mapped with huge pages.
+            framebuffer_area_base: USER_FRAMEBUFFER_AREA_BASE
+                + aslr::random_offset(USER_ASLR_RANGE, HUGE_PAGE_SIZE),
             thread_count: 1,
             highest_thread_id: 0,
             state: ProcessState::Active,
@@ -51,6 +62,8 @@ impl PCB {
         assert_has_not_been_called!("There should only be one idle PCB.");
         PCB {
             address_space: AddressSpace::idle_address_space(),
+            stack_area_base: USER_STACK_AREA_BASE,
+            framebuffer_area_base: USER_FRAMEBUFFER_AREA_BASE,
             thread_count: get_cpu_num() as u16,
             highest_thread_id: get_cpu_num() as u16 - 1,
             state: ProcessState::Active,
@@ -1,14 +1,16 @@
 //! This module implements a scheduler.
 
 use super::{TCB, ThreadState};
+use super::wait;
 use super::tcb::SleepTimeSortedTCB;
 use alloc::binary_heap::BinaryHeap;
-use arch::schedule;
+use arch::{get_cpu_id, get_cpu_num, schedule, schedule_on};
 use arch::context::switch_context;
-use core::mem::swap;
-use sync::{disable_preemption, enable_preemption, restore_preemption_state};
+use core::mem::{size_of, swap};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use sync::{disable_preemption, enable_preemption, enable_preemption_and_halt,
+           restore_preemption_state};
 use sync::PreemptableMutex;
-use x86_64::instructions::halt;
 
 cpu_local! {
     pub static ref READY_LIST: PreemptableMutex<BinaryHeap<TCB>> = |_| PreemptableMutex::new(BinaryHeap::new());
@@ -28,6 +30,85 @@ cpu_local! {
     static mut ref OLD_THREAD: Option<TCB> = |_| None;
 }
 
+/// The CPUs that are halted in their idle thread, one bit per CPU.
+///
+/// CPUs that don't fit in the mask are never woken up early.
+static IDLE_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes the given thread ready to run.
+///
+/// The thread is queued on the current CPU and an idle CPU is woken up to
+/// take it, if there is one.
+pub fn make_ready(thread: TCB) {
+    let is_next = {
+        let mut ready_list = READY_LIST.lock();
+        let is_next = ready_list
+            .peek()
+            .map_or(true, |next_thread| thread.priority > next_thread.priority);
+        ready_list.push(thread);
+        is_next
+    };
+
+    // Without a periodic tick, the current thread is only preempted if the
+    // timer is programmed for the end of its time slice.
+    if is_next {
+        ::sync::timer::reprogram();
+    }
+
+    wake_idle_cpu();
+}
+
+/// Wakes up one idle CPU other than the current one.
+fn wake_idle_cpu() {
+    let own_mask = idle_mask(get_cpu_id());
+
+    loop {
+        let idle_cpus = IDLE_CPUS.load(Ordering::SeqCst) & !own_mask;
+        if idle_cpus == 0 {
+            return;
+        }
+
+        let cpu_id = idle_cpus.trailing_zeros() as usize;
+
+        // Another CPU may be waking the same one.
+        if IDLE_CPUS.fetch_and(!idle_mask(cpu_id), Ordering::SeqCst) & idle_mask(cpu_id) != 0 {
+            schedule_on(cpu_id);
+            return;
+        }
+    }
+}
+
+/// Returns the bit of the given CPU in `IDLE_CPUS`.
+fn idle_mask(cpu_id: usize) -> usize {
+    if cpu_id < size_of::<usize>() * 8 {
+        1 << cpu_id
+    } else {
+        0
+    }
+}
+
+/// Takes a thread that is ready to run from another CPU.
+///
+/// Idle threads are never taken, because they are bound to their CPU.
+fn steal_thread() -> Option<TCB> {
+    let cpu_id = get_cpu_id();
+
+    for other_cpu_id in 0..get_cpu_num() {
+        if other_cpu_id == cpu_id {
+            continue;
+        }
+
+        // Waiting for the lock could deadlock with a CPU stealing from here.
+        if let Some(mut ready_list) = READY_LIST.get_specific(other_cpu_id).try_lock() {
+            if ready_list.peek().map_or(false, |thread| !thread.is_idle()) {
+                return ready_list.pop();
+            }
+        }
+    }
+
+    None
+}
+
 /// Schedules the next thread to run and dispatches it.
 ///
 /// # Safety
@@ -40,6 +121,17 @@ pub unsafe fn schedule_next_thread() {
 
     let mut ready_list = READY_LIST.lock();
 
+    // Take work from other CPUs, if this one would become idle otherwise.
+    let has_work = ready_list.peek().map_or(false, |thread| !thread.is_idle());
+    let current_is_busy = {
+        let current_thread = CURRENT_THREAD.lock();
+        !current_thread.is_idle() && current_thread.is_running()
+    };
+    if !has_work && !current_is_busy {
+        if let Some(thread) = steal_thread() {
+            ready_list.push(thread);
+        }
+    }
 
     // Scheduling is needed if:
     // There is another thread to schedule.
@@ -69,6 +161,7 @@ pub unsafe fn schedule_next_thread() {
             OLD_THREAD.as_mut().as_mut().unwrap().set_ready();
         }
         CURRENT_THREAD.lock().set_running();
+        IDLE_CPUS.fetch_and(!idle_mask(get_cpu_id()), Ordering::SeqCst);
 
         // This is where the actual switch happens.
         switch_context(&mut OLD_THREAD.as_mut().as_mut().unwrap().context,
@@ -97,14 +190,18 @@ pub fn after_context_switch() {
             return_old_thread_to_queue(old_thread);
         }
     }
-    // TODO: Start the timer again here to ensure fairness.
+    // Start the timer again to ensure fairness.
+    ::sync::timer::reprogram();
 }
 
 /// Returns the old thread to the corresponding queue after switching the context.
 fn return_old_thread_to_queue(thread: TCB) {
     match thread.state {
-        ThreadState::Ready => READY_LIST.lock().push(thread),
+        // The idle thread can't run elsewhere, so nobody needs to be woken up.
+        ThreadState::Ready if thread.is_idle() => READY_LIST.lock().push(thread),
+        ThreadState::Ready => make_ready(thread),
         ThreadState::Sleeping(_) => SLEEPING_LIST.lock().push(SleepTimeSortedTCB(thread)),
+        ThreadState::Blocked(_) => wait::return_blocked_thread(thread),
         _ => panic!("Running or dead thread is being returned to a queue.")
     }
 }
@@ -123,7 +220,12 @@ pub fn idle() -> ! {
     loop {
         // TODO: Perform periodic cleanup here.
         unsafe {
-            halt();
+            // Announce being idle with preemption disabled, so the wake up
+            // can't be handled before halting.
+            let preemption_state = disable_preemption();
+            IDLE_CPUS.fetch_or(idle_mask(get_cpu_id()), Ordering::SeqCst);
+            enable_preemption_and_halt();
+            restore_preemption_state(&preemption_state);
         }
     }
 }
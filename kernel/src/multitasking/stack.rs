@@ -4,7 +4,7 @@ use arch::STACK_TYPE;
 use core::cmp::{max, min};
 use core::fmt;
 use core::mem::size_of;
-use memory::{map_page, unmap_page, PageFlags, VirtualAddress, PAGE_SIZE};
+use memory::{map_page, unmap_page, PageFlags, VirtualAddress, PAGE_SIZE, STACK_GUARD_SIZE};
 use memory::address_space::{AddressSpace, Segment, SegmentType};
 
 // NOTE: For now only full descending stacks are supported.
@@ -38,6 +38,8 @@ pub struct Stack {
     bottom_address: VirtualAddress,
     /// Represents the maximum stack size.
     max_size: usize,
+    /// The lowest address reserved for the stack, where its guard pages start.
+    guard_address: VirtualAddress,
     /// Represents the first address of the stack.
     pub base_stack_pointer: VirtualAddress,
     /// The access type for this stack.
@@ -83,45 +85,67 @@ impl Stack {
     //
     // }
 
-    /// Creates a new stack of size zero with the given start address.
+    /// Creates a new stack of the given initial size with the given start
+    /// address.
+    ///
+    /// The lowest `STACK_GUARD_SIZE` bytes of the maximum size are never
+    /// mapped, so that an overflow faults instead of corrupting memory.
+    ///
+    /// Returns `None` if the stack overlaps a segment of the given address
+    /// space. Stacks without an address space can always be created.
     pub fn new(
         initial_size: usize,
         max_size: usize,
         start_address: VirtualAddress,
         access_type: AccessType,
         mut address_space: Option<&mut AddressSpace>,
-    ) -> Stack {
-        let mut stack = match STACK_TYPE {
-            StackType::FullDescending => Stack {
-                top_address: start_address + max_size,
-                bottom_address: start_address + max_size,
-                max_size,
-                base_stack_pointer: start_address + max_size,
-                access_type,
-            },
-        };
+    ) -> Option<Stack> {
+        let guard_size = min(STACK_GUARD_SIZE, max_size);
 
         if let Some(ref mut address_space) = address_space {
             let mut flags = PageFlags::READABLE | PageFlags::WRITABLE;
 
-            if stack.access_type == AccessType::UserAccessible {
+            if access_type == AccessType::UserAccessible {
                 flags |= PageFlags::USER_ACCESSIBLE;
             }
 
-            assert!(
-                address_space.add_segment(Segment::new(
-                    start_address,
-                    max_size,
-                    flags,
-                    SegmentType::MemoryOnly
-                )),
-                "Could not add stack segment."
+            let segment = Segment::new(
+                start_address + guard_size,
+                max_size - guard_size,
+                flags,
+                SegmentType::MemoryOnly,
             );
+            if !address_space.add_segment(segment) {
+                return None;
+            }
         }
 
+        let mut stack = match STACK_TYPE {
+            StackType::FullDescending => Stack {
+                top_address: start_address + max_size,
+                bottom_address: start_address + max_size,
+                max_size: max_size - guard_size,
+                guard_address: start_address,
+                base_stack_pointer: start_address + max_size,
+                access_type,
+            },
+        };
+
         stack.resize(initial_size, address_space);
 
-        stack
+        Some(stack)
+    }
+
+    /// Unmaps the stack and removes its segment from the address space it was
+    /// created in.
+    pub fn release(mut self, address_space: &mut AddressSpace) {
+        self.resize(0, Some(&mut *address_space));
+
+        match STACK_TYPE {
+            StackType::FullDescending => unsafe {
+                address_space.remove_segment(self.top_address - self.max_size);
+            },
+        }
     }
 
     /// Grows the stack by the given amount.
@@ -187,6 +211,18 @@ impl Stack {
         }
     }
 
+    /// Checks if an access to the address means that the stack overflowed.
+    ///
+    /// This is the case for the unmapped addresses below the stack, down to
+    /// the end of its guard pages.
+    pub fn is_overflow_address(&self, address: VirtualAddress) -> bool {
+        match STACK_TYPE {
+            StackType::FullDescending => {
+                address >= self.guard_address && address < self.bottom_address
+            }
+        }
+    }
+
     /// Resizes the stack to the given size.
     pub fn resize(&mut self, new_size: usize, address_space: Option<&mut AddressSpace>) {
         let current_size = (self.top_address - self.bottom_address) as isize;
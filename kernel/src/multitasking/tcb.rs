@@ -2,13 +2,13 @@
 
 use super::{ProcessID, Stack, ThreadID, PCB, PROCESS_LIST};
 use super::stack::AccessType;
+use super::wait::BlockToken;
 use arch::Context;
 use core::cmp::Ordering;
 use core::fmt;
-use memory::{VirtualAddress, KERNEL_STACK_AREA_BASE, KERNEL_STACK_MAX_SIZE, KERNEL_STACK_OFFSET,
-             USER_STACK_AREA_BASE, USER_STACK_MAX_SIZE, USER_STACK_OFFSET};
+use memory::{get_current_page_table_address, VirtualAddress, KERNEL_STACK_AREA_BASE, KERNEL_STACK_MAX_SIZE, KERNEL_STACK_OFFSET,
+             USER_STACK_MAX_SIZE, USER_STACK_OFFSET};
 use sync::time::Timestamp;
-use x86_64::registers::control_regs::cr3;
 
 /// Represents the possible states a thread can have.
 #[derive(Debug, PartialEq, Clone)]
@@ -19,6 +19,8 @@ pub enum ThreadState {
     Ready,
     /// The thread is sleeping for a specified amount of time.
     Sleeping(Timestamp),
+    /// The thread is blocked until it is woken up.
+    Blocked(BlockToken),
     /// The thread is dead.
     Dead,
 }
@@ -92,17 +94,19 @@ impl Drop for TCB {
 
         if drop_pcb {
             process_list.remove(&self.pid);
+
+            // The PID could be reused, so drop everything that refers to it.
+            drop(process_list);
+            super::release_process_resources(self.pid);
         }
     }
 }
 
 impl TCB {
-    /// Creates a new thread in the given process at the given start address.
-    pub fn in_process(pid: ProcessID, id: ThreadID, pc: VirtualAddress, pcb: &mut PCB) -> TCB {
-        TCB::in_process_with_arguments(pid, id, pc, pcb, 0, 0, 0, 0, 0)
-    }
-
     /// Creates a new thread in the given process at the given start address with the given arguments.
+    ///
+    /// Returns `None` if the stacks of the thread overlap segments of the
+    /// process.
     pub fn in_process_with_arguments(
         pid: ProcessID,
         id: ThreadID,
@@ -113,27 +117,33 @@ impl TCB {
         arg3: u64,
         arg4: u64,
         arg5: u64,
-    ) -> TCB {
+    ) -> Option<TCB> {
         let kernel_stack = Stack::new(
             0x4000,
             KERNEL_STACK_MAX_SIZE,
             KERNEL_STACK_AREA_BASE + KERNEL_STACK_OFFSET * (id as usize),
             AccessType::KernelOnly,
             Some(&mut pcb.address_space),
-        );
+        )?;
 
-        let user_stack = Stack::new(
+        let user_stack = match Stack::new(
             0x2000,
             USER_STACK_MAX_SIZE,
-            USER_STACK_AREA_BASE + USER_STACK_OFFSET * (id as usize),
+            pcb.stack_area_base + USER_STACK_OFFSET * (id as usize),
             AccessType::UserAccessible,
             Some(&mut pcb.address_space),
-        );
+        ) {
+            Some(user_stack) => user_stack,
+            None => {
+                kernel_stack.release(&mut pcb.address_space);
+                return None;
+            }
+        };
 
         let stack_pointer = user_stack.base_stack_pointer;
         let kernel_stack_pointer = kernel_stack.base_stack_pointer;
 
-        TCB {
+        Some(TCB {
             id,
             pid,
             kernel_stack,
@@ -151,7 +161,7 @@ impl TCB {
                 arg4,
                 arg5,
             ),
-        }
+        })
     }
 
     /// Creates a new TCB for an idle thread.
@@ -165,7 +175,7 @@ impl TCB {
             KERNEL_STACK_AREA_BASE + KERNEL_STACK_OFFSET * (id as usize),
             AccessType::KernelOnly,
             None,
-        );
+        ).unwrap();
 
         let stack_pointer = kernel_stack.base_stack_pointer;
 
@@ -173,10 +183,38 @@ impl TCB {
             id,
             pid: 0,
             kernel_stack,
-            user_stack: Stack::new(0, 0, 0, AccessType::KernelOnly, None),
+            user_stack: Stack::new(0, 0, 0, AccessType::KernelOnly, None).unwrap(),
             state: ThreadState::Ready,
             priority: i32::min_value(),
-            context: Context::idle_context(stack_pointer, cr3().0 as usize),
+            context: Context::idle_context(stack_pointer, get_current_page_table_address()),
+        }
+    }
+
+    /// Creates a new TCB for a kernel thread that runs the given function.
+    pub fn kernel_tcb(id: ThreadID, function: fn() -> !, priority: i32) -> TCB {
+        // NOTE: This assumes that the idle address space is currently active.
+        let kernel_stack = Stack::new(
+            0x4000,
+            KERNEL_STACK_MAX_SIZE,
+            KERNEL_STACK_AREA_BASE + KERNEL_STACK_OFFSET * (id as usize),
+            AccessType::KernelOnly,
+            None,
+        ).unwrap();
+
+        let stack_pointer = kernel_stack.base_stack_pointer;
+
+        TCB {
+            id,
+            pid: 0,
+            kernel_stack,
+            user_stack: Stack::new(0, 0, 0, AccessType::KernelOnly, None).unwrap(),
+            state: ThreadState::Ready,
+            priority,
+            context: Context::kernel_context(
+                function,
+                stack_pointer,
+                get_current_page_table_address(),
+            ),
         }
     }
 
@@ -190,6 +228,11 @@ impl TCB {
         self.state == ThreadState::Dead || process.is_dead()
     }
 
+    /// Returns true if this is the idle thread of a CPU.
+    pub fn is_idle(&self) -> bool {
+        self.pid == 0
+    }
+
     /// Returns true if the thread state is running.
     pub fn is_running(&self) -> bool {
         self.state == ThreadState::Running
@@ -0,0 +1,238 @@
+//! Provides timer objects for userspace.
+//!
+//! A timer expires once or periodically. Each expiry either wakes the threads
+//! waiting on the timer or sends a message to the owning process.
+
+use super::ProcessID;
+use super::message::{send_message, Message, TIMER_MESSAGE};
+use super::wait::{block, prepare_to_block, wake, WakeReason, Waiter};
+use alloc::boxed::Box;
+use alloc::btree_map::BTreeMap;
+use alloc::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use sync::PreemptableMutex;
+use sync::time::Timestamp;
+use sync::timer::{add_timer, cancel_timer, TimerId};
+
+/// Identifies a user timer.
+pub type TimerHandle = u64;
+
+/// The way the expiry of a timer is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerDelivery {
+    /// Threads waiting on the timer are woken up.
+    Wakeup,
+    /// A message is sent to the owning process.
+    Message,
+}
+
+/// The errors that can occur while using timers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// The timer doesn't exist or belongs to another process.
+    InvalidTimer,
+    /// The timer delivers messages and can't be waited on.
+    NotWaitable,
+    /// The timeout expired before the timer.
+    TimedOut,
+}
+
+/// A timer created by userspace.
+struct UserTimer {
+    /// The process that owns the timer.
+    owner: ProcessID,
+    /// The way expiries are delivered.
+    delivery: TimerDelivery,
+    /// The time of the first expiry.
+    start: Timestamp,
+    /// The period in nanoseconds, or zero for one-shot timers.
+    period: u64,
+    /// The number of expiries so far.
+    expiries: u64,
+    /// The expiries that were not waited for yet.
+    pending: u64,
+    /// The threads waiting on the timer.
+    waiters: Vec<Waiter>,
+    /// The kernel timer for the next expiry.
+    kernel_timer: Option<TimerId>,
+}
+
+/// The handle for the next timer.
+static NEXT_HANDLE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+lazy_static! {
+    /// All user timers.
+    static ref USER_TIMERS: PreemptableMutex<BTreeMap<TimerHandle, UserTimer>> =
+        PreemptableMutex::new(BTreeMap::new());
+}
+
+/// Creates a timer that first expires at `start` and then every `period`
+/// nanoseconds, unless `period` is zero.
+pub fn create_timer(
+    owner: ProcessID,
+    start: Timestamp,
+    period: u64,
+    delivery: TimerDelivery,
+) -> TimerHandle {
+    // Handle zero is never used, so userspace can use it as "no timer".
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed) as TimerHandle + 1;
+
+    let mut timers = USER_TIMERS.lock();
+    timers.insert(
+        handle,
+        UserTimer {
+            owner,
+            delivery,
+            start,
+            period,
+            expiries: 0,
+            pending: 0,
+            waiters: Vec::new(),
+            kernel_timer: Some(arm(handle, start)),
+        },
+    );
+
+    handle
+}
+
+/// Waits until the given timer expires, returning the number of expiries
+/// since the last wait.
+pub fn wait_timer(
+    owner: ProcessID,
+    handle: TimerHandle,
+    timeout: Option<Timestamp>,
+) -> Result<u64, TimerError> {
+    loop {
+        let waiter = prepare_to_block();
+
+        {
+            let mut timers = USER_TIMERS.lock();
+            let timer = match timers.get_mut(&handle) {
+                Some(timer) => timer,
+                None => return Err(TimerError::InvalidTimer),
+            };
+
+            if timer.owner != owner {
+                return Err(TimerError::InvalidTimer);
+            }
+
+            if timer.delivery != TimerDelivery::Wakeup {
+                return Err(TimerError::NotWaitable);
+            }
+
+            if timer.pending > 0 {
+                let expiries = timer.pending;
+                timer.pending = 0;
+                return Ok(expiries);
+            }
+
+            timer.waiters.push(waiter);
+        }
+
+        if block(waiter, timeout) == WakeReason::TimedOut {
+            if let Some(timer) = USER_TIMERS.lock().get_mut(&handle) {
+                timer.waiters.retain(|other| *other != waiter);
+            }
+
+            return Err(TimerError::TimedOut);
+        }
+    }
+}
+
+/// Deletes the given timer.
+///
+/// Threads waiting on it are woken up.
+pub fn delete_timer(owner: ProcessID, handle: TimerHandle) -> Result<(), TimerError> {
+    let mut timers = USER_TIMERS.lock();
+
+    match timers.get(&handle) {
+        Some(timer) if timer.owner == owner => (),
+        _ => return Err(TimerError::InvalidTimer),
+    }
+
+    release_timer(timers.remove(&handle).unwrap());
+
+    Ok(())
+}
+
+/// Deletes all timers of the given process.
+pub fn remove_process_timers(pid: ProcessID) {
+    let mut timers = USER_TIMERS.lock();
+
+    let handles: Vec<TimerHandle> = timers
+        .iter()
+        .filter(|&(_, timer)| timer.owner == pid)
+        .map(|(handle, _)| *handle)
+        .collect();
+
+    for handle in handles {
+        release_timer(timers.remove(&handle).unwrap());
+    }
+}
+
+/// Cancels the pending expiry of a removed timer and wakes its waiters.
+fn release_timer(timer: UserTimer) {
+    if let Some(kernel_timer) = timer.kernel_timer {
+        cancel_timer(kernel_timer);
+    }
+
+    for waiter in timer.waiters {
+        wake(waiter, WakeReason::Woken);
+    }
+}
+
+/// Schedules the expiry of the given timer at the given time.
+fn arm(handle: TimerHandle, expiry: Timestamp) -> TimerId {
+    add_timer(expiry, Box::new(move || timer_expired(handle)))
+}
+
+/// Delivers the expiry of the given timer and schedules the next one.
+fn timer_expired(handle: TimerHandle) {
+    let mut timers = USER_TIMERS.lock();
+    let timer = match timers.get_mut(&handle) {
+        Some(timer) => timer,
+        None => return,
+    };
+
+    let now = Timestamp::get_current().as_nanoseconds();
+    let start = timer.start.as_nanoseconds();
+
+    // The next expiry is based on the start, so late interrupts don't
+    // accumulate. Periods that were missed completely are counted as well.
+    let mut expiries = 0;
+    timer.kernel_timer = None;
+    loop {
+        timer.expiries += 1;
+        expiries += 1;
+
+        if timer.period == 0 {
+            break;
+        }
+
+        let next_expiry = start.saturating_add(timer.expiries.saturating_mul(timer.period));
+        if next_expiry > now {
+            timer.kernel_timer = Some(arm(handle, Timestamp::from_nanoseconds(next_expiry)));
+            break;
+        }
+    }
+
+    match timer.delivery {
+        TimerDelivery::Wakeup => {
+            timer.pending += expiries;
+
+            for waiter in timer.waiters.drain(..) {
+                wake(waiter, WakeReason::Woken);
+            }
+        }
+        TimerDelivery::Message => {
+            send_message(
+                timer.owner,
+                Message {
+                    kind: TIMER_MESSAGE,
+                    arg1: handle,
+                    arg2: expiries,
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Blocks threads until they are woken up or a timeout expires.
+//!
+//! A thread first calls `prepare_to_block` to get a `Waiter`, registers it
+//! wherever it expects a wakeup from and then calls `block`. Wakeups that
+//! happen between both calls are not lost.
+
+use super::{ProcessID, ThreadID, ThreadState, CURRENT_THREAD, TCB};
+use super::scheduler::make_ready;
+use alloc::boxed::Box;
+use alloc::btree_map::BTreeMap;
+use alloc::Vec;
+use arch::schedule;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use sync::PreemptableMutex;
+use sync::time::Timestamp;
+use sync::timer::{add_timer, cancel_timer};
+
+/// Identifies a single time a thread blocks.
+pub type BlockToken = usize;
+
+/// The reason a blocked thread continues running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeReason {
+    /// The thread was woken up.
+    Woken,
+    /// The timeout expired before the thread was woken up.
+    TimedOut,
+    /// The process of the thread was killed.
+    ProcessDied,
+}
+
+/// A thread that can be woken up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Waiter {
+    /// The process of the thread.
+    pub pid: ProcessID,
+    /// The ID of the thread.
+    pub id: ThreadID,
+    /// Identifies the block the wakeup is meant for.
+    token: BlockToken,
+}
+
+/// The token for the next block.
+static NEXT_TOKEN: AtomicUsize = ATOMIC_USIZE_INIT;
+
+lazy_static! {
+    /// The threads that are blocked, waiting to be woken up.
+    static ref BLOCKED_THREADS: PreemptableMutex<BTreeMap<(ProcessID, ThreadID), TCB>> =
+        PreemptableMutex::new(BTreeMap::new());
+
+    /// The current block of each thread that prepared to block and the reason
+    /// it was woken up for, once it was.
+    static ref WAKEUPS: PreemptableMutex<BTreeMap<(ProcessID, ThreadID), (BlockToken, Option<WakeReason>)>> =
+        PreemptableMutex::new(BTreeMap::new());
+}
+
+/// Prepares the current thread to block.
+///
+/// The returned waiter can be woken up from now on.
+pub fn prepare_to_block() -> Waiter {
+    let (pid, id) = {
+        let thread = CURRENT_THREAD.lock();
+        (thread.pid, thread.id)
+    };
+
+    let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+
+    // This replaces the entry of a previous block that never happened.
+    WAKEUPS.lock().insert((pid, id), (token, None));
+
+    Waiter { pid, id, token }
+}
+
+/// Blocks the current thread until the waiter is woken up or the timeout
+/// expires.
+///
+/// Threads of dead processes don't return from this.
+pub fn block(waiter: Waiter, timeout: Option<Timestamp>) -> WakeReason {
+    let timer = timeout.map(|timeout| {
+        add_timer(
+            timeout,
+            Box::new(move || {
+                wake(waiter, WakeReason::TimedOut);
+            }),
+        )
+    });
+
+    let reason = loop {
+        {
+            let wakeups = WAKEUPS.lock();
+
+            match wakeups.get(&(waiter.pid, waiter.id)) {
+                Some(&(token, Some(reason))) if token == waiter.token => break reason,
+                _ => CURRENT_THREAD.lock().state = ThreadState::Blocked(waiter.token),
+            }
+        }
+
+        schedule();
+    };
+
+    if let Some(timer) = timer {
+        cancel_timer(timer);
+    }
+
+    WAKEUPS.lock().remove(&(waiter.pid, waiter.id));
+
+    if reason == WakeReason::ProcessDied {
+        // The scheduler drops the threads of dead processes once they switch out.
+        schedule();
+        unreachable!();
+    }
+
+    reason
+}
+
+/// Wakes up the given waiter with the given reason.
+///
+/// Returns false if the waiter was already woken up.
+pub fn wake(waiter: Waiter, reason: WakeReason) -> bool {
+    let key = (waiter.pid, waiter.id);
+    let mut wakeups = WAKEUPS.lock();
+
+    // Only the first wakeup counts, wakeups for other blocks are ignored.
+    if wakeups.get(&key) != Some(&(waiter.token, None)) {
+        return false;
+    }
+
+    wakeups.insert(key, (waiter.token, Some(reason)));
+
+    let is_blocked = BLOCKED_THREADS
+        .lock()
+        .get(&key)
+        .map_or(false, |thread| thread.state == ThreadState::Blocked(waiter.token));
+
+    if is_blocked {
+        let mut thread = BLOCKED_THREADS.lock().remove(&key).unwrap();
+        thread.state = ThreadState::Ready;
+        make_ready(thread);
+    }
+
+    true
+}
+
+/// Wakes up all blocked threads of the given process.
+///
+/// This is used to let the scheduler clean up the threads of dead processes.
+pub fn wake_process(pid: ProcessID) {
+    let waiters: Vec<Waiter> = BLOCKED_THREADS
+        .lock()
+        .values()
+        .filter(|thread| thread.pid == pid)
+        .filter_map(|thread| match thread.state {
+            ThreadState::Blocked(token) => Some(Waiter {
+                pid: thread.pid,
+                id: thread.id,
+                token,
+            }),
+            _ => None,
+        })
+        .collect();
+
+    for waiter in waiters {
+        wake(waiter, WakeReason::ProcessDied);
+    }
+}
+
+/// Removes the wakeups of all threads of the given process.
+pub fn remove_process_wakeups(pid: ProcessID) {
+    let mut wakeups = WAKEUPS.lock();
+
+    let keys: Vec<(ProcessID, ThreadID)> = wakeups
+        .keys()
+        .filter(|&&(wakeup_pid, _)| wakeup_pid == pid)
+        .cloned()
+        .collect();
+
+    for key in keys {
+        wakeups.remove(&key);
+    }
+}
+
+/// Stores a thread that blocked after it was switched out.
+///
+/// If it was woken up in the meantime, it becomes ready again.
+pub fn return_blocked_thread(mut thread: TCB) {
+    let token = match thread.state {
+        ThreadState::Blocked(token) => token,
+        _ => unreachable!(),
+    };
+
+    let wakeups = WAKEUPS.lock();
+
+    match wakeups.get(&(thread.pid, thread.id)) {
+        Some(&(wakeup_token, Some(_))) if wakeup_token == token => {
+            thread.state = ThreadState::Ready;
+            make_ready(thread);
+        }
+        _ => {
+            BLOCKED_THREADS.lock().insert((thread.pid, thread.id), thread);
+        }
+    }
+}
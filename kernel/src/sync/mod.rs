@@ -2,6 +2,8 @@
 
 pub mod preemptable_mutex;
 pub mod time;
+pub mod timer;
+mod timer_wheel;
 
 pub use self::preemptable_mutex::PreemptableMutex;
 use arch;
@@ -59,6 +61,18 @@ pub unsafe fn cpu_halt() {
     arch::sync::cpu_halt();
 }
 
+/// Enables preemption and halts the CPU.
+///
+/// No interrupt is handled between enabling preemption and halting, so a
+/// wake up signalled after preemption was disabled can't be missed.
+///
+/// # Safety
+/// - This should only be used by idle loops.
+#[inline(always)]
+pub unsafe fn enable_preemption_and_halt() {
+    arch::sync::enable_interrupts_and_halt();
+}
+
 /// Disables preemption and returns the previous state.
 ///
 /// # Safety
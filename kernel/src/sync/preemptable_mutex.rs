@@ -182,9 +182,12 @@ impl<'a, T: ?Sized> Drop for PreemptableMutexGuard<'a, T> {
     /// The dropping of the PreemptableMutexGuard will release the lock it was created
     /// from.
     fn drop(&mut self) {
+        // Once the lock is released, another CPU may overwrite the saved state.
+        let preemption_state = unsafe { self.preemption_state.copy() };
+
         self.lock.store(false, Ordering::Release);
         unsafe {
-            restore_preemption_state(self.preemption_state);
+            restore_preemption_state(&preemption_state);
         }
     }
 }
@@ -1,62 +1,246 @@
 //! Handles time related functionality.
 
-use arch::sync::get_current_timestamp;
+use arch::sync::get_fallback_clock_source;
 use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use spin::Once;
 
 /// Represents a unit of time.
 pub enum Time {
+    /// A nanosecond is 1/1,000,000,000 of a second.
+    Nanoseconds(i64),
     /// A microsecond (µ-second) is 1/1,000,000 of a second.
     Microseconds(i64),
     /// A millisecond is 1/1,000 of a second.
     Milliseconds(i64),
+    /// A second.
+    Seconds(i64),
 }
 
-/// The number of µ-seconds in a millisecond.
-const MILLISECOND_MULTIPLIER: i64 = 1000;
+/// The number of nanoseconds in a microsecond.
+const MICROSECOND_MULTIPLIER: i64 = 1000;
+
+/// The number of nanoseconds in a millisecond.
+const MILLISECOND_MULTIPLIER: i64 = 1000 * MICROSECOND_MULTIPLIER;
+
+/// The number of nanoseconds in a second.
+pub const NANOSECONDS_PER_SECOND: u64 = 1000 * MILLISECOND_MULTIPLIER as u64;
 
 impl Time {
-    /// Returns the µ-second representation of the time.
-    pub fn as_microseconds(self) -> Time {
+    /// Returns the nanosecond representation of the time.
+    pub fn as_nanoseconds(self) -> Time {
         match self {
-            Time::Microseconds(time) => Time::Microseconds(time),
-            Time::Milliseconds(time) => Time::Microseconds(time.saturating_mul(MILLISECOND_MULTIPLIER)),
+            Time::Nanoseconds(time) => Time::Nanoseconds(time),
+            Time::Microseconds(time) => Time::Nanoseconds(time.saturating_mul(MICROSECOND_MULTIPLIER)),
+            Time::Milliseconds(time) => Time::Nanoseconds(time.saturating_mul(MILLISECOND_MULTIPLIER)),
+            Time::Seconds(time) => {
+                Time::Nanoseconds(time.saturating_mul(NANOSECONDS_PER_SECOND as i64))
+            }
         }
     }
 }
 
 /// Represents a timestamp within the kernel.
 ///
-/// Currently that is roughly the number of µ-seconds since boot.
+/// This is the number of nanoseconds since the clock source was selected.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct Timestamp(u64);
 
 impl fmt::Debug for Timestamp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "<{}ms after boot>", self.0 / 1000)
+        write!(f, "<{}ms after boot>", self.0 / MILLISECOND_MULTIPLIER as u64)
     }
 }
 
 impl Timestamp {
-    /// Returns a new time stamp with the offset of the given amount of microseconds.
-    pub fn from_microseconds(time: u64) -> Timestamp {
+    /// Returns a time stamp from the given amount of nanoseconds.
+    pub fn from_nanoseconds(time: u64) -> Timestamp {
         Timestamp(time)
     }
 
+    /// Returns a time stamp from the given amount of microseconds.
+    pub fn from_microseconds(time: u64) -> Timestamp {
+        Timestamp(time.saturating_mul(MICROSECOND_MULTIPLIER as u64))
+    }
+
     /// Returns the current time stamp.
     pub fn get_current() -> Timestamp {
-        get_current_timestamp()
+        let source = get_clock_source();
+        let start = CLOCK_SOURCE_START.load(Ordering::Relaxed) as u64;
+
+        Timestamp(counter_to_nanoseconds(
+            source.read().wrapping_sub(start),
+            source.frequency(),
+        ))
+    }
+
+    /// Returns the number of nanoseconds this time stamp represents.
+    pub fn as_nanoseconds(&self) -> u64 {
+        self.0
     }
 
     /// Offsets the time stamp by the given amount.
     pub fn offset(&mut self, time: Time) {
-        if let Time::Microseconds(microseconds) = time.as_microseconds() {
-            let signed_stamp = self.0 as i64;
-
-            assert!(signed_stamp >= 0);
-
-            self.0 = (signed_stamp.saturating_add(microseconds)) as u64;
+        if let Time::Nanoseconds(nanoseconds) = time.as_nanoseconds() {
+            if nanoseconds >= 0 {
+                self.0 = self.0.saturating_add(nanoseconds as u64);
+            } else {
+                self.0 = self.0.saturating_sub(nanoseconds.wrapping_neg() as u64);
+            }
         } else {
             unreachable!();
         }
     }
 }
+
+/// A counter that can be used to measure time.
+pub trait ClockSource: Sync {
+    /// The name of the clock source.
+    fn name(&self) -> &'static str;
+
+    /// Reads the current value of the counter.
+    ///
+    /// The counter must not decrease and must not wrap around.
+    fn read(&self) -> u64;
+
+    /// The frequency of the counter in Hz.
+    fn frequency(&self) -> u64;
+}
+
+/// The selected clock source.
+static CLOCK_SOURCE: Once<&'static ClockSource> = Once::new();
+
+/// The counter value of the selected clock source when it was selected.
+static CLOCK_SOURCE_START: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The number of nanoseconds between the UNIX epoch and the zero timestamp.
+static REALTIME_OFFSET: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Selects the clock source used for time stamps.
+///
+/// Time stamps continue from the value of the previous clock source.
+pub fn set_clock_source(source: &'static ClockSource) {
+    assert_has_not_been_called!("The clock source should only be selected once.");
+
+    let now = Timestamp::get_current().as_nanoseconds();
+
+    // Choose the start, so that the new source continues at the current time.
+    let elapsed_counter = nanoseconds_to_counter(now, source.frequency());
+    let start = source.read().wrapping_sub(elapsed_counter);
+
+    CLOCK_SOURCE_START.store(start as usize, Ordering::Relaxed);
+    CLOCK_SOURCE.call_once(|| source);
+
+    debugln!(
+        "Using {} at {}kHz as the clock source.",
+        source.name(),
+        source.frequency() / 1000
+    );
+}
+
+/// Returns the clock source used for time stamps.
+fn get_clock_source() -> &'static ClockSource {
+    match CLOCK_SOURCE.try() {
+        Some(source) => *source,
+        None => get_fallback_clock_source(),
+    }
+}
+
+/// Sets the current time since the UNIX epoch in nanoseconds.
+pub fn set_realtime(nanoseconds_since_epoch: u64) {
+    let now = Timestamp::get_current().as_nanoseconds();
+
+    REALTIME_OFFSET.store(
+        nanoseconds_since_epoch.saturating_sub(now) as usize,
+        Ordering::Relaxed,
+    );
+}
+
+/// Returns the current time since the UNIX epoch in nanoseconds.
+pub fn get_realtime() -> u64 {
+    Timestamp::get_current().as_nanoseconds() + REALTIME_OFFSET.load(Ordering::Relaxed) as u64
+}
+
+/// Converts counter ticks at the given frequency to nanoseconds.
+///
+/// Saturates at `u64::max_value()`.
+fn counter_to_nanoseconds(ticks: u64, frequency: u64) -> u64 {
+    // Split the calculation to avoid overflows.
+    (ticks / frequency)
+        .saturating_mul(NANOSECONDS_PER_SECOND)
+        .saturating_add(ticks % frequency * NANOSECONDS_PER_SECOND / frequency)
+}
+
+/// Converts nanoseconds to counter ticks at the given frequency.
+///
+/// Saturates at `u64::max_value()`.
+fn nanoseconds_to_counter(nanoseconds: u64, frequency: u64) -> u64 {
+    (nanoseconds / NANOSECONDS_PER_SECOND)
+        .saturating_mul(frequency)
+        .saturating_add(nanoseconds % NANOSECONDS_PER_SECOND * frequency / NANOSECONDS_PER_SECOND)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The frequency of the HPET in QEMU, which doesn't divide a second.
+    const HPET_FREQUENCY: u64 = 14_318_180;
+
+    /// A TSC frequency that doesn't divide a second either.
+    const TSC_FREQUENCY: u64 = 3_000_000_007;
+
+    /// Tests converting counter values to nanoseconds.
+    #[test]
+    fn test_counter_to_nanoseconds() {
+        assert_eq!(counter_to_nanoseconds(1, HPET_FREQUENCY), 69);
+        assert_eq!(counter_to_nanoseconds(HPET_FREQUENCY, HPET_FREQUENCY), 1_000_000_000);
+        assert_eq!(
+            counter_to_nanoseconds(12_345_678_901_234, HPET_FREQUENCY),
+            862_238_001_005_295
+        );
+        assert_eq!(
+            counter_to_nanoseconds(u64::max_value(), 1_000_000_000),
+            u64::max_value()
+        );
+        assert_eq!(
+            counter_to_nanoseconds(u64::max_value(), TSC_FREQUENCY),
+            6_148_914_676_889_049_625
+        );
+        assert_eq!(
+            counter_to_nanoseconds(u64::max_value(), HPET_FREQUENCY),
+            u64::max_value()
+        );
+    }
+
+    /// Tests converting nanoseconds to counter values.
+    #[test]
+    fn test_nanoseconds_to_counter() {
+        assert_eq!(nanoseconds_to_counter(1, HPET_FREQUENCY), 0);
+        assert_eq!(nanoseconds_to_counter(1_000_000_001, HPET_FREQUENCY), HPET_FREQUENCY);
+        assert_eq!(
+            nanoseconds_to_counter(123_456_789_012, TSC_FREQUENCY),
+            370_370_367_900
+        );
+        assert_eq!(
+            nanoseconds_to_counter(u64::max_value(), TSC_FREQUENCY),
+            u64::max_value()
+        );
+    }
+
+    /// Tests that converting back and forth loses less than a counter tick.
+    #[test]
+    fn test_round_trip() {
+        for &frequency in [HPET_FREQUENCY, 1_000_000_000, TSC_FREQUENCY].iter() {
+            let tick_length = NANOSECONDS_PER_SECOND / frequency + 1;
+
+            for &nanoseconds in [0, 1, 999_999_999, 1 << 40, 1 << 62].iter() {
+                let counter = nanoseconds_to_counter(nanoseconds, frequency);
+                let round_trip = counter_to_nanoseconds(counter, frequency);
+
+                assert!(round_trip <= nanoseconds);
+                assert!(nanoseconds - round_trip <= tick_length);
+            }
+        }
+    }
+}
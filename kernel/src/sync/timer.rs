@@ -0,0 +1,215 @@
+//! Provides kernel timers that call a function once they expire.
+
+use super::PreemptableMutex;
+use super::time::{Time, Timestamp};
+use super::timer_wheel::TimerWheel;
+use alloc::Vec;
+use alloc::boxed::Box;
+use arch::time::{is_tickless, set_timer_deadline};
+use core::mem;
+use multitasking::{create_kernel_thread, CURRENT_THREAD};
+use multitasking::scheduler::{READY_LIST, SLEEPING_LIST};
+use multitasking::wait::{block, prepare_to_block, wake, WakeReason, Waiter};
+
+pub use super::timer_wheel::TimerId;
+
+/// The bits of a nanosecond time stamp that make up a wheel tick (~1ms).
+const GRANULARITY_SHIFT: u32 = 20;
+
+/// The time in milliseconds a thread runs before other ready threads are
+/// scheduled.
+const TIME_SLICE_MS: i64 = 150;
+
+/// The function that is called once a timer expires.
+pub type TimerCallback = Box<FnMut() + Send>;
+
+lazy_static! {
+    /// The pending kernel timers.
+    static ref TIMERS: PreemptableMutex<TimerWheel<TimerCallback>> =
+        PreemptableMutex::new(TimerWheel::new(GRANULARITY_SHIFT));
+
+    /// The functions of expired timers that the timer thread still has to call.
+    static ref EXPIRED_TIMERS: PreemptableMutex<Vec<TimerCallback>> =
+        PreemptableMutex::new(Vec::new());
+
+    /// The timer thread, while it waits for timers to expire.
+    static ref TIMER_THREAD: PreemptableMutex<Option<Waiter>> = PreemptableMutex::new(None);
+}
+
+/// Starts the thread that calls the functions of expired timers.
+///
+/// This has to be called while the idle address space is active.
+pub fn init() {
+    assert_has_not_been_called!("The timer thread should only be started once.");
+
+    create_kernel_thread(timer_thread, i32::max_value());
+}
+
+/// Calls the given function once the given time has been reached.
+///
+/// The function is called from the timer thread, shortly after the timer
+/// interrupt noticed the expiry.
+pub fn add_timer(expiry: Timestamp, callback: TimerCallback) -> TimerId {
+    let id = TIMERS.lock().insert(expiry.as_nanoseconds(), callback);
+
+    reprogram();
+
+    id
+}
+
+/// Cancels the given timer.
+///
+/// Returns false if the timer already expired.
+pub fn cancel_timer(id: TimerId) -> bool {
+    TIMERS.lock().remove(id).is_some()
+}
+
+/// Returns the time the next timer expires at, if there is one.
+pub fn next_expiry() -> Option<Timestamp> {
+    TIMERS
+        .lock()
+        .next_expiry()
+        .map(Timestamp::from_nanoseconds)
+}
+
+/// Hands the functions of all expired timers to the timer thread.
+///
+/// This is called from the timer interrupt.
+pub fn run_expired_timers() {
+    let now = Timestamp::get_current();
+
+    let mut expired = TIMERS.lock().advance(now.as_nanoseconds());
+    if expired.is_empty() {
+        return;
+    }
+
+    EXPIRED_TIMERS.lock().append(&mut expired);
+
+    if let Some(waiter) = TIMER_THREAD.lock().take() {
+        wake(waiter, WakeReason::Woken);
+    }
+}
+
+/// Calls the functions of expired timers outside of the timer interrupt.
+fn timer_thread() -> ! {
+    unsafe {
+        ::sync::enable_preemption();
+    }
+
+    loop {
+        let waiter = prepare_to_block();
+
+        let expired = {
+            let mut expired_timers = EXPIRED_TIMERS.lock();
+            if expired_timers.is_empty() {
+                // The timer interrupt takes both locks in the same order, so
+                // no expiry can slip in before the waiter is registered.
+                *TIMER_THREAD.lock() = Some(waiter);
+            }
+
+            mem::replace(&mut *expired_timers, Vec::new())
+        };
+
+        if expired.is_empty() {
+            block(waiter, None);
+        }
+
+        // Don't hold any lock while calling the functions, they might add timers.
+        for mut callback in expired {
+            callback();
+        }
+    }
+}
+
+/// Programs the timer of the current CPU for the next event.
+///
+/// The next event is the earliest of the next sleeping thread to wake up, the
+/// next kernel timer and the end of the time slice, if another thread is ready
+/// to preempt the current one. If there is no event, the timer is stopped, so
+/// idle CPUs stay halted.
+pub fn reprogram() {
+    if !is_tickless() {
+        return;
+    }
+
+    let next_wakeup = SLEEPING_LIST
+        .lock()
+        .peek()
+        .map(|thread| thread.get_sleep_time());
+    let next_event = earliest(next_expiry(), next_wakeup);
+
+    let next_priority = READY_LIST.lock().peek().map(|thread| thread.priority);
+    let current_priority = CURRENT_THREAD.lock().priority;
+
+    set_timer_deadline(next_deadline(
+        next_event,
+        next_priority,
+        current_priority,
+        Timestamp::get_current(),
+    ));
+}
+
+/// Returns the time the timer has to expire at for the given state.
+///
+/// The current thread is only preempted at the end of its time slice, if the
+/// next ready thread has at least the same priority.
+fn next_deadline(
+    next_event: Option<Timestamp>,
+    next_priority: Option<i32>,
+    current_priority: i32,
+    now: Timestamp,
+) -> Option<Timestamp> {
+    let slice_needed = next_priority.map_or(false, |priority| priority >= current_priority);
+
+    if slice_needed {
+        let mut slice_end = now;
+        slice_end.offset(Time::Milliseconds(TIME_SLICE_MS));
+        earliest(next_event, Some(slice_end))
+    } else {
+        next_event
+    }
+}
+
+/// Returns the earlier of the given times.
+fn earliest(first: Option<Timestamp>, second: Option<Timestamp>) -> Option<Timestamp> {
+    match (first, second) {
+        (Some(first), Some(second)) => Some(if first < second { first } else { second }),
+        (first, None) => first,
+        (None, second) => second,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that a thread that becomes ready preempts the running thread at
+    /// the end of its time slice.
+    #[test]
+    fn test_ready_thread_preempts() {
+        let now = Timestamp::from_nanoseconds(1_000_000_000);
+        let mut slice_end = now;
+        slice_end.offset(Time::Milliseconds(TIME_SLICE_MS));
+
+        // Only the idle thread is ready, so the timer can stay off.
+        assert_eq!(next_deadline(None, Some(i32::min_value()), 1, now), None);
+
+        assert_eq!(next_deadline(None, Some(1), 1, now), Some(slice_end));
+        assert_eq!(next_deadline(None, Some(2), 1, now), Some(slice_end));
+        assert_eq!(next_deadline(None, Some(0), 1, now), None);
+    }
+
+    /// Tests that earlier events aren't delayed by the time slice.
+    #[test]
+    fn test_earliest_event() {
+        let now = Timestamp::from_nanoseconds(1_000_000_000);
+        let soon = Timestamp::from_nanoseconds(1_000_001_000);
+        let late = Timestamp::from_nanoseconds(9_000_000_000);
+        let mut slice_end = now;
+        slice_end.offset(Time::Milliseconds(TIME_SLICE_MS));
+
+        assert_eq!(next_deadline(Some(soon), Some(1), 1, now), Some(soon));
+        assert_eq!(next_deadline(Some(late), Some(1), 1, now), Some(slice_end));
+        assert_eq!(next_deadline(Some(late), None, 1, now), Some(late));
+    }
+}
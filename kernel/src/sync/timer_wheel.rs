@@ -0,0 +1,294 @@
+//! A hierarchical timer wheel.
+//!
+//! Each level has `SLOTS_PER_LEVEL` slots, each slot of a level covers the
+//! range of a whole lower level. Timers are moved ("cascaded") into the lower
+//! levels once their range is reached.
+
+use alloc::Vec;
+use core::cmp::min;
+use core::mem::replace;
+
+/// The number of slots per level, as a power of two.
+const SLOT_BITS: u32 = 6;
+
+/// The number of slots per level.
+const SLOTS_PER_LEVEL: usize = 1 << SLOT_BITS;
+
+/// The number of levels.
+const LEVELS: usize = 6;
+
+/// Identifies a timer in the wheel.
+pub type TimerId = u64;
+
+/// A timer in the wheel.
+struct Entry<T> {
+    /// The time at which the timer expires.
+    expiry: u64,
+    /// The ID of the timer.
+    id: TimerId,
+    /// The value that is returned when the timer expires.
+    value: T,
+}
+
+/// A hierarchical timer wheel.
+///
+/// Times are given in an arbitrary unit, `granularity_shift` determines how
+/// many bits of the time make up a tick of the lowest level.
+pub struct TimerWheel<T> {
+    /// The slots of all levels.
+    levels: Vec<Vec<Vec<Entry<T>>>>,
+    /// The next tick that is processed.
+    current_tick: u64,
+    /// The bits of a time that make up a tick.
+    granularity_shift: u32,
+    /// The ID for the next timer.
+    next_id: TimerId,
+    /// The number of timers in the wheel.
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    /// Creates a new empty timer wheel.
+    pub fn new(granularity_shift: u32) -> TimerWheel<T> {
+        let mut levels = Vec::with_capacity(LEVELS);
+
+        for _ in 0..LEVELS {
+            let mut slots = Vec::with_capacity(SLOTS_PER_LEVEL);
+            for _ in 0..SLOTS_PER_LEVEL {
+                slots.push(Vec::new());
+            }
+            levels.push(slots);
+        }
+
+        TimerWheel {
+            levels,
+            current_tick: 0,
+            granularity_shift,
+            next_id: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of timers in the wheel.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there are no timers in the wheel.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Inserts a timer expiring at the given time.
+    pub fn insert(&mut self, expiry: u64, value: T) -> TimerId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.len += 1;
+
+        self.insert_entry(Entry { expiry, id, value });
+
+        id
+    }
+
+    /// Removes the timer with the given ID, returning its value.
+    pub fn remove(&mut self, id: TimerId) -> Option<T> {
+        for level in self.levels.iter_mut() {
+            for slot in level.iter_mut() {
+                if let Some(index) = slot.iter().position(|entry| entry.id == id) {
+                    self.len -= 1;
+                    return Some(slot.swap_remove(index).value);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the earliest expiry time of all timers.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.levels
+            .iter()
+            .flat_map(|level| level.iter())
+            .flat_map(|slot| slot.iter())
+            .map(|entry| entry.expiry)
+            .min()
+    }
+
+    /// Advances the wheel to the given time, returning the expired timers.
+    ///
+    /// Only the ticks at which timers expire or are cascaded are visited, so
+    /// this is cheap even after a long time without advancing.
+    pub fn advance(&mut self, now: u64) -> Vec<T> {
+        let mut expired = Vec::new();
+        let now_tick = now >> self.granularity_shift;
+
+        while self.current_tick <= now_tick {
+            self.current_tick = match self.next_event_tick() {
+                Some(tick) => min(tick, now_tick),
+                None => now_tick,
+            };
+
+            let tick = self.current_tick;
+            self.cascade(tick);
+
+            let slot_index = tick as usize % SLOTS_PER_LEVEL;
+            let slot = replace(&mut self.levels[0][slot_index], Vec::new());
+
+            for entry in slot {
+                if entry.expiry <= now {
+                    self.len -= 1;
+                    expired.push(entry.value);
+                } else {
+                    // Only possible in the slot of the current tick.
+                    self.levels[0][slot_index].push(entry);
+                }
+            }
+
+            if tick == now_tick {
+                break;
+            }
+
+            self.current_tick += 1;
+        }
+
+        expired
+    }
+
+    /// Returns the first tick, starting at the current one, at which a slot
+    /// with timers is reached on any level.
+    ///
+    /// Slots of the higher levels are reached when their timers are cascaded.
+    fn next_event_tick(&self) -> Option<u64> {
+        let mut next_tick = None;
+
+        for (level, slots) in self.levels.iter().enumerate() {
+            let level_shift = SLOT_BITS * level as u32;
+
+            // The first tick at which a slot of this level is reached.
+            let start = ((self.current_tick + (1 << level_shift) - 1) >> level_shift)
+                << level_shift;
+            let start_index = (start >> level_shift) as usize;
+
+            let offset = (0..SLOTS_PER_LEVEL)
+                .find(|offset| !slots[(start_index + offset) % SLOTS_PER_LEVEL].is_empty());
+
+            if let Some(offset) = offset {
+                let tick = start + ((offset as u64) << level_shift);
+
+                next_tick = Some(next_tick.map_or(tick, |next_tick| min(next_tick, tick)));
+            }
+        }
+
+        next_tick
+    }
+
+    /// Moves the timers of the higher levels that are reached at the given
+    /// tick into the lower levels.
+    fn cascade(&mut self, tick: u64) {
+        // Cascade the highest levels first, so their timers can move further down.
+        for level in (1..LEVELS).rev() {
+            let level_shift = SLOT_BITS * level as u32;
+
+            if tick & ((1 << level_shift) - 1) != 0 {
+                continue;
+            }
+
+            let slot_index = (tick >> level_shift) as usize % SLOTS_PER_LEVEL;
+            let slot = replace(&mut self.levels[level][slot_index], Vec::new());
+
+            for entry in slot {
+                self.insert_entry(entry);
+            }
+        }
+    }
+
+    /// Inserts the given entry in the right slot.
+    fn insert_entry(&mut self, entry: Entry<T>) {
+        let max_delta = (1u64 << (SLOT_BITS * LEVELS as u32)) - 1;
+
+        let tick = entry.expiry >> self.granularity_shift;
+        let tick = if tick < self.current_tick {
+            // Already expired timers are handled at the next advance.
+            self.current_tick
+        } else if tick - self.current_tick > max_delta {
+            self.current_tick + max_delta
+        } else {
+            tick
+        };
+
+        let delta = tick - self.current_tick;
+
+        let mut level = 0;
+        while level < LEVELS - 1 && delta >= 1 << (SLOT_BITS * (level as u32 + 1)) {
+            level += 1;
+        }
+
+        let slot_index = (tick >> (SLOT_BITS * level as u32)) as usize % SLOTS_PER_LEVEL;
+        self.levels[level][slot_index].push(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that timers expire in the right order.
+    #[test]
+    fn test_expiry() {
+        let mut wheel = TimerWheel::new(0);
+        wheel.insert(5, 5);
+        wheel.insert(100, 100);
+        wheel.insert(10000, 10000);
+
+        assert_eq!(wheel.next_expiry(), Some(5));
+        assert!(wheel.advance(4).is_empty());
+        assert_eq!(wheel.advance(5), [5]);
+        assert!(wheel.advance(99).is_empty());
+        assert_eq!(wheel.advance(150), [100]);
+        assert!(wheel.advance(9999).is_empty());
+        assert_eq!(wheel.advance(10000), [10000]);
+        assert!(wheel.is_empty());
+    }
+
+    /// Tests that timers within a tick only expire once their time is reached.
+    #[test]
+    fn test_granularity() {
+        let mut wheel = TimerWheel::new(4);
+        wheel.insert(0x25, 1);
+        wheel.insert(0x2a, 2);
+
+        assert_eq!(wheel.advance(0x26), [1]);
+        assert_eq!(wheel.advance(0x2a), [2]);
+    }
+
+    /// Tests that removed timers don't expire.
+    #[test]
+    fn test_remove() {
+        let mut wheel = TimerWheel::new(0);
+        let id = wheel.insert(5000, 1);
+        wheel.insert(6000, 2);
+
+        assert_eq!(wheel.remove(id), Some(1));
+        assert_eq!(wheel.remove(id), None);
+        assert_eq!(wheel.advance(7000), [2]);
+    }
+
+    /// Tests that timers on all levels expire after a long jump.
+    #[test]
+    fn test_long_jump() {
+        let mut wheel = TimerWheel::new(0);
+        wheel.insert(70, 70);
+        wheel.insert(5000, 5000);
+        wheel.insert(300_000, 300_000);
+        wheel.insert(1 << 30, 1 << 30);
+
+        assert_eq!(wheel.advance((1 << 30) - 1), [70, 5000, 300_000]);
+        assert_eq!(wheel.next_expiry(), Some(1 << 30));
+        assert_eq!(wheel.advance(1 << 40), [1 << 30]);
+        assert!(wheel.is_empty());
+
+        wheel.insert((1 << 40) + 3, 3);
+        assert!(wheel.advance((1 << 40) + 2).is_empty());
+        assert_eq!(wheel.advance((1 << 40) + 3), [3]);
+    }
+}
@@ -1,12 +1,23 @@
 //! This module handles system calls.
 
+use alloc::String;
 use arch::schedule;
 use arch;
+use boring_core::pci::PciDeviceInfo;
+use boring_core::syscall::*;
+use core::cmp::min;
+use drivers::framebuffer;
+use drivers::pci;
 use elf;
-use memory::VirtualAddress;
-use multitasking::{get_current_process, CURRENT_THREAD, TCB};
-use multitasking::scheduler::READY_LIST;
-use sync::time::{Time, Timestamp};
+use initramfs;
+use memory::{PageFlags, VirtualAddress};
+use memory::user::{UserPtr, UserSlice};
+use multitasking::{get_current_process, CURRENT_THREAD, INIT_PROCESS_ID, TCB};
+use multitasking::futex::{futex_wait, futex_wake, FutexError};
+use multitasking::message::receive_message;
+use multitasking::user_timer::{create_timer, delete_timer, wait_timer, TimerDelivery, TimerError};
+use multitasking::scheduler::make_ready;
+use sync::time::{get_realtime, Time, Timestamp};
 
 /// This function accepts the syscalls and calls the corresponding handlers.
 pub fn syscall_handler(
@@ -19,16 +30,52 @@ pub fn syscall_handler(
     arg6: u64,
 ) -> i64 {
     match num {
-        0 => print_char(arg1 as u8 as char),
-        1 => kill_process(),
-        2 => return_pid(),
-        3 => exec(arg1 as VirtualAddress, arg2 as usize),
-        4 => sleep(arg1),
-        5 => create_thread(arg1 as VirtualAddress, arg2, arg3, arg4, arg5, arg6),
-        6 => kill_thread(),
-        7 => serial_char(arg1 as u8),
-        8 => panic_char(arg1 as u8),
-        9 => register_kb_interrupt(arg1 as VirtualAddress, arg2),
+        PRINT_CHAR_SYSCALL_NUM => print_char(arg1 as u8 as char),
+        EXIT_SYSCALL_NUM => kill_process(),
+        GET_PID_SYSCALL_NUM => return_pid(),
+        EXEC_SYSCALL_NUM => exec(arg1 as VirtualAddress, arg2 as usize),
+        SLEEP_SYSCALL_NUM => sleep(arg1),
+        NEW_THREAD_SYSCALL_NUM => {
+            create_thread(arg1 as VirtualAddress, arg2, arg3, arg4, arg5, arg6)
+        }
+        KILL_THREAD_SYSCALL_NUM => kill_thread(),
+        SERIAL_CHAR_SYSCALL_NUM => serial_char(arg1 as u8),
+        PANIC_SERIAL_CHAR_SYSCALL_NUM => panic_char(arg1 as u8),
+        REGISTER_KB_INTERRUPT_SYSCALL_NUM => register_kb_interrupt(arg1 as VirtualAddress, arg2),
+        PCI_DEVICE_INFO_SYSCALL_NUM => pci_device_info(arg1 as usize, arg2 as VirtualAddress),
+        SHUTDOWN_SYSCALL_NUM => shutdown(arg1 as u8),
+        REBOOT_SYSCALL_NUM => reboot(),
+        CLOCK_GETTIME_SYSCALL_NUM => clock_gettime(arg1),
+        FUTEX_WAIT_SYSCALL_NUM => futex_wait_syscall(arg1 as VirtualAddress, arg2 as u32, arg3),
+        FUTEX_WAKE_SYSCALL_NUM => futex_wake_syscall(arg1 as VirtualAddress, arg2 as usize),
+        RECEIVE_SYSCALL_NUM => receive(arg1 as VirtualAddress, arg2),
+        TIMER_CREATE_SYSCALL_NUM => timer_create(arg1, arg2, arg3),
+        TIMER_WAIT_SYSCALL_NUM => timer_wait(arg1, arg2),
+        TIMER_DELETE_SYSCALL_NUM => timer_delete(arg1),
+        MAP_FRAMEBUFFER_SYSCALL_NUM => map_framebuffer(arg1 as VirtualAddress),
+        FRAMEBUFFER_MODE_SYSCALL_NUM => framebuffer_mode(arg1 as usize, arg2 as VirtualAddress),
+        SET_FRAMEBUFFER_MODE_SYSCALL_NUM => {
+            set_framebuffer_mode(arg1 as u32, arg2 as u32, arg3 as u8)
+        }
+        FLIP_FRAMEBUFFER_SYSCALL_NUM => flip_framebuffer(arg1 as u32),
+        SCROLL_CONSOLE_SYSCALL_NUM => scroll_console(arg1 as i64 as isize),
+        FILE_SIZE_SYSCALL_NUM => file_size(arg1 as VirtualAddress, arg2 as usize),
+        READ_FILE_SYSCALL_NUM => read_file(
+            arg1 as VirtualAddress,
+            arg2 as usize,
+            arg3 as VirtualAddress,
+            arg4 as usize,
+            arg5,
+        ),
+        MAP_FILE_SYSCALL_NUM => map_file(
+            arg1 as VirtualAddress,
+            arg2 as usize,
+            arg3,
+            arg4 as VirtualAddress,
+            arg5 as usize,
+            arg6,
+        ),
+        MAP_MEMORY_SYSCALL_NUM => map_memory(arg1 as VirtualAddress, arg2 as usize, arg3),
         _ => unknown_syscall(num),
     }
 }
@@ -49,8 +96,12 @@ fn panic_char(character: u8) -> i64 {
 }
 
 fn kill_process() -> i64 {
+    let pid = CURRENT_THREAD.lock().pid;
     get_current_process().kill();
 
+    // Let the scheduler clean up blocked threads.
+    ::multitasking::wait::wake_process(pid);
+
     schedule();
     0
 }
@@ -61,28 +112,17 @@ fn return_pid() -> i64 {
 }
 
 fn exec(name_ptr: VirtualAddress, name_length: usize) -> i64 {
-    let name_ptr_valid = {
-        let pcb = get_current_process();
-
-        pcb.address_space.contains_range(name_ptr, name_length)
+    let name = match user_string(name_ptr, name_length) {
+        Ok(name) => name,
+        Err(error) => return error,
     };
 
-    if name_ptr_valid {
-        let name = from_raw_str!(name_ptr, name_length);
-
-        if let Ok(name) = name {
-            let process_id = elf::process_from_initramfs_file(name);
+    let process_id = elf::process_from_initramfs_file(&name);
 
-            if let Ok(process_id) = process_id {
-                assert!(process_id as i64 > 0, "Process ID too large.");
+    if let Ok(process_id) = process_id {
+        assert!(process_id as i64 > 0, "Process ID too large.");
 
-                process_id as i64
-            } else {
-                -1
-            }
-        } else {
-            -1
-        }
+        process_id as i64
     } else {
         -1
     }
@@ -100,27 +140,32 @@ fn create_thread(
     let mut pcb = get_current_process();
     let id = pcb.find_thread_id();
 
-    match id {
-        Some(id) => {
-            let thread = TCB::in_process_with_arguments(
-                pid,
-                id,
-                start_address,
-                &mut pcb,
-                arg1,
-                arg2,
-                arg3,
-                arg4,
-                arg5,
-            );
+    let id = match id {
+        Some(id) => id,
+        None => return -1,
+    };
+
+    let thread = TCB::in_process_with_arguments(
+        pid,
+        id,
+        start_address,
+        &mut pcb,
+        arg1,
+        arg2,
+        arg3,
+        arg4,
+        arg5,
+    );
 
+    match thread {
+        Some(thread) => {
             pcb.add_thread(id);
 
-            READY_LIST.lock().push(thread);
+            make_ready(thread);
 
             id as i64
         }
-        None => -1,
+        None => INVALID_ARGUMENT,
     }
 }
 
@@ -131,6 +176,49 @@ fn register_kb_interrupt(start_address: VirtualAddress, arg1: u64) -> i64 {
     0
 }
 
+fn pci_device_info(index: usize, info_ptr: VirtualAddress) -> i64 {
+    let device = match pci::get_device_by_index(index) {
+        Some(device) => device,
+        None => return -1,
+    };
+
+    let info = PciDeviceInfo {
+        bus: device.bus,
+        slot: device.device_id,
+        function: device.function,
+        owned: device.is_owned() as u8,
+        vendor_id: device.header.vendor_id,
+        device_id: device.header.device_id,
+        class_code: device.header.class_code,
+        subclass: device.header.subclass,
+        prog_if: device.header.prog_if,
+        revision_id: device.header.revision_id,
+    };
+
+    match UserPtr::new(info_ptr).write(info) {
+        Ok(()) => 0,
+        Err(_) => BAD_ADDRESS,
+    }
+}
+
+fn shutdown(status: u8) -> i64 {
+    if !is_init_process() {
+        return PERMISSION_DENIED;
+    }
+
+    debugln!("Shutting down with status {}.", status);
+    arch::power::shutdown(status)
+}
+
+fn reboot() -> i64 {
+    if !is_init_process() {
+        return PERMISSION_DENIED;
+    }
+
+    debugln!("Rebooting.");
+    arch::power::reboot()
+}
+
 fn kill_thread() -> i64 {
     CURRENT_THREAD.lock().kill();
 
@@ -150,6 +238,539 @@ fn sleep(ms: u64) -> i64 {
     0
 }
 
+/// The ID of the monotonic clock.
+const CLOCK_MONOTONIC: u64 = 0;
+
+/// The ID of the realtime clock.
+const CLOCK_REALTIME: u64 = 1;
+
+fn clock_gettime(clock_id: u64) -> i64 {
+    match clock_id {
+        CLOCK_MONOTONIC => Timestamp::get_current().as_nanoseconds() as i64,
+        CLOCK_REALTIME => get_realtime() as i64,
+        _ => -1,
+    }
+}
+
+/// The framebuffer information passed to userspace.
+#[repr(C)]
+struct UserFramebufferInfo {
+    address: u64,
+    width: u32,
+    height: u32,
+    pitch: u32,
+    bpp: u8,
+    red_position: u8,
+    red_size: u8,
+    green_position: u8,
+    green_size: u8,
+    blue_position: u8,
+    blue_size: u8,
+    reserved: u8,
+    pages: u32,
+}
+
+/// Maps all pages of the framebuffer into the process.
+///
+/// The mapping stays valid across mode switches, but the information has to
+/// be queried again. It is replaced if the framebuffer memory grew.
+fn map_framebuffer(info_ptr: VirtualAddress) -> i64 {
+    use memory::PAGE_SIZE;
+
+    let framebuffer = match framebuffer::get_framebuffer() {
+        Some(framebuffer) => framebuffer,
+        None => return INVALID_ARGUMENT,
+    };
+    let info = framebuffer.info;
+
+    let info_ptr = UserPtr::new(info_ptr);
+
+    if !info_ptr.is_writable() {
+        return BAD_ADDRESS;
+    }
+
+    let page_offset = info.address % PAGE_SIZE;
+    let length = page_offset + framebuffer.memory_size;
+
+    let mut pcb = get_current_process();
+    let area_base = pcb.framebuffer_area_base;
+
+    // Mapping twice just returns the existing mapping, unless a mode switch
+    // made the framebuffer larger than it.
+    if !pcb.address_space.contains_range(area_base, length) {
+        // The framebuffer memory isn't freed, so stale accesses are harmless.
+        unsafe {
+            pcb.address_space.remove_segment(area_base);
+        }
+
+        let mapped = pcb.address_space.map_physical_area(
+            area_base,
+            info.address - page_offset,
+            length,
+            PageFlags::READABLE | PageFlags::WRITABLE | PageFlags::USER_ACCESSIBLE,
+        );
+
+        if !mapped {
+            return INVALID_ARGUMENT;
+        }
+    }
+
+    let user_info = UserFramebufferInfo {
+        address: (area_base + page_offset) as u64,
+        width: info.width,
+        height: info.height,
+        pitch: info.pitch,
+        bpp: info.bpp,
+        red_position: info.red.position,
+        red_size: info.red.size,
+        green_position: info.green.position,
+        green_size: info.green.size,
+        blue_position: info.blue.position,
+        blue_size: info.blue.size,
+        reserved: 0,
+        pages: framebuffer.pages,
+    };
+
+    // Accessing user memory needs the process lock.
+    drop(pcb);
+
+    match info_ptr.write(user_info) {
+        Ok(()) => 0,
+        Err(_) => BAD_ADDRESS,
+    }
+}
+
+/// A display mode passed to userspace.
+#[repr(C)]
+struct UserFramebufferMode {
+    width: u32,
+    height: u32,
+    bpp: u32,
+}
+
+/// Writes the display mode with the given index.
+fn framebuffer_mode(index: usize, mode_ptr: VirtualAddress) -> i64 {
+    let mode = match framebuffer::modes().get(index) {
+        Some(mode) => *mode,
+        None => return INVALID_ARGUMENT,
+    };
+
+    let user_mode = UserFramebufferMode {
+        width: mode.width,
+        height: mode.height,
+        bpp: mode.bpp as u32,
+    };
+
+    match UserPtr::new(mode_ptr).write(user_mode) {
+        Ok(()) => 0,
+        Err(_) => BAD_ADDRESS,
+    }
+}
+
+fn set_framebuffer_mode(width: u32, height: u32, bpp: u8) -> i64 {
+    if !is_init_process() {
+        return PERMISSION_DENIED;
+    }
+
+    let mode = framebuffer::Mode { width, height, bpp };
+
+    match framebuffer::set_mode(mode) {
+        Ok(()) => 0,
+        Err(_) => INVALID_ARGUMENT,
+    }
+}
+
+fn flip_framebuffer(page: u32) -> i64 {
+    if !is_init_process() {
+        return PERMISSION_DENIED;
+    }
+
+    match framebuffer::flip(page) {
+        Ok(()) => 0,
+        Err(_) => INVALID_ARGUMENT,
+    }
+}
+
+fn scroll_console(lines: isize) -> i64 {
+    if !is_init_process() {
+        return PERMISSION_DENIED;
+    }
+
+    ::drivers::console::scroll(lines);
+    0
+}
+
+/// Returns true if the current process is the init process.
+///
+/// Only the init process may make syscalls that affect the whole machine.
+fn is_init_process() -> bool {
+    CURRENT_THREAD.lock().pid == INIT_PROCESS_ID
+}
+
+/// The size of the chunks in which files are copied to userspace.
+const FILE_CHUNK_SIZE: usize = 4096;
+
+/// Copies the string at the given address out of the current process.
+///
+/// Returns the error code for the syscall if that fails.
+fn user_string(address: VirtualAddress, length: usize) -> Result<String, i64> {
+    let bytes = UserSlice::new(address, length)
+        .read_to_vec()
+        .map_err(|_| BAD_ADDRESS)?;
+
+    String::from_utf8(bytes).map_err(|_| INVALID_ARGUMENT)
+}
+
+/// Returns the name of the file in the current process.
+fn file_name(name_ptr: VirtualAddress, name_length: usize) -> Result<String, i64> {
+    if name_length == 0 {
+        return Err(INVALID_ARGUMENT);
+    }
+
+    user_string(name_ptr, name_length)
+}
+
+/// Returns the size of the initramfs file with the given name.
+fn file_size(name_ptr: VirtualAddress, name_length: usize) -> i64 {
+    let name = match file_name(name_ptr, name_length) {
+        Ok(name) => name,
+        Err(error) => return error,
+    };
+
+    match initramfs::open(&name) {
+        Ok(mut file) => file.len() as i64,
+        Err(_) => NOT_FOUND,
+    }
+}
+
+/// Reads the initramfs file with the given name into the buffer.
+///
+/// Reading starts at `offset` and ends at the end of the buffer or the file.
+/// Returns the number of bytes read.
+fn read_file(
+    name_ptr: VirtualAddress,
+    name_length: usize,
+    buffer_ptr: VirtualAddress,
+    buffer_length: usize,
+    offset: u64,
+) -> i64 {
+    let name = match file_name(name_ptr, name_length) {
+        Ok(name) => name,
+        Err(error) => return error,
+    };
+
+    let buffer_slice = UserSlice::new(buffer_ptr, buffer_length);
+
+    let mut file = match initramfs::open(&name) {
+        Ok(file) => file,
+        Err(_) => return NOT_FOUND,
+    };
+
+    let file_length = file.len();
+    if offset >= file_length {
+        return 0;
+    }
+
+    let length = if ((file_length - offset) as usize) < buffer_length {
+        (file_length - offset) as usize
+    } else {
+        buffer_length
+    };
+
+    let mut buffer = [0u8; FILE_CHUNK_SIZE];
+    let mut copied = 0;
+
+    while copied < length {
+        let chunk_length = if length - copied < FILE_CHUNK_SIZE {
+            length - copied
+        } else {
+            FILE_CHUNK_SIZE
+        };
+
+        let chunk = &mut buffer[..chunk_length];
+        if file.read_at(chunk, offset + copied as u64).is_err() {
+            return INVALID_ARGUMENT;
+        }
+
+        if buffer_slice.write_at(copied, chunk).is_err() {
+            return BAD_ADDRESS;
+        }
+
+        copied += chunk_length;
+    }
+
+    copied as i64
+}
+
+/// The mapping flag that allows executing the mapped memory.
+const MAP_EXECUTABLE: u64 = 0x1;
+
+/// The mapping flag that allows writing to the mapped memory.
+const MAP_WRITABLE: u64 = 0x2;
+
+/// The mapping flag that allows reading the mapped memory.
+const MAP_READABLE: u64 = 0x4;
+
+/// Converts the flags of a mapping syscall to page flags.
+fn mapping_flags(flags: u64) -> Result<PageFlags, i64> {
+    if flags & !(MAP_EXECUTABLE | MAP_WRITABLE | MAP_READABLE) != 0 {
+        return Err(INVALID_ARGUMENT);
+    }
+
+    let mut page_flags = PageFlags::USER_ACCESSIBLE;
+
+    if flags & MAP_READABLE != 0 {
+        page_flags |= PageFlags::READABLE;
+    }
+
+    if flags & MAP_WRITABLE != 0 {
+        page_flags |= PageFlags::WRITABLE;
+    }
+
+    if flags & MAP_EXECUTABLE != 0 {
+        page_flags |= PageFlags::EXECUTABLE;
+    }
+
+    Ok(page_flags)
+}
+
+/// Checks that the area to map starts at a page boundary, isn't empty and lies
+/// within the interpreter and library areas.
+///
+/// The kernel places the thread stacks and the framebuffer above these areas,
+/// so processes can't take their addresses.
+fn is_mappable_area(address: VirtualAddress, length: usize) -> bool {
+    use memory::{PAGE_SIZE, USER_FRAMEBUFFER_AREA_BASE, USER_INTERPRETER_AREA_BASE};
+
+    address % PAGE_SIZE == 0 && length != 0 && address >= USER_INTERPRETER_AREA_BASE
+        && address
+            .checked_add(length)
+            .map_or(false, |end| end <= USER_FRAMEBUFFER_AREA_BASE)
+}
+
+/// Maps `length` bytes of the initramfs file starting at `offset` at `address`.
+///
+/// Areas that aren't writable share the frames of the initramfs between all
+/// processes, if the file is aligned for it. Writable areas get a private
+/// copy. The part of the area after the end of the file is zeroed.
+fn map_file(
+    name_ptr: VirtualAddress,
+    name_length: usize,
+    offset: u64,
+    address: VirtualAddress,
+    length: usize,
+    flags: u64,
+) -> i64 {
+    use memory::PAGE_SIZE;
+    use memory::address_space::{Segment, SegmentType};
+
+    let name = match file_name(name_ptr, name_length) {
+        Ok(name) => name,
+        Err(error) => return error,
+    };
+
+    let page_flags = match mapping_flags(flags) {
+        Ok(page_flags) => page_flags,
+        Err(error) => return error,
+    };
+
+    if !is_mappable_area(address, length) || offset % PAGE_SIZE as u64 != 0 {
+        return INVALID_ARGUMENT;
+    }
+
+    let (physical_start, file_length) = match initramfs::get_physical_area(&name) {
+        Ok(area) => area,
+        Err(_) => return NOT_FOUND,
+    };
+
+    if offset > file_length as u64 {
+        return INVALID_ARGUMENT;
+    }
+    let offset = offset as usize;
+
+    let mut pcb = get_current_process();
+
+    // The initramfs pads files to full pages, so the last page can be shared.
+    let padded_length = (file_length - offset + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+    let shareable = !page_flags.contains(PageFlags::WRITABLE)
+        && (physical_start + offset) % PAGE_SIZE == 0
+        && length <= padded_length
+        && physical_start + offset + length <= initramfs::get_physical_end();
+
+    if shareable {
+        let mapped = pcb.address_space.map_physical_area(
+            address,
+            physical_start + offset,
+            length,
+            page_flags,
+        );
+
+        return if mapped { 0 } else { INVALID_ARGUMENT };
+    }
+
+    let mut file = match initramfs::open(&name) {
+        Ok(file) => file,
+        Err(_) => return NOT_FOUND,
+    };
+
+    let segment = Segment::new(address, length, page_flags, SegmentType::FromFile);
+    if !pcb.address_space.add_segment(segment) {
+        return INVALID_ARGUMENT;
+    }
+    pcb.address_space.map_anonymous_area(address, length);
+
+    let copy_length = min(length, file_length - offset);
+    let mut buffer = [0u8; FILE_CHUNK_SIZE];
+    let mut copied = 0;
+
+    while copied < copy_length {
+        let chunk_length = min(copy_length - copied, FILE_CHUNK_SIZE);
+
+        let chunk = &mut buffer[..chunk_length];
+        if file.read_at(chunk, (offset + copied) as u64).is_err() {
+            // Nothing can reference the segment before the syscall returns.
+            unsafe {
+                pcb.address_space.remove_segment(address);
+            }
+
+            return INVALID_ARGUMENT;
+        }
+
+        pcb.address_space.write_to(chunk, address + copied);
+
+        copied += chunk_length;
+    }
+
+    0
+}
+
+/// Maps `length` bytes of zeroed memory at `address`.
+fn map_memory(address: VirtualAddress, length: usize, flags: u64) -> i64 {
+    use memory::address_space::{Segment, SegmentType};
+
+    let page_flags = match mapping_flags(flags) {
+        Ok(page_flags) => page_flags,
+        Err(error) => return error,
+    };
+
+    if !is_mappable_area(address, length) {
+        return INVALID_ARGUMENT;
+    }
+
+    let mut pcb = get_current_process();
+
+    let segment = Segment::new(address, length, page_flags, SegmentType::MemoryOnly);
+    if !pcb.address_space.add_segment(segment) {
+        return INVALID_ARGUMENT;
+    }
+    pcb.address_space.map_anonymous_area(address, length);
+
+    0
+}
+
+/// The error returned if an argument is invalid.
+const INVALID_ARGUMENT: i64 = -1;
+
+/// The error returned if the timeout of a blocking syscall expired.
+const TIMED_OUT: i64 = -2;
+
+/// The error returned if a futex didn't hold the expected value.
+const VALUE_CHANGED: i64 = -3;
+
+/// The error returned if a file doesn't exist.
+const NOT_FOUND: i64 = -4;
+
+/// The error returned if a pointer argument can't be accessed.
+const BAD_ADDRESS: i64 = -5;
+
+/// The error returned if the process isn't allowed to make the syscall.
+const PERMISSION_DENIED: i64 = -6;
+
+/// The timeout value that blocks without a timeout.
+const NO_TIMEOUT: u64 = <u64>::max_value();
+
+/// Converts a relative timeout in nanoseconds to a deadline.
+fn get_deadline(timeout: u64) -> Option<Timestamp> {
+    if timeout == NO_TIMEOUT {
+        None
+    } else {
+        let now = Timestamp::get_current().as_nanoseconds();
+        Some(Timestamp::from_nanoseconds(now.saturating_add(timeout)))
+    }
+}
+
+fn futex_wait_syscall(address: VirtualAddress, expected: u32, timeout: u64) -> i64 {
+    match futex_wait(address, expected, get_deadline(timeout)) {
+        Ok(()) => 0,
+        Err(FutexError::InvalidAddress) => INVALID_ARGUMENT,
+        Err(FutexError::ValueChanged) => VALUE_CHANGED,
+        Err(FutexError::TimedOut) => TIMED_OUT,
+    }
+}
+
+fn futex_wake_syscall(address: VirtualAddress, count: usize) -> i64 {
+    futex_wake(address, count) as i64
+}
+
+fn receive(message_ptr: VirtualAddress, timeout: u64) -> i64 {
+    use multitasking::message::Message;
+
+    let pid = CURRENT_THREAD.lock().pid;
+    let message_ptr = UserPtr::<Message>::new(message_ptr);
+
+    // Check first, so that no message is lost.
+    if !message_ptr.is_writable() {
+        return BAD_ADDRESS;
+    }
+
+    match receive_message(pid, get_deadline(timeout)) {
+        Some(message) => match message_ptr.write(message) {
+            Ok(()) => 0,
+            Err(_) => BAD_ADDRESS,
+        },
+        None => TIMED_OUT,
+    }
+}
+
+/// The delivery of timers that wake waiting threads.
+const TIMER_DELIVERY_WAKEUP: u64 = 0;
+
+/// The delivery of timers that send messages.
+const TIMER_DELIVERY_MESSAGE: u64 = 1;
+
+fn timer_create(delay: u64, period: u64, delivery: u64) -> i64 {
+    let delivery = match delivery {
+        TIMER_DELIVERY_WAKEUP => TimerDelivery::Wakeup,
+        TIMER_DELIVERY_MESSAGE => TimerDelivery::Message,
+        _ => return INVALID_ARGUMENT,
+    };
+
+    let pid = CURRENT_THREAD.lock().pid;
+    let now = Timestamp::get_current().as_nanoseconds();
+    let start = Timestamp::from_nanoseconds(now.saturating_add(delay));
+
+    create_timer(pid, start, period, delivery) as i64
+}
+
+fn timer_wait(handle: u64, timeout: u64) -> i64 {
+    let pid = CURRENT_THREAD.lock().pid;
+
+    match wait_timer(pid, handle, get_deadline(timeout)) {
+        Ok(expiries) => expiries as i64,
+        Err(TimerError::TimedOut) => TIMED_OUT,
+        Err(_) => INVALID_ARGUMENT,
+    }
+}
+
+fn timer_delete(handle: u64) -> i64 {
+    let pid = CURRENT_THREAD.lock().pid;
+
+    match delete_timer(pid, handle) {
+        Ok(()) => 0,
+        Err(_) => INVALID_ARGUMENT,
+    }
+}
+
 fn unknown_syscall(num: u64) -> ! {
     if cfg!(debug) {
         panic!("The syscall {} is not known.", num);
@@ -38,6 +38,9 @@ const FILE_METADATA_OFFSET: usize = size_of::<[u8; 8]>() + size_of::<u64>();
 /// The size of a file metadata object.
 const FILE_METADATA_SIZE: usize = size_of::<u64>() * 4;
 
+/// The alignment of file contents, so that they can be mapped into processes.
+const PAGE_SIZE: u64 = 0x1000;
+
 /// The error message if there is a seek error.
 const COULD_NOT_SEEK_TARGET: &str = "Could not seek target file";
 
@@ -106,6 +109,7 @@ fn write_file(file: &mut File, file_num: usize, file_name: &str, file_path: &Pat
     file.write_u64::<BigEndian>(file_name.len() as u64).unwrap_or_exit(COULD_NOT_WRITE_TO_TARGET);
 
     // Write file content.
+    pad_to_page(file);
     let content_position = file.seek(SeekFrom::End(0)).unwrap_or_exit(COULD_NOT_SEEK_TARGET);
     let mut source_file = File::open(file_path).unwrap_or_exit(&format!("Could not open {}", file_path.display()));
 
@@ -124,6 +128,7 @@ fn write_file(file: &mut File, file_num: usize, file_name: &str, file_path: &Pat
             }
         }
     }
+    pad_to_page(file);
 
     // Write file content metadata.
     file.seek(SeekFrom::Start((file_metadata_start + size_of::<u64>() * 2) as u64)).unwrap_or_exit(COULD_NOT_SEEK_TARGET);
@@ -131,6 +136,14 @@ fn write_file(file: &mut File, file_num: usize, file_name: &str, file_path: &Pat
     file.write_u64::<BigEndian>(source_file.metadata().unwrap_or_exit(&format!("Could not read length of {}", file_path.display())).len() as u64).unwrap_or_exit(COULD_NOT_WRITE_TO_TARGET);
 }
 
+/// Pads the file with zeros up to the next page boundary.
+fn pad_to_page(file: &mut File) {
+    let end = file.seek(SeekFrom::End(0)).unwrap_or_exit(COULD_NOT_SEEK_TARGET);
+    let padded_end = (end + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+
+    file.set_len(padded_end).unwrap_or_exit(COULD_NOT_WRITE_TO_TARGET);
+}
+
 /// Writes the header information to the file.
 ///
 /// Returns the size of the header.
@@ -221,3 +234,84 @@ fn print_usage(error: &str) -> ! {
     eprintln!("    base_path is the path that all the listed files start from. Default is \"/\".");
     exit(1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{remove_file, OpenOptions};
+    use std::process;
+
+    /// Creates an empty file in the temporary directory.
+    fn temp_file(name: &str) -> (File, PathBuf) {
+        let path = temp_dir().join(format!("mkinitramfs-{}-{}", process::id(), name));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+
+        (file, path)
+    }
+
+    /// Tests that files are padded to the next page boundary.
+    #[test]
+    fn test_pad_to_page() {
+        let (mut file, path) = temp_file("pad");
+
+        pad_to_page(&mut file);
+        assert_eq!(file.metadata().unwrap().len(), 0);
+
+        file.write_all(&[1]).unwrap();
+        pad_to_page(&mut file);
+        assert_eq!(file.metadata().unwrap().len(), PAGE_SIZE);
+
+        pad_to_page(&mut file);
+        assert_eq!(file.metadata().unwrap().len(), PAGE_SIZE);
+
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents[0], 1);
+        assert!(contents[1..].iter().all(|&byte| byte == 0));
+
+        remove_file(path).unwrap();
+    }
+
+    /// Tests that file contents start on a page boundary.
+    #[test]
+    fn test_write_file() {
+        let (mut source, source_path) = temp_file("source");
+        source.write_all(b"content").unwrap();
+
+        let (mut file, path) = temp_file("initramfs");
+        let file_list = vec![("/name", source_path.clone())];
+        write_file_header(&mut file, &file_list).unwrap();
+        write_file(&mut file, 0, "/name", &source_path);
+
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut contents).unwrap();
+
+        let read_u64 = |offset: usize| {
+            contents[offset..offset + 8]
+                .iter()
+                .fold(0, |value, &byte| value << 8 | byte as u64)
+        };
+        let name_position = read_u64(FILE_METADATA_OFFSET) as usize;
+        let content_position = read_u64(FILE_METADATA_OFFSET + 16) as usize;
+
+        assert_eq!(&contents[..8], &MAGIC);
+        assert_eq!(read_u64(8), 1);
+        assert_eq!(&contents[name_position..name_position + 5], b"/name");
+        assert_eq!(content_position as u64 % PAGE_SIZE, 0);
+        assert_eq!(read_u64(FILE_METADATA_OFFSET + 24), 7);
+        assert_eq!(&contents[content_position..content_position + 7], b"content");
+        assert_eq!(contents.len() as u64 % PAGE_SIZE, 0);
+
+        remove_file(source_path).unwrap();
+        remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,30 @@
+//! Reports errors of the loader.
+
+use core::fmt;
+use core::fmt::Write;
+use sys::serial_char;
+
+/// Prints an error message and exits the process.
+macro_rules! fail {
+    ($($arg:tt)*) => ({
+        $crate::io::print_error(format_args!($($arg)*));
+        $crate::sys::exit()
+    });
+}
+
+/// A dummy struct to implement fmt::Write on.
+struct SerialOut;
+
+impl fmt::Write for SerialOut {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            serial_char(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Prints the error message on the serial port.
+pub fn print_error(args: fmt::Arguments) {
+    SerialOut.write_fmt(format_args!("ld.so: {}\n", args)).unwrap();
+}
@@ -0,0 +1,61 @@
+//! The runtime loader of BoringOS.
+//!
+//! The kernel starts this instead of programs that request it as their
+//! interpreter. It loads the shared objects the program needs from the
+//! initramfs, relocates everything and then calls the program.
+//!
+//! Calls into shared objects are bound lazily on their first use, unless the
+//! program was linked with `-z now`.
+
+#![feature(asm)]
+#![feature(lang_items)]
+#![feature(naked_functions)]
+#![no_std]
+#[macro_use]
+extern crate boring_core;
+#[allow(unused_extern_crates)]
+extern crate rlibc;
+
+#[macro_use]
+mod io;
+mod object;
+mod sys;
+
+use core::mem;
+
+/// The entry point of the loader.
+///
+/// The arguments are passed by the kernel when it starts a program that
+/// requests the loader as its interpreter.
+#[no_mangle]
+pub extern "C" fn loader_start(
+    program_base: usize,
+    dynamic_address: usize,
+    entry_address: usize,
+    _loader_base: usize,
+    library_area_base: usize,
+) -> ! {
+    if dynamic_address != 0 {
+        unsafe {
+            object::link_program(program_base, dynamic_address, library_area_base);
+        }
+    }
+
+    // Dynamically linked programs start at their main function.
+    let entry: extern "C" fn() = unsafe { mem::transmute(entry_address) };
+    entry();
+
+    sys::exit();
+}
+
+#[lang = "eh_personality"]
+extern "C" fn eh_personality() {
+    unimplemented!();
+}
+
+/// The panic handler of the loader.
+#[lang = "panic_fmt"]
+#[no_mangle]
+pub extern "C" fn panic_fmt(fmt: core::fmt::Arguments, file: &'static str, line: u32) -> ! {
+    fail!("panicked in file '{}' at line {}: {}", file, line, fmt);
+}
@@ -0,0 +1,617 @@
+//! Loads shared objects and links them with the program.
+
+use core::cmp::{max, min};
+use core::mem::size_of;
+use core::{ptr, slice, str};
+use boring_core::elf::*;
+use sys;
+
+/// The maximum number of loaded objects, including the program.
+const MAX_OBJECTS: usize = 16;
+
+/// The directory shared objects are loaded from.
+const LIBRARY_DIRECTORY: &str = "/lib/";
+
+/// The maximum length of the name of a shared object.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The maximum number of program headers in a shared object.
+const MAX_PROGRAM_HEADERS: usize = 32;
+
+/// The size of a page.
+const PAGE_SIZE: usize = 0x1000;
+
+/// Represents an object that is part of the process.
+#[derive(Clone, Copy)]
+struct Object {
+    /// The name the object was requested with, empty for the program.
+    name: [u8; MAX_NAME_LENGTH],
+    /// The length of the name.
+    name_length: usize,
+    /// The load base that is added to all addresses in the object.
+    base: usize,
+    /// The address of the dynamic section.
+    dynamic: usize,
+    /// The address of the string table.
+    string_table: usize,
+    /// The address of the symbol table.
+    symbol_table: usize,
+    /// The address of the symbol hash table.
+    hash_table: usize,
+    /// The address of the relocation table.
+    relocations: usize,
+    /// The size of the relocation table in bytes.
+    relocations_size: usize,
+    /// The address of the relocation table for the PLT.
+    plt_relocations: usize,
+    /// The size of the relocation table for the PLT in bytes.
+    plt_relocations_size: usize,
+    /// The address of the global offset table.
+    global_offset_table: usize,
+    /// Whether the object needs to modify non-writable segments.
+    text_relocations: bool,
+    /// Whether the object asks for all functions to be bound at load time.
+    bind_now: bool,
+}
+
+/// An object without any information.
+const EMPTY_OBJECT: Object = Object {
+    name: [0; MAX_NAME_LENGTH],
+    name_length: 0,
+    base: 0,
+    dynamic: 0,
+    string_table: 0,
+    symbol_table: 0,
+    hash_table: 0,
+    relocations: 0,
+    relocations_size: 0,
+    plt_relocations: 0,
+    plt_relocations_size: 0,
+    global_offset_table: 0,
+    text_relocations: false,
+    bind_now: false,
+};
+
+/// The loaded objects, in the order they are searched for symbols.
+///
+/// This is only written to before the program starts.
+static mut OBJECTS: [Object; MAX_OBJECTS] = [EMPTY_OBJECT; MAX_OBJECTS];
+
+/// The number of loaded objects.
+static mut OBJECT_COUNT: usize = 0;
+
+/// The address the next shared object is loaded at.
+static mut NEXT_LOAD_ADDRESS: usize = 0;
+
+impl Object {
+    /// Creates an object from its dynamic section.
+    ///
+    /// # Safety
+    /// - The object has to be mapped already.
+    unsafe fn new(name: &[u8], base: usize, dynamic: usize) -> Object {
+        let mut object = EMPTY_OBJECT;
+
+        object.name[..name.len()].copy_from_slice(name);
+        object.name_length = name.len();
+        object.base = base;
+        object.dynamic = dynamic;
+
+        let mut relocation_size = size_of::<Relocation>();
+        let mut plt_relocation_type = DT_RELA as u64;
+
+        for entry in object.dynamic_entries() {
+            let address = base.wrapping_add(entry.value as usize);
+
+            match entry.tag {
+                DT_PLTRELSZ => object.plt_relocations_size = entry.value as usize,
+                DT_PLTGOT => object.global_offset_table = address,
+                DT_HASH => object.hash_table = address,
+                DT_STRTAB => object.string_table = address,
+                DT_SYMTAB => object.symbol_table = address,
+                DT_RELA => object.relocations = address,
+                DT_RELASZ => object.relocations_size = entry.value as usize,
+                DT_RELAENT => relocation_size = entry.value as usize,
+                DT_PLTREL => plt_relocation_type = entry.value,
+                DT_TEXTREL => object.text_relocations = true,
+                DT_JMPREL => object.plt_relocations = address,
+                DT_BIND_NOW => object.bind_now = true,
+                DT_FLAGS => {
+                    object.text_relocations |= entry.value & DF_TEXTREL != 0;
+                    object.bind_now |= entry.value & DF_BIND_NOW != 0;
+                }
+                DT_FLAGS_1 => object.bind_now |= entry.value & DF_1_NOW != 0,
+                _ => (),
+            }
+        }
+
+        if relocation_size != size_of::<Relocation>() || plt_relocation_type != DT_RELA as u64 {
+            fail!("{}: Only relocations with addends are supported.", object.name());
+        }
+
+        object
+    }
+
+    /// Returns the name of the object for messages.
+    fn name(&self) -> &str {
+        if self.name_length == 0 {
+            "program"
+        } else {
+            str::from_utf8(&self.name[..self.name_length]).unwrap_or("?")
+        }
+    }
+
+    /// Returns the entries of the dynamic section.
+    unsafe fn dynamic_entries(&self) -> &'static [DynamicEntry] {
+        let first = self.dynamic as *const DynamicEntry;
+        let mut count = 0;
+
+        while (*first.offset(count as isize)).tag != DT_NULL {
+            count += 1;
+        }
+
+        slice::from_raw_parts(first, count)
+    }
+
+    /// Returns the null terminated string at the offset in the string table.
+    unsafe fn string_at(&self, offset: usize) -> &'static [u8] {
+        let start = (self.string_table + offset) as *const u8;
+        let mut length = 0;
+
+        while *start.offset(length as isize) != 0 {
+            length += 1;
+        }
+
+        slice::from_raw_parts(start, length)
+    }
+
+    /// Returns the symbol with the given index.
+    unsafe fn symbol(&self, index: usize) -> &'static Symbol {
+        &*(self.symbol_table as *const Symbol).offset(index as isize)
+    }
+
+    /// Returns the relocations of the object.
+    unsafe fn relocations(&self) -> &'static [Relocation] {
+        relocation_table(self.relocations, self.relocations_size)
+    }
+
+    /// Returns the relocations for the PLT of the object.
+    unsafe fn plt_relocations(&self) -> &'static [Relocation] {
+        relocation_table(self.plt_relocations, self.plt_relocations_size)
+    }
+
+    /// Looks up the exported symbol with the given name in the hash table.
+    unsafe fn lookup(&self, name: &[u8]) -> Option<&'static Symbol> {
+        if self.hash_table == 0 {
+            return None;
+        }
+
+        let words = self.hash_table as *const u32;
+        let symbol_num = *words.offset(1) as usize;
+        let word_num = 2 + *words as usize + symbol_num;
+
+        let hash_table = match HashTable::new(slice::from_raw_parts(words, word_num)) {
+            Some(hash_table) => hash_table,
+            None => return None,
+        };
+        let symbols = slice::from_raw_parts(self.symbol_table as *const Symbol, symbol_num);
+
+        hash_table.lookup(symbols, name, |symbol| self.string_at(symbol.name as usize) == name)
+    }
+}
+
+/// Returns the relocation table at the address.
+unsafe fn relocation_table(address: usize, size: usize) -> &'static [Relocation] {
+    if address == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(address as *const Relocation, size / size_of::<Relocation>())
+    }
+}
+
+/// Returns the objects loaded so far.
+unsafe fn loaded_objects() -> &'static [Object] {
+    &OBJECTS[..OBJECT_COUNT]
+}
+
+/// Loads the shared objects the program needs and relocates everything.
+///
+/// # Safety
+/// - This should only be called once, before the program runs.
+pub unsafe fn link_program(program_base: usize, dynamic_address: usize, library_area_base: usize) {
+    NEXT_LOAD_ADDRESS = library_area_base;
+    add_object(Object::new(&[], program_base, dynamic_address));
+
+    // Loading breadth first also yields the order symbols are searched in.
+    let mut index = 0;
+    while index < OBJECT_COUNT {
+        let object = OBJECTS[index];
+
+        for entry in object.dynamic_entries() {
+            if entry.tag == DT_NEEDED {
+                let name = object.string_at(entry.value as usize);
+
+                if !loaded_objects()
+                    .iter()
+                    .any(|loaded| &loaded.name[..loaded.name_length] == name)
+                {
+                    load_library(name);
+                }
+            }
+        }
+
+        index += 1;
+    }
+
+    let bind_now = OBJECTS[0].bind_now;
+
+    // Objects are relocated before the ones that depend on them, so that the
+    // data copied from them is already relocated.
+    for index in (0..OBJECT_COUNT).rev() {
+        relocate(index, bind_now);
+    }
+}
+
+/// Adds the object to the loaded objects.
+unsafe fn add_object(object: Object) {
+    if OBJECT_COUNT == MAX_OBJECTS {
+        fail!("Too many shared objects are needed.");
+    }
+
+    OBJECTS[OBJECT_COUNT] = object;
+    OBJECT_COUNT += 1;
+}
+
+/// Rounds the address down to the start of its page.
+fn page_start(address: usize) -> usize {
+    address / PAGE_SIZE * PAGE_SIZE
+}
+
+/// Rounds the address up to the start of the next page.
+fn page_end(address: usize) -> usize {
+    page_start(address + PAGE_SIZE - 1)
+}
+
+/// Reads the bytes at the offset in the file into the buffer.
+fn read_exactly(path: &str, buffer: &mut [u8], offset: usize) {
+    match sys::read_file(path, buffer, offset) {
+        Ok(length) if length == buffer.len() => (),
+        _ => fail!("{}: The file is too short.", path),
+    }
+}
+
+/// Loads the shared object with the given name from the library directory.
+unsafe fn load_library(name: &[u8]) {
+    if name.len() > MAX_NAME_LENGTH {
+        fail!("The name of a needed shared object is too long.");
+    }
+
+    let mut path_buffer = [0; MAX_NAME_LENGTH + 8];
+    let directory_length = LIBRARY_DIRECTORY.len();
+    path_buffer[..directory_length].copy_from_slice(LIBRARY_DIRECTORY.as_bytes());
+    path_buffer[directory_length..directory_length + name.len()].copy_from_slice(name);
+
+    let path = match str::from_utf8(&path_buffer[..directory_length + name.len()]) {
+        Ok(path) => path,
+        Err(_) => fail!("The name of a needed shared object is invalid."),
+    };
+
+    let mut header: Header = EMPTY_HEADER;
+    read_exactly(path, as_bytes(&mut header), 0);
+
+    let program_header_num = header.program_header_entry_num as usize;
+    if !header.is_shared_object()
+        || header.program_header_entry_size as usize != size_of::<ProgramHeader>()
+        || program_header_num > MAX_PROGRAM_HEADERS
+    {
+        fail!("{}: The file is not a supported shared object.", path);
+    }
+
+    let mut program_headers = [EMPTY_PROGRAM_HEADER; MAX_PROGRAM_HEADERS];
+    let program_headers = &mut program_headers[..program_header_num];
+    read_exactly(
+        path,
+        slice::from_raw_parts_mut(
+            program_headers.as_mut_ptr() as *mut u8,
+            program_header_num * size_of::<ProgramHeader>(),
+        ),
+        header.program_header_offset,
+    );
+
+    // Reserve the range spanned by the loadable segments.
+    let (start, end) = program_headers
+        .iter()
+        .filter(|program_header| program_header.segment_type == PT_LOAD)
+        .fold((usize::max_value(), 0), |(start, end), program_header| {
+            let segment_end = program_header.virtual_address + program_header.size_in_memory;
+
+            (
+                min(start, page_start(program_header.virtual_address)),
+                max(end, page_end(segment_end)),
+            )
+        });
+
+    if start >= end {
+        fail!("{}: The file has nothing to load.", path);
+    }
+
+    let base = NEXT_LOAD_ADDRESS - start;
+
+    // Leave an unmapped page between the objects.
+    NEXT_LOAD_ADDRESS += end - start + PAGE_SIZE;
+
+    let mut dynamic = 0;
+    for program_header in program_headers.iter() {
+        match program_header.segment_type {
+            PT_LOAD => map_segment(path, program_header, base),
+            PT_DYNAMIC => dynamic = base + program_header.virtual_address,
+            _ => (),
+        }
+    }
+
+    if dynamic == 0 {
+        fail!("{}: The file has no dynamic section.", path);
+    }
+
+    let object = Object::new(name, base, dynamic);
+
+    // Segments that aren't writable are shared with other processes.
+    if object.text_relocations {
+        fail!("{}: Text relocations are not supported.", path);
+    }
+
+    add_object(object);
+}
+
+/// Maps the segment of the file into the process.
+///
+/// Segments that aren't writable share their pages with other processes that
+/// use the same file.
+unsafe fn map_segment(path: &str, program_header: &ProgramHeader, base: usize) {
+    if program_header.offset % PAGE_SIZE != program_header.virtual_address % PAGE_SIZE {
+        fail!("{}: A segment is not aligned to pages.", path);
+    }
+
+    let start = base + program_header.virtual_address;
+    let file_end = start + program_header.size_in_file;
+    let memory_end = start + program_header.size_in_memory;
+    let flags = program_header.flags;
+
+    let mut anonymous_start = page_start(start);
+
+    if program_header.size_in_file != 0 {
+        let mapped = sys::map_file(
+            path,
+            page_start(program_header.offset),
+            page_start(start),
+            page_end(file_end) - page_start(start),
+            flags,
+        );
+
+        if mapped.is_err() {
+            fail!("{}: A segment could not be mapped.", path);
+        }
+
+        // The rest of the last page holds whatever follows in the file.
+        let zero_end = min(page_end(file_end), memory_end);
+        if file_end < zero_end {
+            if flags & PF_W == 0 {
+                fail!("{}: A read-only segment is not fully in the file.", path);
+            }
+
+            ptr::write_bytes(file_end as *mut u8, 0, zero_end - file_end);
+        }
+
+        anonymous_start = page_end(file_end);
+    }
+
+    if anonymous_start < memory_end {
+        let length = page_end(memory_end) - anonymous_start;
+
+        if sys::map_memory(anonymous_start, length, flags).is_err() {
+            fail!("{}: A segment could not be mapped.", path);
+        }
+    }
+}
+
+/// A header to read into.
+const EMPTY_HEADER: Header = Header {
+    identification: [0; 16],
+    elf_type: 0,
+    instruction_set: 0,
+    version: 0,
+    program_entry: 0,
+    program_header_offset: 0,
+    section_header_offset: 0,
+    flags: 0,
+    header_size: 0,
+    program_header_entry_size: 0,
+    program_header_entry_num: 0,
+    section_header_entry_size: 0,
+    section_header_entry_num: 0,
+    name_string_table_index: 0,
+};
+
+/// A program header to read into.
+const EMPTY_PROGRAM_HEADER: ProgramHeader = ProgramHeader {
+    segment_type: 0,
+    flags: 0,
+    offset: 0,
+    virtual_address: 0,
+    physical_address: 0,
+    size_in_file: 0,
+    size_in_memory: 0,
+    align: 0,
+};
+
+/// Returns the bytes of the value.
+unsafe fn as_bytes<T>(value: &mut T) -> &mut [u8] {
+    slice::from_raw_parts_mut(value as *mut T as *mut u8, size_of::<T>())
+}
+
+/// Applies the relocations of the object with the given index.
+///
+/// Unless `bind_now` is set, functions are bound on their first call.
+unsafe fn relocate(index: usize, bind_now: bool) {
+    let object = &OBJECTS[index];
+
+    for relocation in object.relocations() {
+        apply_relocation(object, relocation);
+    }
+
+    if bind_now || object.bind_now {
+        for relocation in object.plt_relocations() {
+            apply_relocation(object, relocation);
+        }
+    } else if !object.plt_relocations().is_empty() {
+        prepare_lazy_binding(index);
+    }
+}
+
+/// Applies a single relocation of the object.
+unsafe fn apply_relocation(object: &Object, relocation: &Relocation) {
+    let target = object.base + relocation.offset;
+
+    match relocation.relocation_type() {
+        R_X86_64_NONE => (),
+        R_X86_64_COPY => {
+            let (address, size) = resolve_symbol(object, relocation.symbol_index(), true);
+            ptr::copy_nonoverlapping(address as *const u8, target as *mut u8, size);
+        }
+        relocation_type => {
+            let (address, _) = resolve_symbol(object, relocation.symbol_index(), false);
+
+            match relocation.value(object.base, address) {
+                Some(value) => *(target as *mut usize) = value,
+                None => fail!(
+                    "{}: Relocations of type {} are not supported.",
+                    object.name(),
+                    relocation_type
+                ),
+            }
+        }
+    }
+}
+
+/// Returns the address and the size of the definition of the symbol.
+///
+/// The definition is searched for in all loaded objects in order. Copy
+/// relocations have to skip the object that needs the definition.
+unsafe fn resolve_symbol(
+    object: &Object,
+    symbol_index: usize,
+    skip_object: bool,
+) -> (usize, usize) {
+    if symbol_index == 0 {
+        return (0, 0);
+    }
+
+    let symbol = object.symbol(symbol_index);
+    let name = object.string_at(symbol.name as usize);
+
+    for candidate in loaded_objects() {
+        if skip_object && candidate.base == object.base {
+            continue;
+        }
+
+        if let Some(definition) = candidate.lookup(name) {
+            return (candidate.base + definition.value, definition.size);
+        }
+    }
+
+    // Undefined weak symbols are null.
+    if symbol.binding() == STB_WEAK {
+        (0, 0)
+    } else {
+        fail!(
+            "{}: The symbol {} is undefined.",
+            object.name(),
+            str::from_utf8(name).unwrap_or("?")
+        );
+    }
+}
+
+/// Prepares the global offset table of the object for lazy binding.
+///
+/// The entries initially point back into the PLT, which then calls
+/// `resolve_lazily` with the values in the second and third entry.
+unsafe fn prepare_lazy_binding(index: usize) {
+    let object = &OBJECTS[index];
+    let global_offset_table = object.global_offset_table as *mut usize;
+
+    if global_offset_table.is_null() {
+        fail!("{}: The global offset table is missing.", object.name());
+    }
+
+    *global_offset_table.offset(1) = index;
+    *global_offset_table.offset(2) = resolve_lazily as usize;
+
+    for relocation in object.plt_relocations() {
+        if relocation.relocation_type() == R_X86_64_JUMP_SLOT {
+            *((object.base + relocation.offset) as *mut usize) += object.base;
+        } else {
+            apply_relocation(object, relocation);
+        }
+    }
+}
+
+/// Binds the function of the PLT relocation and returns its address.
+///
+/// This is called by `resolve_lazily` on the first call of the function.
+#[no_mangle]
+pub unsafe extern "C" fn bind_lazily(index: usize, relocation_index: usize) -> usize {
+    let object = &OBJECTS[index];
+    let relocation = &object.plt_relocations()[relocation_index];
+
+    let (address, _) = resolve_symbol(object, relocation.symbol_index(), false);
+    *((object.base + relocation.offset) as *mut usize) = address;
+
+    address
+}
+
+/// Resolves a function on its first call and jumps to it.
+///
+/// The PLT pushes the index of the relocation and the index of the object
+/// before jumping here. All argument registers are preserved.
+#[naked]
+unsafe fn resolve_lazily() -> ! {
+    asm!("push rdi
+          push rsi
+          push rdx
+          push rcx
+          push r8
+          push r9
+          push rax
+          sub rsp, 128
+          movdqu [rsp], xmm0
+          movdqu [rsp + 16], xmm1
+          movdqu [rsp + 32], xmm2
+          movdqu [rsp + 48], xmm3
+          movdqu [rsp + 64], xmm4
+          movdqu [rsp + 80], xmm5
+          movdqu [rsp + 96], xmm6
+          movdqu [rsp + 112], xmm7
+          mov rdi, [rsp + 184]
+          mov rsi, [rsp + 192]
+          call bind_lazily
+          mov r11, rax
+          movdqu xmm0, [rsp]
+          movdqu xmm1, [rsp + 16]
+          movdqu xmm2, [rsp + 32]
+          movdqu xmm3, [rsp + 48]
+          movdqu xmm4, [rsp + 64]
+          movdqu xmm5, [rsp + 80]
+          movdqu xmm6, [rsp + 96]
+          movdqu xmm7, [rsp + 112]
+          add rsp, 128
+          pop rax
+          pop r9
+          pop r8
+          pop rcx
+          pop rdx
+          pop rsi
+          pop rdi
+          add rsp, 16
+          jmp r11"
+          : : : : "intel", "volatile");
+    unreachable!();
+}
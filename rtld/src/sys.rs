@@ -0,0 +1,81 @@
+//! The syscalls used by the loader.
+
+use boring_core::syscall::{EXIT_SYSCALL_NUM, MAP_FILE_SYSCALL_NUM, MAP_MEMORY_SYSCALL_NUM,
+                           READ_FILE_SYSCALL_NUM, SERIAL_CHAR_SYSCALL_NUM};
+
+/// Exits the current process.
+pub fn exit() -> ! {
+    unsafe {
+        syscall!(EXIT_SYSCALL_NUM);
+    }
+    unreachable!();
+}
+
+/// Sends the byte to the serial port.
+pub fn serial_char(byte: u8) {
+    unsafe {
+        syscall!(SERIAL_CHAR_SYSCALL_NUM, byte as u64);
+    }
+}
+
+/// Converts the result of a syscall to a result.
+fn syscall_result(result: u64) -> Result<usize, i64> {
+    if (result as i64) < 0 {
+        Err(result as i64)
+    } else {
+        Ok(result as usize)
+    }
+}
+
+/// Reads the file into the buffer, starting at the given offset.
+///
+/// Returns the number of bytes read.
+pub fn read_file(name: &str, buffer: &mut [u8], offset: usize) -> Result<usize, i64> {
+    syscall_result(unsafe {
+        syscall!(
+            READ_FILE_SYSCALL_NUM,
+            name.as_ptr() as u64,
+            name.len() as u64,
+            buffer.as_mut_ptr() as u64,
+            buffer.len() as u64,
+            offset as u64
+        )
+    })
+}
+
+/// Maps `length` bytes of the file starting at `offset` at `address`.
+///
+/// The flags use the same bits as the flags of ELF segments.
+pub fn map_file(
+    name: &str,
+    offset: usize,
+    address: usize,
+    length: usize,
+    flags: u32,
+) -> Result<(), i64> {
+    syscall_result(unsafe {
+        syscall!(
+            MAP_FILE_SYSCALL_NUM,
+            name.as_ptr() as u64,
+            name.len() as u64,
+            offset as u64,
+            address as u64,
+            length as u64,
+            flags as u64
+        )
+    }).map(|_| ())
+}
+
+/// Maps `length` bytes of zeroed memory at `address`.
+///
+/// The flags use the same bits as the flags of ELF segments.
+pub fn map_memory(address: usize, length: usize, flags: u32) -> Result<(), i64> {
+    syscall_result(unsafe {
+        syscall!(
+            MAP_MEMORY_SYSCALL_NUM,
+            address as u64,
+            length as u64,
+            flags as u64
+        )
+    }).map(|_| ())
+}
@@ -0,0 +1,22 @@
+#!/bin/sh
+# Checks that a program uses the standard library from libboringos_std.so.
+#
+# Programs are linked from static libraries that bundle their own copy of
+# boringos_std. The linker only leaves that copy out if the references of the
+# program match the symbols exported by the shared library, which fails
+# silently if the two builds mangle the symbols differently.
+
+program=$1
+
+# The mangled names of the standard library and the crates bundled with it.
+std_symbols=' _ZN(12boringos_std|11boring_core)'
+
+if nm --defined-only --extern-only "$program" | grep -Eq "$std_symbols"; then
+    echo "$program contains its own copy of boringos_std." >&2
+    exit 1
+fi
+
+if ! nm --undefined-only "$program" | grep -Eq "$std_symbols"; then
+    echo "$program doesn't use boringos_std from libboringos_std.so." >&2
+    exit 1
+fi
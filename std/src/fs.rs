@@ -0,0 +1,70 @@
+//! Reads files from the initramfs.
+
+use boring_core::syscall::{FILE_SIZE_SYSCALL_NUM, READ_FILE_SYSCALL_NUM};
+
+/// The error returned if a file doesn't exist.
+const NOT_FOUND_ERROR: i64 = -4;
+
+/// The errors that can occur while reading files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileError {
+    /// The name or the buffer was invalid.
+    InvalidArgument,
+    /// The file doesn't exist.
+    NotFound,
+    /// The file doesn't fit in the buffer.
+    BufferTooSmall,
+}
+
+/// Converts the result of a file syscall.
+fn file_result(result: i64) -> Result<usize, FileError> {
+    match result {
+        NOT_FOUND_ERROR => Err(FileError::NotFound),
+        result if result < 0 => Err(FileError::InvalidArgument),
+        result => Ok(result as usize),
+    }
+}
+
+/// Returns the size of the file in bytes.
+pub fn file_size(name: &str) -> Result<usize, FileError> {
+    let result = unsafe {
+        syscall!(
+            FILE_SIZE_SYSCALL_NUM,
+            name.as_ptr() as u64,
+            name.len() as u64
+        ) as i64
+    };
+
+    file_result(result)
+}
+
+/// Reads the file into the buffer, starting at the given offset.
+///
+/// Returns the number of bytes read, which is less than the length of the
+/// buffer at the end of the file.
+pub fn read_at(name: &str, buffer: &mut [u8], offset: u64) -> Result<usize, FileError> {
+    let result = unsafe {
+        syscall!(
+            READ_FILE_SYSCALL_NUM,
+            name.as_ptr() as u64,
+            name.len() as u64,
+            buffer.as_mut_ptr() as u64,
+            buffer.len() as u64,
+            offset
+        ) as i64
+    };
+
+    file_result(result)
+}
+
+/// Reads the whole file into the buffer and returns the read part.
+pub fn read<'a>(name: &str, buffer: &'a mut [u8]) -> Result<&'a [u8], FileError> {
+    let size = file_size(name)?;
+    if size > buffer.len() {
+        return Err(FileError::BufferTooSmall);
+    }
+
+    let length = read_at(name, &mut buffer[..size], 0)?;
+
+    Ok(&buffer[..length])
+}
@@ -0,0 +1,27 @@
+//! Provides futexes to build blocking synchronization primitives.
+
+use boring_core::syscall::{FUTEX_WAIT_SYSCALL_NUM, FUTEX_WAKE_SYSCALL_NUM};
+use core::sync::atomic::AtomicU32;
+use time::{timeout_argument, wait_result, WaitError};
+
+/// Blocks while the futex holds the expected value, until it is woken up or
+/// the timeout in nanoseconds expires.
+pub fn wait(futex: &AtomicU32, expected: u32, timeout: Option<u64>) -> Result<(), WaitError> {
+    let result = unsafe {
+        syscall!(
+            FUTEX_WAIT_SYSCALL_NUM,
+            futex as *const AtomicU32 as u64,
+            expected as u64,
+            timeout_argument(timeout)
+        ) as i64
+    };
+
+    wait_result(result).map(|_| ())
+}
+
+/// Wakes up to `count` threads waiting on the futex.
+///
+/// Returns the number of woken threads.
+pub fn wake(futex: &AtomicU32, count: usize) -> usize {
+    unsafe { syscall!(FUTEX_WAKE_SYSCALL_NUM, futex as *const AtomicU32 as u64, count as u64) as usize }
+}
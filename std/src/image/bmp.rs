@@ -0,0 +1,412 @@
+//! Decodes Windows bitmaps.
+//!
+//! Uncompressed bitmaps with 1, 4, 8, 16, 24 and 32 bits per pixel are
+//! supported, including bit field masks. Run length encoded bitmaps are not.
+
+use super::{argb, read_u16_le, read_u32_le, scale_to_u8, ImageError, ImageFormat, ImageInfo};
+
+/// The signature at the start of every bitmap file.
+const SIGNATURE: [u8; 2] = [b'B', b'M'];
+
+/// The size of the file header.
+const FILE_HEADER_SIZE: usize = 14;
+
+/// The size of the old OS/2 info header.
+const CORE_HEADER_SIZE: u32 = 12;
+
+/// The size of the basic Windows info header.
+const INFO_HEADER_SIZE: u32 = 40;
+
+/// Pixels are stored without compression.
+const COMPRESSION_RGB: u32 = 0;
+
+/// Pixels are stored without compression, using color masks.
+const COMPRESSION_BITFIELDS: u32 = 3;
+
+/// Pixels are stored without compression, using color masks with alpha.
+const COMPRESSION_ALPHA_BITFIELDS: u32 = 6;
+
+/// The largest supported dimension.
+const MAX_SIZE: i32 = 0x8000;
+
+/// A color channel stored under a bit mask.
+#[derive(Clone, Copy)]
+struct Mask {
+    /// The mask of the channel.
+    mask: u32,
+    /// The position of the lowest bit.
+    shift: u32,
+    /// The number of bits.
+    bits: u32,
+}
+
+impl Mask {
+    /// Creates a channel from the mask.
+    fn new(mask: u32) -> Mask {
+        if mask == 0 {
+            return Mask {
+                mask,
+                shift: 0,
+                bits: 0,
+            };
+        }
+
+        Mask {
+            mask,
+            shift: mask.trailing_zeros(),
+            bits: (mask >> mask.trailing_zeros()).count_ones(),
+        }
+    }
+
+    /// Returns the 8 bit value of the channel in the pixel.
+    fn extract(&self, pixel: u32) -> u8 {
+        scale_to_u8((pixel & self.mask) >> self.shift, self.bits)
+    }
+}
+
+/// The contents of the headers.
+struct Header {
+    /// The width in pixels.
+    width: u32,
+    /// The height in pixels.
+    height: u32,
+    /// Set if the first stored line is the top one.
+    top_down: bool,
+    /// The number of bits per pixel.
+    bits_per_pixel: u16,
+    /// The offset of the palette in the file.
+    palette_offset: usize,
+    /// The number of bytes per palette entry.
+    palette_entry_size: usize,
+    /// The number of palette entries.
+    palette_size: usize,
+    /// The offset of the pixels in the file.
+    pixel_offset: usize,
+    /// The red, green, blue and alpha masks of 16 and 32 bit pixels.
+    masks: [Mask; 4],
+}
+
+/// Returns true if the data starts with the bitmap signature.
+pub fn is_bmp(data: &[u8]) -> bool {
+    data.starts_with(&SIGNATURE)
+}
+
+/// Reads and checks the headers.
+fn header(data: &[u8]) -> Result<Header, ImageError> {
+    let pixel_offset = read_u32_le(data, 10)? as usize;
+    let header_size = read_u32_le(data, FILE_HEADER_SIZE)?;
+    let info = FILE_HEADER_SIZE;
+
+    let (width, height, bits_per_pixel, compression, colors_used) =
+        if header_size == CORE_HEADER_SIZE {
+            (
+                read_u16_le(data, info + 4)? as i32,
+                read_u16_le(data, info + 6)? as i32,
+                read_u16_le(data, info + 10)?,
+                COMPRESSION_RGB,
+                0,
+            )
+        } else if header_size >= INFO_HEADER_SIZE {
+            (
+                read_u32_le(data, info + 4)? as i32,
+                read_u32_le(data, info + 8)? as i32,
+                read_u16_le(data, info + 14)?,
+                read_u32_le(data, info + 16)?,
+                read_u32_le(data, info + 32)? as usize,
+            )
+        } else {
+            return Err(ImageError::Unsupported);
+        };
+
+    if width <= 0 || width > MAX_SIZE || height == 0 || height > MAX_SIZE || height < -MAX_SIZE {
+        return Err(ImageError::Corrupt);
+    }
+
+    let mut palette_offset = info + header_size as usize;
+
+    let masks = match (compression, bits_per_pixel) {
+        (COMPRESSION_RGB, 16) => [0x7c00, 0x03e0, 0x001f, 0],
+        (COMPRESSION_RGB, 32) => [0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0],
+        (COMPRESSION_RGB, 1) | (COMPRESSION_RGB, 4) | (COMPRESSION_RGB, 8)
+        | (COMPRESSION_RGB, 24) => [0; 4],
+        (COMPRESSION_BITFIELDS, 16)
+        | (COMPRESSION_BITFIELDS, 32)
+        | (COMPRESSION_ALPHA_BITFIELDS, 16)
+        | (COMPRESSION_ALPHA_BITFIELDS, 32) => {
+            // The basic header is followed by the masks, newer headers
+            // contain them.
+            let with_alpha = compression == COMPRESSION_ALPHA_BITFIELDS
+                || header_size >= INFO_HEADER_SIZE + 16;
+            if header_size == INFO_HEADER_SIZE {
+                palette_offset += if with_alpha { 16 } else { 12 };
+            }
+
+            [
+                read_u32_le(data, info + 40)?,
+                read_u32_le(data, info + 44)?,
+                read_u32_le(data, info + 48)?,
+                if with_alpha {
+                    read_u32_le(data, info + 52)?
+                } else {
+                    0
+                },
+            ]
+        }
+        _ => return Err(ImageError::Unsupported),
+    };
+
+    let palette_size = if bits_per_pixel <= 8 {
+        if colors_used == 0 || colors_used > 1 << bits_per_pixel {
+            1 << bits_per_pixel
+        } else {
+            colors_used
+        }
+    } else {
+        0
+    };
+
+    Ok(Header {
+        width: width as u32,
+        height: height.abs() as u32,
+        top_down: height < 0,
+        bits_per_pixel,
+        palette_offset,
+        palette_entry_size: if header_size == CORE_HEADER_SIZE { 3 } else { 4 },
+        palette_size,
+        pixel_offset,
+        masks: [
+            Mask::new(masks[0]),
+            Mask::new(masks[1]),
+            Mask::new(masks[2]),
+            Mask::new(masks[3]),
+        ],
+    })
+}
+
+/// Reads the size of the image.
+pub fn info(data: &[u8]) -> Result<ImageInfo, ImageError> {
+    let header = header(data)?;
+
+    Ok(ImageInfo {
+        format: ImageFormat::Bmp,
+        width: header.width,
+        height: header.height,
+        scratch_size: 0,
+    })
+}
+
+/// Decodes the image into the pixel buffer.
+pub fn decode(data: &[u8], info: &ImageInfo, pixels: &mut [u32]) -> Result<(), ImageError> {
+    let header = header(data)?;
+
+    let mut palette = [argb(0, 0, 0, 255); 256];
+    for index in 0..header.palette_size {
+        let offset = header.palette_offset + index * header.palette_entry_size;
+        if offset + 3 > data.len() {
+            return Err(ImageError::Truncated);
+        }
+
+        palette[index] = argb(data[offset + 2], data[offset + 1], data[offset], 255);
+    }
+
+    // Lines are padded to multiples of four bytes.
+    let line_length = (info.width as usize * header.bits_per_pixel as usize + 31) / 32 * 4;
+    let size = line_length * info.height as usize;
+
+    if header.pixel_offset > data.len() || data.len() - header.pixel_offset < size {
+        return Err(ImageError::Truncated);
+    }
+
+    let has_alpha = header.masks[3].mask != 0;
+
+    for line in 0..info.height as usize {
+        let start = header.pixel_offset + line * line_length;
+        let stored = &data[start..start + line_length];
+        let y = if header.top_down {
+            line
+        } else {
+            info.height as usize - 1 - line
+        };
+        let row = &mut pixels[y * info.width as usize..(y + 1) * info.width as usize];
+
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = match header.bits_per_pixel {
+                1 | 4 | 8 => {
+                    let bits = header.bits_per_pixel as usize;
+                    let bit = x * bits;
+                    let shift = 8 - bits - bit % 8;
+                    let index = (stored[bit / 8] >> shift) as usize & ((1 << bits) - 1);
+
+                    palette[index]
+                }
+                24 => argb(stored[3 * x + 2], stored[3 * x + 1], stored[3 * x], 255),
+                _ => {
+                    let value = if header.bits_per_pixel == 16 {
+                        stored[2 * x] as u32 | (stored[2 * x + 1] as u32) << 8
+                    } else {
+                        stored[4 * x] as u32 | (stored[4 * x + 1] as u32) << 8
+                            | (stored[4 * x + 2] as u32) << 16
+                            | (stored[4 * x + 3] as u32) << 24
+                    };
+
+                    let alpha = if has_alpha {
+                        header.masks[3].extract(value)
+                    } else {
+                        255
+                    };
+
+                    argb(
+                        header.masks[0].extract(value),
+                        header.masks[1].extract(value),
+                        header.masks[2].extract(value),
+                        alpha,
+                    )
+                }
+            };
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Appends the little endian u32.
+    fn push_u32(file: &mut Vec<u8>, value: u32) {
+        file.extend_from_slice(&[
+            value as u8,
+            (value >> 8) as u8,
+            (value >> 16) as u8,
+            (value >> 24) as u8,
+        ]);
+    }
+
+    /// Builds a file with an info header, followed by `extra` and the pixels.
+    fn bmp(
+        width: i32,
+        height: i32,
+        bits: u16,
+        compression: u32,
+        extra: &[u8],
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let pixel_offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE as usize + extra.len();
+
+        let mut file = Vec::new();
+        file.extend_from_slice(&SIGNATURE);
+        push_u32(&mut file, (pixel_offset + pixels.len()) as u32);
+        push_u32(&mut file, 0);
+        push_u32(&mut file, pixel_offset as u32);
+
+        push_u32(&mut file, INFO_HEADER_SIZE);
+        push_u32(&mut file, width as u32);
+        push_u32(&mut file, height as u32);
+        file.extend_from_slice(&[1, 0, bits as u8, (bits >> 8) as u8]);
+        push_u32(&mut file, compression);
+        for _ in 0..5 {
+            push_u32(&mut file, 0);
+        }
+
+        file.extend_from_slice(extra);
+        file.extend_from_slice(pixels);
+
+        file
+    }
+
+    fn decode_file(file: &[u8]) -> Result<Vec<u32>, ImageError> {
+        let info = info(file)?;
+        let mut pixels = vec![0; info.pixel_count()];
+
+        decode(file, &info, &mut pixels)?;
+
+        Ok(pixels)
+    }
+
+    /// Tests a bottom-up 24 bit image with padded lines.
+    #[test]
+    fn test_true_color_round_trip() {
+        let pixels = [
+            0x30, 0x20, 0x10, 0x60, 0x50, 0x40, 0x90, 0x80, 0x70, 0, 0, 0, // Bottom line.
+            0xff, 0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0xff, 0, 0, 0, // Top line.
+        ];
+        let file = bmp(3, 2, 24, COMPRESSION_RGB, &[], &pixels);
+
+        assert_eq!(
+            info(&file),
+            Ok(ImageInfo {
+                format: ImageFormat::Bmp,
+                width: 3,
+                height: 2,
+                scratch_size: 0,
+            })
+        );
+        assert_eq!(
+            decode_file(&file),
+            Ok(vec![
+                argb(0x00, 0x00, 0xff, 255),
+                argb(0x00, 0xff, 0x00, 255),
+                argb(0xff, 0x00, 0x00, 255),
+                argb(0x10, 0x20, 0x30, 255),
+                argb(0x40, 0x50, 0x60, 255),
+                argb(0x70, 0x80, 0x90, 255),
+            ])
+        );
+    }
+
+    /// Tests a top-down 4 bit image with a palette.
+    #[test]
+    fn test_palette_round_trip() {
+        // Blue, green and red entries, followed by the reserved byte.
+        let palette = [0xff, 0, 0, 0, 0, 0xff, 0, 0, 0, 0, 0xff, 0];
+        let pixels = [0x01, 0x20, 0, 0, 0x22, 0x10, 0, 0];
+        let mut file = bmp(3, -2, 4, COMPRESSION_RGB, &palette, &pixels);
+        // Only three colors are used.
+        file[FILE_HEADER_SIZE + 32] = 3;
+
+        let blue = argb(0, 0, 0xff, 255);
+        let green = argb(0, 0xff, 0, 255);
+        let red = argb(0xff, 0, 0, 255);
+        assert_eq!(decode_file(&file), Ok(vec![blue, green, red, red, red, green]));
+    }
+
+    /// Tests a 32 bit image with an alpha mask.
+    #[test]
+    fn test_bitfields_round_trip() {
+        let mut masks = Vec::new();
+        for &mask in &[0x0000_00ff, 0x0000_ff00, 0x00ff_0000, 0xff00_0000] {
+            push_u32(&mut masks, mask);
+        }
+        let pixels = [0x10, 0x20, 0x30, 0x40, 0xff, 0x00, 0x00, 0x80];
+        let file = bmp(2, 1, 32, COMPRESSION_ALPHA_BITFIELDS, &masks, &pixels);
+
+        assert_eq!(
+            decode_file(&file),
+            Ok(vec![argb(0x10, 0x20, 0x30, 0x40), argb(0xff, 0x00, 0x00, 0x80)])
+        );
+    }
+
+    /// Tests truncated files, invalid sizes and run length encoding.
+    #[test]
+    fn test_corrupt() {
+        let pixels = [0; 24];
+
+        let file = bmp(3, 2, 24, COMPRESSION_RGB, &[], &pixels[..20]);
+        assert_eq!(decode_file(&file), Err(ImageError::Truncated));
+
+        let file = bmp(3, 2, 24, COMPRESSION_RGB, &[], &pixels);
+        assert_eq!(info(&file[..20]), Err(ImageError::Truncated));
+
+        let file = bmp(0, 2, 24, COMPRESSION_RGB, &[], &pixels);
+        assert_eq!(info(&file), Err(ImageError::Corrupt));
+
+        let file = bmp(3, MAX_SIZE + 1, 24, COMPRESSION_RGB, &[], &pixels);
+        assert_eq!(info(&file), Err(ImageError::Corrupt));
+
+        // Run length encoded bitmaps aren't supported.
+        let file = bmp(3, 2, 8, 1, &[], &pixels);
+        assert_eq!(info(&file), Err(ImageError::Unsupported));
+    }
+}
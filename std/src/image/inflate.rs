@@ -0,0 +1,530 @@
+//! Decompresses zlib streams.
+//!
+//! The whole stream is inflated into an output buffer, which also serves as
+//! the window for back references.
+
+use super::ImageError;
+
+/// The maximum length of a Huffman code in bits.
+const MAX_CODE_LENGTH: usize = 15;
+
+/// The number of literal and length codes.
+const LITERAL_LENGTH_CODES: usize = 288;
+
+/// The number of distance codes.
+const DISTANCE_CODES: usize = 30;
+
+/// The symbol that ends a block.
+const END_OF_BLOCK: u16 = 256;
+
+/// The compression method of zlib streams using deflate.
+const ZLIB_DEFLATE: u8 = 8;
+
+/// Set in the zlib flags if a preset dictionary is used.
+const ZLIB_PRESET_DICTIONARY: u8 = 0x20;
+
+/// The modulus of the Adler-32 checksum.
+const ADLER_MODULUS: u32 = 65521;
+
+/// The base lengths of the length codes.
+static LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+
+/// The number of extra bits of the length codes.
+static LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// The base distances of the distance codes.
+static DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+/// The number of extra bits of the distance codes.
+static DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12,
+    13, 13,
+];
+
+/// The order in which the lengths of the code length codes are stored.
+static CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Reads bits from a byte stream, starting with the lowest bit.
+struct BitReader<I: Iterator<Item = u8>> {
+    /// The remaining bytes.
+    input: I,
+    /// Bits that were read but not consumed yet.
+    buffer: u32,
+    /// The number of bits in the buffer.
+    count: u32,
+}
+
+impl<I: Iterator<Item = u8>> BitReader<I> {
+    /// Returns the next `count` bits, with the first bit in the lowest place.
+    fn bits(&mut self, count: u32) -> Result<u32, ImageError> {
+        while self.count < count {
+            let byte = self.input.next().ok_or(ImageError::Truncated)?;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+
+        let value = self.buffer & ((1u64 << count) - 1) as u32;
+        self.buffer >>= count;
+        self.count -= count;
+
+        Ok(value)
+    }
+
+    /// Discards the bits up to the next byte boundary.
+    fn align(&mut self) {
+        let remainder = self.count % 8;
+        self.buffer >>= remainder;
+        self.count -= remainder;
+    }
+
+    /// Returns the next whole byte.
+    fn byte(&mut self) -> Result<u8, ImageError> {
+        self.bits(8).map(|byte| byte as u8)
+    }
+}
+
+/// A canonical Huffman code.
+struct Huffman {
+    /// The number of codes of each length.
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    /// The symbols ordered by their codes.
+    symbols: [u16; LITERAL_LENGTH_CODES],
+}
+
+impl Huffman {
+    /// Builds the code from the code length of every symbol.
+    ///
+    /// Incomplete codes are allowed, since a single distance code is valid.
+    fn new(lengths: &[u8]) -> Result<Huffman, ImageError> {
+        let mut huffman = Huffman {
+            counts: [0; MAX_CODE_LENGTH + 1],
+            symbols: [0; LITERAL_LENGTH_CODES],
+        };
+
+        for &length in lengths {
+            huffman.counts[length as usize] += 1;
+        }
+
+        let mut left: i32 = 1;
+        for length in 1..MAX_CODE_LENGTH + 1 {
+            left = (left << 1) - huffman.counts[length] as i32;
+            if left < 0 {
+                return Err(ImageError::Corrupt);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 1];
+        for length in 1..MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + huffman.counts[length];
+        }
+
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                huffman.symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(huffman)
+    }
+
+    /// Reads the next symbol.
+    fn decode<I: Iterator<Item = u8>>(&self, reader: &mut BitReader<I>) -> Result<u16, ImageError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for length in 1..MAX_CODE_LENGTH + 1 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(ImageError::Corrupt)
+    }
+}
+
+/// Writes decompressed bytes to the output buffer.
+struct Output<'a> {
+    /// The buffer.
+    buffer: &'a mut [u8],
+    /// The number of bytes written.
+    position: usize,
+}
+
+impl<'a> Output<'a> {
+    /// Appends a byte.
+    fn push(&mut self, byte: u8) -> Result<(), ImageError> {
+        if self.position == self.buffer.len() {
+            return Err(ImageError::Corrupt);
+        }
+
+        self.buffer[self.position] = byte;
+        self.position += 1;
+
+        Ok(())
+    }
+
+    /// Appends `length` bytes starting `distance` bytes back.
+    fn copy(&mut self, distance: usize, length: usize) -> Result<(), ImageError> {
+        if distance > self.position || self.buffer.len() - self.position < length {
+            return Err(ImageError::Corrupt);
+        }
+
+        // The source may overlap the copied bytes, so they are copied one by
+        // one.
+        for _ in 0..length {
+            self.buffer[self.position] = self.buffer[self.position - distance];
+            self.position += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Decompresses the zlib stream into the output buffer.
+///
+/// Returns the number of decompressed bytes. The stream must fit in the
+/// buffer.
+pub fn decompress<I: Iterator<Item = u8>>(input: I, output: &mut [u8]) -> Result<usize, ImageError> {
+    let mut reader = BitReader {
+        input,
+        buffer: 0,
+        count: 0,
+    };
+
+    let method = reader.byte()?;
+    let flags = reader.byte()?;
+
+    if method & 0x0f != ZLIB_DEFLATE || (method as u16 * 256 + flags as u16) % 31 != 0
+        || flags & ZLIB_PRESET_DICTIONARY != 0
+    {
+        return Err(ImageError::Corrupt);
+    }
+
+    let mut output = Output {
+        buffer: output,
+        position: 0,
+    };
+
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut output)?,
+            1 => fixed_block(&mut reader, &mut output)?,
+            2 => dynamic_block(&mut reader, &mut output)?,
+            _ => return Err(ImageError::Corrupt),
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    reader.align();
+    let mut checksum = 0;
+    for _ in 0..4 {
+        checksum = checksum << 8 | reader.byte()? as u32;
+    }
+
+    if checksum != adler32(&output.buffer[..output.position]) {
+        return Err(ImageError::Corrupt);
+    }
+
+    Ok(output.position)
+}
+
+/// Copies an uncompressed block.
+fn stored_block<I: Iterator<Item = u8>>(
+    reader: &mut BitReader<I>,
+    output: &mut Output,
+) -> Result<(), ImageError> {
+    reader.align();
+
+    let length = reader.bits(16)?;
+    let inverted_length = reader.bits(16)?;
+    if length != !inverted_length & 0xffff {
+        return Err(ImageError::Corrupt);
+    }
+
+    for _ in 0..length {
+        output.push(reader.byte()?)?;
+    }
+
+    Ok(())
+}
+
+/// Decodes a block compressed with the fixed codes.
+fn fixed_block<I: Iterator<Item = u8>>(
+    reader: &mut BitReader<I>,
+    output: &mut Output,
+) -> Result<(), ImageError> {
+    let mut lengths = [0u8; LITERAL_LENGTH_CODES];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0...143 => 8,
+            144...255 => 9,
+            256...279 => 7,
+            _ => 8,
+        };
+    }
+
+    let literal_length = Huffman::new(&lengths)?;
+    let distance = Huffman::new(&[5; DISTANCE_CODES])?;
+
+    compressed_block(reader, output, &literal_length, &distance)
+}
+
+/// Decodes a block compressed with codes that are stored in the block.
+fn dynamic_block<I: Iterator<Item = u8>>(
+    reader: &mut BitReader<I>,
+    output: &mut Output,
+) -> Result<(), ImageError> {
+    let literal_length_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    if literal_length_count > 286 || distance_count > DISTANCE_CODES {
+        return Err(ImageError::Corrupt);
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length = Huffman::new(&code_lengths)?;
+
+    // The literal/length and distance code lengths form one sequence.
+    let mut lengths = [0u8; LITERAL_LENGTH_CODES + DISTANCE_CODES];
+    let total = literal_length_count + distance_count;
+    let mut index = 0;
+
+    while index < total {
+        let symbol = code_length.decode(reader)?;
+
+        let (value, repeat) = match symbol {
+            0...15 => (symbol as u8, 1),
+            16 => {
+                if index == 0 {
+                    return Err(ImageError::Corrupt);
+                }
+                (lengths[index - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+
+        if index + repeat > total {
+            return Err(ImageError::Corrupt);
+        }
+
+        for length in &mut lengths[index..index + repeat] {
+            *length = value;
+        }
+        index += repeat;
+    }
+
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(ImageError::Corrupt);
+    }
+
+    let literal_length = Huffman::new(&lengths[..literal_length_count])?;
+    let distance = Huffman::new(&lengths[literal_length_count..total])?;
+
+    compressed_block(reader, output, &literal_length, &distance)
+}
+
+/// Decodes the symbols of a compressed block.
+fn compressed_block<I: Iterator<Item = u8>>(
+    reader: &mut BitReader<I>,
+    output: &mut Output,
+    literal_length: &Huffman,
+    distance: &Huffman,
+) -> Result<(), ImageError> {
+    loop {
+        let symbol = literal_length.decode(reader)?;
+
+        if symbol < END_OF_BLOCK {
+            output.push(symbol as u8)?;
+            continue;
+        }
+
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+
+        let index = (symbol - END_OF_BLOCK - 1) as usize;
+        if index >= LENGTH_BASES.len() {
+            return Err(ImageError::Corrupt);
+        }
+        let length = LENGTH_BASES[index] as usize
+            + reader.bits(LENGTH_EXTRA_BITS[index] as u32)? as usize;
+
+        let index = distance.decode(reader)? as usize;
+        if index >= DISTANCE_BASES.len() {
+            return Err(ImageError::Corrupt);
+        }
+        let distance = DISTANCE_BASES[index] as usize
+            + reader.bits(DISTANCE_EXTRA_BITS[index] as u32)? as usize;
+
+        output.copy(distance, length)?;
+    }
+}
+
+/// Computes the Adler-32 checksum of the data.
+fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+
+    // The sums can't overflow within 5552 bytes.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= ADLER_MODULUS;
+        b %= ADLER_MODULUS;
+    }
+
+    b << 16 | a
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Compresses the data into a zlib stream of stored blocks.
+    pub fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut stream = vec![0x78, 0x01];
+
+        let mut blocks = data.chunks(0xffff).peekable();
+        if blocks.peek().is_none() {
+            stream.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+        }
+        while let Some(block) = blocks.next() {
+            let length = block.len() as u16;
+            stream.push(if blocks.peek().is_none() { 0x01 } else { 0x00 });
+            stream.extend_from_slice(&[length as u8, (length >> 8) as u8]);
+            stream.extend_from_slice(&[!length as u8, (!length >> 8) as u8]);
+            stream.extend_from_slice(block);
+        }
+
+        let checksum = adler32(data);
+        stream.extend_from_slice(&[
+            (checksum >> 24) as u8,
+            (checksum >> 16) as u8,
+            (checksum >> 8) as u8,
+            checksum as u8,
+        ]);
+
+        stream
+    }
+
+    /// `b"hello hello hello hello!"` compressed with a fixed Huffman block.
+    static FIXED: [u8; 17] = [
+        0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x15, 0x01, 0x70, 0xd5,
+        0x08, 0xd2,
+    ];
+
+    /// The result of `dynamic_source` compressed with a dynamic Huffman block.
+    static DYNAMIC: [u8; 61] = [
+        0x78, 0xda, 0x8d, 0x8d, 0xc1, 0x11, 0x00, 0x20, 0x0c, 0xc2, 0x66, 0xad, 0xdd, 0x7f, 0x07,
+        0x03, 0xe5, 0x3c, 0xfd, 0x59, 0x29, 0x41, 0x7d, 0x50, 0xab, 0xcb, 0x92, 0x0d, 0x72, 0xc8,
+        0xbc, 0x18, 0x8f, 0xb2, 0x64, 0x99, 0x1d, 0x40, 0x4d, 0x9d, 0x99, 0x3b, 0x1f, 0xce, 0x02,
+        0x8e, 0x75, 0x67, 0x6f, 0x7d, 0x97, 0xbb, 0xf7, 0xe9, 0x4e, 0xf5, 0x06, 0x0f, 0x2d, 0x4c,
+        0x8d,
+    ];
+
+    /// Returns the data of `DYNAMIC`.
+    fn dynamic_source() -> Vec<u8> {
+        (0..200u32).map(|i| b'a' + ((i * i * 3 + i) % 251 % 3) as u8).collect()
+    }
+
+    /// Tests stored blocks, including one split across two blocks.
+    #[test]
+    fn test_stored_round_trip() {
+        let data: Vec<u8> = (0..70000u32).map(|i| (i * 7 + i / 256) as u8).collect();
+        let mut output = vec![0; data.len()];
+
+        let stream = zlib(&data);
+        assert_eq!(decompress(stream.iter().cloned(), &mut output), Ok(data.len()));
+        assert_eq!(output, data);
+
+        let stream = zlib(&[]);
+        assert_eq!(decompress(stream.iter().cloned(), &mut output), Ok(0));
+    }
+
+    /// Tests a block using the fixed codes.
+    #[test]
+    fn test_fixed_block() {
+        let mut output = [0; 32];
+        assert_eq!(decompress(FIXED.iter().cloned(), &mut output), Ok(24));
+        assert_eq!(&output[..24], b"hello hello hello hello!");
+    }
+
+    /// Tests a block with its own codes.
+    #[test]
+    fn test_dynamic_block() {
+        let source = dynamic_source();
+        let mut output = [0; 256];
+        assert_eq!(decompress(DYNAMIC.iter().cloned(), &mut output), Ok(source.len()));
+        assert_eq!(&output[..source.len()], &source[..]);
+    }
+
+    /// Tests checksum, header and block type errors, truncated input and too
+    /// small output buffers.
+    #[test]
+    fn test_corrupt() {
+        let mut output = [0; 32];
+
+        let mut stream = FIXED;
+        stream[16] ^= 1;
+        assert_eq!(decompress(stream.iter().cloned(), &mut output), Err(ImageError::Corrupt));
+
+        let mut stream = FIXED;
+        stream[1] ^= 1;
+        assert_eq!(decompress(stream.iter().cloned(), &mut output), Err(ImageError::Corrupt));
+
+        let stream = FIXED;
+        assert_eq!(
+            decompress(stream[..10].iter().cloned(), &mut output),
+            Err(ImageError::Truncated)
+        );
+
+        // Reserved block type.
+        let stream = [0x78, 0x01, 0x07];
+        assert_eq!(decompress(stream.iter().cloned(), &mut output), Err(ImageError::Corrupt));
+
+        let mut output = [0; 16];
+        assert_eq!(decompress(FIXED.iter().cloned(), &mut output), Err(ImageError::Corrupt));
+    }
+
+    /// Tests that the inverted length of stored blocks is checked.
+    #[test]
+    fn test_corrupt_stored_length() {
+        let mut stream = zlib(b"abc");
+        stream[5] ^= 1;
+
+        let mut output = [0; 8];
+        assert_eq!(decompress(stream.iter().cloned(), &mut output), Err(ImageError::Corrupt));
+    }
+}
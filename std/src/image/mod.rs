@@ -0,0 +1,165 @@
+//! Decodes PNG, BMP and TGA images.
+//!
+//! Images are decoded into caller provided pixel buffers holding
+//! `0xAARRGGBB` values, which can be drawn with a `screen::Canvas`. PNG
+//! images additionally need a scratch buffer for the decompressed data, its
+//! size is returned by `scratch_size`.
+
+mod bmp;
+mod inflate;
+mod png;
+mod tga;
+
+use fs::{self, FileError};
+use screen::Image;
+
+/// The errors that can occur while decoding images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    /// The data isn't a PNG, BMP or TGA image.
+    UnknownFormat,
+    /// The data ends before the image does.
+    Truncated,
+    /// The image uses a feature that isn't supported.
+    Unsupported,
+    /// The image data is invalid.
+    Corrupt,
+    /// The pixel or scratch buffer is too small for the image.
+    BufferTooSmall,
+    /// The image file couldn't be read.
+    File(FileError),
+}
+
+/// The supported image formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Portable Network Graphics.
+    Png,
+    /// Windows bitmap.
+    Bmp,
+    /// Truevision TGA.
+    Tga,
+}
+
+/// The properties of an image, read from its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+    /// The format of the image.
+    pub format: ImageFormat,
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// The number of bytes needed to decode the image besides the pixels.
+    pub scratch_size: usize,
+}
+
+impl ImageInfo {
+    /// Returns the number of pixels of the image.
+    pub fn pixel_count(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+}
+
+/// Reads the header of the image.
+pub fn info(data: &[u8]) -> Result<ImageInfo, ImageError> {
+    if png::is_png(data) {
+        png::info(data)
+    } else if bmp::is_bmp(data) {
+        bmp::info(data)
+    } else if tga::is_tga(data) {
+        tga::info(data)
+    } else {
+        Err(ImageError::UnknownFormat)
+    }
+}
+
+/// Returns the size of the scratch buffer needed to decode the image.
+pub fn scratch_size(data: &[u8]) -> Result<usize, ImageError> {
+    info(data).map(|info| info.scratch_size)
+}
+
+/// Decodes the image into the pixel buffer.
+///
+/// The pixels are stored line by line, starting at the top left. The scratch
+/// buffer must hold at least `scratch_size` bytes.
+pub fn decode<'a>(
+    data: &[u8],
+    pixels: &'a mut [u32],
+    scratch: &mut [u8],
+) -> Result<Image<'a>, ImageError> {
+    let info = info(data)?;
+
+    if pixels.len() < info.pixel_count() || scratch.len() < info.scratch_size {
+        return Err(ImageError::BufferTooSmall);
+    }
+
+    let pixels = &mut pixels[..info.pixel_count()];
+    match info.format {
+        ImageFormat::Png => png::decode(data, &info, pixels, &mut scratch[..info.scratch_size])?,
+        ImageFormat::Bmp => bmp::decode(data, &info, pixels)?,
+        ImageFormat::Tga => tga::decode(data, &info, pixels)?,
+    }
+
+    Ok(Image::new(info.width, info.height, pixels).expect("The pixel buffer is too small."))
+}
+
+/// Reads the image file from the initramfs and decodes it.
+///
+/// `file_buffer` has to hold the whole file.
+pub fn load<'a>(
+    path: &str,
+    file_buffer: &mut [u8],
+    pixels: &'a mut [u32],
+    scratch: &mut [u8],
+) -> Result<Image<'a>, ImageError> {
+    let data = fs::read(path, file_buffer).map_err(ImageError::File)?;
+
+    decode(data, pixels, scratch)
+}
+
+/// Reads the little endian u16 at the given offset.
+fn read_u16_le(data: &[u8], offset: usize) -> Result<u16, ImageError> {
+    if data.len() < 2 || offset > data.len() - 2 {
+        return Err(ImageError::Truncated);
+    }
+    let bytes = &data[offset..offset + 2];
+
+    Ok(bytes[0] as u16 | (bytes[1] as u16) << 8)
+}
+
+/// Reads the little endian u32 at the given offset.
+fn read_u32_le(data: &[u8], offset: usize) -> Result<u32, ImageError> {
+    if data.len() < 4 || offset > data.len() - 4 {
+        return Err(ImageError::Truncated);
+    }
+    let bytes = &data[offset..offset + 4];
+
+    Ok(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16
+        | (bytes[3] as u32) << 24)
+}
+
+/// Reads the big endian u32 at the given offset.
+fn read_u32_be(data: &[u8], offset: usize) -> Result<u32, ImageError> {
+    if data.len() < 4 || offset > data.len() - 4 {
+        return Err(ImageError::Truncated);
+    }
+    let bytes = &data[offset..offset + 4];
+
+    Ok((bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8
+        | bytes[3] as u32)
+}
+
+/// Returns the `0xAARRGGBB` value of the given channels.
+fn argb(red: u8, green: u8, blue: u8, alpha: u8) -> u32 {
+    (alpha as u32) << 24 | (red as u32) << 16 | (green as u32) << 8 | blue as u32
+}
+
+/// Scales a value with the given number of bits to 8 bits.
+fn scale_to_u8(value: u32, bits: u32) -> u8 {
+    match bits {
+        0 => 0,
+        bits if bits >= 8 => (value >> (bits - 8)) as u8,
+        bits => (value * 255 / ((1 << bits) - 1)) as u8,
+    }
+}
@@ -0,0 +1,795 @@
+//! Decodes PNG images.
+//!
+//! All color types and bit depths are supported, including palettes,
+//! transparency chunks and Adam7 interlacing. Checksums of the chunks are not
+//! verified, the checksum of the compressed data is.
+
+use super::inflate;
+use super::{argb, read_u32_be, ImageError, ImageFormat, ImageInfo};
+
+/// The signature at the start of every PNG file.
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// The size of the length and type fields before the chunk data.
+const CHUNK_HEADER_SIZE: usize = 8;
+
+/// The size of the checksum after the chunk data.
+const CHUNK_CRC_SIZE: usize = 4;
+
+/// The size of the header chunk data.
+const HEADER_SIZE: usize = 13;
+
+/// Grayscale pixels.
+const COLOR_TYPE_GRAY: u8 = 0;
+
+/// Red, green and blue pixels.
+const COLOR_TYPE_RGB: u8 = 2;
+
+/// Palette indices.
+const COLOR_TYPE_PALETTE: u8 = 3;
+
+/// Grayscale pixels with alpha.
+const COLOR_TYPE_GRAY_ALPHA: u8 = 4;
+
+/// Red, green and blue pixels with alpha.
+const COLOR_TYPE_RGBA: u8 = 6;
+
+/// The interlace method of images stored in seven passes.
+const INTERLACE_ADAM7: u8 = 1;
+
+/// The start column, start line, column step and line step of the Adam7
+/// passes.
+static ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// The passes of images that aren't interlaced.
+static SINGLE_PASS: [(u32, u32, u32, u32); 1] = [(0, 0, 1, 1)];
+
+/// The contents of the header chunk.
+struct Header {
+    /// The width in pixels.
+    width: u32,
+    /// The height in pixels.
+    height: u32,
+    /// The number of bits per sample.
+    bit_depth: u8,
+    /// How the samples form pixels.
+    color_type: u8,
+    /// Set if the image is interlaced.
+    interlaced: bool,
+}
+
+impl Header {
+    /// Returns the number of samples per pixel.
+    fn channels(&self) -> u32 {
+        match self.color_type {
+            COLOR_TYPE_RGB => 3,
+            COLOR_TYPE_GRAY_ALPHA => 2,
+            COLOR_TYPE_RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    /// Returns the number of bits per pixel.
+    fn bits_per_pixel(&self) -> u32 {
+        self.channels() * self.bit_depth as u32
+    }
+
+    /// Returns the number of bytes of a line with the given width, without
+    /// the filter type.
+    fn line_length(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel() as usize + 7) / 8
+    }
+
+    /// Returns the passes the image is stored in.
+    fn passes(&self) -> &'static [(u32, u32, u32, u32)] {
+        if self.interlaced {
+            &ADAM7_PASSES
+        } else {
+            &SINGLE_PASS
+        }
+    }
+
+    /// Returns the number of bytes of the decompressed image data.
+    ///
+    /// Returns `None` if the size doesn't fit into a `usize`.
+    fn data_size(&self) -> Option<usize> {
+        let mut size: usize = 0;
+
+        for &pass in self.passes() {
+            let (width, height) = pass_size(self, pass);
+            if width == 0 {
+                continue;
+            }
+
+            let line_bits = (width as usize).checked_mul(self.bits_per_pixel() as usize)?;
+            let line_length = line_bits.checked_add(7)? / 8;
+            let pass_size = (height as usize).checked_mul(line_length.checked_add(1)?)?;
+            size = size.checked_add(pass_size)?;
+        }
+
+        Some(size)
+    }
+}
+
+/// A chunk of the file.
+struct Chunk<'a> {
+    /// The type of the chunk.
+    kind: &'a [u8],
+    /// The data of the chunk.
+    data: &'a [u8],
+}
+
+/// Iterates over the chunks of the file.
+struct Chunks<'a> {
+    /// The file.
+    data: &'a [u8],
+    /// The offset of the next chunk.
+    offset: usize,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>, ImageError>;
+
+    fn next(&mut self) -> Option<Result<Chunk<'a>, ImageError>> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+
+        let length = match read_u32_be(self.data, self.offset) {
+            Ok(length) => length as usize,
+            Err(error) => return Some(Err(error)),
+        };
+
+        let start = self.offset + CHUNK_HEADER_SIZE;
+        if length > self.data.len() || start + length + CHUNK_CRC_SIZE > self.data.len() {
+            self.offset = self.data.len();
+            return Some(Err(ImageError::Truncated));
+        }
+
+        self.offset = start + length + CHUNK_CRC_SIZE;
+
+        Some(Ok(Chunk {
+            kind: &self.data[start - 4..start],
+            data: &self.data[start..start + length],
+        }))
+    }
+}
+
+/// Iterates over the bytes of all image data chunks.
+struct ImageData<'a> {
+    /// The remaining chunks.
+    chunks: Chunks<'a>,
+    /// The remaining bytes of the current chunk.
+    current: &'a [u8],
+}
+
+impl<'a> Iterator for ImageData<'a> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        while self.current.is_empty() {
+            match self.chunks.next() {
+                Some(Ok(chunk)) => {
+                    if chunk.kind == b"IDAT" {
+                        self.current = chunk.data;
+                    } else if chunk.kind == b"IEND" {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        let byte = self.current[0];
+        self.current = &self.current[1..];
+
+        Some(byte)
+    }
+}
+
+/// Returns true if the data starts with the PNG signature.
+pub fn is_png(data: &[u8]) -> bool {
+    data.starts_with(&SIGNATURE)
+}
+
+/// Returns the chunks of the file.
+fn chunks(data: &[u8]) -> Chunks {
+    Chunks {
+        data,
+        offset: SIGNATURE.len(),
+    }
+}
+
+/// Reads and checks the header chunk.
+fn header(data: &[u8]) -> Result<Header, ImageError> {
+    let chunk = match chunks(data).next() {
+        Some(chunk) => chunk?,
+        None => return Err(ImageError::Truncated),
+    };
+
+    if chunk.kind != b"IHDR" || chunk.data.len() < HEADER_SIZE {
+        return Err(ImageError::Corrupt);
+    }
+
+    let header = Header {
+        width: read_u32_be(chunk.data, 0)?,
+        height: read_u32_be(chunk.data, 4)?,
+        bit_depth: chunk.data[8],
+        color_type: chunk.data[9],
+        interlaced: chunk.data[12] == INTERLACE_ADAM7,
+    };
+
+    let valid_depth = match header.color_type {
+        COLOR_TYPE_GRAY => [1, 2, 4, 8, 16].contains(&header.bit_depth),
+        COLOR_TYPE_PALETTE => [1, 2, 4, 8].contains(&header.bit_depth),
+        COLOR_TYPE_RGB | COLOR_TYPE_GRAY_ALPHA | COLOR_TYPE_RGBA => {
+            [8, 16].contains(&header.bit_depth)
+        }
+        _ => return Err(ImageError::Corrupt),
+    };
+
+    // Compression and filter method 0 are the only ones defined.
+    if !valid_depth || chunk.data[10] != 0 || chunk.data[11] != 0 || chunk.data[12] > 1 {
+        return Err(ImageError::Unsupported);
+    }
+
+    if header.width == 0 || header.height == 0 {
+        return Err(ImageError::Corrupt);
+    }
+
+    Ok(header)
+}
+
+/// Reads the size of the image.
+pub fn info(data: &[u8]) -> Result<ImageInfo, ImageError> {
+    let header = header(data)?;
+
+    Ok(ImageInfo {
+        format: ImageFormat::Png,
+        width: header.width,
+        height: header.height,
+        scratch_size: header.data_size().ok_or(ImageError::Unsupported)?,
+    })
+}
+
+/// Returns the width and height of the given pass.
+fn pass_size(header: &Header, pass: (u32, u32, u32, u32)) -> (u32, u32) {
+    let (start_x, start_y, step_x, step_y) = pass;
+
+    if header.width <= start_x || header.height <= start_y {
+        return (0, 0);
+    }
+
+    // Rounds up without overflowing for sizes close to the maximum.
+    (
+        (header.width - start_x - 1) / step_x + 1,
+        (header.height - start_y - 1) / step_y + 1,
+    )
+}
+
+/// The colors used to convert samples to pixels.
+struct Colors {
+    /// The palette of indexed images, including the transparency.
+    palette: [u32; 256],
+    /// The number of palette entries.
+    palette_size: usize,
+    /// The samples of the color that is fully transparent, if there is one.
+    transparent: Option<[u16; 3]>,
+}
+
+/// Decodes the image into the pixel buffer, using the scratch buffer for the
+/// decompressed data.
+pub fn decode(
+    data: &[u8],
+    info: &ImageInfo,
+    pixels: &mut [u32],
+    scratch: &mut [u8],
+) -> Result<(), ImageError> {
+    let header = header(data)?;
+    let mut colors = Colors {
+        palette: [argb(0, 0, 0, 255); 256],
+        palette_size: 0,
+        transparent: None,
+    };
+
+    for chunk in chunks(data) {
+        let chunk = chunk?;
+
+        if chunk.kind == b"PLTE" {
+            colors.palette_size = chunk.data.len() / 3;
+            if colors.palette_size > 256 {
+                return Err(ImageError::Corrupt);
+            }
+
+            for (index, entry) in chunk.data.chunks(3).take(colors.palette_size).enumerate() {
+                colors.palette[index] = argb(entry[0], entry[1], entry[2], 255);
+            }
+        } else if chunk.kind == b"tRNS" {
+            read_transparency(&header, chunk.data, &mut colors)?;
+        } else if chunk.kind == b"IDAT" || chunk.kind == b"IEND" {
+            break;
+        }
+    }
+
+    if header.color_type == COLOR_TYPE_PALETTE && colors.palette_size == 0 {
+        return Err(ImageError::Corrupt);
+    }
+
+    let image_data = ImageData {
+        chunks: chunks(data),
+        current: &[],
+    };
+
+    let size = inflate::decompress(image_data, scratch)?;
+    if size != info.scratch_size {
+        return Err(ImageError::Truncated);
+    }
+
+    let bytes_per_pixel = ((header.bits_per_pixel() + 7) / 8) as usize;
+    let mut offset = 0;
+
+    for &pass in header.passes() {
+        let (width, height) = pass_size(&header, pass);
+        if width == 0 {
+            continue;
+        }
+
+        let line_length = header.line_length(width);
+        let (start_x, start_y, step_x, step_y) = pass;
+
+        for line in 0..height {
+            // The first line of a pass is filtered against zeros.
+            let previous = if line == 0 {
+                None
+            } else {
+                Some(offset - 1 - line_length)
+            };
+
+            unfilter(scratch, offset, previous, line_length, bytes_per_pixel)?;
+
+            let samples = &scratch[offset + 1..offset + 1 + line_length];
+            let y = start_y + line * step_y;
+
+            for column in 0..width {
+                let x = start_x + column * step_x;
+                let index = y as usize * header.width as usize + x as usize;
+                pixels[index] = pixel(&header, &colors, samples, column);
+            }
+
+            offset += 1 + line_length;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the transparency chunk.
+fn read_transparency(header: &Header, data: &[u8], colors: &mut Colors) -> Result<(), ImageError> {
+    let sample = |index: usize| -> Result<u16, ImageError> {
+        if data.len() < 2 * index + 2 {
+            return Err(ImageError::Corrupt);
+        }
+
+        Ok((data[2 * index] as u16) << 8 | data[2 * index + 1] as u16)
+    };
+
+    match header.color_type {
+        COLOR_TYPE_PALETTE => {
+            for (index, &alpha) in data.iter().take(256).enumerate() {
+                colors.palette[index] = colors.palette[index] & 0x00ff_ffff | (alpha as u32) << 24;
+            }
+        }
+        COLOR_TYPE_GRAY => {
+            let gray = sample(0)?;
+            colors.transparent = Some([gray, gray, gray]);
+        }
+        COLOR_TYPE_RGB => colors.transparent = Some([sample(0)?, sample(1)?, sample(2)?]),
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/// Reverses the filter of the line at `offset`.
+///
+/// `previous` is the offset of the previous line of the pass, if there is
+/// one.
+fn unfilter(
+    scratch: &mut [u8],
+    offset: usize,
+    previous: Option<usize>,
+    line_length: usize,
+    bytes_per_pixel: usize,
+) -> Result<(), ImageError> {
+    let filter = scratch[offset];
+    let start = offset + 1;
+
+    for index in 0..line_length {
+        let left = if index >= bytes_per_pixel {
+            scratch[start + index - bytes_per_pixel]
+        } else {
+            0
+        };
+        let (above, upper_left) = match previous {
+            Some(previous) => (
+                scratch[previous + 1 + index],
+                if index >= bytes_per_pixel {
+                    scratch[previous + 1 + index - bytes_per_pixel]
+                } else {
+                    0
+                },
+            ),
+            None => (0, 0),
+        };
+
+        let prediction = match filter {
+            0 => 0,
+            1 => left,
+            2 => above,
+            3 => ((left as u16 + above as u16) / 2) as u8,
+            4 => paeth(left, above, upper_left),
+            _ => return Err(ImageError::Corrupt),
+        };
+
+        scratch[start + index] = scratch[start + index].wrapping_add(prediction);
+    }
+
+    Ok(())
+}
+
+/// Returns the neighbour closest to the gradient of the three.
+fn paeth(left: u8, above: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_above = (estimate - above as i16).abs();
+    let distance_upper_left = (estimate - upper_left as i16).abs();
+
+    if distance_left <= distance_above && distance_left <= distance_upper_left {
+        left
+    } else if distance_above <= distance_upper_left {
+        above
+    } else {
+        upper_left
+    }
+}
+
+/// Returns the sample with the given index from the line.
+fn sample(samples: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => (samples[2 * index] as u16) << 8 | samples[2 * index + 1] as u16,
+        8 => samples[index] as u16,
+        _ => {
+            let bits = bit_depth as usize;
+            let bit = index * bits;
+            let shift = 8 - bits - bit % 8;
+
+            (samples[bit / 8] >> shift) as u16 & ((1 << bits) - 1)
+        }
+    }
+}
+
+/// Converts the pixel in the given column of the line.
+fn pixel(header: &Header, colors: &Colors, samples: &[u8], column: u32) -> u32 {
+    let channels = header.channels() as usize;
+    let depth = header.bit_depth;
+    let first = column as usize * channels;
+    let value = |channel: usize| sample(samples, first + channel, depth);
+
+    // Samples are scaled to 8 bits, 16 bit samples keep their high byte.
+    let scale = |sample: u16| match depth {
+        16 => (sample >> 8) as u8,
+        8 => sample as u8,
+        _ => (sample as u32 * 255 / ((1 << depth) - 1)) as u8,
+    };
+
+    match header.color_type {
+        COLOR_TYPE_PALETTE => colors.palette[value(0) as usize],
+        COLOR_TYPE_GRAY => {
+            let gray = value(0);
+            let alpha = if colors.transparent == Some([gray, gray, gray]) {
+                0
+            } else {
+                255
+            };
+
+            argb(scale(gray), scale(gray), scale(gray), alpha)
+        }
+        COLOR_TYPE_RGB => {
+            let (red, green, blue) = (value(0), value(1), value(2));
+            let alpha = if colors.transparent == Some([red, green, blue]) {
+                0
+            } else {
+                255
+            };
+
+            argb(scale(red), scale(green), scale(blue), alpha)
+        }
+        COLOR_TYPE_GRAY_ALPHA => {
+            let gray = scale(value(0));
+            argb(gray, gray, gray, scale(value(1)))
+        }
+        _ => argb(
+            scale(value(0)),
+            scale(value(1)),
+            scale(value(2)),
+            scale(value(3)),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::inflate::tests::zlib;
+    use super::*;
+    use std::vec::Vec;
+
+    /// Appends a chunk, the decoder doesn't check the CRC.
+    fn chunk(file: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        let length = data.len() as u32;
+        file.extend_from_slice(&[
+            (length >> 24) as u8,
+            (length >> 16) as u8,
+            (length >> 8) as u8,
+            length as u8,
+        ]);
+        file.extend_from_slice(kind);
+        file.extend_from_slice(data);
+        file.extend_from_slice(&[0; CHUNK_CRC_SIZE]);
+    }
+
+    /// Builds a file with the given header, extra chunks and image data.
+    fn png(header: &Header, extra: &[(&[u8; 4], &[u8])], image_data: &[u8]) -> Vec<u8> {
+        let mut ihdr = Vec::new();
+        for &value in &[header.width, header.height] {
+            ihdr.extend_from_slice(&[
+                (value >> 24) as u8,
+                (value >> 16) as u8,
+                (value >> 8) as u8,
+                value as u8,
+            ]);
+        }
+        ihdr.extend_from_slice(&[
+            header.bit_depth,
+            header.color_type,
+            0,
+            0,
+            header.interlaced as u8,
+        ]);
+
+        let mut file = Vec::new();
+        file.extend_from_slice(&SIGNATURE);
+        chunk(&mut file, b"IHDR", &ihdr);
+        for &(kind, data) in extra {
+            chunk(&mut file, kind, data);
+        }
+
+        // Split the image data to check that it is read across chunks.
+        let stream = zlib(image_data);
+        let (first, second) = stream.split_at(stream.len() / 2);
+        chunk(&mut file, b"IDAT", first);
+        chunk(&mut file, b"IDAT", second);
+        chunk(&mut file, b"IEND", &[]);
+
+        file
+    }
+
+    /// Filters the lines of a pass, cycling through all filter types.
+    fn filter(lines: &[&[u8]], bytes_per_pixel: usize) -> Vec<u8> {
+        let mut filtered = Vec::new();
+
+        for (number, line) in lines.iter().enumerate() {
+            let kind = (number % 5) as u8;
+            filtered.push(kind);
+
+            for index in 0..line.len() {
+                let left = if index >= bytes_per_pixel {
+                    line[index - bytes_per_pixel]
+                } else {
+                    0
+                };
+                let (above, upper_left) = if number > 0 {
+                    let previous = lines[number - 1];
+                    let upper_left = if index >= bytes_per_pixel {
+                        previous[index - bytes_per_pixel]
+                    } else {
+                        0
+                    };
+                    (previous[index], upper_left)
+                } else {
+                    (0, 0)
+                };
+
+                let prediction = match kind {
+                    0 => 0,
+                    1 => left,
+                    2 => above,
+                    3 => ((left as u16 + above as u16) / 2) as u8,
+                    _ => paeth(left, above, upper_left),
+                };
+                filtered.push(line[index].wrapping_sub(prediction));
+            }
+        }
+
+        filtered
+    }
+
+    fn decode_file(file: &[u8]) -> Result<Vec<u32>, ImageError> {
+        let info = info(file)?;
+        let mut pixels = vec![0; info.pixel_count()];
+        let mut scratch = vec![0; info.scratch_size];
+
+        decode(file, &info, &mut pixels, &mut scratch)?;
+
+        Ok(pixels)
+    }
+
+    /// Tests an RGBA image using every filter type.
+    #[test]
+    fn test_rgba_round_trip() {
+        let header = Header {
+            width: 3,
+            height: 6,
+            bit_depth: 8,
+            color_type: COLOR_TYPE_RGBA,
+            interlaced: false,
+        };
+
+        let mut image = Vec::new();
+        let mut lines = Vec::new();
+        for y in 0..header.height {
+            let mut line = Vec::new();
+            for x in 0..header.width {
+                let (red, green, blue) = ((x * 80) as u8, (y * 40) as u8, 200);
+                let alpha = 255 - y as u8;
+                line.extend_from_slice(&[red, green, blue, alpha]);
+                image.push(argb(red, green, blue, alpha));
+            }
+            lines.push(line);
+        }
+        let lines: Vec<&[u8]> = lines.iter().map(|line| &line[..]).collect();
+
+        let file = png(&header, &[], &filter(&lines, 4));
+        assert_eq!(
+            info(&file),
+            Ok(ImageInfo {
+                format: ImageFormat::Png,
+                width: 3,
+                height: 6,
+                scratch_size: 6 * (1 + 3 * 4),
+            })
+        );
+        assert_eq!(decode_file(&file), Ok(image));
+    }
+
+    /// Tests a packed palette image with transparency.
+    #[test]
+    fn test_palette_round_trip() {
+        let header = Header {
+            width: 5,
+            height: 2,
+            bit_depth: 2,
+            color_type: COLOR_TYPE_PALETTE,
+            interlaced: false,
+        };
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let transparency = [128, 255];
+
+        // Indices 0 1 2 3 0 and 3 2 1 0 1, padded to whole bytes.
+        let data = [0, 0b00_01_10_11, 0b00_00_00_00, 0, 0b11_10_01_00, 0b01_00_00_00];
+        let file = png(&header, &[(b"PLTE", &palette[..]), (b"tRNS", &transparency[..])], &data);
+
+        let red = argb(255, 0, 0, 128);
+        let green = argb(0, 255, 0, 255);
+        let blue = argb(0, 0, 255, 255);
+        let white = argb(255, 255, 255, 255);
+        assert_eq!(
+            decode_file(&file),
+            Ok(vec![red, green, blue, white, red, white, blue, green, red, green])
+        );
+    }
+
+    /// Tests that the Adam7 passes are put together.
+    #[test]
+    fn test_interlaced_round_trip() {
+        let header = Header {
+            width: 5,
+            height: 5,
+            bit_depth: 8,
+            color_type: COLOR_TYPE_GRAY,
+            interlaced: true,
+        };
+        let gray = |x: u32, y: u32| (y * 50 + x * 3) as u8;
+
+        let mut data = Vec::new();
+        for &pass in &ADAM7_PASSES {
+            let (width, height) = pass_size(&header, pass);
+            if width == 0 {
+                continue;
+            }
+
+            let (start_x, start_y, step_x, step_y) = pass;
+            for line in 0..height {
+                data.push(0);
+                for column in 0..width {
+                    data.push(gray(start_x + column * step_x, start_y + line * step_y));
+                }
+            }
+        }
+
+        let file = png(&header, &[], &data);
+        assert_eq!(info(&file).map(|info| info.scratch_size), Ok(data.len()));
+
+        let mut image = Vec::new();
+        for y in 0..5 {
+            for x in 0..5 {
+                let value = gray(x, y);
+                image.push(argb(value, value, value, 255));
+            }
+        }
+        assert_eq!(decode_file(&file), Ok(image));
+    }
+
+    /// Tests that images whose data size overflows are rejected.
+    #[test]
+    fn test_size_overflow() {
+        let header = Header {
+            width: 0xffff_ffff,
+            height: 0xffff_ffff,
+            bit_depth: 16,
+            color_type: COLOR_TYPE_RGBA,
+            interlaced: false,
+        };
+        assert_eq!(header.data_size(), None);
+
+        let file = png(&header, &[], &[]);
+        assert_eq!(info(&file), Err(ImageError::Unsupported));
+    }
+
+    /// Tests invalid filters, missing data and invalid headers.
+    #[test]
+    fn test_corrupt() {
+        let header = Header {
+            width: 2,
+            height: 2,
+            bit_depth: 8,
+            color_type: COLOR_TYPE_GRAY,
+            interlaced: false,
+        };
+
+        let file = png(&header, &[], &[0, 1, 2, 5, 3, 4]);
+        assert_eq!(decode_file(&file), Err(ImageError::Corrupt));
+
+        let file = png(&header, &[], &[0, 1, 2, 0]);
+        assert_eq!(decode_file(&file), Err(ImageError::Truncated));
+
+        let file = png(&header, &[], &[0, 1, 2, 0, 3, 4]);
+        assert_eq!(decode_file(&file[..file.len() - 20]), Err(ImageError::Truncated));
+
+        let palette = Header {
+            color_type: COLOR_TYPE_PALETTE,
+            ..header
+        };
+        let file = png(&palette, &[], &[0, 0, 0, 0, 0, 0]);
+        assert_eq!(decode_file(&file), Err(ImageError::Corrupt));
+
+        let invalid_depth = Header {
+            bit_depth: 4,
+            color_type: COLOR_TYPE_RGB,
+            ..header
+        };
+        let file = png(&invalid_depth, &[], &[]);
+        assert_eq!(info(&file), Err(ImageError::Unsupported));
+
+        let empty = Header { width: 0, ..header };
+        let file = png(&empty, &[], &[]);
+        assert_eq!(info(&file), Err(ImageError::Corrupt));
+    }
+}
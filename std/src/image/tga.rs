@@ -0,0 +1,397 @@
+//! Decodes Truevision TGA images.
+//!
+//! Color mapped, true color and grayscale images are supported, both raw and
+//! run length encoded.
+
+use super::{argb, read_u16_le, scale_to_u8, ImageError, ImageFormat, ImageInfo};
+
+/// The size of the header.
+const HEADER_SIZE: usize = 18;
+
+/// A raw color mapped image.
+const TYPE_COLOR_MAPPED: u8 = 1;
+
+/// A raw true color image.
+const TYPE_TRUE_COLOR: u8 = 2;
+
+/// A raw grayscale image.
+const TYPE_GRAYSCALE: u8 = 3;
+
+/// The flag of run length encoded image types.
+const TYPE_RLE: u8 = 8;
+
+/// The descriptor bits holding the number of alpha bits.
+const DESCRIPTOR_ALPHA_BITS: u8 = 0x0f;
+
+/// Set in the descriptor if the pixels are stored right to left.
+const DESCRIPTOR_RIGHT_TO_LEFT: u8 = 0x10;
+
+/// Set in the descriptor if the first stored line is the top one.
+const DESCRIPTOR_TOP_DOWN: u8 = 0x20;
+
+/// The contents of the header.
+struct Header {
+    /// The image type without the run length encoding flag.
+    image_type: u8,
+    /// Set if the pixels are run length encoded.
+    rle: bool,
+    /// The index of the first color map entry.
+    color_map_first: u16,
+    /// The number of color map entries.
+    color_map_length: u16,
+    /// The number of bits per color map entry.
+    color_map_depth: u8,
+    /// The offset of the color map in the file.
+    color_map_offset: usize,
+    /// The width in pixels.
+    width: u16,
+    /// The height in pixels.
+    height: u16,
+    /// The number of bits per pixel.
+    depth: u8,
+    /// The image descriptor.
+    descriptor: u8,
+    /// The offset of the pixels in the file.
+    pixel_offset: usize,
+}
+
+impl Header {
+    /// Returns true if the pixels carry an alpha channel.
+    fn has_alpha(&self) -> bool {
+        self.descriptor & DESCRIPTOR_ALPHA_BITS > 0
+    }
+}
+
+/// Reads and checks the header.
+fn header(data: &[u8]) -> Result<Header, ImageError> {
+    if data.len() < HEADER_SIZE {
+        return Err(ImageError::Truncated);
+    }
+
+    let id_length = data[0] as usize;
+    let color_map_type = data[1];
+    let image_type = data[2] & !TYPE_RLE;
+    let rle = data[2] & TYPE_RLE != 0;
+    let color_map_first = read_u16_le(data, 3)?;
+    let color_map_length = read_u16_le(data, 5)?;
+    let color_map_depth = data[7];
+    let width = read_u16_le(data, 12)?;
+    let height = read_u16_le(data, 14)?;
+    let depth = data[16];
+    let descriptor = data[17];
+
+    if color_map_type > 1 || data[2] & !(TYPE_RLE | 3) != 0 || width == 0 || height == 0 {
+        return Err(ImageError::Corrupt);
+    }
+
+    let depth_supported = match image_type {
+        TYPE_COLOR_MAPPED => color_map_type == 1 && depth == 8,
+        TYPE_TRUE_COLOR => match depth {
+            15 | 16 | 24 | 32 => true,
+            _ => false,
+        },
+        TYPE_GRAYSCALE => depth == 8,
+        _ => false,
+    };
+    let color_map_supported = color_map_type == 0 || match color_map_depth {
+        15 | 16 | 24 | 32 => true,
+        _ => false,
+    };
+    if !depth_supported || !color_map_supported {
+        return Err(ImageError::Unsupported);
+    }
+
+    let color_map_offset = HEADER_SIZE + id_length;
+    let color_map_size = if color_map_type == 1 {
+        color_map_length as usize * ((color_map_depth as usize + 7) / 8)
+    } else {
+        0
+    };
+
+    Ok(Header {
+        image_type,
+        rle,
+        color_map_first,
+        color_map_length: if color_map_type == 1 { color_map_length } else { 0 },
+        color_map_depth,
+        color_map_offset,
+        width,
+        height,
+        depth,
+        descriptor,
+        pixel_offset: color_map_offset + color_map_size,
+    })
+}
+
+/// Returns true if the data looks like a TGA image.
+///
+/// TGA files have no signature, so this only checks that the header is
+/// plausible.
+pub fn is_tga(data: &[u8]) -> bool {
+    header(data).is_ok()
+}
+
+/// Reads the size of the image.
+pub fn info(data: &[u8]) -> Result<ImageInfo, ImageError> {
+    let header = header(data)?;
+
+    Ok(ImageInfo {
+        format: ImageFormat::Tga,
+        width: header.width as u32,
+        height: header.height as u32,
+        scratch_size: 0,
+    })
+}
+
+/// Converts a stored pixel or color map entry with the given depth.
+fn color(bytes: &[u8], depth: u8, grayscale: bool, has_alpha: bool) -> u32 {
+    match depth {
+        8 if grayscale => argb(bytes[0], bytes[0], bytes[0], 255),
+        15 | 16 => {
+            let value = bytes[0] as u32 | (bytes[1] as u32) << 8;
+            let alpha = if depth == 16 && has_alpha && value & 0x8000 == 0 {
+                0
+            } else {
+                255
+            };
+
+            argb(
+                scale_to_u8((value >> 10) & 0x1f, 5),
+                scale_to_u8((value >> 5) & 0x1f, 5),
+                scale_to_u8(value & 0x1f, 5),
+                alpha,
+            )
+        }
+        24 => argb(bytes[2], bytes[1], bytes[0], 255),
+        _ => argb(
+            bytes[2],
+            bytes[1],
+            bytes[0],
+            if has_alpha { bytes[3] } else { 255 },
+        ),
+    }
+}
+
+/// Decodes the image into the pixel buffer.
+pub fn decode(data: &[u8], info: &ImageInfo, pixels: &mut [u32]) -> Result<(), ImageError> {
+    let header = header(data)?;
+    let has_alpha = header.has_alpha();
+
+    let mut color_map = [argb(0, 0, 0, 255); 256];
+    let entry_size = (header.color_map_depth as usize + 7) / 8;
+    for index in 0..header.color_map_length as usize {
+        let offset = header.color_map_offset + index * entry_size;
+        let slot = header.color_map_first as usize + index;
+        if offset + entry_size > data.len() {
+            return Err(ImageError::Truncated);
+        }
+
+        if slot < color_map.len() {
+            color_map[slot] = color(
+                &data[offset..offset + entry_size],
+                header.color_map_depth,
+                false,
+                has_alpha,
+            );
+        }
+    }
+
+    let pixel_size = (header.depth as usize + 7) / 8;
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let grayscale = header.image_type == TYPE_GRAYSCALE;
+
+    let mut offset = header.pixel_offset;
+    // The number of pixels left in the current run length packet.
+    let mut packet_left = 0;
+    // Set if the current packet repeats a single pixel.
+    let mut repeat = false;
+
+    for index in 0..info.pixel_count() {
+        if header.rle && packet_left == 0 {
+            if offset >= data.len() {
+                return Err(ImageError::Truncated);
+            }
+            repeat = data[offset] & 0x80 != 0;
+            packet_left = (data[offset] & 0x7f) as usize + 1;
+            offset += 1;
+        }
+
+        if offset + pixel_size > data.len() {
+            return Err(ImageError::Truncated);
+        }
+        let stored = &data[offset..offset + pixel_size];
+
+        let value = if header.image_type == TYPE_COLOR_MAPPED {
+            color_map[stored[0] as usize]
+        } else {
+            color(stored, header.depth, grayscale, has_alpha)
+        };
+
+        // Repeated pixels are stored once, after the packet header.
+        if !header.rle || !repeat || packet_left == 1 {
+            offset += pixel_size;
+        }
+        if header.rle {
+            packet_left -= 1;
+        }
+
+        let (line, column) = (index / width, index % width);
+        let y = if header.descriptor & DESCRIPTOR_TOP_DOWN != 0 {
+            line
+        } else {
+            height - 1 - line
+        };
+        let x = if header.descriptor & DESCRIPTOR_RIGHT_TO_LEFT != 0 {
+            width - 1 - column
+        } else {
+            column
+        };
+        pixels[y * width + x] = value;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Builds a file without an image ID.
+    ///
+    /// `color_map` holds the first index, the entry depth and the entries.
+    fn tga(
+        image_type: u8,
+        color_map: Option<(u16, u8, &[u8])>,
+        size: (u16, u16),
+        depth: u8,
+        descriptor: u8,
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let (first, map_depth, entries) = match color_map {
+            Some(color_map) => color_map,
+            None => (0, 0, &[][..]),
+        };
+        let length = if entries.is_empty() {
+            0
+        } else {
+            (entries.len() / ((map_depth as usize + 7) / 8)) as u16
+        };
+        let (width, height) = size;
+
+        let mut file = vec![0, color_map.is_some() as u8, image_type];
+        for &value in &[first, length] {
+            file.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+        }
+        file.push(map_depth);
+        for &value in &[0, 0, width, height] {
+            file.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+        }
+        file.extend_from_slice(&[depth, descriptor]);
+
+        file.extend_from_slice(entries);
+        file.extend_from_slice(pixels);
+
+        file
+    }
+
+    fn decode_file(file: &[u8]) -> Result<Vec<u32>, ImageError> {
+        let info = info(file)?;
+        let mut pixels = vec![0; info.pixel_count()];
+
+        decode(file, &info, &mut pixels)?;
+
+        Ok(pixels)
+    }
+
+    /// Tests a bottom-up 24 bit image.
+    #[test]
+    fn test_true_color_round_trip() {
+        let pixels = [
+            0x30, 0x20, 0x10, 0x60, 0x50, 0x40, // Bottom line.
+            0xff, 0x00, 0x00, 0x00, 0xff, 0x00, // Top line.
+        ];
+        let file = tga(TYPE_TRUE_COLOR, None, (2, 2), 24, 0, &pixels);
+
+        assert_eq!(
+            info(&file),
+            Ok(ImageInfo {
+                format: ImageFormat::Tga,
+                width: 2,
+                height: 2,
+                scratch_size: 0,
+            })
+        );
+        assert_eq!(
+            decode_file(&file),
+            Ok(vec![
+                argb(0x00, 0x00, 0xff, 255),
+                argb(0x00, 0xff, 0x00, 255),
+                argb(0x10, 0x20, 0x30, 255),
+                argb(0x40, 0x50, 0x60, 255),
+            ])
+        );
+    }
+
+    /// Tests repeated and raw packets of a top-down 32 bit image with alpha.
+    #[test]
+    fn test_rle_round_trip() {
+        let pixels = [
+            0x82, 0x30, 0x20, 0x10, 0x80, // Three repeated pixels.
+            0x00, 0x60, 0x50, 0x40, 0xff, // One raw pixel.
+        ];
+        let descriptor = DESCRIPTOR_TOP_DOWN | 8;
+        let file = tga(TYPE_TRUE_COLOR | TYPE_RLE, None, (2, 2), 32, descriptor, &pixels);
+
+        let repeated = argb(0x10, 0x20, 0x30, 0x80);
+        assert_eq!(
+            decode_file(&file),
+            Ok(vec![repeated, repeated, repeated, argb(0x40, 0x50, 0x60, 0xff)])
+        );
+    }
+
+    /// Tests a right-to-left image whose color map doesn't start at zero.
+    #[test]
+    fn test_color_mapped_round_trip() {
+        let entries = [0xff, 0x00, 0x00, 0x00, 0xff, 0x00];
+        let descriptor = DESCRIPTOR_TOP_DOWN | DESCRIPTOR_RIGHT_TO_LEFT;
+        let color_map = Some((1, 24, &entries[..]));
+        let file = tga(TYPE_COLOR_MAPPED, color_map, (3, 1), 8, descriptor, &[0, 1, 2]);
+
+        assert_eq!(
+            decode_file(&file),
+            Ok(vec![argb(0, 0xff, 0, 255), argb(0, 0, 0xff, 255), argb(0, 0, 0, 255)])
+        );
+    }
+
+    /// Tests truncated data and invalid or unsupported headers.
+    #[test]
+    fn test_corrupt() {
+        let pixels = [0; 12];
+
+        let file = tga(TYPE_TRUE_COLOR, None, (2, 2), 24, 0, &pixels[..11]);
+        assert_eq!(decode_file(&file), Err(ImageError::Truncated));
+
+        // The last packet is missing its pixel.
+        let file = tga(TYPE_TRUE_COLOR | TYPE_RLE, None, (2, 2), 24, 0, &[0x82, 1, 2, 3, 0x00]);
+        assert_eq!(decode_file(&file), Err(ImageError::Truncated));
+
+        let file = tga(TYPE_TRUE_COLOR, None, (2, 2), 24, 0, &pixels);
+        assert_eq!(info(&file[..HEADER_SIZE - 1]), Err(ImageError::Truncated));
+
+        let file = tga(TYPE_TRUE_COLOR, None, (0, 2), 24, 0, &pixels);
+        assert_eq!(info(&file), Err(ImageError::Corrupt));
+
+        let file = tga(TYPE_TRUE_COLOR, None, (2, 2), 12, 0, &pixels);
+        assert_eq!(info(&file), Err(ImageError::Unsupported));
+
+        // Color mapped images need a color map.
+        let file = tga(TYPE_COLOR_MAPPED, None, (2, 2), 8, 0, &pixels);
+        assert_eq!(info(&file), Err(ImageError::Unsupported));
+
+        let mut file = tga(TYPE_TRUE_COLOR, None, (2, 2), 24, 0, &pixels);
+        file[1] = 2;
+        assert_eq!(info(&file), Err(ImageError::Corrupt));
+    }
+}
@@ -1,13 +1,10 @@
 //! This module defines IO functions.
 
+use boring_core::syscall::{PANIC_SERIAL_CHAR_SYSCALL_NUM, PRINT_CHAR_SYSCALL_NUM,
+                           SCROLL_CONSOLE_SYSCALL_NUM, SERIAL_CHAR_SYSCALL_NUM};
 use core::fmt;
 use core::fmt::Write;
 
-/// The number of the print char syscall.
-const PRINT_CHAR_SYSCALL: u64 = 0;
-const SERIAL_CHAR_SYSCALL: u64 = 7;
-const PANIC_SERIAL_CHAR_SYSCALL: u64 = 8;
-
 /// A dummy struct to implement fmt::Write on.
 struct StdOut;
 
@@ -95,7 +92,7 @@ pub fn print(args: fmt::Arguments) {
 /// Prints a character to the screen.
 fn print_char(character: char) {
     unsafe {
-        syscall!(PRINT_CHAR_SYSCALL, character as u64);
+        syscall!(PRINT_CHAR_SYSCALL_NUM, character as u64);
     }
 }
 
@@ -105,7 +102,7 @@ pub fn serial(args: fmt::Arguments) {
 
 fn serial_char(character: char) {
     unsafe {
-        syscall!(SERIAL_CHAR_SYSCALL, character as u64);
+        syscall!(SERIAL_CHAR_SYSCALL_NUM, character as u64);
     }
 }
 
@@ -115,6 +112,17 @@ pub fn panic_serial(args: fmt::Arguments) {
 
 fn panic_serial_char(character: char) {
     unsafe {
-        syscall!(PANIC_SERIAL_CHAR_SYSCALL, character as u64);
+        syscall!(PANIC_SERIAL_CHAR_SYSCALL_NUM, character as u64);
+    }
+}
+
+/// Scrolls the framebuffer console the given number of lines back into its
+/// history.
+///
+/// Negative numbers scroll towards the newest output. This has no effect on
+/// the VGA text console or if the current process isn't the init process.
+pub fn scroll_console(lines: isize) {
+    unsafe {
+        syscall!(SCROLL_CONSOLE_SYSCALL_NUM, lines as u64);
     }
 }
@@ -5,99 +5,17 @@
 #![feature(unique)]
 #![feature(from_ref)]
 #![feature(ptr_internals)]
+#![feature(integer_atomics)]
 #![no_std]
 #![allow(unused)]
+#[macro_use]
+extern crate boring_core;
+#[cfg(test)]
+#[macro_use]
+extern crate std;
 extern crate spin;
 extern crate volatile;
 
-/// Makes a syscall with the given arguments.
-macro_rules! syscall {
-    ($num: expr) => {{
-        let result: u64;
-        asm!("syscall" :
-            "={rax}"(result) :
-            "{rax}"($num)
-            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
-            : "intel", "volatile");
-        result
-    }};
-    ($num: expr, $arg1: expr) => {{
-        let result: u64;
-        asm!("syscall" :
-            "={rax}"(result) :
-            "{rax}"($num),
-            "{rdi}"($arg1)
-            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
-            : "intel", "volatile");
-        result
-    }};
-    ($num: expr, $arg1: expr, $arg2: expr) => {{
-        let result: u64;
-        asm!("syscall" :
-            "={rax}"(result) :
-            "{rax}"($num),
-            "{rdi}"($arg1),
-            "{rsi}"($arg2)
-            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
-            : "intel", "volatile");
-        result
-    }};
-    ($num: expr, $arg1: expr, $arg2: expr, $arg3: expr) => {{
-        let result: u64;
-        asm!("syscall" :
-            "={rax}"(result) :
-            "{rax}"($num),
-            "{rdi}"($arg1),
-            "{rsi}"($arg2),
-            "{rdx}"($arg3)
-            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
-            : "intel", "volatile");
-        result
-    }};
-    ($num: expr, $arg1: expr, $arg2: expr, $arg3: expr, $arg4: expr) => {{
-        let result: u64;
-        asm!("syscall" :
-            "={rax}"(result) :
-            "{rax}"($num),
-            "{rdi}"($arg1),
-            "{rsi}"($arg2),
-            "{rdx}"($arg3),
-            "{r10}"($arg4)
-            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
-            : "intel", "volatile");
-        result
-    }};
-    ($num: expr, $arg1: expr, $arg2: expr, $arg3: expr, $arg4: expr, $arg5: expr) => {{
-        let result: u64;
-        asm!("syscall" :
-            "={rax}"(result) :
-            "{rax}"($num),
-            "{rdi}"($arg1),
-            "{rsi}"($arg2),
-            "{rdx}"($arg3),
-            "{r10}"($arg4),
-            "{r8}"($arg5)
-            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
-            : "intel", "volatile");
-        result
-    }};
-    ($num: expr, $arg1: expr, $arg2: expr, $arg3: expr, $arg4: expr, $arg5: expr, $arg6: expr) => {{
-        let result: u64;
-        asm!("syscall" :
-            "={rax}"(result) :
-            "{rax}"($num),
-            "{rdi}"($arg1),
-            "{rsi}"($arg2),
-            "{rdx}"($arg3),
-            "{r10}"($arg4),
-            "{r8}"($arg5),
-            "{r9}"($arg6)
-            : "rax", "rdi", "rsi", "rdx", "r10", "r8", "r9", "r12", "r11", "rcx"
-            : "intel", "volatile");
-        result
-    }};
-}
-
 #[macro_use]
 pub mod io;
 pub mod process;
@@ -105,8 +23,17 @@ pub mod thread;
 pub mod video;
 pub mod screen;
 pub mod math;
+pub mod pci;
+pub mod power;
+pub mod time;
+pub mod futex;
+pub mod message;
+pub mod timer;
+pub mod fs;
+pub mod image;
 use process::exit;
 
+#[cfg(not(test))]
 extern "Rust" {
     /// The function that the program provides as a start.
     fn main();
@@ -115,6 +42,10 @@ extern "Rust" {
 /// The start of the application.
 ///
 /// This should perform initialization and call main. After main returns, it should exit.
+///
+/// Dynamically linked programs are started by the runtime loader instead,
+/// which calls main and exits in the same way.
+#[cfg(not(test))]
 #[start]
 #[no_mangle]
 pub fn _start(_: isize, _: *const *const u8) -> isize {
@@ -124,6 +55,7 @@ pub fn _start(_: isize, _: *const *const u8) -> isize {
     exit();
 }
 
+#[cfg(not(test))]
 #[lang = "eh_personality"]
 extern "C" fn eh_personality() {
     unimplemented!();
@@ -132,6 +64,7 @@ extern "C" fn eh_personality() {
 /// The panic handler of the program.
 ///
 /// This exits after printing some debug information.
+#[cfg(not(test))]
 #[lang = "panic_fmt"]
 #[no_mangle]
 pub extern "C" fn panic_fmt(fmt: core::fmt::Arguments, file: &'static str, line: u32) -> ! {
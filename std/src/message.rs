@@ -0,0 +1,38 @@
+//! Receives messages sent to the process.
+
+use boring_core::syscall::RECEIVE_SYSCALL_NUM;
+use time::{timeout_argument, wait_result, WaitError};
+
+/// The kind of a message sent when a timer expires.
+pub const TIMER_MESSAGE: u64 = 1;
+
+/// A message sent to the process.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Message {
+    /// The kind of the message.
+    pub kind: u64,
+    /// The first argument, depending on the kind.
+    ///
+    /// For timer messages this is the timer handle.
+    pub arg1: u64,
+    /// The second argument, depending on the kind.
+    ///
+    /// For timer messages this is the number of expiries.
+    pub arg2: u64,
+}
+
+/// Receives the next message, waiting at most `timeout` nanoseconds.
+pub fn receive(timeout: Option<u64>) -> Result<Message, WaitError> {
+    let mut message = Message::default();
+
+    let result = unsafe {
+        syscall!(
+            RECEIVE_SYSCALL_NUM,
+            &mut message as *mut Message as u64,
+            timeout_argument(timeout)
+        ) as i64
+    };
+
+    wait_result(result).map(|_| message)
+}
@@ -0,0 +1,44 @@
+//! Allows querying the PCI devices known to the kernel.
+
+use boring_core::syscall::PCI_DEVICE_INFO_SYSCALL_NUM;
+
+pub use boring_core::pci::PciDeviceInfo;
+
+/// Returns information about the PCI function with the given index.
+pub fn device_info(index: usize) -> Option<PciDeviceInfo> {
+    let mut info = PciDeviceInfo::default();
+    let info_ptr = &mut info as *mut PciDeviceInfo as u64;
+
+    let result = unsafe { syscall!(PCI_DEVICE_INFO_SYSCALL_NUM, index as u64, info_ptr) as i64 };
+
+    if result < 0 {
+        None
+    } else {
+        Some(info)
+    }
+}
+
+/// Iterates over all PCI functions.
+pub fn devices() -> PciDeviceIterator {
+    PciDeviceIterator { index: 0 }
+}
+
+/// An iterator over the PCI functions known to the kernel.
+pub struct PciDeviceIterator {
+    /// The index of the next function.
+    index: usize,
+}
+
+impl Iterator for PciDeviceIterator {
+    type Item = PciDeviceInfo;
+
+    fn next(&mut self) -> Option<PciDeviceInfo> {
+        let info = device_info(self.index);
+
+        if info.is_some() {
+            self.index += 1;
+        }
+
+        info
+    }
+}
@@ -0,0 +1,33 @@
+//! Powers off or resets the machine.
+
+use boring_core::syscall::{REBOOT_SYSCALL_NUM, SHUTDOWN_SYSCALL_NUM};
+
+/// The errors that can occur while changing the power state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerError {
+    /// Only the init process may power off or reset the machine.
+    PermissionDenied,
+}
+
+/// Powers off the machine.
+///
+/// When running in QEMU with the `isa-debug-exit` device, a non-zero status
+/// makes QEMU exit with `(status << 1) | 1`.
+///
+/// Only returns if the current process isn't allowed to do this.
+pub fn shutdown(status: u8) -> PowerError {
+    unsafe {
+        syscall!(SHUTDOWN_SYSCALL_NUM, status as u64);
+    }
+    PowerError::PermissionDenied
+}
+
+/// Resets the machine.
+///
+/// Only returns if the current process isn't allowed to do this.
+pub fn reboot() -> PowerError {
+    unsafe {
+        syscall!(REBOOT_SYSCALL_NUM);
+    }
+    PowerError::PermissionDenied
+}
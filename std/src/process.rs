@@ -1,13 +1,6 @@
 //! Handles process related system calls.
 
-/// The number of the exit syscall.
-const EXIT_SYSCALL_NUM: u64 = 1;
-
-/// The number of the get_pid syscall.
-const GET_PID_SYSCALL_NUM: u64 = 2;
-
-/// The number of the exec syscall.
-const EXEC_SYSCALL_NUM: u64 = 3;
+use boring_core::syscall::{EXEC_SYSCALL_NUM, EXIT_SYSCALL_NUM, GET_PID_SYSCALL_NUM};
 
 /// The possible types of errors that are process related.
 #[derive(Debug)]
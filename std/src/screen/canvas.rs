@@ -0,0 +1,812 @@
+//! Safe drawing on the screen buffer.
+//!
+//! A canvas borrows the screen buffer and clips everything it draws to its
+//! clip rectangle, which never extends past the screen. Colors with an alpha
+//! value below 255 are blended with the pixels already drawn. Every drawn
+//! region is marked as dirty so that syncing only copies what changed.
+
+use super::font::Font;
+use super::Buffer;
+
+/// The maximum number of vertices of a filled polygon.
+pub const MAX_POLYGON_VERTICES: usize = 64;
+
+/// A rectangle on the screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    /// The left edge.
+    pub x: i32,
+    /// The top edge.
+    pub y: i32,
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+}
+
+impl Rect {
+    /// Creates a rectangle.
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Creates the rectangle between the given corners.
+    ///
+    /// The second corner lies just outside of the rectangle.
+    pub fn from_corners(x1: i32, y1: i32, x2: i32, y2: i32) -> Rect {
+        let (left, right) = if x1 <= x2 { (x1, x2) } else { (x2, x1) };
+        let (top, bottom) = if y1 <= y2 { (y1, y2) } else { (y2, y1) };
+
+        Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+    }
+
+    /// Returns the first column right of the rectangle.
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    /// Returns the first line below the rectangle.
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    /// Returns true if the rectangle contains no pixels.
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns true if the given pixel lies within the rectangle.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Returns the part that lies in both rectangles.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let left = if self.x > other.x { self.x } else { other.x };
+        let top = if self.y > other.y { self.y } else { other.y };
+        let right = if self.right() < other.right() {
+            self.right()
+        } else {
+            other.right()
+        };
+        let bottom = if self.bottom() < other.bottom() {
+            self.bottom()
+        } else {
+            other.bottom()
+        };
+
+        if left >= right || top >= bottom {
+            Rect::new(left, top, 0, 0)
+        } else {
+            Rect::from_corners(left, top, right, bottom)
+        }
+    }
+
+    /// Returns the smallest rectangle containing both rectangles.
+    ///
+    /// Empty rectangles are ignored.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if other.is_empty() {
+            return *self;
+        }
+        if self.is_empty() {
+            return *other;
+        }
+
+        let left = if self.x < other.x { self.x } else { other.x };
+        let top = if self.y < other.y { self.y } else { other.y };
+        let right = if self.right() > other.right() {
+            self.right()
+        } else {
+            other.right()
+        };
+        let bottom = if self.bottom() > other.bottom() {
+            self.bottom()
+        } else {
+            other.bottom()
+        };
+
+        Rect::from_corners(left, top, right, bottom)
+    }
+
+    /// Returns true if the rectangles overlap or share an edge.
+    pub fn touches(&self, other: &Rect) -> bool {
+        !self.is_empty() && !other.is_empty() && self.x <= other.right()
+            && other.x <= self.right() && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+}
+
+/// A color with an alpha value.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    /// The red channel.
+    pub red: u8,
+    /// The green channel.
+    pub green: u8,
+    /// The blue channel.
+    pub blue: u8,
+    /// The opacity, 255 is opaque and 0 is invisible.
+    pub alpha: u8,
+}
+
+impl Color {
+    /// Creates an opaque color.
+    pub fn rgb(red: u8, green: u8, blue: u8) -> Color {
+        Color {
+            red,
+            green,
+            blue,
+            alpha: 255,
+        }
+    }
+
+    /// Creates a color with the given opacity.
+    pub fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Color {
+        Color {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    /// Creates a color from a `0xAARRGGBB` value.
+    pub fn from_argb(value: u32) -> Color {
+        Color::rgba(
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+            (value >> 24) as u8,
+        )
+    }
+
+    /// Returns the color as a `0xAARRGGBB` value.
+    pub fn to_argb(&self) -> u32 {
+        (self.alpha as u32) << 24 | (self.red as u32) << 16 | (self.green as u32) << 8
+            | self.blue as u32
+    }
+
+    /// Returns true if the color hides what is below it.
+    pub fn is_opaque(&self) -> bool {
+        self.alpha == 255
+    }
+
+    /// Draws this color over the given opaque color.
+    pub fn blend_over(&self, below: Color) -> Color {
+        let alpha = self.alpha as u32;
+        let blend = |above: u8, below: u8| {
+            ((above as u32 * alpha + below as u32 * (255 - alpha) + 127) / 255) as u8
+        };
+
+        Color::rgb(
+            blend(self.red, below.red),
+            blend(self.green, below.green),
+            blend(self.blue, below.blue),
+        )
+    }
+}
+
+/// An image stored as `0xAARRGGBB` values, line by line.
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a> {
+    /// The width in pixels.
+    width: u32,
+    /// The height in pixels.
+    height: u32,
+    /// The pixels.
+    pixels: &'a [u32],
+}
+
+impl<'a> Image<'a> {
+    /// Creates an image from the given pixels.
+    ///
+    /// Returns `None` if there are fewer pixels than the size requires.
+    pub fn new(width: u32, height: u32, pixels: &'a [u32]) -> Option<Image<'a>> {
+        if (pixels.len() as u64) < width as u64 * height as u64 {
+            return None;
+        }
+
+        Some(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// The width in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the color of the given pixel.
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        Color::from_argb(self.pixels[(y * self.width + x) as usize])
+    }
+}
+
+/// Draws on a screen buffer within a clip rectangle.
+pub struct Canvas<'a> {
+    /// The buffer that is drawn on.
+    buffer: &'a mut Buffer,
+    /// Nothing outside of this rectangle is drawn.
+    clip: Rect,
+}
+
+impl<'a> Canvas<'a> {
+    /// Creates a canvas for the whole buffer.
+    pub fn new(buffer: &'a mut Buffer) -> Canvas<'a> {
+        let clip = buffer.bounds();
+
+        Canvas { buffer, clip }
+    }
+
+    /// The width of the screen in pixels.
+    pub fn width(&self) -> u32 {
+        self.buffer.width()
+    }
+
+    /// The height of the screen in pixels.
+    pub fn height(&self) -> u32 {
+        self.buffer.height()
+    }
+
+    /// Returns the clip rectangle.
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    /// Restricts drawing to the given rectangle.
+    ///
+    /// The parts of the rectangle outside of the screen are ignored.
+    pub fn set_clip(&mut self, clip: Rect) {
+        self.clip = clip.intersection(&self.buffer.bounds());
+    }
+
+    /// Allows drawing on the whole screen again.
+    pub fn reset_clip(&mut self) {
+        self.clip = self.buffer.bounds();
+    }
+
+    /// Returns the color of the given pixel, if it lies on the screen.
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Color> {
+        if self.buffer.bounds().contains(x, y) {
+            Some(self.buffer.read_pixel(x as u32, y as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Fills the clip rectangle with the given color.
+    pub fn clear(&mut self, color: Color) {
+        let clip = self.clip;
+        self.fill_rect(clip, color);
+    }
+
+    /// Draws a single pixel.
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if self.clip.contains(x, y) {
+            self.span(x, y, 1, color);
+            self.buffer.mark_dirty(Rect::new(x, y, 1, 1));
+        }
+    }
+
+    /// Draws a line between the given points, including both of them.
+    pub fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: Color) {
+        if y1 == y2 {
+            let (left, right) = if x1 <= x2 { (x1, x2) } else { (x2, x1) };
+            return self.fill_rect(Rect::from_corners(left, y1, right + 1, y1 + 1), color);
+        }
+
+        if x1 == x2 {
+            let (top, bottom) = if y1 <= y2 { (y1, y2) } else { (y2, y1) };
+            return self.fill_rect(Rect::from_corners(x1, top, x1 + 1, bottom + 1), color);
+        }
+
+        let (x1, y1, x2, y2) = match clip_line(&self.clip, x1, y1, x2, y2) {
+            Some(line) => line,
+            None => return,
+        };
+
+        let dx = (x2 - x1).abs();
+        let dy = -(y2 - y1).abs();
+        let step_x = if x1 < x2 { 1 } else { -1 };
+        let step_y = if y1 < y2 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let (mut x, mut y) = (x1, y1);
+
+        loop {
+            // Rounding while clipping can move the end points by a pixel.
+            if self.clip.contains(x, y) {
+                self.span(x, y, 1, color);
+            }
+
+            if x == x2 && y == y2 {
+                break;
+            }
+
+            let double_error = 2 * error;
+            if double_error >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if double_error <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+
+        let bounds = Rect::from_corners(x1, y1, x2, y2);
+        let bounds = Rect::new(bounds.x, bounds.y, bounds.width + 1, bounds.height + 1);
+        self.buffer.mark_dirty(bounds.intersection(&self.clip));
+    }
+
+    /// Draws the outline of the rectangle.
+    pub fn rect(&mut self, rect: Rect, color: Color) {
+        if rect.is_empty() {
+            return;
+        }
+
+        let (right, bottom) = (rect.right() - 1, rect.bottom() - 1);
+
+        self.line(rect.x, rect.y, right, rect.y, color);
+        if bottom > rect.y {
+            self.line(rect.x, bottom, right, bottom, color);
+        }
+        if bottom > rect.y + 1 {
+            self.line(rect.x, rect.y + 1, rect.x, bottom - 1, color);
+            if right > rect.x {
+                self.line(right, rect.y + 1, right, bottom - 1, color);
+            }
+        }
+    }
+
+    /// Fills the rectangle.
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let rect = rect.intersection(&self.clip);
+        if rect.is_empty() {
+            return;
+        }
+
+        for y in rect.y..rect.bottom() {
+            self.span(rect.x, y, rect.width, color);
+        }
+
+        self.buffer.mark_dirty(rect);
+    }
+
+    /// Draws the outline of a circle around the given center.
+    pub fn circle(&mut self, center_x: i32, center_y: i32, radius: u32, color: Color) {
+        let radius = radius as i32;
+        let (mut x, mut y) = (radius, 0);
+        let mut error = 1 - radius;
+
+        while x >= y {
+            for &(dx, dy) in &[
+                (x, y),
+                (y, x),
+                (-y, x),
+                (-x, y),
+                (-x, -y),
+                (-y, -x),
+                (y, -x),
+                (x, -y),
+            ] {
+                let (px, py) = (center_x + dx, center_y + dy);
+                if self.clip.contains(px, py) {
+                    self.span(px, py, 1, color);
+                }
+            }
+
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+
+        let bounds = circle_bounds(center_x, center_y, radius);
+        self.buffer.mark_dirty(bounds.intersection(&self.clip));
+    }
+
+    /// Fills a circle around the given center.
+    pub fn fill_circle(&mut self, center_x: i32, center_y: i32, radius: u32, color: Color) {
+        let radius = radius as i32;
+        let bounds = circle_bounds(center_x, center_y, radius).intersection(&self.clip);
+        if bounds.is_empty() {
+            return;
+        }
+
+        for y in bounds.y..bounds.bottom() {
+            let dy = (y - center_y) as i64;
+            let half_width = isqrt((radius as i64 * radius as i64 - dy * dy) as u64) as i32;
+            let line = Rect::from_corners(
+                center_x - half_width,
+                y,
+                center_x + half_width + 1,
+                y + 1,
+            ).intersection(&self.clip);
+
+            if !line.is_empty() {
+                self.span(line.x, y, line.width, color);
+            }
+        }
+
+        self.buffer.mark_dirty(bounds);
+    }
+
+    /// Draws the outline of the polygon with the given corners.
+    pub fn polygon(&mut self, points: &[(i32, i32)], color: Color) {
+        for (index, &(x1, y1)) in points.iter().enumerate() {
+            let (x2, y2) = points[(index + 1) % points.len()];
+            self.line(x1, y1, x2, y2, color);
+        }
+    }
+
+    /// Fills the polygon with the given corners.
+    ///
+    /// Pixels are filled if their center lies inside of the polygon, using
+    /// the even-odd rule for self intersecting polygons. Polygons with more
+    /// than `MAX_POLYGON_VERTICES` corners aren't drawn.
+    pub fn fill_polygon(&mut self, points: &[(i32, i32)], color: Color) {
+        if points.len() < 3 || points.len() > MAX_POLYGON_VERTICES {
+            return;
+        }
+
+        let mut top = points[0].1;
+        let mut bottom = points[0].1;
+        for &(_, y) in points {
+            if y < top {
+                top = y;
+            }
+            if y > bottom {
+                bottom = y;
+            }
+        }
+
+        let lines = Rect::from_corners(self.clip.x, top, self.clip.right(), bottom)
+            .intersection(&self.clip);
+        let mut crossings = [0i32; MAX_POLYGON_VERTICES];
+
+        for y in lines.y..lines.bottom() {
+            // The scanline passes through the pixel centers, doubled to stay
+            // in integers.
+            let center = 2 * y as i64 + 1;
+            let mut count = 0;
+
+            for (index, &(x1, y1)) in points.iter().enumerate() {
+                let (x2, y2) = points[(index + 1) % points.len()];
+                let (x1, y1, x2, y2) = (x1 as i64, 2 * y1 as i64, x2 as i64, 2 * y2 as i64);
+
+                if (y1 <= center) == (y2 <= center) {
+                    continue;
+                }
+
+                // The first pixel whose center lies right of the edge.
+                let numerator = (2 * x1 - 1) * (y2 - y1) + 2 * (center - y1) * (x2 - x1);
+                let x = div_ceil(numerator, 2 * (y2 - y1));
+
+                // Insertion sort, there are only a few crossings.
+                let mut position = count;
+                while position > 0 && crossings[position - 1] as i64 > x {
+                    crossings[position] = crossings[position - 1];
+                    position -= 1;
+                }
+                crossings[position] = x as i32;
+                count += 1;
+            }
+
+            for pair in crossings[..count].chunks(2).filter(|pair| pair.len() == 2) {
+                let line = Rect::from_corners(pair[0], y, pair[1], y + 1).intersection(&self.clip);
+                if !line.is_empty() {
+                    self.span(line.x, y, line.width, color);
+                }
+            }
+        }
+
+        self.buffer.mark_dirty(lines);
+    }
+
+    /// Draws the image with its upper left corner at the given position.
+    pub fn blit(&mut self, image: &Image, x: i32, y: i32) {
+        let destination = Rect::new(x, y, image.width, image.height).intersection(&self.clip);
+        if destination.is_empty() {
+            return;
+        }
+
+        for line in destination.y..destination.bottom() {
+            for column in destination.x..destination.right() {
+                let color = image.pixel((column - x) as u32, (line - y) as u32);
+                self.span(column, line, 1, color);
+            }
+        }
+
+        self.buffer.mark_dirty(destination);
+    }
+
+    /// Draws the image scaled to fill the given rectangle.
+    ///
+    /// The nearest pixel of the image is used for every pixel of the
+    /// rectangle.
+    pub fn blit_scaled(&mut self, image: &Image, rect: Rect) {
+        if image.width == 0 || image.height == 0 {
+            return;
+        }
+
+        let destination = rect.intersection(&self.clip);
+        if destination.is_empty() {
+            return;
+        }
+
+        for line in destination.y..destination.bottom() {
+            let source_y = (line - rect.y) as u64 * image.height as u64 / rect.height as u64;
+
+            for column in destination.x..destination.right() {
+                let source_x = (column - rect.x) as u64 * image.width as u64 / rect.width as u64;
+                let color = image.pixel(source_x as u32, source_y as u32);
+                self.span(column, line, 1, color);
+            }
+        }
+
+        self.buffer.mark_dirty(destination);
+    }
+
+    /// Draws the text with its upper left corner at the given position.
+    ///
+    /// Line feeds start a new line below the first character. Returns the
+    /// position after the last character.
+    pub fn text(&mut self, font: &Font, x: i32, y: i32, text: &str, color: Color) -> (i32, i32) {
+        let (width, height) = (font.width() as i32, font.height() as i32);
+        let (mut column, mut line) = (x, y);
+
+        for character in text.chars() {
+            if character == '\n' {
+                column = x;
+                line += height;
+                continue;
+            }
+
+            let bounds = Rect::new(column, line, width as u32, height as u32);
+            let visible = bounds.intersection(&self.clip);
+
+            if !visible.is_empty() {
+                let glyph = font.glyph(character);
+
+                for py in visible.y..visible.bottom() {
+                    for px in visible.x..visible.right() {
+                        if font.is_set(glyph, (px - column) as u32, (py - line) as u32) {
+                            self.span(px, py, 1, color);
+                        }
+                    }
+                }
+
+                self.buffer.mark_dirty(visible);
+            }
+
+            column += width;
+        }
+
+        (column, line)
+    }
+
+    /// Draws a horizontal run of pixels, which must lie within the clip
+    /// rectangle.
+    fn span(&mut self, x: i32, y: i32, length: u32, color: Color) {
+        if color.alpha == 0 {
+            return;
+        }
+
+        if color.is_opaque() {
+            let value = self.buffer.format.color(color.red, color.green, color.blue);
+            self.buffer.fill_span(x as u32, y as u32, length, value);
+            return;
+        }
+
+        for column in x..x + length as i32 {
+            let below = self.buffer.read_pixel(column as u32, y as u32);
+            let blended = color.blend_over(below);
+            let value = self.buffer.format.color(blended.red, blended.green, blended.blue);
+            self.buffer.fill_span(column as u32, y as u32, 1, value);
+        }
+    }
+}
+
+/// Returns the rectangle around a circle.
+fn circle_bounds(center_x: i32, center_y: i32, radius: i32) -> Rect {
+    Rect::from_corners(
+        center_x - radius,
+        center_y - radius,
+        center_x + radius + 1,
+        center_y + radius + 1,
+    )
+}
+
+/// Returns the largest integer whose square is at most `value`.
+fn isqrt(value: u64) -> u64 {
+    if value < 2 {
+        return value;
+    }
+
+    let mut root = value;
+    let mut next = (root + 1) / 2;
+    while next < root {
+        root = next;
+        next = (root + value / root) / 2;
+    }
+
+    root
+}
+
+/// Divides and rounds towards positive infinity.
+fn div_ceil(numerator: i64, denominator: i64) -> i64 {
+    let (numerator, denominator) = if denominator < 0 {
+        (-numerator, -denominator)
+    } else {
+        (numerator, denominator)
+    };
+
+    if numerator > 0 {
+        (numerator + denominator - 1) / denominator
+    } else {
+        numerator / denominator
+    }
+}
+
+/// Set in an outcode if the point lies left of the rectangle.
+const LEFT: u8 = 0x1;
+
+/// Set in an outcode if the point lies right of the rectangle.
+const RIGHT: u8 = 0x2;
+
+/// Set in an outcode if the point lies above the rectangle.
+const TOP: u8 = 0x4;
+
+/// Set in an outcode if the point lies below the rectangle.
+const BOTTOM: u8 = 0x8;
+
+/// Returns on which sides of the rectangle the point lies.
+fn outcode(rect: &Rect, x: i64, y: i64) -> u8 {
+    let mut code = 0;
+
+    if x < rect.x as i64 {
+        code |= LEFT;
+    } else if x >= rect.right() as i64 {
+        code |= RIGHT;
+    }
+
+    if y < rect.y as i64 {
+        code |= TOP;
+    } else if y >= rect.bottom() as i64 {
+        code |= BOTTOM;
+    }
+
+    code
+}
+
+/// Clips the line to the rectangle.
+///
+/// Returns `None` if no part of the line lies within the rectangle.
+fn clip_line(rect: &Rect, x1: i32, y1: i32, x2: i32, y2: i32) -> Option<(i32, i32, i32, i32)> {
+    if rect.is_empty() {
+        return None;
+    }
+
+    let (mut x1, mut y1, mut x2, mut y2) = (x1 as i64, y1 as i64, x2 as i64, y2 as i64);
+    let (left, top) = (rect.x as i64, rect.y as i64);
+    let (right, bottom) = (rect.right() as i64 - 1, rect.bottom() as i64 - 1);
+
+    loop {
+        let code1 = outcode(rect, x1, y1);
+        let code2 = outcode(rect, x2, y2);
+
+        if code1 | code2 == 0 {
+            return Some((x1 as i32, y1 as i32, x2 as i32, y2 as i32));
+        }
+        if code1 & code2 != 0 {
+            return None;
+        }
+
+        let code = if code1 != 0 { code1 } else { code2 };
+        let (x, y) = if code & TOP != 0 {
+            (x1 + (x2 - x1) * (top - y1) / (y2 - y1), top)
+        } else if code & BOTTOM != 0 {
+            (x1 + (x2 - x1) * (bottom - y1) / (y2 - y1), bottom)
+        } else if code & RIGHT != 0 {
+            (right, y1 + (y2 - y1) * (right - x1) / (x2 - x1))
+        } else {
+            (left, y1 + (y2 - y1) * (left - x1) / (x2 - x1))
+        };
+
+        if code == code1 {
+            x1 = x;
+            y1 = y;
+        } else {
+            x2 = x;
+            y2 = y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::buffer;
+
+    /// Tests the intersection and union of rectangles.
+    #[test]
+    fn test_rect() {
+        let first = Rect::new(0, 0, 4, 4);
+        let second = Rect::new(2, -2, 4, 4);
+
+        assert_eq!(first.intersection(&second), Rect::new(2, 0, 2, 2));
+        assert_eq!(first.union(&second), Rect::new(0, -2, 6, 6));
+        assert!(first.intersection(&Rect::new(8, 8, 1, 1)).is_empty());
+        assert_eq!(first.union(&Rect::default()), first);
+        assert!(first.touches(&Rect::new(4, 0, 1, 1)));
+        assert!(!first.touches(&Rect::new(5, 0, 1, 1)));
+    }
+
+    /// Tests that drawing is clipped and marks the drawn region as dirty.
+    #[test]
+    fn test_fill_rect_clipped() {
+        static mut ONSCREEN: [u32; 64] = [0; 64];
+        static mut OFFSCREEN: [u32; 64] = [0; 64];
+        let mut buffer = unsafe { buffer(&mut ONSCREEN, &mut OFFSCREEN, 8, 8) };
+        let white = Color::rgb(255, 255, 255);
+
+        {
+            let mut canvas = buffer.canvas();
+            canvas.set_clip(Rect::new(2, 2, 10, 10));
+            canvas.fill_rect(Rect::new(-4, 0, 8, 8), white);
+
+            assert_eq!(canvas.pixel(3, 3), Some(white));
+            assert_eq!(canvas.pixel(1, 3), Some(Color::rgb(0, 0, 0)));
+            assert_eq!(canvas.pixel(4, 3), Some(Color::rgb(0, 0, 0)));
+            assert_eq!(canvas.pixel(8, 3), None);
+        }
+
+        assert_eq!(buffer.dirty.count, 1);
+        assert_eq!(buffer.dirty.rects[0], Rect::new(2, 2, 2, 6));
+    }
+
+    /// Tests filling polygons.
+    #[test]
+    fn test_fill_polygon() {
+        static mut ONSCREEN: [u32; 64] = [0; 64];
+        static mut OFFSCREEN: [u32; 64] = [0; 64];
+        let mut buffer = unsafe { buffer(&mut ONSCREEN, &mut OFFSCREEN, 8, 8) };
+        let white = Color::rgb(255, 255, 255);
+
+        let mut canvas = buffer.canvas();
+        canvas.fill_polygon(&[(1, 1), (5, 1), (5, 4), (1, 4)], white);
+
+        let filled = (0..8)
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .filter(|&(x, y)| canvas.pixel(x, y) == Some(white))
+            .count();
+        assert_eq!(filled, 12);
+        assert_eq!(canvas.pixel(1, 1), Some(white));
+        assert_eq!(canvas.pixel(5, 1), Some(Color::rgb(0, 0, 0)));
+
+        // Polygons with too many corners are ignored instead of panicking.
+        let mut points = [(0, 0); MAX_POLYGON_VERTICES + 1];
+        for (index, point) in points.iter_mut().enumerate() {
+            *point = (index as i32 % 8, index as i32 / 8);
+        }
+        canvas.clear(Color::rgb(0, 0, 0));
+        canvas.fill_polygon(&points, white);
+        assert_eq!(canvas.pixel(3, 3), Some(Color::rgb(0, 0, 0)));
+    }
+}
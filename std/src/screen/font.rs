@@ -0,0 +1,5 @@
+//! Bitmap fonts for drawing text.
+//!
+//! The fonts are shared with the kernel console, see `boring_core::font`.
+
+pub use boring_core::font::{Font, FontError};
@@ -1,10 +1,22 @@
+//! Draws on the linear framebuffer.
+//!
+//! Drawing happens on an offscreen buffer or a hidden framebuffer page and is
+//! shown by syncing. A `Canvas` offers clipped drawing of shapes, images and
+//! text.
+
+pub mod canvas;
+pub mod font;
 mod primitive;
 
+pub use self::canvas::{Canvas, Color, Image, Rect};
+pub use self::font::{Font, FontError};
+use boring_core::syscall::{FLIP_FRAMEBUFFER_SYSCALL_NUM, FRAMEBUFFER_MODE_SYSCALL_NUM,
+                           MAP_FRAMEBUFFER_SYSCALL_NUM, SET_FRAMEBUFFER_MODE_SYSCALL_NUM};
 use volatile::Volatile;
 use core::ptr::Unique;
 use core::marker::Copy;
 pub use spin::{Mutex, Once};
-use core::{cmp, slice};
+use core::slice;
 use core::mem::size_of;
 use core::mem;
 use self::primitive::{fast_copy32, fast_set32, fast_set64};
@@ -74,23 +86,101 @@ pub struct Buffer {
     height: u32,
     pitch: u32,
     pixelwidth: u8,
+    format: PixelFormat,
     len: usize,
+    /// The number of pixels between two lines of the drawing buffer.
+    line_length: u32,
+    /// The number of framebuffer pages, drawing happens offscreen if it is one.
+    pages: u32,
+    /// The displayed page.
+    page: u32,
+    /// The regions drawn since the last sync.
+    dirty: DirtyRegion,
+    /// The bounds of the pixels written one at a time since the last sync.
+    ///
+    /// These are added to the dirty region at once, instead of per pixel.
+    written: Rect,
 }
 
 impl Buffer {
     pub fn new(info: Info) -> Buffer {
-        Buffer {
+        let page_length = info.pitch / 4 * info.height;
+
+        let mut buffer = Buffer {
             onscreen: unsafe {
-                slice::from_raw_parts_mut(info.address as *mut u32, info.width * info.height)
+                slice::from_raw_parts_mut(info.address as *mut u32, page_length * info.pages)
             },
-            offscreen: unsafe { &mut OFFSCREEN },
+            offscreen: unsafe { &mut OFFSCREEN[..info.width * info.height] },
             location: info.address as u64,
             width: info.width as u32,
             height: info.height as u32,
             pitch: info.pitch as u32,
             pixelwidth: info.bpp / 8,
+            format: info.format,
             len: info.width * info.height as usize,
+            line_length: info.width as u32,
+            pages: 1,
+            page: 0,
+            dirty: DirtyRegion::new(),
+            written: Rect::default(),
+        };
+
+        // With several pages, drawing happens directly on the hidden page.
+        if info.pages > 1 && flip(0) {
+            buffer.pages = info.pages as u32;
+            buffer.line_length = info.pitch as u32 / 4;
+            buffer.offscreen = unsafe { buffer.page_slice(1) };
         }
+
+        buffer
+    }
+
+    /// Switches the screen to the given mode.
+    ///
+    /// Returns false if the mode isn't supported, the buffer is unchanged then.
+    pub fn set_mode(&mut self, mode: Mode) -> bool {
+        if !is_drawable(mode.width as usize, mode.height as usize, mode.bpp) {
+            return false;
+        }
+
+        let result = unsafe {
+            syscall!(
+                SET_FRAMEBUFFER_MODE_SYSCALL_NUM,
+                mode.width as u64,
+                mode.height as u64,
+                mode.bpp as u64
+            ) as i64
+        };
+
+        if result < 0 {
+            return false;
+        }
+
+        let info = match map_framebuffer() {
+            Some(info) => info,
+            None => return false,
+        };
+
+        if !is_drawable(info.width, info.height, info.bpp as u32) {
+            return false;
+        }
+
+        *self = Buffer::new(info);
+
+        true
+    }
+
+    /// Returns the given framebuffer page.
+    ///
+    /// # Safety
+    /// - The page must exist and the slice must not outlive the buffer.
+    unsafe fn page_slice(&mut self, page: u32) -> &'static mut [u32] {
+        let page_length = self.pitch as usize / 4 * self.height as usize;
+
+        slice::from_raw_parts_mut(
+            self.onscreen.as_mut_ptr().offset((page_length * page as usize) as isize),
+            page_length,
+        )
     }
 
     pub fn width(&self) -> u32 {
@@ -101,193 +191,339 @@ impl Buffer {
         self.height
     }
 
+    /// Returns the pixel format of the screen.
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Returns the pixel value for the given color.
+    pub fn color(&self, red: u8, green: u8, blue: u8) -> u32 {
+        self.format.color(red, green, blue)
+    }
+
+    /// Returns true if drawing happens on a hidden framebuffer page.
+    pub fn is_page_flipping(&self) -> bool {
+        self.pages > 1
+    }
+
+    /// Returns a canvas for drawing on the whole screen.
+    pub fn canvas(&mut self) -> Canvas {
+        Canvas::new(self)
+    }
+
+    /// Returns the rectangle covering the screen.
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Marks the given region as changed, so that the next sync shows it.
+    pub fn mark_dirty(&mut self, rect: Rect) {
+        let rect = rect.intersection(&self.bounds());
+        self.dirty.add(rect);
+    }
+
+    /// Marks the whole screen as changed.
+    pub fn invalidate(&mut self) {
+        let bounds = self.bounds();
+        self.dirty.add(bounds);
+    }
+
+    /// Returns the number of bytes between two lines of the drawing buffer.
     #[inline(always)]
-    fn offset(&self, x: u32, y: u32) -> isize {
-        (x + y * 1024) as isize
+    fn stride(&self) -> usize {
+        self.line_length as usize * size_of::<u32>()
     }
 
     #[inline(always)]
-    pub unsafe fn write(&mut self, x: u32, y: u32, color: u32) {
-        if x >= self.width || y >= self.height {
-            return;
+    fn offset(&self, x: u32, y: u32) -> usize {
+        (x + y * self.line_length) as usize
+    }
+
+    /// Sets `length` pixels starting at the given position to the value.
+    ///
+    /// The pixels must lie on one line of the screen.
+    #[inline(always)]
+    fn fill_span(&mut self, x: u32, y: u32, length: u32, value: u32) {
+        debug_assert!(x + length <= self.width && y < self.height);
+
+        let start = self.offset(x, y);
+        let span = &mut self.offscreen[start..start + length as usize];
+
+        unsafe {
+            fast_set32(span.as_mut_ptr(), value, span.len());
         }
+    }
 
-        fast_set32(
-            self.offscreen.as_mut_ptr().offset(self.offset(x, y)) as *mut u32,
-            color,
-            1,
-        );
+    /// Returns the color of the pixel at the given position.
+    #[inline(always)]
+    fn read_pixel(&self, x: u32, y: u32) -> Color {
+        let value = self.offscreen[self.offset(x, y)];
+
+        self.format.components(value)
     }
 
+    /// Sets the pixel to the given value.
+    ///
+    /// Pixels outside of the screen are ignored.
     #[inline(always)]
-    pub unsafe fn write_buf(&mut self, x: u32, y: u32, buf: &[u32]) {
+    pub fn write(&mut self, x: u32, y: u32, color: u32) {
         if x >= self.width || y >= self.height {
             return;
         }
 
-        let seek = self.offset(x, y);
-        let size = cmp::max(
-            0,
-            cmp::min(self.offscreen.len() as isize - seek, (buf.len()) as isize),
-        ) as usize;
-        if size > 0 {
-            unsafe {
-                fast_copy32(
-                    self.offscreen.as_mut_ptr().offset(seek) as *mut u32,
-                    buf.as_ptr(),
-                    size,
-                );
-            }
-        }
+        let offset = self.offset(x, y);
+        self.offscreen[offset] = color;
+        self.written = self.written.union(&Rect::new(x as i32, y as i32, 1, 1));
     }
 
-    pub unsafe fn draw_line(&mut self, x1: u32, y1: u32, x2: u32, y2: u32, color: u32) {
-        if x1 >= self.width || y1 >= self.height || x2 >= self.width || y2 >= self.height {
+    /// Sets the pixels starting at the given position to the values.
+    ///
+    /// Values beyond the end of the line are ignored.
+    #[inline(always)]
+    pub fn write_buf(&mut self, x: u32, y: u32, buf: &[u32]) {
+        if x >= self.width || y >= self.height {
             return;
         }
 
-        let dx = x2 as i32 - x1 as i32;
-        let dy = y2 as i32 - y1 as i32;
-
-        let dxabs = i32::abs(dx);
-        let dyabs = i32::abs(dy);
+        let length = if buf.len() < (self.width - x) as usize {
+            buf.len()
+        } else {
+            (self.width - x) as usize
+        };
+        let start = self.offset(x, y);
 
-        let sdx = i32::signum(dx);
-        let sdy = i32::signum(dy);
+        self.offscreen[start..start + length].copy_from_slice(&buf[..length]);
+        self.written = self.written
+            .union(&Rect::new(x as i32, y as i32, length as u32, 1));
+    }
 
-        let mut x = dxabs >> 1;
-        let mut y = dyabs >> 1;
+    /// Draws a line between the given points, including both of them.
+    pub fn draw_line(&mut self, x1: u32, y1: u32, x2: u32, y2: u32, color: u32) {
+        let color = self.format.components(color);
+        self.canvas()
+            .line(x1 as i32, y1 as i32, x2 as i32, y2 as i32, color);
+    }
 
-        let mut px = x1 as i32;
-        let mut py = y1 as i32;
+    /// Draws a vertical line from `ytop` to just above `ybottom`.
+    #[inline(always)]
+    pub fn vertical_line(&mut self, x: u32, ytop: u32, ybottom: u32, color: u32) {
+        if ytop >= ybottom {
+            return;
+        }
 
-        self.write(px as u32, py as u32, color);
+        let color = self.format.components(color);
+        self.canvas()
+            .fill_rect(Rect::new(x as i32, ytop as i32, 1, ybottom - ytop), color);
+    }
 
-        if dxabs >= dyabs {
-            for _ in 0..dxabs {
-                y += dyabs;
+    /// Draws a horizontal line from `xleft` to just before `xright`.
+    #[inline(always)]
+    pub fn horizontal_line(&mut self, xleft: u32, xright: u32, y: u32, color: u32) {
+        if xleft >= xright {
+            return;
+        }
 
-                if y >= dxabs {
-                    y -= dxabs;
-                    py += sdy;
-                }
+        let color = self.format.components(color);
+        self.canvas()
+            .fill_rect(Rect::new(xleft as i32, y as i32, xright - xleft, 1), color);
+    }
 
-                px += sdx;
-                self.write(px as u32, py as u32, color);
-            }
-        } else {
-            for _ in 0..dyabs {
-                x += dxabs;
-                if x >= dyabs {
-                    x -= dyabs;
-                    px += sdx;
-                }
-                py += sdy;
-                self.write(px as u32, py as u32, color);
-            }
-        }
+    /// Fills the rectangle between the given corners.
+    ///
+    /// The second corner lies just outside of the rectangle.
+    #[inline(always)]
+    pub fn fill_rect(&mut self, x1: u32, y1: u32, x2: u32, y2: u32, color: u32) {
+        let color = self.format.components(color);
+        self.canvas().fill_rect(
+            Rect::from_corners(x1 as i32, y1 as i32, x2 as i32, y2 as i32),
+            color,
+        );
     }
 
+    /// Shows the drawn frame.
+    ///
+    /// With page flipping, the drawn page is displayed and drawing continues
+    /// on the previously displayed page, which holds an older frame.
+    /// Otherwise the regions drawn since the last sync are copied to the
+    /// screen.
     #[inline(always)]
-    pub unsafe fn vertical_line(&mut self, x: u32, ytop: u32, ybottom: u32, color: u32) {
-        if ytop >= ybottom || x >= self.width || ytop >= self.height {
+    pub fn sync(&mut self) {
+        let written = mem::replace(&mut self.written, Rect::default());
+        self.dirty.add(written);
+
+        if self.pages > 1 {
+            let page = (self.page + 1) % self.pages;
+            if flip(page) {
+                self.page = page;
+                self.offscreen = unsafe { self.page_slice((page + 1) % self.pages) };
+            }
+            self.dirty.clear();
             return;
         }
 
-        // debugln!("x: {}, ytop: {}, ybottom: {}", x, ytop, ybottom);
+        let pitch = self.pitch as usize / size_of::<u32>();
+
+        for index in 0..self.dirty.count {
+            let rect = self.dirty.rects[index];
+            let (x, y) = (rect.x as usize, rect.y as usize);
+            let width = rect.width as usize;
 
-        // let start = self.onscreen.as_mut_ptr();
-        // let mut location_ptr = start.offset(self.offset(x, ytop));
-        let mut onscreen_ptr = self.offscreen.as_mut_ptr().offset(self.offset(x, ytop)) as usize;
+            // The whole screen is copied at once if the lines have no gaps.
+            if rect == self.bounds() && pitch == self.line_length as usize {
+                self.onscreen[..self.len].copy_from_slice(&self.offscreen[..self.len]);
+                continue;
+            }
 
-        let offset = self.pitch as usize;
-        for _ in 0..(ybottom - ytop) as isize {
-            fast_set32(onscreen_ptr as *mut u32, color, 1);
-            onscreen_ptr += offset;
+            for line in y..y + rect.height as usize {
+                let source = line * self.line_length as usize + x;
+                let destination = line * pitch + x;
+                let source = &self.offscreen[source..source + width];
+                let destination = &mut self.onscreen[destination..destination + width];
+
+                unsafe {
+                    fast_copy32(destination.as_mut_ptr(), source.as_ptr(), width);
+                }
+            }
         }
-        // fast_set32(onscreen_ptr as *mut u32, color, 1);
+
+        self.dirty.clear();
     }
+}
 
-    #[inline(always)]
-    pub unsafe fn horizontal_line(&mut self, xtop: u32, xbottom: u32, y: u32, color: u32) {
-        if xtop >= xbottom || xtop >= self.width || y >= self.height {
-            return;
-        }
+/// The maximum number of separate dirty rectangles.
+///
+/// If more regions are drawn, they are merged into one.
+const MAX_DIRTY_RECTS: usize = 16;
+
+/// The regions of the screen that changed since the last sync.
+struct DirtyRegion {
+    /// The changed rectangles, which don't touch each other.
+    rects: [Rect; MAX_DIRTY_RECTS],
+    /// The number of rectangles.
+    count: usize,
+}
 
-        let start = self.offscreen.as_mut_ptr();
-        let mut location_ptr = start.offset(self.offset(xtop, y));
-        fast_set32(location_ptr as *mut u32, color, (xtop - xbottom) as usize);
+impl DirtyRegion {
+    /// Creates an empty region.
+    fn new() -> DirtyRegion {
+        DirtyRegion {
+            rects: [Rect::default(); MAX_DIRTY_RECTS],
+            count: 0,
+        }
     }
 
-    #[inline(always)]
-    pub unsafe fn fill_rect(
-        &mut self,
-        mut x1: u32,
-        mut y1: u32,
-        mut x2: u32,
-        mut y2: u32,
-        color: u32,
-    ) {
-        if x1 >= self.width || y1 >= self.height || x2 >= self.width || y2 >= self.height {
+    /// Adds the rectangle to the region.
+    ///
+    /// Touching rectangles are merged, so drawing neighbouring pixels grows a
+    /// single rectangle.
+    fn add(&mut self, mut rect: Rect) {
+        if rect.is_empty() {
             return;
         }
 
-        if (x1 > x2) {
-            let temp = x1;
-            x1 = x2;
-            x2 = temp;
+        let mut index = 0;
+        while index < self.count {
+            if self.rects[index].touches(&rect) {
+                rect = rect.union(&self.rects[index]);
+
+                // The grown rectangle may touch rectangles that were already
+                // checked.
+                self.count -= 1;
+                self.rects[index] = self.rects[self.count];
+                index = 0;
+            } else {
+                index += 1;
+            }
+        }
+
+        if self.count == MAX_DIRTY_RECTS {
+            for index in 1..self.count {
+                rect = rect.union(&self.rects[index]);
+            }
+            self.rects[0] = rect.union(&self.rects[0]);
+            self.count = 1;
+        } else {
+            self.rects[self.count] = rect;
+            self.count += 1;
         }
+    }
 
-        if (y1 > y2) {
-            let temp = y1;
-            y1 = y2;
-            y2 = temp;
+    /// Empties the region.
+    fn clear(&mut self) {
+        self.count = 0;
+    }
+}
+
+pub static SCREEN: Once<Mutex<Buffer>> = Once::new();
+
+/// The position and size of a color channel within a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorField {
+    /// The position of the lowest bit of the channel.
+    pub position: u8,
+    /// The number of bits of the channel.
+    pub size: u8,
+}
+
+impl ColorField {
+    /// Returns the 8 bit channel value stored in the pixel.
+    fn extract(&self, pixel: u32) -> u8 {
+        if self.size == 0 {
+            return 0;
         }
 
-        let mut onscreen_ptr = self.offscreen.as_mut_ptr().offset(self.offset(x1, y1)) as usize;
+        let mask = if self.size >= 32 {
+            0xffff_ffff
+        } else {
+            (1u32 << self.size) - 1
+        };
+        let value = pixel >> self.position & mask;
 
-        let offset = self.pitch as usize;
-        for _ in 0..(y2 - y1) {
-            fast_set32(onscreen_ptr as *mut u32, color, (x2 - x1) as usize);
-            onscreen_ptr += offset;
+        if self.size >= 8 {
+            (value >> (self.size - 8)) as u8
+        } else {
+            (value * 255 / mask) as u8
         }
     }
 
-    #[inline(always)]
-    pub unsafe fn sync(&mut self) {
-        // let start_y = cmp::min(self.height, y);
-        // let end_y = cmp::min(self.height, y + h);
-        //
-        // let start_x = cmp::min(self.width, x);
-        // let len = (cmp::min(self.width, x + w) - start_x) * 4;
-        let mut offscreen_ptr = self.offscreen.as_mut_ptr() as usize;
-        let mut onscreen_ptr = self.onscreen.as_mut_ptr() as usize;
-
-        fast_copy32(
-            onscreen_ptr as *mut u32,
-            offscreen_ptr as *const u32,
-            self.len as usize,
-        );
+    /// Places the given 8 bit channel value in the field.
+    fn place(&self, value: u8) -> u32 {
+        let value = if self.size >= 8 {
+            (value as u32) << (self.size - 8)
+        } else {
+            value as u32 >> (8 - self.size)
+        };
 
-        // let stride = self.width * 4;
-        //
-        // let offset = y * stride + start_x * 4;
-        // offscreen_ptr += offset;
-        // onscreen_ptr += offset;
-        //
-        // let mut rows = end_y - start_y;
-        // while rows > 0 {
-        //     unsafe {
-        //         fast_copy(onscreen_ptr as *mut u8, offscreen_ptr as *const u8, len);
-        //     }
-        //     offscreen_ptr += stride;
-        //     onscreen_ptr += stride;
-        //     rows -= 1;
-        // }
+        value << self.position
     }
 }
 
-pub static SCREEN: Once<Mutex<Buffer>> = Once::new();
+/// Describes how colors are stored in a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormat {
+    /// The red channel.
+    pub red: ColorField,
+    /// The green channel.
+    pub green: ColorField,
+    /// The blue channel.
+    pub blue: ColorField,
+}
+
+impl PixelFormat {
+    /// Returns the pixel value for the given color.
+    pub fn color(&self, red: u8, green: u8, blue: u8) -> u32 {
+        self.red.place(red) | self.green.place(green) | self.blue.place(blue)
+    }
+
+    /// Returns the opaque color of the given pixel value.
+    pub fn components(&self, pixel: u32) -> Color {
+        Color::rgb(
+            self.red.extract(pixel),
+            self.green.extract(pixel),
+            self.blue.extract(pixel),
+        )
+    }
+}
 
 pub struct Info {
     pub height: usize,
@@ -295,17 +531,148 @@ pub struct Info {
     pub address: usize,
     pub bpp: u8,
     pub pitch: usize,
+    pub format: PixelFormat,
+    /// The number of pages, each `pitch * height` bytes after the previous.
+    pub pages: usize,
 }
 
-pub fn init() {
-    let info = Info {
-        height: 768,
-        width: 1024,
-        address: 0xffff8000fd000000,
-        bpp: 32,
-        pitch: 4096,
+/// The framebuffer information as returned by the kernel.
+#[repr(C)]
+#[derive(Default)]
+struct FramebufferInfo {
+    address: u64,
+    width: u32,
+    height: u32,
+    pitch: u32,
+    bpp: u8,
+    red_position: u8,
+    red_size: u8,
+    green_position: u8,
+    green_size: u8,
+    blue_position: u8,
+    blue_size: u8,
+    reserved: u8,
+    pages: u32,
+}
+
+/// Maps the framebuffer into the process and returns its description.
+///
+/// Returns `None` if there is no linear framebuffer.
+pub fn map_framebuffer() -> Option<Info> {
+    let mut info = FramebufferInfo::default();
+
+    let result = unsafe {
+        syscall!(
+            MAP_FRAMEBUFFER_SYSCALL_NUM,
+            &mut info as *mut FramebufferInfo as u64
+        ) as i64
     };
 
+    if result < 0 {
+        return None;
+    }
+
+    Some(Info {
+        height: info.height as usize,
+        width: info.width as usize,
+        address: info.address as usize,
+        bpp: info.bpp,
+        pitch: info.pitch as usize,
+        format: PixelFormat {
+            red: ColorField {
+                position: info.red_position,
+                size: info.red_size,
+            },
+            green: ColorField {
+                position: info.green_position,
+                size: info.green_size,
+            },
+            blue: ColorField {
+                position: info.blue_position,
+                size: info.blue_size,
+            },
+        },
+        pages: info.pages as usize,
+    })
+}
+
+/// A display mode.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// The number of bits per pixel.
+    pub bpp: u32,
+}
+
+/// An iterator over the supported display modes.
+pub struct Modes {
+    /// The index of the next mode.
+    index: u64,
+}
+
+impl Iterator for Modes {
+    type Item = Mode;
+
+    fn next(&mut self) -> Option<Mode> {
+        let mut mode = Mode::default();
+
+        let result = unsafe {
+            syscall!(
+                FRAMEBUFFER_MODE_SYSCALL_NUM,
+                self.index,
+                &mut mode as *mut Mode as u64
+            ) as i64
+        };
+
+        if result < 0 {
+            return None;
+        }
+
+        self.index += 1;
+        Some(mode)
+    }
+}
+
+/// Returns the display modes that can be switched to.
+///
+/// There are none if the graphics device has no driver.
+pub fn modes() -> Modes {
+    Modes { index: 0 }
+}
+
+/// Displays the given framebuffer page.
+///
+/// Returns false if the page doesn't exist or the current process isn't
+/// allowed to do this.
+pub fn flip(page: u32) -> bool {
+    unsafe { syscall!(FLIP_FRAMEBUFFER_SYSCALL_NUM, page as u64) as i64 >= 0 }
+}
+
+/// Returns true if a buffer can be drawn on in the given mode.
+fn is_drawable(width: usize, height: usize, bpp: u32) -> bool {
+    bpp == 32
+        && width
+            .checked_mul(height)
+            .map_or(false, |size| size <= unsafe { OFFSCREEN.len() })
+}
+
+/// Panics if the framebuffer can't be drawn on.
+fn check_info(info: &Info) {
+    assert_eq!(info.bpp, 32, "Only 32 bit framebuffers are supported.");
+    assert!(
+        info.width * info.height <= unsafe { OFFSCREEN.len() },
+        "The screen is too large for the offscreen buffer."
+    );
+}
+
+pub fn init() {
+    let info = map_framebuffer().expect("No linear framebuffer is available.");
+    check_info(&info);
+
     SCREEN.call_once(|| Mutex::new(Buffer::new(info)));
 }
 
@@ -315,18 +682,130 @@ pub fn test() {
     let width = buffer.width;
     let height = buffer.height;
 
-    // buffer.write(100, 100, &[255, 255, 255, 0, 255, 255, 255, 0]);
-    // buffer.write(101, 100, &[255, 255, 255, 0]);
-    // for i in 0..100 {
-    //     buffer.write(i, 100, &[0x00ffffff]);
-    // }
-
-    unsafe {
-        buffer.write_buf(100, 100, &[255, 0, 255, 0, 255, 0, 255, 0, 255, 0, 255, 0]);
-        buffer.draw_line(0, 0, width - 100, height - 20, 0x00ffffff);
-        buffer.draw_line(100, 99, 200, 99, 0x00ffffff);
-        buffer.fill_rect(100, 100, 100, 100, 0x00ff0a1f);
-        buffer.vertical_line(20, 20, 600, 0x000affff);
-        buffer.horizontal_line(10, height - 100, width - 50, 0x00ffffff);
+    buffer.write_buf(100, 100, &[255, 0, 255, 0, 255, 0, 255, 0, 255, 0, 255, 0]);
+    buffer.draw_line(0, 0, width - 100, height - 20, 0x00ffffff);
+    buffer.draw_line(100, 99, 200, 99, 0x00ffffff);
+    buffer.fill_rect(100, 100, 200, 200, 0x00ff0a1f);
+    buffer.vertical_line(20, 20, 600, 0x000affff);
+    buffer.horizontal_line(10, width - 50, height - 100, 0x00ffffff);
+
+    {
+        let mut canvas = buffer.canvas();
+        canvas.set_clip(Rect::new(150, 150, 300, 200));
+        canvas.fill_circle(200, 200, 80, Color::rgba(0, 128, 255, 128));
+        canvas.fill_polygon(&[(300, 160), (440, 330), (160, 330)], Color::rgb(0, 200, 0));
+        canvas.reset_clip();
+        canvas.text(&Font::builtin(), 160, 360, "BoringOS", Color::rgb(255, 255, 255));
+    }
+
+    buffer.sync();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pixel format used by the tests.
+    const FORMAT: PixelFormat = PixelFormat {
+        red: ColorField {
+            position: 16,
+            size: 8,
+        },
+        green: ColorField {
+            position: 8,
+            size: 8,
+        },
+        blue: ColorField {
+            position: 0,
+            size: 8,
+        },
+    };
+
+    /// Creates a buffer of the given size that draws on `offscreen`.
+    pub fn buffer(
+        onscreen: &'static mut [u32],
+        offscreen: &'static mut [u32],
+        width: u32,
+        height: u32,
+    ) -> Buffer {
+        Buffer {
+            onscreen,
+            offscreen,
+            location: 0,
+            width,
+            height,
+            pitch: width * 4,
+            pixelwidth: 4,
+            format: FORMAT,
+            len: (width * height) as usize,
+            line_length: width,
+            pages: 1,
+            page: 0,
+            dirty: DirtyRegion::new(),
+            written: Rect::default(),
+        }
+    }
+
+    /// Tests that touching rectangles are merged.
+    #[test]
+    fn test_dirty_region_merge() {
+        let mut region = DirtyRegion::new();
+
+        region.add(Rect::new(0, 0, 2, 2));
+        region.add(Rect::new(2, 0, 2, 2));
+        region.add(Rect::new(10, 10, 1, 1));
+        region.add(Rect::new(5, 5, 0, 3));
+
+        assert_eq!(region.count, 2);
+        assert_eq!(region.rects[0], Rect::new(0, 0, 4, 2));
+        assert_eq!(region.rects[1], Rect::new(10, 10, 1, 1));
+
+        // Bridging both rectangles merges all of them.
+        region.add(Rect::new(4, 2, 6, 8));
+
+        assert_eq!(region.count, 1);
+        assert_eq!(region.rects[0], Rect::new(0, 0, 11, 11));
+    }
+
+    /// Tests that too many rectangles are merged into one.
+    #[test]
+    fn test_dirty_region_overflow() {
+        let mut region = DirtyRegion::new();
+
+        for index in 0..MAX_DIRTY_RECTS as i32 + 1 {
+            region.add(Rect::new(index * 3, 0, 1, 1));
+        }
+
+        assert_eq!(region.count, 1);
+        assert_eq!(region.rects[0], Rect::new(0, 0, MAX_DIRTY_RECTS as u32 * 3 + 1, 1));
+
+        region.clear();
+        assert_eq!(region.count, 0);
+    }
+
+    /// Tests that single pixels are marked as dirty at once and shown by
+    /// syncing.
+    #[test]
+    fn test_write_batches_dirty_region() {
+        static mut ONSCREEN: [u32; 16] = [0; 16];
+        static mut OFFSCREEN: [u32; 16] = [0; 16];
+        let mut buffer = unsafe { buffer(&mut ONSCREEN, &mut OFFSCREEN, 4, 4) };
+
+        buffer.write(1, 1, 0xff);
+        buffer.write(2, 2, 0xff00);
+        buffer.write(4, 0, 0xff0000);
+        buffer.write_buf(0, 3, &[1, 2, 3, 4, 5]);
+
+        assert_eq!(buffer.dirty.count, 0);
+        assert_eq!(buffer.written, Rect::new(0, 1, 4, 3));
+
+        buffer.sync();
+
+        assert_eq!(buffer.dirty.count, 0);
+        assert!(buffer.written.is_empty());
+        assert_eq!(buffer.onscreen[5], 0xff);
+        assert_eq!(buffer.onscreen[10], 0xff00);
+        assert_eq!(&buffer.onscreen[12..], &[1, 2, 3, 4]);
+        assert_eq!(buffer.onscreen[0], 0);
     }
 }
@@ -1,15 +1,7 @@
 //! Handles thread related syscalls.
 
-/// The number of the exit syscall.
-const SLEEP_SYSCALL_NUM: u64 = 4;
-
-/// The number of the syscall to create a new thread.
-const NEW_THREAD_SYSCALL_NUM: u64 = 5;
-
-/// Kills the current thread.
-const KILL_THREAD_SYSCALL_NUM: u64 = 6;
-
-const REG_KB_INTERRUPT_NUM: u64 = 9;
+use boring_core::syscall::{KILL_THREAD_SYSCALL_NUM, NEW_THREAD_SYSCALL_NUM,
+                           REGISTER_KB_INTERRUPT_SYSCALL_NUM, SLEEP_SYSCALL_NUM};
 
 /// Lets the current thread sleep for `ms` milliseconds.
 pub fn sleep(ms: u64) {
@@ -56,7 +48,7 @@ extern "C" fn new_thread_creator(
 pub fn register_kb_interrupt(function: fn(u64, u64, u64, u64)) {
     unsafe {
         syscall!(
-            REG_KB_INTERRUPT_NUM,
+            REGISTER_KB_INTERRUPT_SYSCALL_NUM,
             new_thread_creator as u64,
             function as u64
         );
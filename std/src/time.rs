@@ -0,0 +1,164 @@
+//! Provides access to the system clocks.
+
+use boring_core::syscall::CLOCK_GETTIME_SYSCALL_NUM;
+use core::fmt;
+
+pub use boring_core::time::DateTime;
+
+/// The number of nanoseconds in a second.
+pub const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+/// The clocks that can be read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockId {
+    /// Counts from an arbitrary point at boot and never jumps.
+    Monotonic = 0,
+    /// Counts from the UNIX epoch.
+    Realtime = 1,
+}
+
+/// A point in time as seconds and nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timespec {
+    /// The whole seconds.
+    pub seconds: u64,
+    /// The nanoseconds within the second.
+    pub nanoseconds: u32,
+}
+
+impl Timespec {
+    /// Creates a timespec from the given amount of nanoseconds.
+    pub fn from_nanoseconds(nanoseconds: u64) -> Timespec {
+        Timespec {
+            seconds: nanoseconds / NANOSECONDS_PER_SECOND,
+            nanoseconds: (nanoseconds % NANOSECONDS_PER_SECOND) as u32,
+        }
+    }
+
+    /// Returns the total amount of nanoseconds.
+    pub fn as_nanoseconds(&self) -> u64 {
+        self.seconds * NANOSECONDS_PER_SECOND + self.nanoseconds as u64
+    }
+}
+
+/// Reads the given clock in nanoseconds.
+fn read_clock(clock: ClockId) -> u64 {
+    let result = unsafe { syscall!(CLOCK_GETTIME_SYSCALL_NUM, clock as u64) as i64 };
+    assert!(result >= 0, "The clock {:?} could not be read.", clock);
+
+    result as u64
+}
+
+/// Returns the current time of the given clock.
+pub fn clock_gettime(clock: ClockId) -> Timespec {
+    Timespec::from_nanoseconds(read_clock(clock))
+}
+
+/// The timeout argument that blocks without a timeout.
+const NO_TIMEOUT: u64 = <u64>::max_value();
+
+/// The error returned by blocking syscalls if the timeout expired.
+const TIMED_OUT_ERROR: i64 = -2;
+
+/// The error returned by futexes that don't hold the expected value.
+const VALUE_CHANGED_ERROR: i64 = -3;
+
+/// The errors that can occur while blocking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+    /// An argument was invalid.
+    InvalidArgument,
+    /// The timeout expired.
+    TimedOut,
+    /// The futex didn't hold the expected value.
+    ValueChanged,
+}
+
+/// Converts an optional timeout in nanoseconds to a syscall argument.
+pub(crate) fn timeout_argument(timeout: Option<u64>) -> u64 {
+    match timeout {
+        Some(timeout) if timeout < NO_TIMEOUT => timeout,
+        _ => NO_TIMEOUT,
+    }
+}
+
+/// Converts the result of a blocking syscall.
+pub(crate) fn wait_result(result: i64) -> Result<u64, WaitError> {
+    match result {
+        TIMED_OUT_ERROR => Err(WaitError::TimedOut),
+        VALUE_CHANGED_ERROR => Err(WaitError::ValueChanged),
+        result if result < 0 => Err(WaitError::InvalidArgument),
+        result => Ok(result as u64),
+    }
+}
+
+/// A measurement of the monotonic clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(u64);
+
+impl Instant {
+    /// Returns the current instant.
+    pub fn now() -> Instant {
+        Instant(read_clock(ClockId::Monotonic))
+    }
+
+    /// Returns the nanoseconds between an earlier instant and this one.
+    pub fn nanoseconds_since(&self, earlier: Instant) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    /// Returns the nanoseconds that passed since this instant.
+    pub fn elapsed_nanoseconds(&self) -> u64 {
+        Instant::now().nanoseconds_since(*self)
+    }
+}
+
+/// A measurement of the realtime clock.
+///
+/// Unlike `Instant` this can jump, if the system time is changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SystemTime(u64);
+
+/// The UNIX epoch, 1970-01-01 00:00:00 UTC.
+pub const UNIX_EPOCH: SystemTime = SystemTime(0);
+
+impl SystemTime {
+    /// Returns the current system time.
+    pub fn now() -> SystemTime {
+        SystemTime(read_clock(ClockId::Realtime))
+    }
+
+    /// Returns the system time the given amount of nanoseconds after the UNIX
+    /// epoch.
+    pub fn from_unix_nanoseconds(nanoseconds: u64) -> SystemTime {
+        SystemTime(nanoseconds)
+    }
+
+    /// Returns the number of nanoseconds since the UNIX epoch.
+    pub fn unix_nanoseconds(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns the time since the UNIX epoch.
+    pub fn since_unix_epoch(&self) -> Timespec {
+        Timespec::from_nanoseconds(self.0)
+    }
+
+    /// Returns the nanoseconds between an earlier system time and this one.
+    ///
+    /// Returns `None` if the given time is later than this one.
+    pub fn nanoseconds_since(&self, earlier: SystemTime) -> Option<u64> {
+        self.0.checked_sub(earlier.0)
+    }
+
+    /// Returns the calendar date and time in UTC.
+    pub fn to_date_time(&self) -> DateTime {
+        DateTime::from_unix_seconds(self.0 / NANOSECONDS_PER_SECOND)
+    }
+}
+
+impl fmt::Display for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.to_date_time().fmt(f)
+    }
+}
@@ -0,0 +1,88 @@
+//! Provides timers that expire once or periodically.
+
+use boring_core::syscall::{TIMER_CREATE_SYSCALL_NUM, TIMER_DELETE_SYSCALL_NUM,
+                           TIMER_WAIT_SYSCALL_NUM};
+use time::{timeout_argument, wait_result, WaitError, NANOSECONDS_PER_SECOND};
+
+/// The way the expiry of a timer is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerDelivery {
+    /// Threads waiting on the timer are woken up.
+    Wakeup = 0,
+    /// A timer message is sent to the process.
+    Message = 1,
+}
+
+/// A timer owned by the process.
+///
+/// The timer is deleted when it is dropped.
+#[derive(Debug)]
+pub struct Timer {
+    /// The kernel handle of the timer.
+    handle: u64,
+}
+
+impl Timer {
+    /// Creates a timer expiring once after `delay` nanoseconds.
+    pub fn one_shot(delay: u64, delivery: TimerDelivery) -> Timer {
+        Timer::new(delay, 0, delivery)
+    }
+
+    /// Creates a timer expiring every `period` nanoseconds.
+    pub fn periodic(period: u64, delivery: TimerDelivery) -> Timer {
+        assert!(period > 0, "The period of a timer must not be zero.");
+
+        Timer::new(period, period, delivery)
+    }
+
+    /// Creates a timer expiring `frequency` times per second.
+    pub fn with_frequency(frequency: u64, delivery: TimerDelivery) -> Timer {
+        Timer::periodic(NANOSECONDS_PER_SECOND / frequency, delivery)
+    }
+
+    /// Creates a timer with the given delay and period.
+    fn new(delay: u64, period: u64, delivery: TimerDelivery) -> Timer {
+        let handle =
+            unsafe { syscall!(TIMER_CREATE_SYSCALL_NUM, delay, period, delivery as u64) as i64 };
+        assert!(handle > 0, "The timer could not be created.");
+
+        Timer {
+            handle: handle as u64,
+        }
+    }
+
+    /// Returns the handle that timer messages refer to.
+    pub fn handle(&self) -> u64 {
+        self.handle
+    }
+
+    /// Waits for the next expiry of the timer.
+    ///
+    /// Returns the number of expiries since the last wait, which is more than
+    /// one if expiries were missed.
+    pub fn wait(&self) -> u64 {
+        self.wait_with_timeout(None)
+            .expect("Waiting on the timer failed.")
+    }
+
+    /// Waits for the next expiry for at most `timeout` nanoseconds.
+    pub fn wait_with_timeout(&self, timeout: Option<u64>) -> Result<u64, WaitError> {
+        let result = unsafe {
+            syscall!(
+                TIMER_WAIT_SYSCALL_NUM,
+                self.handle,
+                timeout_argument(timeout)
+            ) as i64
+        };
+
+        wait_result(result)
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        unsafe {
+            syscall!(TIMER_DELETE_SYSCALL_NUM, self.handle);
+        }
+    }
+}
@@ -184,12 +184,14 @@ pub struct Info {
 }
 
 pub fn init() {
+    let framebuffer = ::screen::map_framebuffer().expect("No linear framebuffer is available.");
+
     let info = Info {
-        height: 200,
-        width: 320,
-        address: 0xffff8000fd000000,
-        bpp: 32,
-        pitch: 1280,
+        height: framebuffer.height,
+        width: framebuffer.width,
+        address: framebuffer.address,
+        bpp: framebuffer.bpp,
+        pitch: framebuffer.pitch,
     };
 
     SCREEN.call_once(|| Mutex::new(Buffer::new(info)));